@@ -0,0 +1,199 @@
+use crate::ast::{
+    EvaluatedRegionData, EvaluatedRegionTable, GeomStmtWide, RegionTable, SourceLocation,
+};
+use crate::parser::geom::GeometryStatement;
+use crate::ParseError;
+use std::collections::BTreeMap;
+
+/// `@alias name=target` statements by alias name, with the statement location
+pub type AliasTable = BTreeMap<String, (String, SourceLocation)>;
+
+/// Collect the `@alias` statements, checking that each alias name is new: not
+/// aliased twice and not a region with geometry in `region_table`
+pub fn collect_aliases(
+    geom_stmts: &[GeomStmtWide],
+    region_table: &RegionTable,
+) -> Result<AliasTable, ParseError> {
+    let mut aliases = AliasTable::new();
+    for stmt in geom_stmts {
+        let GeometryStatement::Alias { name, target } = &stmt.statement else {
+            continue;
+        };
+        let source = SourceLocation::new(stmt.tuple_idx, stmt.stmt_idx);
+        let first_source = match (aliases.get(name), region_table.regions.get(name)) {
+            (Some((_, first_source)), _) => Some(first_source.clone()),
+            (None, Some(entry)) => Some(
+                entry
+                    .sources()
+                    .first()
+                    .map_or_else(|| source.clone(), |&first| first.clone()),
+            ),
+            (None, None) => None,
+        };
+        if let Some(first_source) = first_source {
+            return Err(ParseError::DuplicateRegionDefinition(Box::new(
+                crate::DuplicateRegionDefinitionError {
+                    region: name.clone(),
+                    first_source,
+                    duplicate_source: source,
+                },
+            )));
+        }
+        aliases.insert(name.clone(), (target.clone(), source));
+    }
+    Ok(aliases)
+}
+
+/// The chain of names from alias `name` to the region it finally copies, e.g.
+/// `["c", "b", "a"]` for `@alias c=b` and `@alias b=a`.
+///
+/// Aliases leading back to themselves are a `CycleDetected` error, and a final
+/// target without geometry in `region_table` an `UnknownRegion`.
+pub fn alias_chain(
+    aliases: &AliasTable,
+    region_table: &RegionTable,
+    name: &str,
+) -> Result<Vec<String>, ParseError> {
+    let mut chain = vec![name.to_string()];
+    let mut source = None;
+    while let Some((target, target_source)) = aliases.get(chain.last().expect("chain is not empty"))
+    {
+        if let Some(start) = chain.iter().position(|link| link == target) {
+            return Err(ParseError::cycle_detected(chain[start..].to_vec()));
+        }
+        chain.push(target.clone());
+        source = Some(target_source);
+    }
+
+    let last = chain.last().expect("chain is not empty");
+    match source {
+        Some(source) if !region_table.regions.contains_key(last) => {
+            Err(ParseError::UnknownRegion {
+                region: last.clone(),
+                source: source.clone(),
+            })
+        }
+        _ => Ok(chain),
+    }
+}
+
+/// Give every alias the boxes and box labels of the region it copies, and
+/// the region's metadata for keys the alias does not set itself.
+///
+/// Along a chain of aliases, metadata set on an alias nearer to `name` wins.
+pub fn apply_aliases(
+    evaluated_table: &mut EvaluatedRegionTable,
+    region_table: &RegionTable,
+    geom_stmts: &[GeomStmtWide],
+) -> Result<(), ParseError> {
+    let aliases = collect_aliases(geom_stmts, region_table)?;
+    if aliases.is_empty() {
+        return Ok(());
+    }
+    let chains = aliases
+        .keys()
+        .map(|name| alias_chain(&aliases, region_table, name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Copy from the entries as they were, so one alias's copy does not leak
+    // into another alias's own metadata
+    let original: BTreeMap<&str, EvaluatedRegionData> = chains
+        .iter()
+        .flat_map(|chain| &chain[1..])
+        .filter_map(|link| Some((link.as_str(), evaluated_table.regions.get(link)?.clone())))
+        .collect();
+    for chain in &chains {
+        let (name, links) = chain.split_first().expect("chain is not empty");
+        let Some(target) = links.last().and_then(|last| original.get(last.as_str())) else {
+            continue;
+        };
+        evaluated_table.set_region_boxes(name.clone(), target.boxes.clone());
+        if let Some(labels) = &target.box_labels {
+            evaluated_table.set_region_box_labels(name, labels.clone());
+        }
+        for link in links {
+            let Some(data) = original.get(link.as_str()) else {
+                continue;
+            };
+            for (key, assignment) in &data.metadata {
+                evaluated_table.add_default_metadata(name.clone(), key.clone(), assignment.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alias(stmt_idx: usize, name: &str, target: &str) -> GeomStmtWide {
+        GeomStmtWide::new(
+            0,
+            stmt_idx,
+            GeometryStatement::Alias {
+                name: name.to_string(),
+                target: target.to_string(),
+            },
+        )
+    }
+
+    fn table_with(region: &str) -> RegionTable {
+        let mut table = RegionTable::new();
+        let stmt = crate::ast::GeomStmt::new(
+            1,
+            0,
+            GeometryStatement::AbsoluteCoordinate {
+                region: Some(region.to_string()),
+                corners: ([0, 0, 0], [1, 1, 1]),
+                label: None,
+            },
+        );
+        table.add_geometry(&stmt, [0, 0, 0]).unwrap();
+        table
+    }
+
+    #[test]
+    fn test_alias_chain() {
+        let region_table = table_with("a");
+        let stmts = [alias(0, "c", "b"), alias(1, "b", "a")];
+        let aliases = collect_aliases(&stmts, &region_table).unwrap();
+        assert_eq!(
+            alias_chain(&aliases, &region_table, "c").unwrap(),
+            vec!["c", "b", "a"]
+        );
+
+        let stmts = [alias(0, "x", "y"), alias(1, "y", "x")];
+        let aliases = collect_aliases(&stmts, &region_table).unwrap();
+        for name in ["x", "y"] {
+            match alias_chain(&aliases, &region_table, name) {
+                Err(ParseError::CycleDetected { cycle }) => assert_eq!(cycle, vec!["x", "y"]),
+                other => panic!("Expected CycleDetected, got {:?}", other),
+            }
+        }
+
+        let stmts = [alias(0, "x", "missing")];
+        let aliases = collect_aliases(&stmts, &region_table).unwrap();
+        match alias_chain(&aliases, &region_table, "x") {
+            Err(ParseError::UnknownRegion { region, source }) => {
+                assert_eq!(region, "missing");
+                assert_eq!(source, SourceLocation::new(0, 0));
+            }
+            other => panic!("Expected UnknownRegion, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_alias_name_must_be_new() {
+        let region_table = table_with("a");
+        for stmts in [
+            vec![alias(0, "a", "b")],
+            vec![alias(0, "b", "a"), alias(1, "b", "a")],
+        ] {
+            assert!(matches!(
+                collect_aliases(&stmts, &region_table),
+                Err(ParseError::DuplicateRegionDefinition(_))
+            ));
+        }
+    }
+}
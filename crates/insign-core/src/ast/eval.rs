@@ -1,40 +1,167 @@
+use crate::ast::output::narrow_vec3;
 use crate::ast::{BooleanExpr, RegionEntry, RegionTable, SourceLocation};
-use crate::{BoxPair, ParseError};
+use crate::boxutil;
+use crate::{BoxPair, BoxPair64, CompileOptions, CoordWidth, ParseError};
 use std::collections::BTreeMap;
 
+/// Default for [`CompileOptions::max_eval_depth`]
+pub const DEFAULT_MAX_EVAL_DEPTH: usize = 256;
+
 /// Result of evaluating a region's geometry
 #[derive(Debug, Clone, PartialEq)]
 pub struct EvaluatedRegion {
     /// The final bounding boxes for this region
-    pub boxes: Vec<BoxPair>,
+    pub boxes: Vec<BoxPair64>,
     /// Whether this region was successfully evaluated
     pub evaluated: bool,
 }
 
-/// Context for geometry evaluation
+/// Memoizing evaluator for region geometry.
+///
+/// Holds no reference to the table, so it can live alongside one and evaluate
+/// regions on demand; results stay cached across calls.
 #[derive(Debug)]
-struct EvaluationContext<'a> {
-    /// The region table to evaluate
-    table: &'a RegionTable,
+pub struct Evaluator {
     /// Cache of already evaluated regions
     cache: BTreeMap<String, EvaluatedRegion>,
+    /// Defined regions that are a bare reference (`@r=a`), mapped to their target.
+    /// Aliases share the target's cache entry instead of storing their own.
+    aliases: BTreeMap<String, String>,
     /// Current evaluation path for cycle detection
     evaluation_path: Vec<String>,
+    /// Coordinate range that evaluated boxes must stay within
+    coordinate_width: CoordWidth,
+    /// Largest absolute coordinate of any evaluated region's boxes
+    max_abs_coordinate: Option<u64>,
+    /// Box operations still allowed, shared by every region evaluated
+    budget: EvalBudget,
+    /// Current nesting of region evaluations and sub-expressions
+    depth: usize,
+    /// Nesting beyond which evaluation fails
+    max_depth: usize,
+    /// Metadata of every region, matched by `group_by` terms
+    #[cfg(feature = "metadata_groups")]
+    group_metadata: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+}
+
+/// Count of box operations an evaluation may still perform.
+///
+/// One step is one box visited by a union, one box pair tested by an
+/// intersection, one box subtraction in a difference, or one byte of
+/// interpolated metadata text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalBudget {
+    limit: u64,
+    used: u64,
+}
+
+impl EvalBudget {
+    /// A budget allowing `limit` steps
+    pub fn new(limit: u64) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    /// A budget that is never exhausted
+    pub fn unlimited() -> Self {
+        Self::new(u64::MAX)
+    }
+
+    /// Steps performed so far
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Record `steps` operations, failing once the limit is passed
+    pub fn spend(&mut self, steps: u64) -> Result<(), ParseError> {
+        self.used = self.used.saturating_add(steps);
+        if self.used > self.limit {
+            return Err(ParseError::EvaluationBudgetExceeded { limit: self.limit });
+        }
+        Ok(())
+    }
 }
 
-impl<'a> EvaluationContext<'a> {
-    fn new(table: &'a RegionTable) -> Self {
+impl Evaluator {
+    /// Create an evaluator with an empty cache, no step limit and the default
+    /// depth limit
+    pub fn new(coordinate_width: CoordWidth) -> Self {
         Self {
-            table,
+            coordinate_width,
+            max_abs_coordinate: None,
+            budget: EvalBudget::unlimited(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_EVAL_DEPTH,
             cache: BTreeMap::new(),
+            aliases: BTreeMap::new(),
             evaluation_path: Vec::new(),
+            #[cfg(feature = "metadata_groups")]
+            group_metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Create an evaluator honoring the coordinate width and limits in `options`
+    pub fn with_options(options: &CompileOptions) -> Self {
+        Self {
+            budget: EvalBudget::new(options.max_eval_steps),
+            max_depth: options.max_eval_depth,
+            max_abs_coordinate: options.max_abs_coordinate,
+            ..Self::new(options.coordinate_width.resolved())
         }
     }
 
+    /// Bound evaluated boxes by `width` instead of the literal width in the
+    /// options, e.g. to produce i64 output from i32 literals
+    pub fn with_coordinate_width(self, width: CoordWidth) -> Self {
+        Self {
+            coordinate_width: width,
+            ..self
+        }
+    }
+
+    /// Provide the metadata that `group_by` terms match against.
+    ///
+    /// Call before evaluating: cached results are not recomputed. Without it,
+    /// every group is empty.
+    #[cfg(feature = "metadata_groups")]
+    pub fn set_group_metadata(&mut self, table: &crate::ast::EvaluatedRegionTable) {
+        self.group_metadata = table
+            .regions
+            .iter()
+            .map(|(region, data)| {
+                let metadata = data
+                    .metadata
+                    .iter()
+                    .map(|(key, assignment)| (key.clone(), assignment.value.clone()))
+                    .collect();
+                (region.clone(), metadata)
+            })
+            .collect();
+    }
+
+    /// Box operations performed so far across all evaluated regions
+    pub fn steps_used(&self) -> u64 {
+        self.budget.used()
+    }
+
     /// Evaluate a region and return its bounding boxes
-    fn evaluate_region(&mut self, region_name: &str) -> Result<Vec<BoxPair>, ParseError> {
-        // Check if already cached
-        if let Some(cached) = self.cache.get(region_name) {
+    pub fn evaluate_region(
+        &mut self,
+        table: &RegionTable,
+        region_name: &str,
+    ) -> Result<Vec<BoxPair64>, ParseError> {
+        self.evaluate_region_from(table, region_name, None)
+    }
+
+    /// Evaluate `region_name`, referenced by the statement at `referenced_at`
+    /// or looked up by name when that is `None`
+    fn evaluate_region_from(
+        &mut self,
+        table: &RegionTable,
+        region_name: &str,
+        referenced_at: Option<&SourceLocation>,
+    ) -> Result<Vec<BoxPair64>, ParseError> {
+        // Check if already cached, looking through resolved aliases
+        if let Some(cached) = self.cache.get(self.resolve_alias(region_name)) {
             return Ok(cached.boxes.clone());
         }
 
@@ -46,18 +173,25 @@ impl<'a> EvaluationContext<'a> {
                 .position(|r| r == region_name)
                 .unwrap_or(0);
             let cycle = self.evaluation_path[cycle_start..].to_vec();
-            return Err(ParseError::CycleDetected { cycle });
+            return Err(ParseError::cycle_detected(cycle));
         }
 
         // Add to evaluation path
+        self.enter(region_name)?;
         self.evaluation_path.push(region_name.to_string());
 
-        let result = self.evaluate_region_impl(region_name);
+        let result = self
+            .evaluate_region_impl(table, region_name, referenced_at)
+            .and_then(|boxes| self.check_coordinate_limit(region_name, boxes));
 
         // Remove from evaluation path
         self.evaluation_path.pop();
+        self.depth -= 1;
 
-        // Cache the result if successful
+        // Cache the result if successful; aliases reuse their target's entry
+        if self.aliases.contains_key(region_name) {
+            return result;
+        }
         if let Ok(boxes) = &result {
             self.cache.insert(
                 region_name.to_string(),
@@ -71,40 +205,148 @@ impl<'a> EvaluationContext<'a> {
         result
     }
 
+    /// Go one level deeper on behalf of `region_name`, failing past the depth
+    /// limit. Every successful call is paired with `self.depth -= 1`.
+    fn enter(&mut self, region_name: &str) -> Result<(), ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::EvaluationDepthExceeded {
+                region: region_name.to_string(),
+                depth: self.max_depth,
+            });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Pass `boxes` through if they stay within the absolute coordinate limit
+    fn check_coordinate_limit(
+        &self,
+        region_name: &str,
+        boxes: Vec<BoxPair64>,
+    ) -> Result<Vec<BoxPair64>, ParseError> {
+        let Some(limit) = self.max_abs_coordinate else {
+            return Ok(boxes);
+        };
+        let beyond = boxes
+            .iter()
+            .flat_map(|(min, max)| min.iter().chain(max))
+            .any(|c| c.unsigned_abs() > limit);
+        if beyond {
+            return Err(ParseError::CoordinateLimitExceeded {
+                region: region_name.to_string(),
+                limit,
+            });
+        }
+        Ok(boxes)
+    }
+
+    /// Follow alias links to the region whose cache entry holds the boxes
+    fn resolve_alias<'n>(&'n self, mut region_name: &'n str) -> &'n str {
+        while let Some(target) = self.aliases.get(region_name) {
+            region_name = target;
+        }
+        region_name
+    }
+
     /// Internal implementation of region evaluation
-    fn evaluate_region_impl(&mut self, region_name: &str) -> Result<Vec<BoxPair>, ParseError> {
-        match self.table.regions.get(region_name) {
+    fn evaluate_region_impl(
+        &mut self,
+        table: &RegionTable,
+        region_name: &str,
+        referenced_at: Option<&SourceLocation>,
+    ) -> Result<Vec<BoxPair64>, ParseError> {
+        match table.regions.get(region_name) {
             Some(RegionEntry::Accumulator { boxes, .. }) => {
                 // Accumulator regions directly return their boxes
                 Ok(boxes.clone())
             }
             Some(RegionEntry::Defined { expr, source }) => {
+                if let Some(target) = expr.as_region_ref() {
+                    // A bare reference is an alias: delegate directly to the target
+                    if target == region_name {
+                        return Err(ParseError::SelfReference {
+                            region: region_name.to_string(),
+                            source: source.clone(),
+                        });
+                    }
+                    let boxes = self.evaluate_region_from(table, target, Some(source))?;
+                    self.aliases
+                        .insert(region_name.to_string(), target.to_string());
+                    return Ok(boxes);
+                }
+
                 // Defined regions need expression evaluation
-                self.evaluate_expression(expr, region_name, source)
+                self.evaluate_expression(table, expr, region_name, source)
             }
             Some(RegionEntry::Anonymous { box_pair, .. }) => {
                 // Anonymous regions return their single box
                 Ok(vec![*box_pair])
             }
-            None => {
-                // Unknown region - we need a source location for the error
-                // For now, use a default source. In a full implementation,
-                // we'd track where each reference comes from
-                Err(ParseError::UnknownRegion {
+            None => Err(match referenced_at {
+                Some(source) => ParseError::UnknownRegion {
                     region: region_name.to_string(),
-                    source: SourceLocation::new(0, 0), // TODO: Track actual source
-                })
-            }
+                    source: source.clone(),
+                },
+                None => ParseError::RegionNotFound {
+                    region: region_name.to_string(),
+                },
+            }),
         }
     }
 
-    /// Evaluate a boolean expression
+    /// Evaluate a boolean expression, one level deeper than its parent
     fn evaluate_expression(
         &mut self,
+        table: &RegionTable,
+        expr: &BooleanExpr,
+        current_region: &str,
+        source: &SourceLocation,
+    ) -> Result<Vec<BoxPair64>, ParseError> {
+        self.enter(current_region)?;
+        let result = self.evaluate_expression_impl(table, expr, current_region, source);
+        self.depth -= 1;
+        result
+    }
+
+    /// Evaluate the operands of a [`left_chain`] in order and combine them
+    /// left to right with `combine`, as the nested expression would.
+    ///
+    /// The chain takes a single level of depth however long it is, so a flat
+    /// `r0 - r1 - … - r999` is no deeper than `r0 - r1`.
+    #[cfg(feature = "boolean_ops")]
+    fn fold_chain(
+        &mut self,
+        table: &RegionTable,
+        operands: Vec<&BooleanExpr>,
+        current_region: &str,
+        source: &SourceLocation,
+        combine: impl Fn(
+            &mut Self,
+            Vec<BoxPair64>,
+            Vec<BoxPair64>,
+        ) -> Result<Vec<BoxPair64>, ParseError>,
+    ) -> Result<Vec<BoxPair64>, ParseError> {
+        let mut acc: Option<Vec<BoxPair64>> = None;
+        for operand in operands {
+            let boxes = self.evaluate_expression(table, operand, current_region, source)?;
+            // Check for coordinate overflow before combining
+            check_boxes_bounds(&boxes, self.coordinate_width)?;
+            acc = Some(match acc {
+                None => boxes,
+                Some(acc) => combine(self, acc, boxes)?,
+            });
+        }
+        Ok(acc.unwrap_or_default())
+    }
+
+    /// Internal implementation of expression evaluation
+    fn evaluate_expression_impl(
+        &mut self,
+        table: &RegionTable,
         expr: &BooleanExpr,
         current_region: &str,
         source: &SourceLocation,
-    ) -> Result<Vec<BoxPair>, ParseError> {
+    ) -> Result<Vec<BoxPair64>, ParseError> {
         match expr {
             BooleanExpr::RegionRef(ref_name) => {
                 // Check for self-reference
@@ -115,71 +357,176 @@ impl<'a> EvaluationContext<'a> {
                     });
                 }
 
-                // Evaluate the referenced region
-                self.evaluate_region(ref_name)
+                // Evaluate the referenced region; an unknown one is reported at
+                // the referencing statement
+                self.evaluate_region_from(table, ref_name, Some(source))
             }
-            BooleanExpr::Union(left, right) => {
-                // Evaluate both sides and concatenate boxes
-                let mut left_boxes = self.evaluate_expression(left, current_region, source)?;
-                let mut right_boxes = self.evaluate_expression(right, current_region, source)?;
-
-                // Check for coordinate overflow during union
-                check_boxes_bounds(&left_boxes)?;
-                check_boxes_bounds(&right_boxes)?;
-
-                left_boxes.append(&mut right_boxes);
-                Ok(left_boxes)
+            BooleanExpr::Union(..) => {
+                let operands = left_chain(expr, |e| match e {
+                    BooleanExpr::Union(left, right) => Some((left, right)),
+                    _ => None,
+                });
+                // Each operand's boxes are charged once, however long the chain
+                let mut boxes = Vec::new();
+                for operand in operands {
+                    let mut operand_boxes =
+                        self.evaluate_expression(table, operand, current_region, source)?;
+                    check_boxes_bounds(&operand_boxes, self.coordinate_width)?;
+                    self.budget.spend(operand_boxes.len() as u64)?;
+                    boxes.append(&mut operand_boxes);
+                }
+                Ok(boxes)
+            }
+            BooleanExpr::Expand(inner, amount) | BooleanExpr::Inset(inner, amount) => {
+                let boxes = self.evaluate_expression(table, inner, current_region, source)?;
+                self.budget.spend(boxes.len() as u64)?;
+
+                // Saturate so that an out-of-range result is caught by the bounds check
+                let amount = i64::try_from(*amount).unwrap_or(i64::MAX);
+                let delta = if matches!(expr, BooleanExpr::Inset(..)) {
+                    -amount
+                } else {
+                    amount
+                };
+                let boxes: Vec<BoxPair64> = boxes
+                    .into_iter()
+                    .map(|(min, max)| {
+                        (
+                            min.map(|c| c.saturating_sub(delta)),
+                            max.map(|c| c.saturating_add(delta)),
+                        )
+                    })
+                    .filter(|(min, max)| (0..3).all(|i| min[i] <= max[i]))
+                    .collect();
+
+                check_boxes_bounds(&boxes, self.coordinate_width)?;
+                Ok(boxes)
+            }
+            BooleanExpr::Translate(inner, offset) => {
+                let boxes = self.evaluate_expression(table, inner, current_region, source)?;
+                self.budget.spend(boxes.len() as u64)?;
+
+                // Saturate so that an out-of-range result is caught by the bounds check
+                let shift = |corner: [i64; 3]| {
+                    [0, 1, 2].map(|axis| corner[axis].saturating_add(offset[axis]))
+                };
+                let boxes: Vec<BoxPair64> = boxes
+                    .into_iter()
+                    .map(|(min, max)| (shift(min), shift(max)))
+                    .collect();
+
+                check_boxes_bounds(&boxes, self.coordinate_width)?;
+                Ok(boxes)
             }
             #[cfg(feature = "boolean_ops")]
-            BooleanExpr::Difference(left, right) => {
-                let left_boxes = self.evaluate_expression(left, current_region, source)?;
-                let right_boxes = self.evaluate_expression(right, current_region, source)?;
-
-                check_boxes_bounds(&left_boxes)?;
-                check_boxes_bounds(&right_boxes)?;
-
-                Ok(compute_difference(&left_boxes, &right_boxes))
+            BooleanExpr::Difference(..) => {
+                let operands = left_chain(expr, |e| match e {
+                    BooleanExpr::Difference(left, right) => Some((left, right)),
+                    _ => None,
+                });
+                self.fold_chain(
+                    table,
+                    operands,
+                    current_region,
+                    source,
+                    |evaluator, acc, boxes| {
+                        compute_difference_budgeted(&acc, &boxes, &mut evaluator.budget)
+                    },
+                )
             }
             #[cfg(feature = "boolean_ops")]
-            BooleanExpr::Intersection(left, right) => {
-                let left_boxes = self.evaluate_expression(left, current_region, source)?;
-                let right_boxes = self.evaluate_expression(right, current_region, source)?;
-
-                check_boxes_bounds(&left_boxes)?;
-                check_boxes_bounds(&right_boxes)?;
-
-                Ok(compute_intersection(&left_boxes, &right_boxes))
+            BooleanExpr::Intersection(..) => {
+                let operands = left_chain(expr, |e| match e {
+                    BooleanExpr::Intersection(left, right) => Some((left, right)),
+                    _ => None,
+                });
+                self.fold_chain(
+                    table,
+                    operands,
+                    current_region,
+                    source,
+                    |evaluator, acc, boxes| {
+                        compute_intersection(&acc, &boxes, &mut evaluator.budget)
+                    },
+                )
             }
             #[cfg(feature = "boolean_ops")]
-            BooleanExpr::Xor(left, right) => {
-                let left_boxes = self.evaluate_expression(left, current_region, source)?;
-                let right_boxes = self.evaluate_expression(right, current_region, source)?;
-
-                check_boxes_bounds(&left_boxes)?;
-                check_boxes_bounds(&right_boxes)?;
-
-                Ok(compute_xor(&left_boxes, &right_boxes))
+            BooleanExpr::Xor(..) => {
+                let operands = left_chain(expr, |e| match e {
+                    BooleanExpr::Xor(left, right) => Some((left, right)),
+                    _ => None,
+                });
+                self.fold_chain(
+                    table,
+                    operands,
+                    current_region,
+                    source,
+                    |evaluator, acc, boxes| compute_xor(&acc, &boxes, &mut evaluator.budget),
+                )
+            }
+            #[cfg(feature = "metadata_groups")]
+            BooleanExpr::GroupBy { key, value } => {
+                // Members are evaluated like references, so a group that matches
+                // itself (or a region depending on it) is reported as a cycle
+                let members: Vec<String> = self
+                    .group_metadata
+                    .iter()
+                    .filter(|(region, metadata)| {
+                        table.regions.contains_key(*region) && metadata.get(key) == Some(value)
+                    })
+                    .map(|(region, _)| region.clone())
+                    .collect();
+
+                let mut boxes = Vec::new();
+                for member in members {
+                    let mut member_boxes = self.evaluate_region(table, &member)?;
+                    check_boxes_bounds(&member_boxes, self.coordinate_width)?;
+                    self.budget.spend(member_boxes.len() as u64)?;
+                    boxes.append(&mut member_boxes);
+                }
+                Ok(boxes)
             }
         }
     }
 }
 
-/// Check that all boxes are within i32 bounds
-fn check_boxes_bounds(boxes: &[BoxPair]) -> Result<(), ParseError> {
+/// Operands of a left-nested chain of one binary operator, leftmost first:
+/// `[a, b, c]` for `(a - b) - c`. `split` yields the operands of a node of
+/// that operator and `None` for anything else.
+fn left_chain<'e>(
+    expr: &'e BooleanExpr,
+    split: impl Fn(&'e BooleanExpr) -> Option<(&'e BooleanExpr, &'e BooleanExpr)>,
+) -> Vec<&'e BooleanExpr> {
+    let mut operands = Vec::new();
+    let mut node = expr;
+    while let Some((left, right)) = split(node) {
+        operands.push(right);
+        node = left;
+    }
+    operands.push(node);
+    operands.reverse();
+    operands
+}
+
+/// Check that all boxes stay strictly inside the bounds of `width`
+fn check_boxes_bounds(boxes: &[BoxPair64], width: CoordWidth) -> Result<(), ParseError> {
     for (min_corner, max_corner) in boxes {
         // Check each coordinate component
         for i in 0..3 {
-            if min_corner[i] == i32::MIN
-                || min_corner[i] == i32::MAX
-                || max_corner[i] == i32::MIN
-                || max_corner[i] == i32::MAX
+            if min_corner[i] <= width.min()
+                || min_corner[i] >= width.max()
+                || max_corner[i] <= width.min()
+                || max_corner[i] >= width.max()
             {
-                return Err(ParseError::Internal {
+                return Err(ParseError::InvalidBox {
                     message: format!(
-                        "Coordinate overflow detected: box ({:?}, {:?})",
-                        min_corner, max_corner
+                        "box ({:?}, {:?}) reaches the coordinate limit on the {} axis",
+                        min_corner,
+                        max_corner,
+                        ["x", "y", "z"][i]
                     ),
                     position: 0,
+                    context: None,
                 });
             }
         }
@@ -191,14 +538,45 @@ fn check_boxes_bounds(boxes: &[BoxPair]) -> Result<(), ParseError> {
 pub fn evaluate_geometry(
     table: &RegionTable,
 ) -> Result<BTreeMap<String, Vec<BoxPair>>, ParseError> {
-    let mut context = EvaluationContext::new(table);
+    evaluate_geometry_with_options(table, &CompileOptions::default())
+}
+
+/// Evaluate all regions in a RegionTable, honoring the coordinate width in
+/// `options` as far as [`BoxPair`] can hold it
+pub fn evaluate_geometry_with_options(
+    table: &RegionTable,
+    options: &CompileOptions,
+) -> Result<BTreeMap<String, Vec<BoxPair>>, ParseError> {
+    let evaluator = Evaluator::with_options(options).with_coordinate_width(Ord::min(
+        options.coordinate_width.resolved(),
+        CoordWidth::COORD,
+    ));
+    evaluate_all(table, evaluator)?
+        .into_iter()
+        .map(|(name, boxes)| Ok((name, narrow_boxes(boxes)?)))
+        .collect()
+}
+
+/// Evaluate all regions in a RegionTable with i64 boxes, honoring the
+/// coordinate width in `options`
+pub fn evaluate_geometry_wide(
+    table: &RegionTable,
+    options: &CompileOptions,
+) -> Result<BTreeMap<String, Vec<BoxPair64>>, ParseError> {
+    evaluate_all(table, Evaluator::with_options(options))
+}
+
+fn evaluate_all(
+    table: &RegionTable,
+    mut evaluator: Evaluator,
+) -> Result<BTreeMap<String, Vec<BoxPair64>>, ParseError> {
     let mut results = BTreeMap::new();
 
     // Collect all region names for evaluation
     let region_names: Vec<String> = table.regions.keys().cloned().collect();
 
     for region_name in region_names {
-        let boxes = context.evaluate_region(&region_name)?;
+        let boxes = evaluator.evaluate_region(table, &region_name)?;
         results.insert(region_name, boxes);
     }
 
@@ -210,20 +588,254 @@ pub fn evaluate_region_boxes(
     table: &RegionTable,
     region_name: &str,
 ) -> Result<Vec<BoxPair>, ParseError> {
-    let mut context = EvaluationContext::new(table);
-    context.evaluate_region(region_name)
+    narrow_boxes(Evaluator::new(CoordWidth::COORD).evaluate_region(table, region_name)?)
 }
 
-// Boolean operation implementations (feature-gated)
+/// Evaluate a specific region and return its bounding boxes in i64
+pub fn evaluate_region_boxes_wide(
+    table: &RegionTable,
+    region_name: &str,
+) -> Result<Vec<BoxPair64>, ParseError> {
+    Evaluator::new(CoordWidth::I64).evaluate_region(table, region_name)
+}
 
-#[cfg(feature = "boolean_ops")]
-/// Compute the difference of two sets of boxes: left - right
-/// Returns all parts of left that don't overlap with any box in right
-fn compute_difference(left: &[BoxPair], right: &[BoxPair]) -> Vec<BoxPair> {
-    if right.is_empty() {
-        return left.to_vec();
+/// Narrow evaluated boxes to [`BoxPair`], which bounded evaluation makes fit
+fn narrow_boxes(boxes: Vec<BoxPair64>) -> Result<Vec<BoxPair>, ParseError> {
+    boxes
+        .into_iter()
+        .map(|(min, max)| Some((narrow_vec3(min)?, narrow_vec3(max)?)))
+        .collect::<Option<Vec<BoxPair>>>()
+        .ok_or_else(|| ParseError::InvalidBox {
+            message: "expression result exceeds the i32 range".to_string(),
+            position: 0,
+            context: None,
+        })
+}
+
+/// Evaluate `expr` against named box lists, without a compiled program.
+///
+/// Each entry of `regions` acts as an accumulator region holding its boxes.
+/// A reference to a name missing from `regions` is an `UnknownRegion` error,
+/// and a result beyond the i32 range of [`crate::compile`] output is an
+/// `InvalidBox` error.
+pub fn evaluate_expr(
+    expr: &BooleanExpr,
+    regions: &BTreeMap<String, Vec<BoxPair>>,
+) -> Result<Vec<BoxPair>, ParseError> {
+    let mut table = RegionTable::new();
+    for (name, boxes) in regions {
+        table.regions.insert(
+            name.clone(),
+            RegionEntry::Accumulator {
+                boxes: boxes
+                    .iter()
+                    .map(|(min, max)| (min.map(i64::from), max.map(i64::from)))
+                    .collect(),
+                labels: vec![None; boxes.len()],
+                sources: Vec::new(),
+            },
+        );
+    }
+
+    // No region holds the expression, so no reference is a self-reference
+    let boxes = Evaluator::new(CoordWidth::COORD).evaluate_expression(
+        &table,
+        expr,
+        "",
+        &SourceLocation::new(0, 0),
+    )?;
+    narrow_boxes(boxes)
+}
+
+/// Largest grid, in cells, that [`minimize_boxes`] will build
+pub const MINIMIZE_CELL_LIMIT: usize = 1 << 22;
+
+/// Cover the same blocks as `boxes` with as few boxes as a greedy sweep finds.
+///
+/// Axes are compressed to the distinct box boundaries, so the work depends on
+/// the number of boxes rather than their volume. Maximal boxes are then taken
+/// from the covered cells, growing along z, then y, then x. The result is
+/// disjoint and sorted, and covers exactly the input blocks. The input is
+/// returned unchanged when the grid would exceed [`MINIMIZE_CELL_LIMIT`] cells
+/// or the sweep finds no fewer boxes.
+pub fn minimize_boxes(boxes: &[BoxPair64]) -> Vec<BoxPair64> {
+    if boxes.len() < 2 {
+        return boxes.to_vec();
+    }
+
+    // Every box spans from its min to just before max + 1 on each axis
+    let bounds: [Vec<i128>; 3] = [0, 1, 2].map(|axis| {
+        let mut bounds: Vec<i128> = boxes
+            .iter()
+            .flat_map(|(min, max)| [i128::from(min[axis]), i128::from(max[axis]) + 1])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+        bounds
+    });
+    let dims = [0, 1, 2].map(|axis| bounds[axis].len() - 1);
+    let cell_count = match dims[0]
+        .checked_mul(dims[1])
+        .and_then(|count| count.checked_mul(dims[2]))
+    {
+        Some(count) if count <= MINIMIZE_CELL_LIMIT => count,
+        _ => return boxes.to_vec(),
+    };
+
+    let cell = |x: usize, y: usize, z: usize| (x * dims[1] + y) * dims[2] + z;
+    let cells_of = |(min, max): &BoxPair64, axis: usize| {
+        let start = bounds[axis].partition_point(|&b| b < i128::from(min[axis]));
+        let end = bounds[axis].partition_point(|&b| b <= i128::from(max[axis]));
+        start..end
+    };
+
+    let mut filled = vec![false; cell_count];
+    for box_pair in boxes {
+        for x in cells_of(box_pair, 0) {
+            for y in cells_of(box_pair, 1) {
+                for z in cells_of(box_pair, 2) {
+                    filled[cell(x, y, z)] = true;
+                }
+            }
+        }
     }
 
+    let mut result = Vec::new();
+    for x in 0..dims[0] {
+        for y in 0..dims[1] {
+            for z in 0..dims[2] {
+                if !filled[cell(x, y, z)] {
+                    continue;
+                }
+
+                // Grow along z, then whole rows along y, then whole slabs along x
+                let mut z_end = z + 1;
+                while z_end < dims[2] && filled[cell(x, y, z_end)] {
+                    z_end += 1;
+                }
+                let mut y_end = y + 1;
+                while y_end < dims[1] && (z..z_end).all(|z| filled[cell(x, y_end, z)]) {
+                    y_end += 1;
+                }
+                let mut x_end = x + 1;
+                while x_end < dims[0]
+                    && (y..y_end).all(|y| (z..z_end).all(|z| filled[cell(x_end, y, z)]))
+                {
+                    x_end += 1;
+                }
+
+                for x in x..x_end {
+                    for y in y..y_end {
+                        for z in z..z_end {
+                            filled[cell(x, y, z)] = false;
+                        }
+                    }
+                }
+
+                // Bounds come from i64 corners, so both ends fit back in i64
+                let corner = |cells: [usize; 3], shift: i128| {
+                    [0, 1, 2].map(|axis| (bounds[axis][cells[axis]] + shift) as i64)
+                };
+                result.push((corner([x, y, z], 0), corner([x_end, y_end, z_end], -1)));
+            }
+        }
+    }
+
+    if result.len() < boxes.len() {
+        result
+    } else {
+        boxes.to_vec()
+    }
+}
+
+/// Merge boxes whose union is exactly one box, keeping the rest as they are.
+///
+/// Two boxes merge when one contains the other (which includes duplicates), or
+/// when they span the same range on two axes and touch or overlap on the third.
+/// Merging repeats until no pair qualifies; a merged box takes the place of the
+/// earliest box it absorbed. Unlike [`minimize_boxes`], boxes are never split,
+/// so shapes such as an L keep their boxes.
+///
+/// Each round sorts the boxes once per axis and merges whole runs in a single
+/// sweep, so a row of n blocks collapses in one round instead of n rescans.
+pub fn coalesce_boxes(boxes: &[BoxPair64]) -> Vec<BoxPair64> {
+    // Each box carries the position of the earliest input box merged into it
+    let mut result: Vec<(usize, BoxPair64)> = boxes.iter().copied().enumerate().collect();
+    loop {
+        let before = result.len();
+        for axis in 0..3 {
+            result = merge_along_axis(result, axis);
+        }
+        result = absorb_contained(result);
+        if result.len() == before {
+            break;
+        }
+    }
+    result.sort_unstable_by_key(|&(position, _)| position);
+    result.into_iter().map(|(_, pair)| pair).collect()
+}
+
+/// Merge boxes that span the same range on the other two axes and touch or
+/// overlap on `axis`
+fn merge_along_axis(mut boxes: Vec<(usize, BoxPair64)>, axis: usize) -> Vec<(usize, BoxPair64)> {
+    let others = [(axis + 1) % 3, (axis + 2) % 3];
+    let section = |(min, max): BoxPair64| others.map(|other| (min[other], max[other]));
+    boxes.sort_unstable_by_key(|&(_, pair)| (section(pair), pair.0[axis]));
+
+    let mut merged: Vec<(usize, BoxPair64)> = Vec::with_capacity(boxes.len());
+    for (position, pair) in boxes {
+        if let Some((last_position, last)) = merged.last_mut() {
+            // Compared in i128 so `max + 1` cannot overflow
+            if section(*last) == section(pair)
+                && i128::from(pair.0[axis]) <= i128::from(last.1[axis]) + 1
+            {
+                last.1[axis] = last.1[axis].max(pair.1[axis]);
+                *last_position = (*last_position).min(position);
+                continue;
+            }
+        }
+        merged.push((position, pair));
+    }
+    merged
+}
+
+/// Drop boxes contained in another box
+fn absorb_contained(mut boxes: Vec<(usize, BoxPair64)>) -> Vec<(usize, BoxPair64)> {
+    // A box's containers are at least as large, so they are kept before it
+    boxes.sort_unstable_by_key(|&(position, pair)| {
+        (std::cmp::Reverse(boxutil::volume(pair)), position)
+    });
+
+    let mut kept: Vec<(usize, BoxPair64)> = Vec::with_capacity(boxes.len());
+    for (position, pair) in boxes {
+        match kept
+            .iter_mut()
+            .find(|(_, outer)| boxutil::contains(*outer, pair))
+        {
+            Some((kept_position, _)) => *kept_position = (*kept_position).min(position),
+            None => kept.push((position, pair)),
+        }
+    }
+    kept
+}
+
+// Boolean operation implementations. Difference is always available for
+// geometry helpers like `complement`; the other operators are feature-gated.
+
+/// Compute the difference of two sets of boxes: left - right
+/// Returns all parts of left that don't overlap with any box in right, sorted
+/// by `(min, max)` so the order does not depend on how boxes are split
+pub fn compute_difference(left: &[BoxPair64], right: &[BoxPair64]) -> Vec<BoxPair64> {
+    compute_difference_budgeted(left, right, &mut EvalBudget::unlimited())
+        .expect("an unlimited budget is never exceeded")
+}
+
+/// Compute `left - right`, charging one step of `budget` per box subtraction
+pub fn compute_difference_budgeted(
+    left: &[BoxPair64],
+    right: &[BoxPair64],
+    budget: &mut EvalBudget,
+) -> Result<Vec<BoxPair64>, ParseError> {
     let mut result = Vec::new();
 
     for left_box in left {
@@ -233,6 +845,7 @@ fn compute_difference(left: &[BoxPair], right: &[BoxPair]) -> Vec<BoxPair> {
         for right_box in right {
             let mut new_remaining = Vec::new();
 
+            budget.spend(remaining.len() as u64)?;
             for current_box in remaining {
                 new_remaining.extend(subtract_box(current_box, *right_box));
             }
@@ -243,43 +856,53 @@ fn compute_difference(left: &[BoxPair], right: &[BoxPair]) -> Vec<BoxPair> {
         result.extend(remaining);
     }
 
-    result
+    result.sort_unstable();
+    Ok(result)
 }
 
 #[cfg(feature = "boolean_ops")]
 /// Compute the intersection of two sets of boxes
 /// Returns all parts where left and right overlap
-fn compute_intersection(left: &[BoxPair], right: &[BoxPair]) -> Vec<BoxPair> {
+fn compute_intersection(
+    left: &[BoxPair64],
+    right: &[BoxPair64],
+    budget: &mut EvalBudget,
+) -> Result<Vec<BoxPair64>, ParseError> {
+    budget.spend((left.len() as u64).saturating_mul(right.len() as u64))?;
     let mut result = Vec::new();
 
     for left_box in left {
         for right_box in right {
-            if let Some(intersection) = intersect_boxes(*left_box, *right_box) {
+            if let Some(intersection) = boxutil::intersection(*left_box, *right_box) {
                 result.push(intersection);
             }
         }
     }
 
-    result
+    Ok(result)
 }
 
 #[cfg(feature = "boolean_ops")]
 /// Compute the XOR of two sets of boxes
-/// Returns (left - right) + (right - left)
-fn compute_xor(left: &[BoxPair], right: &[BoxPair]) -> Vec<BoxPair> {
-    let mut result = compute_difference(left, right);
-    result.extend(compute_difference(right, left));
-    result
+/// Returns (left - right) + (right - left), sorted by `(min, max)`
+fn compute_xor(
+    left: &[BoxPair64],
+    right: &[BoxPair64],
+    budget: &mut EvalBudget,
+) -> Result<Vec<BoxPair64>, ParseError> {
+    let mut result = compute_difference_budgeted(left, right, budget)?;
+    result.extend(compute_difference_budgeted(right, left, budget)?);
+    result.sort_unstable();
+    Ok(result)
 }
 
-#[cfg(feature = "boolean_ops")]
-/// Subtract one box from another, returning the remaining pieces
-fn subtract_box(from: BoxPair, subtract: BoxPair) -> Vec<BoxPair> {
+/// Subtract one box from another, returning the remaining (disjoint) pieces
+pub fn subtract_box(from: BoxPair64, subtract: BoxPair64) -> Vec<BoxPair64> {
     let (from_min, from_max) = from;
     let (sub_min, sub_max) = subtract;
 
     // Check if there's any intersection at all
-    if !boxes_intersect(from, subtract) {
+    if !boxutil::intersects(from, subtract) {
         return vec![from];
     }
 
@@ -334,54 +957,17 @@ fn subtract_box(from: BoxPair, subtract: BoxPair) -> Vec<BoxPair> {
     result
 }
 
-#[cfg(feature = "boolean_ops")]
-/// Check if two boxes intersect
-fn boxes_intersect(box1: BoxPair, box2: BoxPair) -> bool {
-    let (min1, max1) = box1;
-    let (min2, max2) = box2;
-
-    // Boxes intersect if they overlap on all three axes
-    min1[0] <= max2[0]
-        && max1[0] >= min2[0]
-        && min1[1] <= max2[1]
-        && max1[1] >= min2[1]
-        && min1[2] <= max2[2]
-        && max1[2] >= min2[2]
-}
-
-#[cfg(feature = "boolean_ops")]
-/// Compute the intersection of two boxes, returning None if they don't intersect
-fn intersect_boxes(box1: BoxPair, box2: BoxPair) -> Option<BoxPair> {
-    let (min1, max1) = box1;
-    let (min2, max2) = box2;
-
-    let intersection_min = [
-        min1[0].max(min2[0]),
-        min1[1].max(min2[1]),
-        min1[2].max(min2[2]),
-    ];
-
-    let intersection_max = [
-        max1[0].min(max2[0]),
-        max1[1].min(max2[1]),
-        max1[2].min(max2[2]),
-    ];
-
-    // Check if intersection is valid (min <= max on all axes)
-    if intersection_min[0] <= intersection_max[0]
-        && intersection_min[1] <= intersection_max[1]
-        && intersection_min[2] <= intersection_max[2]
-    {
-        Some((intersection_min, intersection_max))
-    } else {
-        None
-    }
+/// Check if two inclusive boxes intersect
+pub fn boxes_intersect(box1: BoxPair64, box2: BoxPair64) -> bool {
+    boxutil::intersects(box1, box2)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ast::{BooleanExpr, RegionEntry};
+    use proptest::prelude::*;
+    use std::collections::BTreeSet;
 
     /// Helper to create a test RegionTable
     fn make_test_table() -> RegionTable {
@@ -392,6 +978,7 @@ mod tests {
             "base".to_string(),
             RegionEntry::Accumulator {
                 boxes: vec![([0, 0, 0], [1, 1, 1]), ([2, 2, 2], [3, 3, 3])],
+                labels: vec![None, None],
                 sources: vec![SourceLocation::new(0, 0), SourceLocation::new(0, 1)],
             },
         );
@@ -401,6 +988,7 @@ mod tests {
             "ext".to_string(),
             RegionEntry::Accumulator {
                 boxes: vec![([10, 10, 10], [11, 11, 11])],
+                labels: vec![None],
                 sources: vec![SourceLocation::new(1, 0)],
             },
         );
@@ -465,12 +1053,45 @@ mod tests {
         let table = make_test_table();
         let result = evaluate_region_boxes(&table, "nonexistent");
 
+        // No statement refers to it, so there is no location to report
         assert!(result.is_err());
         match result.unwrap_err() {
-            ParseError::UnknownRegion { region, .. } => {
+            ParseError::RegionNotFound { region } => {
                 assert_eq!(region, "nonexistent");
             }
-            _ => panic!("Expected UnknownRegion error"),
+            _ => panic!("Expected RegionNotFound error"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_region_reported_at_reference() {
+        let mut table = make_test_table();
+        table.regions.insert(
+            "union".to_string(),
+            RegionEntry::Defined {
+                expr: BooleanExpr::Union(
+                    Box::new(BooleanExpr::RegionRef("base".to_string())),
+                    Box::new(BooleanExpr::RegionRef("missing".to_string())),
+                ),
+                source: SourceLocation::new(3, 1),
+            },
+        );
+        table.regions.insert(
+            "alias".to_string(),
+            RegionEntry::Defined {
+                expr: BooleanExpr::RegionRef("missing".to_string()),
+                source: SourceLocation::new(4, 0),
+            },
+        );
+
+        for (region, expected) in [("union", (3, 1)), ("alias", (4, 0))] {
+            match evaluate_region_boxes(&table, region).unwrap_err() {
+                ParseError::UnknownRegion { region, source } => {
+                    assert_eq!(region, "missing");
+                    assert_eq!(source, SourceLocation::new(expected.0, expected.1));
+                }
+                other => panic!("Expected UnknownRegion error, got {:?}", other),
+            }
         }
     }
 
@@ -498,6 +1119,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_alias_shares_target_cache_entry() {
+        let mut table = make_test_table();
+
+        // Chain of aliases: outer -> inner -> combined
+        table.regions.insert(
+            "inner".to_string(),
+            RegionEntry::Defined {
+                expr: BooleanExpr::region_ref("combined"),
+                source: SourceLocation::new(2, 0),
+            },
+        );
+        table.regions.insert(
+            "outer".to_string(),
+            RegionEntry::Defined {
+                expr: BooleanExpr::region_ref("inner"),
+                source: SourceLocation::new(2, 1),
+            },
+        );
+
+        let mut context = Evaluator::new(CoordWidth::I32);
+        let boxes = context.evaluate_region(&table, "outer").unwrap();
+
+        assert_eq!(
+            boxes,
+            evaluate_region_boxes_wide(&table, "combined").unwrap()
+        );
+        assert!(context.cache.contains_key("combined"));
+        assert!(!context.cache.contains_key("inner"));
+        assert!(!context.cache.contains_key("outer"));
+        assert_eq!(context.resolve_alias("outer"), "combined");
+
+        // Repeated lookups go through the shared entry
+        assert_eq!(context.evaluate_region(&table, "inner").unwrap(), boxes);
+    }
+
     #[test]
     fn test_cycle_detection() {
         let mut table = RegionTable::new();
@@ -531,6 +1188,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cycle_starts_at_smallest_name() {
+        // b -> c -> a -> b, entered from each member
+        let mut table = RegionTable::new();
+        for (stmt_idx, (region, target)) in
+            [("b", "c"), ("c", "a"), ("a", "b")].into_iter().enumerate()
+        {
+            table.regions.insert(
+                region.to_string(),
+                RegionEntry::Defined {
+                    expr: BooleanExpr::region_ref(target),
+                    source: SourceLocation::new(0, stmt_idx),
+                },
+            );
+        }
+
+        for start in ["a", "b", "c"] {
+            match evaluate_region_boxes(&table, start) {
+                Err(ParseError::CycleDetected { cycle }) => {
+                    assert_eq!(cycle, vec!["a", "b", "c"], "entered from {}", start);
+                }
+                other => panic!("Expected CycleDetected, got {:?}", other),
+            }
+        }
+    }
+
     #[test]
     fn test_evaluate_all_regions() {
         let table = make_test_table();
@@ -557,10 +1240,15 @@ mod tests {
 
     #[test]
     fn test_coordinate_bounds_checking() {
-        let boxes = vec![([i32::MAX, 0, 0], [i32::MAX, 1, 1])];
+        let boxes = vec![([i64::from(i32::MAX), 0, 0], [i64::from(i32::MAX), 1, 1])];
 
-        let result = check_boxes_bounds(&boxes);
+        let result = check_boxes_bounds(&boxes, CoordWidth::I32);
         assert!(result.is_err());
+
+        // The same box is fine in i64 mode, but the i64 limits still apply
+        assert!(check_boxes_bounds(&boxes, CoordWidth::I64).is_ok());
+        let boxes = vec![([i64::MAX, 0, 0], [i64::MAX, 1, 1])];
+        assert!(check_boxes_bounds(&boxes, CoordWidth::I64).is_err());
     }
 
     #[test]
@@ -570,7 +1258,384 @@ mod tests {
             ([-1000, -1000, -1000], [0, 0, 0]),
         ];
 
-        let result = check_boxes_bounds(&boxes);
+        let result = check_boxes_bounds(&boxes, CoordWidth::I32);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_eval_budget_exceeded() {
+        let table = make_test_table();
+
+        // "combined" visits three boxes in its union
+        let options = CompileOptions {
+            max_eval_steps: 3,
+            ..Default::default()
+        };
+        let mut evaluator = Evaluator::with_options(&options);
+        assert_eq!(
+            evaluator.evaluate_region(&table, "combined").unwrap().len(),
+            3
+        );
+        assert_eq!(evaluator.steps_used(), 3);
+
+        let options = CompileOptions {
+            max_eval_steps: 2,
+            ..Default::default()
+        };
+        assert!(matches!(
+            evaluate_geometry_with_options(&table, &options),
+            Err(ParseError::EvaluationBudgetExceeded { limit: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_difference_budget_counts_intermediate_boxes() {
+        // Carving four holes out of a slab: the final result is small, but each
+        // subtraction step operates on the growing set of pieces
+        let slab = vec![([0, 0, 0], [9, 0, 9])];
+        let holes = vec![
+            ([2, 0, 2], [2, 0, 2]),
+            ([7, 0, 2], [7, 0, 2]),
+            ([2, 0, 7], [2, 0, 7]),
+            ([7, 0, 7], [7, 0, 7]),
+        ];
+
+        let mut budget = EvalBudget::unlimited();
+        let pieces = compute_difference_budgeted(&slab, &holes, &mut budget).unwrap();
+        assert_eq!(pieces, compute_difference(&slab, &holes));
+        assert!(budget.used() > holes.len() as u64);
+
+        let mut budget = EvalBudget::new(holes.len() as u64);
+        assert!(compute_difference_budgeted(&slab, &holes, &mut budget).is_err());
+    }
+
+    #[test]
+    fn test_difference_is_sorted() {
+        // subtract_box emits x, then y, then z slabs; the result is reordered
+        let pieces = compute_difference(&[([0, 0, 0], [4, 4, 4])], &[([1, 1, 1], [3, 3, 3])]);
+        assert_eq!(pieces.len(), 6);
+        assert!(pieces.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(pieces[0], ([0, 0, 0], [0, 4, 4]));
+
+        let left = [([5, 0, 0], [5, 0, 0]), ([0, 0, 0], [0, 0, 0])];
+        assert_eq!(
+            compute_difference(&left, &[]),
+            vec![([0, 0, 0], [0, 0, 0]), ([5, 0, 0], [5, 0, 0])]
+        );
+    }
+
+    #[cfg(feature = "boolean_ops")]
+    #[test]
+    fn test_xor_is_sorted() {
+        let left = [([2, 0, 0], [5, 0, 0])];
+        let right = [([0, 0, 0], [3, 0, 0])];
+
+        let pieces = compute_xor(&left, &right, &mut EvalBudget::unlimited()).unwrap();
+        assert_eq!(pieces, vec![([0, 0, 0], [1, 0, 0]), ([4, 0, 0], [5, 0, 0])]);
+    }
+
+    /// Regions `a` and `b`, overlapping at x = 2..=3
+    fn expr_regions() -> BTreeMap<String, Vec<BoxPair>> {
+        BTreeMap::from([
+            ("a".to_string(), vec![([0, 0, 0], [3, 0, 0])]),
+            ("b".to_string(), vec![([2, 0, 0], [5, 0, 0])]),
+        ])
+    }
+
+    #[test]
+    fn test_evaluate_expr_union() {
+        let expr = BooleanExpr::union(BooleanExpr::region_ref("a"), BooleanExpr::region_ref("b"));
+        assert_eq!(
+            evaluate_expr(&expr, &expr_regions()).unwrap(),
+            vec![([0, 0, 0], [3, 0, 0]), ([2, 0, 0], [5, 0, 0])]
+        );
+
+        let unknown =
+            BooleanExpr::union(BooleanExpr::region_ref("a"), BooleanExpr::region_ref("c"));
+        assert!(matches!(
+            evaluate_expr(&unknown, &expr_regions()),
+            Err(ParseError::UnknownRegion { region, .. }) if region == "c"
+        ));
+
+        // Leaving the coordinate range is a user error, not an internal one
+        let shifted = BooleanExpr::translate(
+            BooleanExpr::region_ref("b"),
+            [CoordWidth::COORD.max(), 0, 0],
+        );
+        match evaluate_expr(&shifted, &expr_regions()) {
+            Err(ParseError::InvalidBox { message, .. }) => {
+                assert!(message.contains("x axis"), "{}", message)
+            }
+            other => panic!("expected InvalidBox, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "boolean_ops")]
+    #[test]
+    fn test_evaluate_expr_boolean_ops() {
+        let a = || BooleanExpr::region_ref("a");
+        let b = || BooleanExpr::region_ref("b");
+
+        assert_eq!(
+            evaluate_expr(&BooleanExpr::intersection(a(), b()), &expr_regions()).unwrap(),
+            vec![([2, 0, 0], [3, 0, 0])]
+        );
+        assert_eq!(
+            evaluate_expr(&BooleanExpr::difference(a(), b()), &expr_regions()).unwrap(),
+            vec![([0, 0, 0], [1, 0, 0])]
+        );
+    }
+
+    /// Every block covered by `boxes`
+    fn voxels(boxes: &[BoxPair64]) -> BTreeSet<[i64; 3]> {
+        let mut voxels = BTreeSet::new();
+        for (min, max) in boxes {
+            for x in min[0]..=max[0] {
+                for y in min[1]..=max[1] {
+                    for z in min[2]..=max[2] {
+                        voxels.insert([x, y, z]);
+                    }
+                }
+            }
+        }
+        voxels
+    }
+
+    #[test]
+    fn test_minimize_boxes() {
+        // A row of single blocks becomes one box
+        let row: Vec<BoxPair64> = (0..8).map(|x| ([x, 0, 0], [x, 0, 0])).collect();
+        assert_eq!(minimize_boxes(&row), vec![([0, 0, 0], [7, 0, 0])]);
+
+        // Two halves of a slab, split along z
+        let halves = vec![([0, 0, 0], [3, 2, 1]), ([0, 0, 2], [3, 2, 5])];
+        assert_eq!(minimize_boxes(&halves), vec![([0, 0, 0], [3, 2, 5])]);
+
+        // An L shape needs two boxes
+        let l_shape = vec![
+            ([0, 0, 0], [0, 0, 0]),
+            ([1, 0, 0], [1, 0, 0]),
+            ([0, 1, 0], [0, 1, 0]),
+        ];
+        let minimized = minimize_boxes(&l_shape);
+        assert_eq!(minimized.len(), 2);
+        assert_eq!(voxels(&minimized), voxels(&l_shape));
+
+        // Already minimal: unchanged, even if the boxes overlap
+        let cross = vec![([0, 1, 0], [2, 1, 0]), ([1, 0, 0], [1, 2, 0])];
+        assert_eq!(minimize_boxes(&cross), cross);
+
+        // Coordinates near the i64 limits
+        let far = vec![
+            ([i64::MAX - 1, 0, 0], [i64::MAX, 0, 0]),
+            ([i64::MAX - 3, 0, 0], [i64::MAX - 2, 0, 0]),
+        ];
+        assert_eq!(
+            minimize_boxes(&far),
+            vec![([i64::MAX - 3, 0, 0], [i64::MAX, 0, 0])]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_minimize_boxes_preserves_blocks(
+            boxes in prop::collection::vec(
+                (prop::array::uniform3(-4i64..4), prop::array::uniform3(0i64..3)),
+                0..8,
+            )
+        ) {
+            let boxes: Vec<BoxPair64> = boxes
+                .into_iter()
+                .map(|(min, size)| (min, [min[0] + size[0], min[1] + size[1], min[2] + size[2]]))
+                .collect();
+            let minimized = minimize_boxes(&boxes);
+
+            prop_assert_eq!(voxels(&minimized), voxels(&boxes));
+            prop_assert!(minimized.len() <= boxes.len());
+        }
+
+        #[test]
+        fn test_coalesce_boxes_preserves_blocks(
+            boxes in prop::collection::vec(
+                (prop::array::uniform3(-4i64..4), prop::array::uniform3(0i64..3)),
+                0..8,
+            )
+        ) {
+            let boxes: Vec<BoxPair64> = boxes
+                .into_iter()
+                .map(|(min, size)| (min, [min[0] + size[0], min[1] + size[1], min[2] + size[2]]))
+                .collect();
+            let coalesced = coalesce_boxes(&boxes);
+
+            prop_assert_eq!(voxels(&coalesced), voxels(&boxes));
+            prop_assert!(coalesced.len() <= boxes.len());
+        }
+    }
+
+    #[test]
+    fn test_coalesce_boxes() {
+        // Exact duplicates collapse to one box
+        let duplicates = vec![([0, 0, 0], [2, 2, 2]), ([0, 0, 0], [2, 2, 2])];
+        assert_eq!(coalesce_boxes(&duplicates), vec![([0, 0, 0], [2, 2, 2])]);
+
+        // A box inside another is absorbed
+        let nested = vec![([1, 1, 1], [1, 1, 1]), ([0, 0, 0], [2, 2, 2])];
+        assert_eq!(coalesce_boxes(&nested), vec![([0, 0, 0], [2, 2, 2])]);
+
+        // Boxes sharing a face, or overlapping, along one axis form a larger box
+        let touching = vec![([0, 0, 0], [3, 1, 1]), ([4, 0, 0], [5, 1, 1])];
+        assert_eq!(coalesce_boxes(&touching), vec![([0, 0, 0], [5, 1, 1])]);
+        let overlapping = vec![([0, 2, 0], [1, 6, 1]), ([0, 0, 0], [1, 3, 1])];
+        assert_eq!(coalesce_boxes(&overlapping), vec![([0, 0, 0], [1, 6, 1])]);
+
+        // Merges chain: the third box only fits once the first two are merged
+        let row = vec![
+            ([0, 0, 0], [0, 0, 0]),
+            ([2, 0, 0], [2, 0, 0]),
+            ([1, 0, 0], [1, 0, 0]),
+        ];
+        assert_eq!(coalesce_boxes(&row), vec![([0, 0, 0], [2, 0, 0])]);
+
+        // An L shape, a gap and a partial face do not form a box
+        let l_shape = vec![([0, 0, 0], [1, 0, 0]), ([0, 1, 0], [0, 1, 0])];
+        assert_eq!(coalesce_boxes(&l_shape), l_shape);
+        let gap = vec![([0, 0, 0], [0, 0, 0]), ([2, 0, 0], [2, 0, 0])];
+        assert_eq!(coalesce_boxes(&gap), gap);
+        let partial = vec![([0, 0, 0], [1, 1, 0]), ([2, 0, 0], [2, 2, 0])];
+        assert_eq!(coalesce_boxes(&partial), partial);
+
+        // Touching at the i64 limit
+        let far = vec![
+            ([i64::MAX, 0, 0], [i64::MAX, 0, 0]),
+            ([i64::MIN, 0, 0], [i64::MAX - 1, 0, 0]),
+        ];
+        assert_eq!(
+            coalesce_boxes(&far),
+            vec![([i64::MIN, 0, 0], [i64::MAX, 0, 0])]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_boxes_long_row() {
+        // A shuffled row of single blocks merges in one sweep per round
+        let row: Vec<BoxPair64> = (0..20_000i64)
+            .map(|i| (i * 7919) % 20_000)
+            .map(|x| ([x, 0, 0], [x, 0, 0]))
+            .collect();
+        assert_eq!(coalesce_boxes(&row), vec![([0, 0, 0], [19_999, 0, 0])]);
+
+        // Columns inside a later slab are absorbed by it, which takes their place
+        let mut inside: Vec<BoxPair64> = (0..5_000i64)
+            .map(|i| ([i % 100, i / 100, 0], [i % 100, i / 100, 0]))
+            .step_by(2)
+            .collect();
+        inside.push(([0, 0, 0], [99, 99, 0]));
+        assert_eq!(coalesce_boxes(&inside), vec![([0, 0, 0], [99, 99, 0])]);
+    }
+
+    fn table_with_expr(boxes: Vec<BoxPair64>, expr: BooleanExpr) -> RegionTable {
+        let mut table = RegionTable::new();
+        table.regions.insert(
+            "core".to_string(),
+            RegionEntry::Accumulator {
+                labels: vec![None; boxes.len()],
+                sources: vec![SourceLocation::new(0, 0); boxes.len()],
+                boxes,
+            },
+        );
+        table.regions.insert(
+            "result".to_string(),
+            RegionEntry::Defined {
+                expr,
+                source: SourceLocation::new(0, 1),
+            },
+        );
+        table
+    }
+
+    #[test]
+    fn test_evaluate_expand() {
+        let core = BooleanExpr::region_ref("core");
+        let table = table_with_expr(
+            vec![([0, 0, 0], [3, 1, 2])],
+            BooleanExpr::expand(core.clone(), 2),
+        );
+        assert_eq!(
+            evaluate_region_boxes(&table, "result").unwrap(),
+            vec![([-2, -2, -2], [5, 3, 4])]
+        );
+
+        // Growing past the coordinate width is an error, not a wrap
+        let table = table_with_expr(
+            vec![([0, 0, 0], [CoordWidth::COORD.max() - 2, 0, 0])],
+            BooleanExpr::expand(core, 2),
+        );
+        assert!(evaluate_region_boxes(&table, "result").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_geometry_wide() {
+        let far = 1_i64 << 40;
+        let table = table_with_expr(
+            vec![([far, 0, 0], [far + 1, 1, 1])],
+            BooleanExpr::region_ref("core"),
+        );
+        let options = CompileOptions {
+            coordinate_width: CoordWidth::I64,
+            ..Default::default()
+        };
+
+        let wide = evaluate_geometry_wide(&table, &options).unwrap();
+        assert_eq!(wide["result"], vec![([far, 0, 0], [far + 1, 1, 1])]);
+        assert_eq!(
+            evaluate_region_boxes_wide(&table, "result").unwrap(),
+            wide["result"]
+        );
+        assert_eq!(
+            evaluate_geometry_with_options(&table, &options).is_ok(),
+            CoordWidth::COORD == CoordWidth::I64
+        );
+    }
+
+    #[test]
+    fn test_evaluate_translate() {
+        let core = BooleanExpr::region_ref("core");
+        let table = table_with_expr(
+            vec![([0, 0, 0], [1, 1, 1]), ([5, 0, 0], [6, 2, 1])],
+            BooleanExpr::translate(core.clone(), [10, -1, 3]),
+        );
+        assert_eq!(
+            evaluate_region_boxes(&table, "result").unwrap(),
+            vec![([10, -1, 3], [11, 0, 4]), ([15, -1, 3], [16, 1, 4])]
+        );
+
+        let table = table_with_expr(
+            vec![([0, 0, 0], [1, 1, 1])],
+            BooleanExpr::translate(core, [CoordWidth::COORD.max(), 0, 0]),
+        );
+        assert!(evaluate_region_boxes(&table, "result").is_err());
+
+        // The translated region is still checked for self-reference
+        let table = table_with_expr(
+            vec![([0, 0, 0], [1, 1, 1])],
+            BooleanExpr::translate(BooleanExpr::region_ref("result"), [1, 0, 0]),
+        );
+        assert!(matches!(
+            evaluate_region_boxes(&table, "result"),
+            Err(ParseError::SelfReference { .. })
+        ));
+    }
+
+    #[test]
+    fn test_evaluate_inset_drops_thin_boxes() {
+        let table = table_with_expr(
+            vec![([0, 0, 0], [9, 9, 9]), ([20, 0, 0], [30, 1, 30])],
+            BooleanExpr::inset(BooleanExpr::region_ref("core"), 1),
+        );
+        // The second box is two voxels thick along y and vanishes
+        assert_eq!(
+            evaluate_region_boxes(&table, "result").unwrap(),
+            vec![([1, 1, 1], [8, 8, 8])]
+        );
+    }
 }
@@ -1,10 +1,22 @@
-/// Boolean expression for region definitions
-#[derive(Debug, Clone, PartialEq)]
+/// Boolean expression for region definitions.
+///
+/// Chains of binary operators nest to the left, so `a + b + c` is
+/// `(a + b) + c` and a long chain is a deep tree. Cloning, comparing,
+/// printing and dropping walk that left spine in a loop rather than
+/// recursing once per operand.
+#[derive(Debug)]
 pub enum BooleanExpr {
     /// Reference to a region by name (e.g., "dataloop", "cpu.core")
     RegionRef(String),
     /// Union of two expressions (a + b)
     Union(Box<BooleanExpr>, Box<BooleanExpr>),
+    /// Every box of an expression grown by `n` on each side (`expand(a, n)`)
+    Expand(Box<BooleanExpr>, u64),
+    /// Every box of an expression shrunk by `n` on each side (`inset(a, n)`);
+    /// boxes thinner than `2n` vanish
+    Inset(Box<BooleanExpr>, u64),
+    /// Every box of an expression shifted by a vector (`translate(a, [dx,dy,dz])`)
+    Translate(Box<BooleanExpr>, [i64; 3]),
     /// Difference/subtraction of two expressions (a - b)
     #[cfg(feature = "boolean_ops")]
     Difference(Box<BooleanExpr>, Box<BooleanExpr>),
@@ -14,6 +26,51 @@ pub enum BooleanExpr {
     /// XOR of two expressions (a ^ b)
     #[cfg(feature = "boolean_ops")]
     Xor(Box<BooleanExpr>, Box<BooleanExpr>),
+    /// Union of all regions whose metadata `key` equals `value`
+    /// (`group_by(power == "low")`)
+    #[cfg(feature = "metadata_groups")]
+    GroupBy {
+        key: String,
+        value: serde_json::Value,
+    },
+}
+
+/// The binary operators of [`BooleanExpr`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Union,
+    #[cfg(feature = "boolean_ops")]
+    Difference,
+    #[cfg(feature = "boolean_ops")]
+    Intersection,
+    #[cfg(feature = "boolean_ops")]
+    Xor,
+}
+
+impl Operator {
+    fn symbol(self) -> char {
+        match self {
+            Operator::Union => '+',
+            #[cfg(feature = "boolean_ops")]
+            Operator::Difference => '-',
+            #[cfg(feature = "boolean_ops")]
+            Operator::Intersection => '&',
+            #[cfg(feature = "boolean_ops")]
+            Operator::Xor => '^',
+        }
+    }
+
+    fn apply(self, left: BooleanExpr, right: BooleanExpr) -> BooleanExpr {
+        match self {
+            Operator::Union => BooleanExpr::union(left, right),
+            #[cfg(feature = "boolean_ops")]
+            Operator::Difference => BooleanExpr::difference(left, right),
+            #[cfg(feature = "boolean_ops")]
+            Operator::Intersection => BooleanExpr::intersection(left, right),
+            #[cfg(feature = "boolean_ops")]
+            Operator::Xor => BooleanExpr::xor(left, right),
+        }
+    }
 }
 
 impl BooleanExpr {
@@ -22,6 +79,21 @@ impl BooleanExpr {
         BooleanExpr::Union(Box::new(left), Box::new(right))
     }
 
+    /// Create an expansion of an expression by `amount`
+    pub fn expand(expr: BooleanExpr, amount: u64) -> Self {
+        BooleanExpr::Expand(Box::new(expr), amount)
+    }
+
+    /// Create an inset of an expression by `amount`
+    pub fn inset(expr: BooleanExpr, amount: u64) -> Self {
+        BooleanExpr::Inset(Box::new(expr), amount)
+    }
+
+    /// Create a translation of an expression by `offset`
+    pub fn translate(expr: BooleanExpr, offset: [i64; 3]) -> Self {
+        BooleanExpr::Translate(Box::new(expr), offset)
+    }
+
     /// Create a difference of two expressions
     #[cfg(feature = "boolean_ops")]
     pub fn difference(left: BooleanExpr, right: BooleanExpr) -> Self {
@@ -40,11 +112,59 @@ impl BooleanExpr {
         BooleanExpr::Xor(Box::new(left), Box::new(right))
     }
 
+    /// Create a metadata group term
+    #[cfg(feature = "metadata_groups")]
+    pub fn group_by(key: impl Into<String>, value: serde_json::Value) -> Self {
+        BooleanExpr::GroupBy {
+            key: key.into(),
+            value,
+        }
+    }
+
     /// Create a region reference
     pub fn region_ref(name: impl Into<String>) -> Self {
         BooleanExpr::RegionRef(name.into())
     }
 
+    /// The referenced name if this expression is a bare region reference
+    pub fn as_region_ref(&self) -> Option<&str> {
+        match self {
+            BooleanExpr::RegionRef(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The operator and operands of a binary node
+    fn as_binary(&self) -> Option<(Operator, &BooleanExpr, &BooleanExpr)> {
+        match self {
+            BooleanExpr::Union(left, right) => Some((Operator::Union, left, right)),
+            #[cfg(feature = "boolean_ops")]
+            BooleanExpr::Difference(left, right) => Some((Operator::Difference, left, right)),
+            #[cfg(feature = "boolean_ops")]
+            BooleanExpr::Intersection(left, right) => Some((Operator::Intersection, left, right)),
+            #[cfg(feature = "boolean_ops")]
+            BooleanExpr::Xor(left, right) => Some((Operator::Xor, left, right)),
+            _ => None,
+        }
+    }
+
+    /// Split the left spine of binary nodes whose operator passes `through`:
+    /// the leftmost other node, then the operators and right operands above
+    /// it, innermost first. `(a + b) - c` splits into `a`, `[(+, b), (-, c)]`.
+    fn left_spine(
+        &self,
+        through: impl Fn(Operator) -> bool,
+    ) -> (&BooleanExpr, Vec<(Operator, &BooleanExpr)>) {
+        let mut spine = Vec::new();
+        let mut node = self;
+        while let Some((operator, left, right)) = node.as_binary().filter(|(op, ..)| through(*op)) {
+            spine.push((operator, right));
+            node = left;
+        }
+        spine.reverse();
+        (node, spine)
+    }
+
     /// Get all region references mentioned in this expression
     pub fn region_refs(&self) -> Vec<&str> {
         let mut refs = Vec::new();
@@ -52,35 +172,237 @@ impl BooleanExpr {
         refs
     }
 
-    fn collect_region_refs<'a>(&'a self, refs: &mut Vec<&'a str>) {
+    /// A copy of this expression with repeated union operands removed.
+    ///
+    /// Nested unions are flattened into one left-leaning chain (`(a + b) + c`)
+    /// keeping the first occurrence of each operand, so `a + a` becomes `a` and
+    /// `(a + b) + a` becomes `a + b`. Other terms keep their shape, with their
+    /// operands simplified.
+    pub fn simplify(&self) -> BooleanExpr {
         match self {
-            BooleanExpr::RegionRef(name) => refs.push(name),
-            BooleanExpr::Union(left, right) => {
-                left.collect_region_refs(refs);
-                right.collect_region_refs(refs);
+            BooleanExpr::RegionRef(_) => self.clone(),
+            BooleanExpr::Union(..) => {
+                let mut operands = Vec::new();
+                self.collect_union_operands(&mut operands);
+                let mut unique: Vec<BooleanExpr> = Vec::with_capacity(operands.len());
+                for operand in operands {
+                    if !unique.contains(&operand) {
+                        unique.push(operand);
+                    }
+                }
+                unique
+                    .into_iter()
+                    .reduce(BooleanExpr::union)
+                    .expect("a union has at least two operands")
+            }
+            BooleanExpr::Expand(expr, amount) => BooleanExpr::expand(expr.simplify(), *amount),
+            BooleanExpr::Inset(expr, amount) => BooleanExpr::inset(expr.simplify(), *amount),
+            BooleanExpr::Translate(expr, offset) => {
+                BooleanExpr::translate(expr.simplify(), *offset)
             }
             #[cfg(feature = "boolean_ops")]
-            BooleanExpr::Difference(left, right)
-            | BooleanExpr::Intersection(left, right)
-            | BooleanExpr::Xor(left, right) => {
-                left.collect_region_refs(refs);
-                right.collect_region_refs(refs);
+            BooleanExpr::Difference(..) | BooleanExpr::Intersection(..) | BooleanExpr::Xor(..) => {
+                // Unions in the chain are simplified as operands of their own
+                let (first, spine) = self.left_spine(|op| op != Operator::Union);
+                spine
+                    .into_iter()
+                    .fold(first.simplify(), |acc, (operator, right)| {
+                        operator.apply(acc, right.simplify())
+                    })
+            }
+            #[cfg(feature = "metadata_groups")]
+            BooleanExpr::GroupBy { .. } => self.clone(),
+        }
+    }
+
+    /// Push the simplified operands of a chain of unions, left to right
+    fn collect_union_operands(&self, operands: &mut Vec<BooleanExpr>) {
+        let (first, spine) = self.left_spine(|op| op == Operator::Union);
+        for operand in std::iter::once(first).chain(spine.into_iter().map(|(_, right)| right)) {
+            if matches!(operand, BooleanExpr::Union(..)) {
+                operand.collect_union_operands(operands);
+            } else {
+                operands.push(operand.simplify());
             }
         }
     }
+
+    fn collect_region_refs<'a>(&'a self, refs: &mut Vec<&'a str>) {
+        let (first, spine) = self.left_spine(|_| true);
+        match first {
+            BooleanExpr::RegionRef(name) => refs.push(name),
+            BooleanExpr::Expand(expr, _)
+            | BooleanExpr::Inset(expr, _)
+            | BooleanExpr::Translate(expr, _) => expr.collect_region_refs(refs),
+            // Members are only known once metadata is applied
+            #[cfg(feature = "metadata_groups")]
+            BooleanExpr::GroupBy { .. } => {}
+            _ => unreachable!("the left spine ends at a non-binary node"),
+        }
+        for (_, right) in spine {
+            right.collect_region_refs(refs);
+        }
+    }
+
+    /// Take the left operand out of a binary node whose left operand is
+    /// itself binary, leaving a placeholder behind
+    fn take_binary_left(&mut self) -> Option<Box<BooleanExpr>> {
+        let left = match self {
+            BooleanExpr::Union(left, _) => left,
+            #[cfg(feature = "boolean_ops")]
+            BooleanExpr::Difference(left, _)
+            | BooleanExpr::Intersection(left, _)
+            | BooleanExpr::Xor(left, _) => left,
+            _ => return None,
+        };
+        left.as_binary()?;
+        Some(std::mem::replace(
+            left,
+            Box::new(BooleanExpr::RegionRef(String::new())),
+        ))
+    }
+}
+
+impl Clone for BooleanExpr {
+    fn clone(&self) -> Self {
+        let (first, spine) = self.left_spine(|_| true);
+        let first = match first {
+            BooleanExpr::RegionRef(name) => BooleanExpr::RegionRef(name.clone()),
+            BooleanExpr::Expand(expr, amount) => BooleanExpr::Expand(expr.clone(), *amount),
+            BooleanExpr::Inset(expr, amount) => BooleanExpr::Inset(expr.clone(), *amount),
+            BooleanExpr::Translate(expr, offset) => BooleanExpr::Translate(expr.clone(), *offset),
+            #[cfg(feature = "metadata_groups")]
+            BooleanExpr::GroupBy { key, value } => BooleanExpr::GroupBy {
+                key: key.clone(),
+                value: value.clone(),
+            },
+            _ => unreachable!("the left spine ends at a non-binary node"),
+        };
+        spine.into_iter().fold(first, |acc, (operator, right)| {
+            operator.apply(acc, right.clone())
+        })
+    }
+}
+
+impl PartialEq for BooleanExpr {
+    fn eq(&self, other: &Self) -> bool {
+        let (first, spine) = self.left_spine(|_| true);
+        let (other_first, other_spine) = other.left_spine(|_| true);
+        let same_first = match (first, other_first) {
+            (BooleanExpr::RegionRef(a), BooleanExpr::RegionRef(b)) => a == b,
+            (BooleanExpr::Expand(a, n), BooleanExpr::Expand(b, m))
+            | (BooleanExpr::Inset(a, n), BooleanExpr::Inset(b, m)) => n == m && a == b,
+            (BooleanExpr::Translate(a, n), BooleanExpr::Translate(b, m)) => n == m && a == b,
+            #[cfg(feature = "metadata_groups")]
+            (
+                BooleanExpr::GroupBy { key, value },
+                BooleanExpr::GroupBy {
+                    key: other_key,
+                    value: other_value,
+                },
+            ) => key == other_key && value == other_value,
+            _ => false,
+        };
+        same_first
+            && spine.len() == other_spine.len()
+            && spine
+                .iter()
+                .zip(&other_spine)
+                .all(|((op, right), (other_op, other_right))| {
+                    op == other_op && right == other_right
+                })
+    }
+}
+
+impl Drop for BooleanExpr {
+    fn drop(&mut self) {
+        // Unlink the left spine node by node so that no drop recurses down it
+        let mut next = self.take_binary_left();
+        while let Some(mut node) = next {
+            next = node.take_binary_left();
+        }
+    }
 }
 
 impl std::fmt::Display for BooleanExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            BooleanExpr::RegionRef(name) => write!(f, "{}", name),
-            BooleanExpr::Union(left, right) => write!(f, "({} + {})", left, right),
-            #[cfg(feature = "boolean_ops")]
-            BooleanExpr::Difference(left, right) => write!(f, "({} - {})", left, right),
-            #[cfg(feature = "boolean_ops")]
-            BooleanExpr::Intersection(left, right) => write!(f, "({} & {})", left, right),
-            #[cfg(feature = "boolean_ops")]
-            BooleanExpr::Xor(left, right) => write!(f, "({} ^ {})", left, right),
+        // Every binary node is parenthesized: `((a + b) + c)`
+        let (first, spine) = self.left_spine(|_| true);
+        for _ in &spine {
+            f.write_str("(")?;
         }
+        match first {
+            BooleanExpr::RegionRef(name) => write!(f, "{}", name)?,
+            BooleanExpr::Expand(expr, amount) => write!(f, "expand({}, {})", expr, amount)?,
+            BooleanExpr::Inset(expr, amount) => write!(f, "inset({}, {})", expr, amount)?,
+            BooleanExpr::Translate(expr, [dx, dy, dz]) => {
+                write!(f, "translate({}, [{},{},{}])", expr, dx, dy, dz)?
+            }
+            #[cfg(feature = "metadata_groups")]
+            BooleanExpr::GroupBy { key, value } => write!(f, "group_by({} == {})", key, value)?,
+            _ => unreachable!("the left spine ends at a non-binary node"),
+        }
+        for (operator, right) in spine {
+            write!(f, " {} {})", operator.symbol(), right)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(name: &str) -> BooleanExpr {
+        BooleanExpr::region_ref(name)
+    }
+
+    #[test]
+    fn test_long_chain_without_recursion() {
+        let chain = (1..100_000).fold(r("r0"), |acc, i| {
+            BooleanExpr::union(acc, r(&format!("r{}", i)))
+        });
+        let copy = chain.clone();
+        assert_eq!(copy, chain);
+        assert_eq!(chain.region_refs().len(), 100_000);
+
+        let text = chain.to_string();
+        let body = text.trim_start_matches('(');
+        assert_eq!(text.len() - body.len(), 99_999);
+        assert!(body.starts_with("r0 + r1) + r2)"));
+        assert!(body.ends_with(" + r99999)"));
+
+        let repeated = (1..100_000).fold(r("a"), |acc, _| BooleanExpr::union(acc, r("a")));
+        assert_eq!(repeated.simplify(), r("a"));
+    }
+
+    #[test]
+    fn test_simplify_repeated_operand() {
+        assert_eq!(BooleanExpr::union(r("a"), r("a")).simplify(), r("a"));
+        assert_eq!(
+            BooleanExpr::expand(BooleanExpr::union(r("a"), r("a")), 2).simplify(),
+            BooleanExpr::expand(r("a"), 2)
+        );
+        assert_eq!(r("a").simplify(), r("a"));
+    }
+
+    #[test]
+    fn test_simplify_nested_unions() {
+        // (a + b) + a
+        let nested = BooleanExpr::union(BooleanExpr::union(r("a"), r("b")), r("a"));
+        assert_eq!(nested.simplify(), BooleanExpr::union(r("a"), r("b")));
+
+        // a + (b + (c + b)) becomes ((a + b) + c)
+        let right_leaning = BooleanExpr::union(
+            r("a"),
+            BooleanExpr::union(r("b"), BooleanExpr::union(r("c"), r("b"))),
+        );
+        let simplified = right_leaning.simplify();
+        assert_eq!(simplified.to_string(), "((a + b) + c)");
+
+        // Identical compound operands are also merged
+        let shifted = BooleanExpr::translate(r("a"), [1, 0, 0]);
+        let twice = BooleanExpr::union(shifted.clone(), BooleanExpr::union(r("b"), shifted));
+        assert_eq!(twice.simplify().to_string(), "(translate(a, [1,0,0]) + b)");
     }
 }
@@ -1,23 +1,46 @@
-use crate::parser::geom::GeometryStatement;
+use crate::parser::geom::{GeometryStatement, Vec3, Vec3Wide};
 
 /// High-level AST node for geometry statements
+///
+/// Non-exhaustive since `append` was added after 0.1.1, and fields may be
+/// added in any release: outside this crate, build one with [`GeomStmt::new`]
+/// and set fields on it.
 #[derive(Debug, Clone, PartialEq)]
-pub struct GeomStmt {
+#[non_exhaustive]
+pub struct GeomStmt<V = Vec3> {
     /// The tuple index this statement belongs to
     pub tuple_idx: usize,
     /// The statement index within the tuple
     pub stmt_idx: usize,
     /// The parsed geometry statement
-    pub statement: GeometryStatement,
+    pub statement: GeometryStatement<V>,
+    /// Written `@region+=`: explicitly adds boxes to the region
+    pub append: bool,
 }
 
+/// A [`GeomStmt`] with i64 coordinates, as the compiler evaluates them
+pub type GeomStmtWide = GeomStmt<Vec3Wide>;
+
 impl GeomStmt {
+    /// This statement with i64 coordinates
+    pub fn widen(&self) -> GeomStmtWide {
+        GeomStmt {
+            tuple_idx: self.tuple_idx,
+            stmt_idx: self.stmt_idx,
+            statement: self.statement.widen(),
+            append: self.append,
+        }
+    }
+}
+
+impl<V> GeomStmt<V> {
     /// Create a new geometry statement
-    pub fn new(tuple_idx: usize, stmt_idx: usize, statement: GeometryStatement) -> Self {
+    pub fn new(tuple_idx: usize, stmt_idx: usize, statement: GeometryStatement<V>) -> Self {
         Self {
             tuple_idx,
             stmt_idx,
             statement,
+            append: false,
         }
     }
 
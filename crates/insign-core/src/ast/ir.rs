@@ -1,18 +1,58 @@
-use crate::ast::{BooleanExpr, GeomStmt, MetaStmt};
+use crate::ast::metadata::is_wildcard_pattern;
+use crate::ast::{BooleanExpr, GeomStmt, GeomStmtWide, MetaStmt};
 use crate::parser::geom::GeometryStatement;
-use crate::{BoxPair, ParseError};
+use crate::parser::geom::{Vec3, Vec3Wide};
+use crate::{BoxPair64, CompileOptions, CoordWidth, ParseError, UnitPosition};
+use serde::Serialize;
 use std::collections::BTreeMap;
 
+/// A character position within a tuple's text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SourceSpan {
+    /// Byte offset from the start of the tuple text
+    pub offset: usize,
+    /// 1-based line within the tuple text
+    pub line: usize,
+    /// 1-based column, counted in characters
+    pub column: usize,
+}
+
+impl std::fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {} col {}", self.line, self.column)
+    }
+}
+
 /// Source location information for error reporting
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize)]
 pub struct SourceLocation {
     pub tuple_idx: usize,
     pub stmt_idx: usize,
+    /// Line and column within the tuple text, for syntax errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Box<SourceSpan>>,
 }
 
 impl std::fmt::Display for SourceLocation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "tuple {} statement {}", self.tuple_idx, self.stmt_idx)
+        write!(f, "tuple {} statement {}", self.tuple_idx, self.stmt_idx)?;
+        if let Some(span) = &self.span {
+            write!(f, ", {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+// Like the serialized form, omits `span` when absent
+impl std::fmt::Debug for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("SourceLocation");
+        debug.field("tuple_idx", &self.tuple_idx);
+        debug.field("stmt_idx", &self.stmt_idx);
+        if let Some(span) = &self.span {
+            debug.field("span", span);
+        }
+        debug.finish()
     }
 }
 
@@ -23,6 +63,15 @@ impl SourceLocation {
         Self {
             tuple_idx,
             stmt_idx,
+            span: None,
+        }
+    }
+
+    /// This location at `span` within the tuple text
+    pub fn with_span(self, span: SourceSpan) -> Self {
+        Self {
+            span: Some(Box::new(span)),
+            ..self
         }
     }
 }
@@ -33,7 +82,9 @@ pub enum RegionEntry {
     /// Accumulator region: collects multiple geometry statements into boxes
     Accumulator {
         /// Direct box pairs from rc/ac statements
-        boxes: Vec<BoxPair>,
+        boxes: Vec<BoxPair64>,
+        /// Label of each box (`rc(...)#north`), parallel to `boxes`
+        labels: Vec<Option<String>>,
         /// Source locations of all contributing statements
         sources: Vec<SourceLocation>,
     },
@@ -47,7 +98,7 @@ pub enum RegionEntry {
     /// Anonymous region: unnamed geometry statement
     Anonymous {
         /// The single box pair
-        box_pair: BoxPair,
+        box_pair: BoxPair64,
         /// Source location
         source: SourceLocation,
     },
@@ -80,8 +131,38 @@ impl RegionTable {
         }
     }
 
+    /// Per-box labels of an accumulator region, if any of its boxes is labeled
+    pub fn box_labels(&self, region: &str) -> Option<&[Option<String>]> {
+        match self.regions.get(region)? {
+            RegionEntry::Accumulator { labels, .. } if labels.iter().any(Option::is_some) => {
+                Some(labels)
+            }
+            _ => None,
+        }
+    }
+
     /// Add a geometry statement to the region table
-    pub fn add_geometry(&mut self, stmt: &GeomStmt, offset: [i32; 3]) -> Result<(), ParseError> {
+    pub fn add_geometry(&mut self, stmt: &GeomStmt, offset: Vec3) -> Result<(), ParseError> {
+        self.add_geometry_wide(&stmt.widen(), offset.to_wide())
+    }
+
+    /// Add a geometry statement with i64 coordinates to the region table
+    pub fn add_geometry_wide(
+        &mut self,
+        stmt: &GeomStmtWide,
+        offset: Vec3Wide,
+    ) -> Result<(), ParseError> {
+        self.add_geometry_within(stmt, offset, CoordWidth::I64)
+    }
+
+    /// Add a geometry statement whose boxes must fit `width` once offset,
+    /// failing with an `InvalidBox` at the start of the statement otherwise
+    pub(crate) fn add_geometry_within(
+        &mut self,
+        stmt: &GeomStmtWide,
+        offset: Vec3Wide,
+        width: CoordWidth,
+    ) -> Result<(), ParseError> {
         let source = SourceLocation::new(stmt.tuple_idx, stmt.stmt_idx);
 
         match &stmt.statement {
@@ -89,23 +170,54 @@ impl RegionTable {
                 // This is a defined region
                 self.add_defined_region(region.clone(), expr.clone(), source)?;
             }
+            GeometryStatement::Const { .. } => {
+                // Constants are substituted while parsing and cover no blocks
+            }
+            GeometryStatement::Alias { .. } => {
+                // Aliases copy their target after evaluation, see `apply_aliases`
+            }
             geom_stmt => {
                 // This is an accumulator or anonymous region
-                let box_pair =
-                    geom_stmt
-                        .to_box_pair(offset)
-                        .ok_or_else(|| ParseError::Internal {
-                            message: "Geometry statement should produce a box pair".to_string(),
-                            position: 0,
-                        })?;
+                let boxes = geom_stmt.to_boxes_wide(offset).ok_or_else(|| {
+                    let axis = geom_stmt.overflowing_axis(offset).unwrap_or(0);
+                    ParseError::InvalidBox {
+                        position: 0,
+                        message: format!(
+                            "tuple offset {:?} overflows the {} axis at {}",
+                            offset,
+                            ["x", "y", "z"][axis],
+                            source
+                        ),
+                        context: None,
+                    }
+                })?;
+                if let Some((corner, axis)) = out_of_range_corner(&boxes, width) {
+                    return Err(ParseError::InvalidBox {
+                        position: 0,
+                        message: format!(
+                            "corner {:?} (tuple offset {:?}) exceeds the i32 range on the {} axis at {}; use compile_wide for larger coordinates",
+                            corner,
+                            offset,
+                            ["x", "y", "z"][axis],
+                            source
+                        ), context: None,
+                    });
+                }
 
                 if let Some(region) = stmt.region() {
                     // Named accumulator region
-                    self.add_accumulator_box(region.to_string(), box_pair, source)?;
+                    let label = geom_stmt.label().map(str::to_string);
+                    self.add_accumulator_boxes(region.to_string(), boxes, label, source)?;
+                } else if matches!(
+                    geom_stmt,
+                    GeometryStatement::Shell { .. } | GeometryStatement::Sphere { .. }
+                ) {
+                    // Anonymous shell or sphere: several boxes under the anonymous key
+                    self.add_accumulator_boxes(stmt.anonymous_key(), boxes, None, source)?;
                 } else {
                     // Anonymous region
                     let key = stmt.anonymous_key();
-                    self.add_anonymous_region(key, box_pair, source);
+                    self.add_anonymous_region(key, boxes[0], source);
                 }
             }
         }
@@ -160,17 +272,24 @@ impl RegionTable {
         Ok(())
     }
 
-    /// Add a box to an accumulator region
-    fn add_accumulator_box(
+    /// Add the boxes of one statement to an accumulator region
+    fn add_accumulator_boxes(
         &mut self,
         region: String,
-        box_pair: BoxPair,
+        new_boxes: Vec<BoxPair64>,
+        label: Option<String>,
         source: SourceLocation,
     ) -> Result<(), ParseError> {
+        let new_labels = vec![label; new_boxes.len()];
         match self.regions.get_mut(&region) {
-            Some(RegionEntry::Accumulator { boxes, sources }) => {
+            Some(RegionEntry::Accumulator {
+                boxes,
+                labels,
+                sources,
+            }) => {
                 // Add to existing accumulator
-                boxes.push(box_pair);
+                boxes.extend(new_boxes);
+                labels.extend(new_labels);
                 sources.push(source);
             }
             Some(RegionEntry::Defined {
@@ -198,7 +317,8 @@ impl RegionTable {
                 self.regions.insert(
                     region,
                     RegionEntry::Accumulator {
-                        boxes: vec![box_pair],
+                        boxes: new_boxes,
+                        labels: new_labels,
                         sources: vec![source],
                     },
                 );
@@ -208,7 +328,7 @@ impl RegionTable {
     }
 
     /// Add an anonymous region
-    fn add_anonymous_region(&mut self, key: String, box_pair: BoxPair, source: SourceLocation) {
+    fn add_anonymous_region(&mut self, key: String, box_pair: BoxPair64, source: SourceLocation) {
         // Anonymous regions should never conflict since they use generated keys
         self.regions
             .insert(key, RegionEntry::Anonymous { box_pair, source });
@@ -223,21 +343,118 @@ impl Default for RegionTable {
 
 /// Assemble a RegionTable from parsed geometry and metadata statements
 pub fn assemble_region_table(
+    geom_stmts: Vec<GeomStmt>,
+    meta_stmts: Vec<MetaStmt>,
+    units: &[(impl UnitPosition, String)],
+) -> Result<RegionTable, ParseError> {
+    assemble_region_table_with_options(geom_stmts, meta_stmts, units, &CompileOptions::default())
+}
+
+/// The first corner of `boxes` outside the range of `width`, with its axis
+fn out_of_range_corner(boxes: &[BoxPair64], width: CoordWidth) -> Option<([i64; 3], usize)> {
+    boxes
+        .iter()
+        .flat_map(|&(min, max)| [min, max])
+        .find_map(|corner| {
+            (0..3)
+                .find(|&axis| corner[axis] < width.min() || corner[axis] > width.max())
+                .map(|axis| (corner, axis))
+        })
+}
+
+/// Whether `name` is reserved for entries without geometry or generated keys:
+/// `$global`, wildcard patterns and anonymous region keys
+pub fn is_reserved_region_name(name: &str) -> bool {
+    name == "$global" || name.starts_with("__anon_") || is_wildcard_pattern(name)
+}
+
+/// Assemble a RegionTable, honoring `options.strict_region_assignment`.
+///
+/// Geometry for a reserved name (see [`is_reserved_region_name`]) is a
+/// `ReservedRegionName` error.
+pub fn assemble_region_table_with_options(
     geom_stmts: Vec<GeomStmt>,
     _meta_stmts: Vec<MetaStmt>, // TODO: Handle metadata in M7
-    units: &[([i32; 3], String)],
+    units: &[(impl UnitPosition, String)],
+    options: &CompileOptions,
+) -> Result<RegionTable, ParseError> {
+    let geom_stmts = geom_stmts.iter().map(GeomStmt::widen).collect();
+    assemble_region_table_wide(geom_stmts, units, options)
+}
+
+/// [`assemble_region_table_with_options`] for statements with i64 coordinates
+pub fn assemble_region_table_wide(
+    geom_stmts: Vec<GeomStmtWide>,
+    units: &[(impl UnitPosition, String)],
+    options: &CompileOptions,
+) -> Result<RegionTable, ParseError> {
+    assemble_region_table_within(&geom_stmts, units, options, CoordWidth::I64)
+}
+
+/// Assemble a RegionTable whose box statements must fit `width`, the width
+/// of the output, so that an offset pushing a box out of the i32 range is
+/// reported at its statement
+pub(crate) fn assemble_region_table_within(
+    geom_stmts: &[GeomStmtWide],
+    units: &[(impl UnitPosition, String)],
+    options: &CompileOptions,
+    width: CoordWidth,
 ) -> Result<RegionTable, ParseError> {
     let mut table = RegionTable::new();
+    // First `@region=` box statement of each region, for strict assignment
+    let mut assigned: BTreeMap<String, SourceLocation> = BTreeMap::new();
 
     // Add all geometry statements
     for stmt in geom_stmts {
+        if let Some(region) = stmt
+            .region()
+            .filter(|region| is_reserved_region_name(region))
+        {
+            return Err(ParseError::ReservedRegionName {
+                name: region.to_string(),
+                source: SourceLocation::new(stmt.tuple_idx, stmt.stmt_idx),
+            });
+        }
+
+        let assigns_boxes = !stmt.append
+            && !matches!(
+                stmt.statement,
+                GeometryStatement::Expression { .. }
+                    | GeometryStatement::Const { .. }
+                    | GeometryStatement::Alias { .. }
+            );
+        if let Some(region) = stmt.region().filter(|_| assigns_boxes) {
+            let source = SourceLocation::new(stmt.tuple_idx, stmt.stmt_idx);
+            match assigned.get(region) {
+                Some(first_source) if options.strict_region_assignment => {
+                    return Err(ParseError::DuplicateRegionDefinition(Box::new(
+                        crate::DuplicateRegionDefinitionError {
+                            region: region.to_string(),
+                            first_source: first_source.clone(),
+                            duplicate_source: source,
+                        },
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    assigned.insert(region.to_string(), source);
+                }
+            }
+        }
+
         // Get the offset for this tuple
         let offset = units
             .get(stmt.tuple_idx)
-            .map(|(pos, _)| *pos)
+            .map(|(pos, _)| pos.to_wide())
             .unwrap_or([0, 0, 0]); // Default offset if tuple_idx is out of bounds
 
-        table.add_geometry(&stmt, offset)?;
+        match &stmt.statement {
+            GeometryStatement::Expression { region, expr } if options.simplify_exprs => {
+                let source = SourceLocation::new(stmt.tuple_idx, stmt.stmt_idx);
+                table.add_defined_region(region.clone(), expr.simplify(), source)?;
+            }
+            _ => table.add_geometry_within(stmt, offset, width)?,
+        }
     }
 
     Ok(table)
@@ -255,13 +472,21 @@ mod tests {
     }
 
     /// Helper to create a RelativeCoordinate statement
-    fn make_rc(region: Option<String>, corners: ([i32; 3], [i32; 3])) -> GeometryStatement {
-        GeometryStatement::RelativeCoordinate { region, corners }
+    fn make_rc(region: Option<String>, corners: (Vec3, Vec3)) -> GeometryStatement {
+        GeometryStatement::RelativeCoordinate {
+            region,
+            corners,
+            label: None,
+        }
     }
 
     /// Helper to create an AbsoluteCoordinate statement
-    fn make_ac(region: Option<String>, corners: ([i32; 3], [i32; 3])) -> GeometryStatement {
-        GeometryStatement::AbsoluteCoordinate { region, corners }
+    fn make_ac(region: Option<String>, corners: (Vec3, Vec3)) -> GeometryStatement {
+        GeometryStatement::AbsoluteCoordinate {
+            region,
+            corners,
+            label: None,
+        }
     }
 
     /// Helper to create an Expression statement
@@ -292,7 +517,7 @@ mod tests {
         // Check that we have an accumulator with both boxes
         assert_eq!(table.regions.len(), 1);
         match table.regions.get("test").unwrap() {
-            RegionEntry::Accumulator { boxes, sources } => {
+            RegionEntry::Accumulator { boxes, sources, .. } => {
                 assert_eq!(boxes.len(), 2);
                 assert_eq!(sources.len(), 2);
 
@@ -476,4 +701,27 @@ mod tests {
             _ => panic!("Expected DuplicateRegionDefinition error"),
         }
     }
+
+    #[test]
+    fn test_reserved_region_names() {
+        let units = vec![([0, 0, 0], String::new())];
+        for name in ["$global", "cpu.*", "core?", "__anon_0_0"] {
+            let stmt = make_geom_stmt(
+                0,
+                1,
+                make_rc(Some(name.to_string()), ([0, 0, 0], [1, 1, 1])),
+            );
+            match assemble_region_table(vec![stmt], vec![], &units) {
+                Err(ParseError::ReservedRegionName {
+                    name: found,
+                    source,
+                }) => {
+                    assert_eq!(found, name);
+                    assert_eq!(source, SourceLocation::new(0, 1));
+                }
+                other => panic!("Expected ReservedRegionName for {}, got {:?}", name, other),
+            }
+        }
+        assert!(!is_reserved_region_name("cpu.__anon_0_0"));
+    }
 }
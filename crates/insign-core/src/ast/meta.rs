@@ -27,7 +27,8 @@ impl MetaStmt {
     pub fn target(&self) -> Option<&str> {
         match &self.statement {
             MetadataStatement::Current { .. } => None,
-            MetadataStatement::Targeted { target, .. } => Some(target),
+            MetadataStatement::Targeted { target, .. }
+            | MetadataStatement::Delete { target, .. } => Some(target),
         }
     }
 
@@ -35,4 +36,18 @@ impl MetaStmt {
     pub fn is_current_region(&self) -> bool {
         matches!(self.statement, MetadataStatement::Current { .. })
     }
+
+    /// Check if this is a `?=` default, applied only where the key is absent
+    pub fn is_default(&self) -> bool {
+        match self.statement {
+            MetadataStatement::Current { default, .. }
+            | MetadataStatement::Targeted { default, .. } => default,
+            MetadataStatement::Delete { .. } => false,
+        }
+    }
+
+    /// Check if this is a `-key` deletion
+    pub fn is_delete(&self) -> bool {
+        matches!(self.statement, MetadataStatement::Delete { .. })
+    }
 }
@@ -1,8 +1,12 @@
-use crate::ast::{GeomStmt, MetaStmt, SourceLocation};
+use crate::ast::{
+    coalesce_boxes, minimize_boxes, EvalBudget, GeomStmt, GeomStmtWide, MetaStmt, SourceLocation,
+};
 use crate::parser::meta::MetadataStatement;
-use crate::{BoxPair, DslEntry, ParseError};
+use crate::{
+    BoxPair64, CompileOptions, DslEntry, DslMap64, Metadata, MetadataMergePolicy, ParseError,
+};
 use serde_json::Value;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Metadata assignment with source tracking
 #[derive(Debug, Clone, PartialEq)]
@@ -11,6 +15,9 @@ pub struct MetadataAssignment {
     pub value: Value,
     /// Source location of this assignment
     pub source: SourceLocation,
+    /// Wildcard pattern this value was inherited through; `None` when the
+    /// statement targeted the region itself
+    pub via: Option<String>,
 }
 
 /// Table of evaluated regions with their boxes and metadata
@@ -24,7 +31,9 @@ pub struct EvaluatedRegionTable {
 #[derive(Debug, Clone, PartialEq)]
 pub struct EvaluatedRegionData {
     /// Bounding boxes for this region (None for special entries like $global)
-    pub boxes: Option<Vec<BoxPair>>,
+    pub boxes: Option<Vec<BoxPair64>>,
+    /// Labels of the boxes, parallel to `boxes`, when any box is labeled
+    pub box_labels: Option<Vec<Option<String>>>,
     /// Metadata assignments for this region
     pub metadata: BTreeMap<String, MetadataAssignment>,
 }
@@ -38,35 +47,70 @@ impl EvaluatedRegionTable {
     }
 
     /// Add or update a region's bounding boxes
-    pub fn set_region_boxes(&mut self, region: String, boxes: Option<Vec<BoxPair>>) {
+    pub fn set_region_boxes(&mut self, region: String, boxes: Option<Vec<BoxPair64>>) {
         let entry = self
             .regions
             .entry(region)
             .or_insert_with(|| EvaluatedRegionData {
                 boxes: None,
+                box_labels: None,
                 metadata: BTreeMap::new(),
             });
         entry.boxes = boxes;
     }
 
+    /// Set the per-box labels of a region that already has boxes
+    pub fn set_region_box_labels(&mut self, region: &str, labels: Vec<Option<String>>) {
+        if let Some(entry) = self.regions.get_mut(region) {
+            entry.box_labels = Some(labels);
+        }
+    }
+
     /// Add metadata to a region, checking for conflicts
     pub fn add_metadata(
         &mut self,
         region: String,
         key: String,
         assignment: MetadataAssignment,
+    ) -> Result<(), ParseError> {
+        self.add_metadata_with_policy(region, key, assignment, MetadataMergePolicy::Conflict)
+    }
+
+    /// Add metadata to a region, combining a different existing value
+    /// according to `policy`
+    pub fn add_metadata_with_policy(
+        &mut self,
+        region: String,
+        key: String,
+        assignment: MetadataAssignment,
+        policy: MetadataMergePolicy,
     ) -> Result<(), ParseError> {
         let entry = self
             .regions
             .entry(region.clone())
             .or_insert_with(|| EvaluatedRegionData {
                 boxes: None,
+                box_labels: None,
                 metadata: BTreeMap::new(),
             });
 
         // Check for existing metadata with different value
         if let Some(existing) = entry.metadata.get(&key) {
             if existing.value != assignment.value {
+                if let Some(value) = merge_values(&existing.value, &assignment.value, policy) {
+                    // The later value replaces the earlier one along with its source;
+                    // for a merge, the region's own assignment takes precedence
+                    let mut merged = if policy == MetadataMergePolicy::LastWins
+                        || (existing.via.is_some() && assignment.via.is_none())
+                    {
+                        assignment
+                    } else {
+                        existing.clone()
+                    };
+                    merged.value = value;
+                    entry.metadata.insert(key, merged);
+                    return Ok(());
+                }
                 return Err(ParseError::MetadataConflict(Box::new(
                     crate::MetadataConflictError {
                         region,
@@ -78,7 +122,11 @@ impl EvaluatedRegionTable {
                     },
                 )));
             }
-            // Identical values are allowed - just keep the existing one
+            // Identical values are allowed - keep the existing one, unless it was
+            // inherited and the region now sets the value itself
+            if existing.via.is_some() && assignment.via.is_none() {
+                entry.metadata.insert(key, assignment);
+            }
         } else {
             // New metadata key
             entry.metadata.insert(key, assignment);
@@ -86,6 +134,32 @@ impl EvaluatedRegionTable {
 
         Ok(())
     }
+
+    /// Add default metadata to a region, unless the region already has the key
+    pub fn add_default_metadata(
+        &mut self,
+        region: String,
+        key: String,
+        assignment: MetadataAssignment,
+    ) {
+        self.regions
+            .entry(region)
+            .or_insert_with(|| EvaluatedRegionData {
+                boxes: None,
+                box_labels: None,
+                metadata: BTreeMap::new(),
+            })
+            .metadata
+            .entry(key)
+            .or_insert(assignment);
+    }
+
+    /// Remove `key` from `region`, if both exist
+    pub fn remove_metadata(&mut self, region: &str, key: &str) {
+        if let Some(data) = self.regions.get_mut(region) {
+            data.metadata.remove(key);
+        }
+    }
 }
 
 impl Default for EvaluatedRegionTable {
@@ -95,7 +169,7 @@ impl Default for EvaluatedRegionTable {
 }
 
 /// Find the last geometry statement in a given tuple
-fn find_last_geometry_in_tuple(geom_stmts: &[GeomStmt], tuple_idx: usize) -> Option<String> {
+fn find_last_geometry_in_tuple<V>(geom_stmts: &[GeomStmt<V>], tuple_idx: usize) -> Option<String> {
     let mut last_region: Option<String> = None;
 
     for stmt in geom_stmts {
@@ -112,22 +186,81 @@ fn find_last_geometry_in_tuple(geom_stmts: &[GeomStmt], tuple_idx: usize) -> Opt
     last_region
 }
 
+/// Find the geometry statement nearest to `stmt_idx` in a given tuple.
+///
+/// Prefers the closest geometry statement before `stmt_idx`; when there is none,
+/// binds forward to the closest one after it.
+fn find_nearest_geometry_in_tuple<V>(
+    geom_stmts: &[GeomStmt<V>],
+    tuple_idx: usize,
+    stmt_idx: usize,
+) -> Option<String> {
+    let in_tuple = || {
+        geom_stmts
+            .iter()
+            .filter(move |stmt| stmt.tuple_idx == tuple_idx)
+    };
+
+    let nearest = in_tuple()
+        .filter(|stmt| stmt.stmt_idx < stmt_idx)
+        .max_by_key(|stmt| stmt.stmt_idx)
+        .or_else(|| {
+            in_tuple()
+                .filter(|stmt| stmt.stmt_idx > stmt_idx)
+                .min_by_key(|stmt| stmt.stmt_idx)
+        })?;
+
+    // Anonymous regions are keyed by their source location
+    Some(
+        nearest
+            .region()
+            .map(str::to_string)
+            .unwrap_or_else(|| nearest.anonymous_key()),
+    )
+}
+
+/// Metadata target standing for every emitted region; `#*:power="low"` sets a
+/// fallback value instead of creating a `*` wildcard entry
+pub const ALL_REGIONS_TARGET: &str = "*";
+
 /// Determine if a target is a wildcard pattern
-fn is_wildcard_pattern(target: &str) -> bool {
-    target.contains('*')
+pub(crate) fn is_wildcard_pattern(target: &str) -> bool {
+    target.contains(['*', '?'])
 }
 
-/// Check if a region name matches a wildcard pattern
+/// Check if a region name matches a glob pattern, where `*` matches any run of
+/// characters (including none) and `?` exactly one; other characters match themselves
 fn matches_wildcard(region_name: &str, pattern: &str) -> bool {
-    // Simple wildcard matching: patterns like "cpu.*" match "cpu.core", "cpu.cache", etc.
-    if let Some(prefix) = pattern.strip_suffix('*') {
-        region_name.starts_with(prefix)
-    } else if let Some(suffix) = pattern.strip_prefix('*') {
-        region_name.ends_with(suffix)
-    } else {
-        // Exact match (no wildcard)
-        region_name == pattern
+    let name: Vec<char> = region_name.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    let (mut n, mut p) = (0, 0);
+    // Position of the last '*' and the name position it was tried at, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&ch) if ch == '?' || ch == name[n] => {
+                n += 1;
+                p += 1;
+            }
+            _ => match star {
+                // Let the last '*' swallow one more character and retry
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
     }
+
+    // Only trailing '*'s may remain
+    pattern[p..].iter().all(|&ch| ch == '*')
 }
 
 /// Process metadata statements and apply them to the evaluated region table
@@ -136,82 +269,533 @@ pub fn apply_metadata_pass(
     geom_stmts: &[GeomStmt],
     meta_stmts: &[MetaStmt],
 ) -> Result<(), ParseError> {
-    for meta_stmt in meta_stmts {
-        let source = SourceLocation::new(meta_stmt.tuple_idx, meta_stmt.stmt_idx);
+    let geom_stmts: Vec<GeomStmtWide> = geom_stmts.iter().map(GeomStmt::widen).collect();
+    apply_metadata_pass_with_options(
+        evaluated_table,
+        &geom_stmts,
+        meta_stmts,
+        &CompileOptions::default(),
+    )
+}
 
-        match &meta_stmt.statement {
-            MetadataStatement::Current { key, value } => {
-                // Find the last geometry statement in the same tuple
-                let target_region = find_last_geometry_in_tuple(geom_stmts, meta_stmt.tuple_idx)
-                    .ok_or_else(|| ParseError::NoCurrentRegion {
-                        source: source.clone(),
-                    })?;
+/// Process metadata statements and apply them to the evaluated region table, honoring `options`
+pub fn apply_metadata_pass_with_options(
+    evaluated_table: &mut EvaluatedRegionTable,
+    geom_stmts: &[GeomStmtWide],
+    meta_stmts: &[MetaStmt],
+    options: &CompileOptions,
+) -> Result<(), ParseError> {
+    apply_metadata_statements(evaluated_table, geom_stmts, meta_stmts, options, Err)
+}
 
-                let assignment = MetadataAssignment {
-                    value: value.clone(),
-                    source: source.clone(),
-                };
+/// Process metadata statements like [`apply_metadata_pass_with_options`], but
+/// keep going after a failing statement and return every error.
+///
+/// A statement that fails (a conflict, or no current region) leaves out the
+/// rest of its own assignments; the other statements are still applied.
+pub fn apply_metadata_pass_collect(
+    evaluated_table: &mut EvaluatedRegionTable,
+    geom_stmts: &[GeomStmtWide],
+    meta_stmts: &[MetaStmt],
+    options: &CompileOptions,
+) -> Vec<ParseError> {
+    let mut errors = Vec::new();
+    let collect = |error| {
+        errors.push(error);
+        Ok(())
+    };
+    // Errors are all passed to `collect`, so this never fails itself
+    let _ = apply_metadata_statements(evaluated_table, geom_stmts, meta_stmts, options, collect);
+    errors
+}
+
+/// Apply all metadata statements, handing each error to `report`, which
+/// either stops the pass by returning it or records it and continues
+pub(crate) fn apply_metadata_statements(
+    evaluated_table: &mut EvaluatedRegionTable,
+    geom_stmts: &[GeomStmtWide],
+    meta_stmts: &[MetaStmt],
+    options: &CompileOptions,
+    mut report: impl FnMut(ParseError) -> Result<(), ParseError>,
+) -> Result<(), ParseError> {
+    // Explicit assignments go first so `?=` defaults see every key set otherwise.
+    // Defaults targeting a region directly precede wildcard defaults, each in
+    // statement order; the first default to reach a region wins. Deletions
+    // come last, so they also remove inherited values and defaults.
+    // Assignments to every region (`#*:key=value`) only fill keys still unset
+    // after all of the above, so any more specific value wins over them.
+    let (deletes, assignments): (Vec<&MetaStmt>, Vec<&MetaStmt>) =
+        meta_stmts.iter().partition(|stmt| stmt.is_delete());
+    let (all_regions, assignments): (Vec<&MetaStmt>, Vec<&MetaStmt>) = assignments
+        .into_iter()
+        .partition(|stmt| stmt.target() == Some(ALL_REGIONS_TARGET));
+    let (defaults, explicit): (Vec<&MetaStmt>, Vec<&MetaStmt>) =
+        assignments.into_iter().partition(|stmt| stmt.is_default());
+    let (wildcard_defaults, direct_defaults): (Vec<&MetaStmt>, Vec<&MetaStmt>) = defaults
+        .into_iter()
+        .partition(|stmt| stmt.target().is_some_and(is_wildcard_pattern));
+
+    for meta_stmt in explicit
+        .into_iter()
+        .chain(direct_defaults)
+        .chain(wildcard_defaults)
+        .chain(all_regions)
+        .chain(deletes)
+    {
+        if let Err(error) =
+            apply_metadata_statement(evaluated_table, geom_stmts, meta_stmt, options)
+        {
+            report(error)?;
+        }
+    }
+
+    if options.interpolate_metadata {
+        let mut budget = EvalBudget::new(options.max_eval_steps);
+        if let Err(error) = interpolate_metadata_budgeted(evaluated_table, &mut budget) {
+            report(error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace `${region:key}` tokens in string metadata values with the value
+/// assigned to `key` of `region`, after all assignments are made.
+///
+/// Strings are inserted as is and other values as JSON text; referenced
+/// values are interpolated first. Tokens without a `:` and an unterminated
+/// `${` are kept literally. A missing region or key is an
+/// `UnknownInterpolationReference` error, and values referring back to
+/// themselves a `MetadataInterpolationCycle`.
+pub fn interpolate_metadata(table: &mut EvaluatedRegionTable) -> Result<(), ParseError> {
+    interpolate_metadata_budgeted(table, &mut EvalBudget::unlimited())
+}
+
+/// [`interpolate_metadata`], charging one step of `budget` per byte of
+/// interpolated text so that values doubling each other cannot grow without
+/// bound
+pub fn interpolate_metadata_budgeted(
+    table: &mut EvaluatedRegionTable,
+    budget: &mut EvalBudget,
+) -> Result<(), ParseError> {
+    let templates: Vec<(String, String)> = table
+        .regions
+        .iter()
+        .flat_map(|(region, data)| {
+            data.metadata
+                .iter()
+                .filter(|(_, assignment)| {
+                    matches!(&assignment.value, Value::String(text) if text.contains("${"))
+                })
+                .map(move |(key, _)| (region.clone(), key.clone()))
+        })
+        .collect();
+
+    let mut resolved = BTreeMap::new();
+    for (region, key) in templates {
+        resolve_interpolated(table, &region, &key, &mut resolved, budget)?;
+    }
 
-                evaluated_table.add_metadata(target_region, key.clone(), assignment)?;
+    for ((region, key), value) in resolved {
+        if let Some(assignment) = table
+            .regions
+            .get_mut(&region)
+            .and_then(|data| data.metadata.get_mut(&key))
+        {
+            assignment.value = value;
+        }
+    }
+    Ok(())
+}
+
+/// The interpolated value of `key` of `region`, which must exist.
+///
+/// References are followed with an explicit stack rather than recursion, so
+/// long chains of values referring to each other cannot overflow the stack.
+fn resolve_interpolated(
+    table: &EvaluatedRegionTable,
+    region: &str,
+    key: &str,
+    resolved: &mut BTreeMap<(String, String), Value>,
+    budget: &mut EvalBudget,
+) -> Result<Value, ParseError> {
+    // Values being interpolated, each waiting on the one after it, and their ids
+    let mut chain: Vec<Interpolation> = Vec::new();
+    let mut open = BTreeSet::new();
+    let mut next = (region.to_string(), key.to_string());
+    loop {
+        let mut value = resolved.get(&next).cloned();
+        if value.is_none() {
+            if open.contains(&next) {
+                let start = chain
+                    .iter()
+                    .position(|entry| entry.id == next)
+                    .expect("open values are in the chain");
+                let chain = chain[start..]
+                    .iter()
+                    .map(|entry| &entry.id)
+                    .chain([&next])
+                    .map(|(region, key)| format!("{}:{}", region, key))
+                    .collect();
+                return Err(ParseError::MetadataInterpolationCycle { chain });
             }
-            MetadataStatement::Targeted { target, key, value } => {
-                let assignment = MetadataAssignment {
-                    value: value.clone(),
-                    source: source.clone(),
-                };
 
-                if is_wildcard_pattern(target) {
-                    // First, create the wildcard region entry itself
-                    evaluated_table.add_metadata(
-                        target.clone(),
+            let assignment = &table.regions[&next.0].metadata[&next.1];
+            match &assignment.value {
+                Value::String(text) if text.contains("${") => {
+                    open.insert(next.clone());
+                    chain.push(Interpolation {
+                        id: next,
+                        text,
+                        source: &assignment.source,
+                        output: String::new(),
+                        offset: 0,
+                    });
+                }
+                other => value = Some(other.clone()),
+            }
+        }
+
+        // Hand finished values to the ones waiting on them until one needs
+        // another reference
+        loop {
+            let Some(entry) = chain.last_mut() else {
+                return Ok(value.expect("a value finishes before the chain empties"));
+            };
+            let written = entry.output.len();
+            match value.take() {
+                Some(Value::String(text)) => entry.output.push_str(&text),
+                Some(value) => entry.output.push_str(&value.to_string()),
+                None => {}
+            }
+            let reference = entry.next_reference(table)?;
+            budget.spend((entry.output.len() - written) as u64)?;
+            if let Some(reference) = reference {
+                next = reference;
+                break;
+            }
+
+            let entry = chain.pop().expect("the chain is not empty");
+            open.remove(&entry.id);
+            let finished = Value::String(entry.output);
+            resolved.insert(entry.id, finished.clone());
+            value = Some(finished);
+        }
+    }
+}
+
+/// A string value part way through interpolation
+struct Interpolation<'a> {
+    /// Region and key holding the value
+    id: (String, String),
+    text: &'a str,
+    source: &'a SourceLocation,
+    /// Interpolated text so far
+    output: String,
+    /// Byte offset in `text` of the part not yet interpolated
+    offset: usize,
+}
+
+impl Interpolation<'_> {
+    /// Copy text up to the next `${region:key}` token and return the existing
+    /// value it references, or `None` once the text is done
+    fn next_reference(
+        &mut self,
+        table: &EvaluatedRegionTable,
+    ) -> Result<Option<(String, String)>, ParseError> {
+        let text = self.text;
+        while let Some(open) = text[self.offset..]
+            .find("${")
+            .map(|open| self.offset + open)
+        {
+            let Some(close) = text[open..].find('}').map(|close| open + close) else {
+                break;
+            };
+            self.output.push_str(&text[self.offset..open]);
+            self.offset = close + 1;
+
+            let reference = &text[open + 2..close];
+            let Some((target, target_key)) = reference.split_once(':') else {
+                self.output.push_str(&text[open..=close]);
+                continue;
+            };
+            let exists = table
+                .regions
+                .get(target)
+                .is_some_and(|data| data.metadata.contains_key(target_key));
+            if !exists {
+                return Err(ParseError::UnknownInterpolationReference {
+                    region: self.id.0.clone(),
+                    key: self.id.1.clone(),
+                    reference: reference.to_string(),
+                    source: self.source.clone(),
+                });
+            }
+            return Ok(Some((target.to_string(), target_key.to_string())));
+        }
+
+        self.output.push_str(&text[self.offset..]);
+        self.offset = text.len();
+        Ok(None)
+    }
+}
+
+/// Check `value` against the kind `options.metadata_schema` requires for `key`
+fn check_metadata_type(
+    options: &CompileOptions,
+    region: &str,
+    key: &str,
+    value: &serde_json::Value,
+    source: &SourceLocation,
+) -> Result<(), ParseError> {
+    match options.metadata_schema.get(key) {
+        Some(&expected) if !expected.matches(value) => Err(ParseError::MetadataTypeMismatch(
+            Box::new(crate::MetadataTypeMismatchError {
+                region: region.to_string(),
+                key: key.to_string(),
+                expected,
+                found: value.clone(),
+                source: source.clone(),
+            }),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Apply a single metadata statement to the evaluated region table
+fn apply_metadata_statement(
+    evaluated_table: &mut EvaluatedRegionTable,
+    geom_stmts: &[GeomStmtWide],
+    meta_stmt: &MetaStmt,
+    options: &CompileOptions,
+) -> Result<(), ParseError> {
+    let source = SourceLocation::new(meta_stmt.tuple_idx, meta_stmt.stmt_idx);
+    let default = meta_stmt.is_default();
+
+    match &meta_stmt.statement {
+        MetadataStatement::Current { key, value, .. } => {
+            // Find the current region in the same tuple
+            let target_region = if options.current_region_binds_forward {
+                find_nearest_geometry_in_tuple(geom_stmts, meta_stmt.tuple_idx, meta_stmt.stmt_idx)
+            } else {
+                find_last_geometry_in_tuple(geom_stmts, meta_stmt.tuple_idx)
+            }
+            .ok_or_else(|| ParseError::NoCurrentRegion {
+                source: source.clone(),
+            })?;
+            check_metadata_type(options, &target_region, key, value, &source)?;
+
+            let assignment = MetadataAssignment {
+                value: value.clone(),
+                source: source.clone(),
+                via: None,
+            };
+
+            assign(
+                evaluated_table,
+                default,
+                options.metadata_merge_policy,
+                target_region,
+                key.clone(),
+                assignment,
+            )?;
+        }
+        MetadataStatement::Targeted {
+            target, key, value, ..
+        } => {
+            check_metadata_type(options, target, key, value, &source)?;
+            let assignment = MetadataAssignment {
+                value: value.clone(),
+                source: source.clone(),
+                via: None,
+            };
+
+            if target == ALL_REGIONS_TARGET {
+                // A fallback on every emitted region, without an entry of its own:
+                // named regions, and anonymous ones that carry metadata
+                let regions: Vec<String> = evaluated_table
+                    .regions
+                    .iter()
+                    .filter(|(region, data)| {
+                        *region != "$global"
+                            && !is_wildcard_pattern(region)
+                            && (!region.starts_with("__anon_") || !data.metadata.is_empty())
+                    })
+                    .map(|(region, _)| region.clone())
+                    .collect();
+
+                let inherited = MetadataAssignment {
+                    via: Some(target.clone()),
+                    ..assignment
+                };
+                for region in regions {
+                    evaluated_table.add_default_metadata(region, key.clone(), inherited.clone());
+                }
+            } else if is_wildcard_pattern(target) {
+                // First, create the wildcard region entry itself
+                assign(
+                    evaluated_table,
+                    default,
+                    options.metadata_merge_policy,
+                    target.clone(),
+                    key.clone(),
+                    assignment.clone(),
+                )?;
+
+                // Then apply to all matching regions
+                let matching_regions: Vec<String> = evaluated_table
+                    .regions
+                    .keys()
+                    .filter(|region| matches_wildcard(region, target) && *region != target)
+                    .cloned()
+                    .collect();
+
+                let inherited = MetadataAssignment {
+                    via: Some(target.clone()),
+                    ..assignment
+                };
+                for region in matching_regions {
+                    assign(
+                        evaluated_table,
+                        default,
+                        options.metadata_merge_policy,
+                        region,
                         key.clone(),
-                        assignment.clone(),
+                        inherited.clone(),
                     )?;
+                }
+            } else {
+                // Direct target
+                assign(
+                    evaluated_table,
+                    default,
+                    options.metadata_merge_policy,
+                    target.clone(),
+                    key.clone(),
+                    assignment,
+                )?;
+            }
+        }
+        MetadataStatement::Delete { target, key } => {
+            // A wildcard removes the key from its own entry and every match
+            let regions: Vec<String> = if is_wildcard_pattern(target) {
+                evaluated_table
+                    .regions
+                    .keys()
+                    .filter(|region| matches_wildcard(region, target))
+                    .cloned()
+                    .collect()
+            } else {
+                vec![target.clone()]
+            };
+            for region in regions {
+                evaluated_table.remove_metadata(&region, key);
+            }
+        }
+    }
 
-                    // Then apply to all matching regions
-                    let matching_regions: Vec<String> = evaluated_table
-                        .regions
-                        .keys()
-                        .filter(|region| matches_wildcard(region, target) && *region != target)
-                        .cloned()
-                        .collect();
+    Ok(())
+}
+
+/// Add an assignment, as a default when `default` is set
+fn assign(
+    table: &mut EvaluatedRegionTable,
+    default: bool,
+    policy: MetadataMergePolicy,
+    region: String,
+    key: String,
+    assignment: MetadataAssignment,
+) -> Result<(), ParseError> {
+    if default {
+        table.add_default_metadata(region, key, assignment);
+        Ok(())
+    } else {
+        table.add_metadata_with_policy(region, key, assignment, policy)
+    }
+}
 
-                    for region in matching_regions {
-                        evaluated_table.add_metadata(region, key.clone(), assignment.clone())?;
+/// Combine two different values of one key under `policy`, or `None` when
+/// they conflict
+fn merge_values(first: &Value, second: &Value, policy: MetadataMergePolicy) -> Option<Value> {
+    match (policy, first, second) {
+        (MetadataMergePolicy::LastWins, _, _) => Some(second.clone()),
+        (MetadataMergePolicy::MergeArrays, Value::Array(first), Value::Array(second)) => {
+            let mut merged: Vec<Value> = Vec::new();
+            for element in first.iter().chain(second) {
+                if !merged.contains(element) {
+                    merged.push(element.clone());
+                }
+            }
+            Some(Value::Array(merged))
+        }
+        (MetadataMergePolicy::DeepMergeObjects, Value::Object(first), Value::Object(second)) => {
+            let mut merged = first.clone();
+            for (key, value) in second {
+                match merged.get(key) {
+                    Some(existing) if existing == value => {}
+                    Some(existing) => {
+                        let value = merge_values(existing, value, policy)?;
+                        merged.insert(key.clone(), value);
+                    }
+                    None => {
+                        merged.insert(key.clone(), value.clone());
                     }
-                } else {
-                    // Direct target
-                    evaluated_table.add_metadata(target.clone(), key.clone(), assignment)?;
                 }
             }
+            Some(Value::Object(merged))
         }
+        _ => None,
     }
+}
 
-    Ok(())
+/// Metadata key holding a region's block count when `compute_volume` is set
+pub const VOLUME_KEY: &str = "__volume";
+
+/// Metadata key set to `true` when a region's block count saturated at `i64::MAX`
+pub const VOLUME_OVERFLOW_KEY: &str = "__volume_overflow";
+
+/// Convert an EvaluatedRegionTable to the (i64) DslMap format
+pub fn build_dsl_map(evaluated_table: EvaluatedRegionTable) -> DslMap64 {
+    build_dsl_map_with_options(evaluated_table, &CompileOptions::default())
 }
 
-/// Convert an EvaluatedRegionTable to the final DslMap format
-pub fn build_dsl_map(evaluated_table: EvaluatedRegionTable) -> BTreeMap<String, DslEntry> {
+/// Convert an EvaluatedRegionTable to the (i64) DslMap format, honoring `options`
+pub fn build_dsl_map_with_options(
+    evaluated_table: EvaluatedRegionTable,
+    options: &CompileOptions,
+) -> DslMap64 {
     let mut dsl_map = BTreeMap::new();
 
     for (region_name, region_data) in evaluated_table.regions {
         // Convert metadata assignments to simple key-value pairs
-        let metadata: BTreeMap<String, Value> = region_data
+        let metadata: Metadata = region_data
             .metadata
-            .into_iter()
-            .map(|(key, assignment)| (key, assignment.value))
+            .iter()
+            .map(|(key, assignment)| (key.clone(), assignment.value.clone()))
             .collect();
 
-        // Skip anonymous regions without metadata
+        // Skip anonymous regions without metadata unless asked to keep them
         let is_anonymous = region_name.starts_with("__anon_");
-        if is_anonymous && metadata.is_empty() {
+        if is_anonymous && metadata.is_empty() && !options.keep_empty_anonymous {
             continue;
         }
 
-        let entry = DslEntry {
-            bounding_boxes: region_data.boxes,
+        let mut entry = DslEntry {
+            bounding_boxes: output_boxes(region_data.boxes, &region_data.box_labels, options),
+            box_labels: region_data.box_labels,
             metadata,
         };
+        add_volume_metadata(&mut entry, options);
+        order_metadata(
+            &mut entry.metadata,
+            |key| {
+                region_data
+                    .metadata
+                    .get(key)
+                    .map(|assignment| &assignment.source)
+            },
+            options,
+        );
 
         dsl_map.insert(region_name, entry);
     }
@@ -219,11 +803,89 @@ pub fn build_dsl_map(evaluated_table: EvaluatedRegionTable) -> BTreeMap<String,
     dsl_map
 }
 
+/// Arrange output metadata by key, or with `options.preserve_metadata_order` in
+/// the order of the statements that set the keys, as `source_of` tells.
+/// Keys without a statement, like [`VOLUME_KEY`], then follow by key.
+pub fn order_metadata<'s>(
+    metadata: &mut Metadata,
+    source_of: impl Fn(&str) -> Option<&'s SourceLocation>,
+    options: &CompileOptions,
+) {
+    if !options.preserve_metadata_order {
+        metadata.sort_keys();
+        return;
+    }
+    // Each key is set by one statement, so its source orders it
+    let position = |key: &str| {
+        source_of(key).map_or((1, 0, 0), |source| (0, source.tuple_idx, source.stmt_idx))
+    };
+    metadata.sort_by(|key1, _, key2, _| {
+        position(key1)
+            .cmp(&position(key2))
+            .then_with(|| key1.cmp(key2))
+    });
+}
+
+/// Record the block count of `entry`'s boxes under [`VOLUME_KEY`] when
+/// `options.compute_volume` is set, replacing any user value.
+///
+/// The count is the sum of the box volumes, so overlapping boxes (as from a
+/// union) are counted once per box; with `minimize_boxes` the boxes are
+/// disjoint and the count is exact. A count beyond `i64::MAX` saturates and
+/// sets [`VOLUME_OVERFLOW_KEY`]. Entries without boxes get no count.
+pub fn add_volume_metadata(entry: &mut DslEntry<BoxPair64>, options: &CompileOptions) {
+    if !options.compute_volume {
+        return;
+    }
+    let Some(boxes) = &entry.bounding_boxes else {
+        return;
+    };
+
+    let volume = boxes
+        .iter()
+        .try_fold(0u128, |total, &box_pair| {
+            total.checked_add(crate::boxutil::volume(box_pair))
+        })
+        .and_then(|total| i64::try_from(total).ok());
+    entry.metadata.insert(
+        VOLUME_KEY.to_string(),
+        Value::from(volume.unwrap_or(i64::MAX)),
+    );
+    if volume.is_none() {
+        entry
+            .metadata
+            .insert(VOLUME_OVERFLOW_KEY.to_string(), Value::Bool(true));
+    }
+}
+
+/// A region's boxes as emitted, coalesced, minimized and sorted when `options`
+/// asks for it and no labels refer to individual boxes
+pub fn output_boxes(
+    boxes: Option<Vec<BoxPair64>>,
+    box_labels: &Option<Vec<Option<String>>>,
+    options: &CompileOptions,
+) -> Option<Vec<BoxPair64>> {
+    let mut boxes = boxes?;
+    if box_labels.is_some() {
+        return Some(boxes);
+    }
+    if options.coalesce_boxes {
+        boxes = coalesce_boxes(&boxes);
+    }
+    if options.minimize_boxes {
+        boxes = minimize_boxes(&boxes);
+    }
+    if options.sort_boxes {
+        boxes.sort_unstable();
+    }
+    Some(boxes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ast::{GeomStmt, MetaStmt};
-    use crate::parser::geom::GeometryStatement;
+    use crate::parser::geom::{GeometryStatement, Vec3};
     use crate::parser::meta::MetadataStatement;
     use serde_json::json;
 
@@ -238,8 +900,12 @@ mod tests {
     }
 
     /// Helper to create a RelativeCoordinate statement
-    fn make_rc(region: Option<String>, corners: ([i32; 3], [i32; 3])) -> GeometryStatement {
-        GeometryStatement::RelativeCoordinate { region, corners }
+    fn make_rc(region: Option<String>, corners: (Vec3, Vec3)) -> GeometryStatement {
+        GeometryStatement::RelativeCoordinate {
+            region,
+            corners,
+            label: None,
+        }
     }
 
     #[test]
@@ -273,6 +939,31 @@ mod tests {
         assert_eq!(find_last_geometry_in_tuple(&geom_stmts, 2), None);
     }
 
+    #[test]
+    fn test_find_nearest_geometry_in_tuple() {
+        let geom_stmts = vec![
+            make_geom_stmt(0, 1, make_rc(Some("a".to_string()), ([0, 0, 0], [1, 1, 1]))),
+            make_geom_stmt(0, 3, make_rc(Some("b".to_string()), ([2, 2, 2], [3, 3, 3]))),
+        ];
+
+        // Backward is preferred whenever a prior geometry exists
+        assert_eq!(
+            find_nearest_geometry_in_tuple(&geom_stmts, 0, 2),
+            Some("a".to_string())
+        );
+        assert_eq!(
+            find_nearest_geometry_in_tuple(&geom_stmts, 0, 4),
+            Some("b".to_string())
+        );
+
+        // Metadata before any geometry binds forward
+        assert_eq!(
+            find_nearest_geometry_in_tuple(&geom_stmts, 0, 0),
+            Some("a".to_string())
+        );
+        assert_eq!(find_nearest_geometry_in_tuple(&geom_stmts, 1, 0), None);
+    }
+
     #[test]
     fn test_find_last_geometry_anonymous() {
         let geom_stmts = vec![
@@ -307,6 +998,91 @@ mod tests {
         assert!(!matches_wildcard("exact2", "exact"));
     }
 
+    #[test]
+    fn test_wildcard_glob_matching() {
+        // Infix '*' matches any run, including an empty one and one with dots
+        assert!(matches_wildcard("cpu.core.alu", "cpu.*.alu"));
+        assert!(matches_wildcard("cpu.a.b.alu", "cpu.*.alu"));
+        assert!(matches_wildcard("cpu..alu", "cpu.*.alu"));
+        assert!(!matches_wildcard("cpu.core.fpu", "cpu.*.alu"));
+        assert!(!matches_wildcard("cpu.alu", "cpu.*.alu"));
+        assert!(matches_wildcard("cpu.alu.alu", "*.alu*"));
+
+        // '?' matches exactly one character
+        assert!(matches_wildcard("cpu.core1", "cpu.core?"));
+        assert!(matches_wildcard("cpu.core.", "cpu.core?"));
+        assert!(!matches_wildcard("cpu.core", "cpu.core?"));
+        assert!(!matches_wildcard("cpu.core12", "cpu.core?"));
+        assert!(matches_wildcard("cpu.core12", "cpu.core??"));
+        assert!(matches_wildcard("gpu.core1", "?pu.*1"));
+
+        // Patterns that match nothing
+        assert!(!matches_wildcard("", "?"));
+        assert!(!matches_wildcard("cpu", "cpu?*"));
+        assert!(!matches_wildcard("cpu.core", "*.gpu.*"));
+        assert!(matches_wildcard("", "*"));
+
+        assert!(is_wildcard_pattern("cpu.core?"));
+        assert!(is_wildcard_pattern("cpu.*.alu"));
+        assert!(!is_wildcard_pattern("cpu.core"));
+    }
+
+    #[test]
+    fn test_infix_wildcard_metadata() {
+        let mut evaluated_table = EvaluatedRegionTable::new();
+        for (idx, region) in ["cpu.core.alu", "cpu.cache.alu", "cpu.core.fpu", "cpu.core1"]
+            .into_iter()
+            .enumerate()
+        {
+            let min = idx as i64 * 2;
+            evaluated_table.set_region_boxes(
+                region.to_string(),
+                Some(vec![([min, 0, 0], [min + 1, 1, 1])]),
+            );
+        }
+
+        let meta_stmts: Vec<MetaStmt> = [("cpu.*.alu", "alu"), ("cpu.core?", "core")]
+            .into_iter()
+            .enumerate()
+            .map(|(stmt_idx, (target, kind))| {
+                make_meta_stmt(
+                    0,
+                    stmt_idx,
+                    MetadataStatement::Targeted {
+                        target: target.to_string(),
+                        key: "kind".to_string(),
+                        value: json!(kind),
+                        default: false,
+                    },
+                )
+            })
+            .collect();
+
+        apply_metadata_pass(&mut evaluated_table, &[], &meta_stmts).unwrap();
+
+        let kind = |region: &str| {
+            evaluated_table.regions[region]
+                .metadata
+                .get("kind")
+                .map(|assignment| assignment.value.clone())
+        };
+        assert_eq!(kind("cpu.core.alu"), Some(json!("alu")));
+        assert_eq!(kind("cpu.cache.alu"), Some(json!("alu")));
+        assert_eq!(kind("cpu.core.fpu"), None);
+        assert_eq!(kind("cpu.core1"), Some(json!("core")));
+
+        // The wildcard entries hold their own value, not one inherited from themselves
+        assert_eq!(evaluated_table.regions["cpu.*.alu"].boxes, None);
+        assert_eq!(
+            evaluated_table.regions["cpu.*.alu"].metadata["kind"].via,
+            None
+        );
+        assert_eq!(
+            evaluated_table.regions["cpu.core?"].metadata["kind"].via,
+            None
+        );
+    }
+
     #[test]
     fn test_current_region_metadata() {
         let mut evaluated_table = EvaluatedRegionTable::new();
@@ -329,6 +1105,7 @@ mod tests {
             MetadataStatement::Current {
                 key: "label".to_string(),
                 value: json!("Test Label"),
+                default: false,
             },
         )];
 
@@ -352,6 +1129,7 @@ mod tests {
                 target: "new_region".to_string(),
                 key: "type".to_string(),
                 value: json!("special"),
+                default: false,
             },
         )];
 
@@ -388,6 +1166,7 @@ mod tests {
                 MetadataStatement::Current {
                     key: "label".to_string(),
                     value: json!("First"),
+                    default: false,
                 },
             ),
             make_meta_stmt(
@@ -396,6 +1175,7 @@ mod tests {
                 MetadataStatement::Current {
                     key: "label".to_string(),
                     value: json!("Second"), // Conflict!
+                    default: false,
                 },
             ),
         ];
@@ -414,6 +1194,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_collect_keeps_applying_past_conflicts() {
+        let mut evaluated_table = EvaluatedRegionTable::new();
+        let geom_stmts = [make_geom_stmt(
+            0,
+            0,
+            make_rc(Some("test".to_string()), ([0, 0, 0], [1, 1, 1])),
+        )
+        .widen()];
+        let assign = |stmt_idx, key: &str, value| {
+            make_meta_stmt(
+                1,
+                stmt_idx,
+                MetadataStatement::Targeted {
+                    target: "test".to_string(),
+                    key: key.to_string(),
+                    value,
+                    default: false,
+                },
+            )
+        };
+        let meta_stmts = vec![
+            assign(0, "label", json!("First")),
+            assign(1, "tier", json!(1)),
+            assign(2, "label", json!("Second")),
+            assign(3, "power", json!("low")),
+            assign(4, "tier", json!(2)),
+        ];
+
+        let errors = apply_metadata_pass_collect(
+            &mut evaluated_table,
+            &geom_stmts,
+            &meta_stmts,
+            &CompileOptions::default(),
+        );
+
+        let conflicts: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                ParseError::MetadataConflict(err) => (err.key.as_str(), err.conflict_value.clone()),
+                other => panic!("Expected MetadataConflict, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            conflicts,
+            vec![("label", json!("Second")), ("tier", json!(2))]
+        );
+        // Keys set after a conflict still land, and the first values stay
+        let metadata = &evaluated_table.regions["test"].metadata;
+        assert_eq!(metadata["power"].value, json!("low"));
+        assert_eq!(metadata["label"].value, json!("First"));
+        assert_eq!(metadata["tier"].value, json!(1));
+    }
+
     #[test]
     fn test_identical_duplicate_allowed() {
         let mut evaluated_table = EvaluatedRegionTable::new();
@@ -438,6 +1272,7 @@ mod tests {
                 MetadataStatement::Current {
                     key: "label".to_string(),
                     value: json!("Same"),
+                    default: false,
                 },
             ),
             make_meta_stmt(
@@ -446,6 +1281,7 @@ mod tests {
                 MetadataStatement::Current {
                     key: "label".to_string(),
                     value: json!("Same"), // Identical - should be OK
+                    default: false,
                 },
             ),
         ];
@@ -478,6 +1314,7 @@ mod tests {
                 target: "cpu.*".to_string(),
                 key: "power".to_string(),
                 value: json!("low"),
+                default: false,
             },
         )];
 
@@ -505,6 +1342,57 @@ mod tests {
             .contains_key("power"));
     }
 
+    #[test]
+    fn test_delete_inherited_metadata() {
+        let mut evaluated_table = EvaluatedRegionTable::new();
+        for region in ["cpu.core", "cpu.cache"] {
+            evaluated_table
+                .set_region_boxes(region.to_string(), Some(vec![([0, 0, 0], [1, 1, 1])]));
+        }
+
+        let targeted = |target: &str, key: &str| MetadataStatement::Targeted {
+            target: target.to_string(),
+            key: key.to_string(),
+            value: json!("low"),
+            default: false,
+        };
+        let delete = |target: &str, key: &str| MetadataStatement::Delete {
+            target: target.to_string(),
+            key: key.to_string(),
+        };
+        // The deletion comes first but still applies after the wildcard set
+        let meta_stmts = vec![
+            make_meta_stmt(0, 0, delete("cpu.core", "power")),
+            make_meta_stmt(0, 1, targeted("cpu.*", "power")),
+            make_meta_stmt(0, 2, delete("cpu.core", "missing")),
+            make_meta_stmt(0, 3, delete("gpu", "power")),
+        ];
+
+        apply_metadata_pass(&mut evaluated_table, &[], &meta_stmts).unwrap();
+
+        assert!(!evaluated_table.regions["cpu.core"]
+            .metadata
+            .contains_key("power"));
+        assert_eq!(
+            evaluated_table.regions["cpu.cache"].metadata["power"].value,
+            json!("low")
+        );
+        assert_eq!(
+            evaluated_table.regions["cpu.*"].metadata["power"].value,
+            json!("low")
+        );
+        // Deleting from a missing region does not create it
+        assert!(!evaluated_table.regions.contains_key("gpu"));
+
+        // A wildcard deletion clears the pattern's entry and all matches
+        let meta_stmts = vec![make_meta_stmt(1, 0, delete("cpu.*", "power"))];
+        apply_metadata_pass(&mut evaluated_table, &[], &meta_stmts).unwrap();
+        assert!(evaluated_table
+            .regions
+            .values()
+            .all(|data| !data.metadata.contains_key("power")));
+    }
+
     #[test]
     fn test_global_metadata() {
         let mut evaluated_table = EvaluatedRegionTable::new();
@@ -518,6 +1406,7 @@ mod tests {
                 target: "$global".to_string(),
                 key: "version".to_string(),
                 value: json!("1.0"),
+                default: false,
             },
         )];
 
@@ -547,6 +1436,7 @@ mod tests {
                 MetadataAssignment {
                     value: json!("labeled"),
                     source: SourceLocation::new(0, 2),
+                    via: None,
                 },
             )
             .unwrap();
@@ -574,6 +1464,7 @@ mod tests {
             MetadataStatement::Current {
                 key: "label".to_string(),
                 value: json!("orphan"),
+                default: false,
             },
         )];
 
@@ -588,4 +1479,258 @@ mod tests {
             _ => panic!("Expected NoCurrentRegion error"),
         }
     }
+
+    #[test]
+    fn test_default_metadata_applies_only_when_absent() {
+        let mut evaluated_table = EvaluatedRegionTable::new();
+        for region in ["cpu.core", "cpu.cache", "cpu.alu"] {
+            evaluated_table
+                .set_region_boxes(region.to_string(), Some(vec![([0, 0, 0], [1, 1, 1])]));
+        }
+
+        let targeted = |target: &str, value, default| MetadataStatement::Targeted {
+            target: target.to_string(),
+            key: "power".to_string(),
+            value,
+            default,
+        };
+        // The group default comes first, but specific assignments still win
+        let meta_stmts = vec![
+            make_meta_stmt(0, 0, targeted("cpu.*", json!("low"), true)),
+            make_meta_stmt(0, 1, targeted("cpu.core", json!("high"), false)),
+            make_meta_stmt(0, 2, targeted("cpu.cache", json!("mid"), true)),
+        ];
+
+        apply_metadata_pass(&mut evaluated_table, &[], &meta_stmts).unwrap();
+
+        let power = |region: &str| &evaluated_table.regions[region].metadata["power"];
+        assert_eq!(power("cpu.core").value, json!("high"));
+        assert_eq!(power("cpu.core").via, None);
+        assert_eq!(power("cpu.cache").value, json!("mid"));
+        assert_eq!(power("cpu.alu").value, json!("low"));
+        assert_eq!(power("cpu.alu").via, Some("cpu.*".to_string()));
+        assert_eq!(power("cpu.*").value, json!("low"));
+    }
+
+    #[test]
+    fn test_add_volume_metadata() {
+        let options = CompileOptions {
+            compute_volume: true,
+            ..Default::default()
+        };
+        let volume_of = |boxes: Option<Vec<BoxPair64>>| {
+            let mut entry = DslEntry {
+                bounding_boxes: boxes,
+                box_labels: None,
+                metadata: Metadata::new(),
+            };
+            add_volume_metadata(&mut entry, &options);
+            entry.metadata
+        };
+
+        assert_eq!(
+            volume_of(Some(vec![([0, 0, 0], [3, 2, 1])])),
+            Metadata::from([(VOLUME_KEY.to_string(), json!(24))])
+        );
+        // Overlapping boxes of a union count twice
+        assert_eq!(
+            volume_of(Some(vec![([0, 0, 0], [1, 1, 1]), ([1, 1, 1], [2, 2, 2])]))[VOLUME_KEY],
+            json!(16)
+        );
+        assert_eq!(volume_of(Some(vec![]))[VOLUME_KEY], json!(0));
+        assert!(volume_of(None).is_empty());
+
+        let huge = volume_of(Some(vec![([0, 0, 0], [i64::MAX - 1, 1, 1])]));
+        assert_eq!(huge[VOLUME_KEY], json!(i64::MAX));
+        assert_eq!(huge[VOLUME_OVERFLOW_KEY], json!(true));
+
+        let mut entry = DslEntry {
+            bounding_boxes: Some(vec![([0, 0, 0], [1, 1, 1])]),
+            box_labels: None,
+            metadata: Metadata::new(),
+        };
+        add_volume_metadata(&mut entry, &CompileOptions::default());
+        assert!(entry.metadata.is_empty());
+    }
+
+    #[test]
+    fn test_metadata_merge_policies() {
+        let merge = |first: Value, second: Value, policy| {
+            let mut table = EvaluatedRegionTable::new();
+            for (stmt_idx, value) in [first, second].into_iter().enumerate() {
+                let assignment = MetadataAssignment {
+                    value,
+                    source: SourceLocation::new(stmt_idx, 0),
+                    via: None,
+                };
+                table.add_metadata_with_policy(
+                    "r".to_string(),
+                    "k".to_string(),
+                    assignment,
+                    policy,
+                )?;
+            }
+            let assignment = table.regions["r"].metadata["k"].clone();
+            Ok::<_, ParseError>((assignment.value, assignment.source))
+        };
+
+        // Conflict is the default and rejects any difference
+        assert_eq!(
+            MetadataMergePolicy::default(),
+            MetadataMergePolicy::Conflict
+        );
+        assert!(matches!(
+            merge(json!(["a"]), json!(["b"]), MetadataMergePolicy::Conflict),
+            Err(ParseError::MetadataConflict(_))
+        ));
+
+        let (value, source) = merge(json!(1), json!("two"), MetadataMergePolicy::LastWins).unwrap();
+        assert_eq!(value, json!("two"));
+        // The winning statement is recorded as the source
+        assert_eq!(source, SourceLocation::new(1, 0));
+
+        let arrays = MetadataMergePolicy::MergeArrays;
+        let (value, _) = merge(json!(["a", "b"]), json!(["c", "a", "d"]), arrays).unwrap();
+        assert_eq!(value, json!(["a", "b", "c", "d"]));
+        // Duplicates within the first array are dropped too
+        let (value, _) = merge(json!(["a", "a", "b"]), json!(["b", "c"]), arrays).unwrap();
+        assert_eq!(value, json!(["a", "b", "c"]));
+        assert!(merge(json!(["a"]), json!("b"), arrays).is_err());
+        assert!(merge(json!({"x": 1}), json!({"y": 2}), arrays).is_err());
+
+        let objects = MetadataMergePolicy::DeepMergeObjects;
+        let (value, _) = merge(
+            json!({"io": {"in": 1}, "name": "cpu"}),
+            json!({"io": {"out": 2}, "name": "cpu", "tier": 3}),
+            objects,
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({"io": {"in": 1, "out": 2}, "name": "cpu", "tier": 3})
+        );
+        // Scalar against object, at the top or nested, is a conflict
+        assert!(merge(json!({"io": 1}), json!({"io": {"out": 2}}), objects).is_err());
+        assert!(merge(json!(1), json!({"io": 1}), objects).is_err());
+        assert!(merge(json!({"n": 1}), json!({"n": 2}), objects).is_err());
+    }
+
+    /// Helper to build a table from `(region, key, value)` assignments
+    fn metadata_table(entries: &[(&str, &str, Value)]) -> EvaluatedRegionTable {
+        let mut table = EvaluatedRegionTable::new();
+        for (stmt_idx, (region, key, value)) in entries.iter().enumerate() {
+            let assignment = MetadataAssignment {
+                value: value.clone(),
+                source: SourceLocation::new(0, stmt_idx),
+                via: None,
+            };
+            table
+                .add_metadata(region.to_string(), key.to_string(), assignment)
+                .unwrap();
+        }
+        table
+    }
+
+    #[test]
+    fn test_interpolate_metadata() {
+        let mut table = metadata_table(&[
+            ("cpu.core", "logic.clock_hz", json!(4)),
+            ("cpu.core", "name", json!("core")),
+            (
+                "cpu.core",
+                "summary",
+                json!("${cpu.core:name} at ${cpu.core:logic.clock_hz} Hz"),
+            ),
+            ("cpu", "doc", json!("see: ${cpu.core:summary}")),
+            ("cpu", "literal", json!("${no_colon} and ${unterminated")),
+            ("cpu", "count", json!(3)),
+        ]);
+        interpolate_metadata(&mut table).unwrap();
+
+        let value = |region: &str, key: &str| table.regions[region].metadata[key].value.clone();
+        assert_eq!(value("cpu.core", "summary"), json!("core at 4 Hz"));
+        assert_eq!(value("cpu", "doc"), json!("see: core at 4 Hz"));
+        assert_eq!(
+            value("cpu", "literal"),
+            json!("${no_colon} and ${unterminated")
+        );
+        assert_eq!(value("cpu", "count"), json!(3));
+    }
+
+    #[test]
+    fn test_interpolate_metadata_errors() {
+        let mut cyclic =
+            metadata_table(&[("a", "x", json!("${b:y}")), ("b", "y", json!("<${a:x}>"))]);
+        match interpolate_metadata(&mut cyclic) {
+            Err(ParseError::MetadataInterpolationCycle { chain }) => {
+                assert_eq!(chain, vec!["a:x", "b:y", "a:x"]);
+            }
+            other => panic!("Expected MetadataInterpolationCycle, got {:?}", other),
+        }
+
+        let mut unknown = metadata_table(&[("a", "x", json!(1)), ("a", "y", json!("${a:z}"))]);
+        match interpolate_metadata(&mut unknown) {
+            Err(ParseError::UnknownInterpolationReference {
+                region,
+                key,
+                reference,
+                source,
+            }) => {
+                assert_eq!((region.as_str(), key.as_str()), ("a", "y"));
+                assert_eq!(reference, "a:z");
+                assert_eq!(source, SourceLocation::new(0, 1));
+            }
+            other => panic!("Expected UnknownInterpolationReference, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_interpolate_metadata_long_chain() {
+        // Each value refers to the next; resolving must not recurse per link
+        let links: Vec<(String, Value)> = (0..100_000)
+            .map(|i| (format!("k{}", i), json!(format!("${{a:k{}}}", i + 1))))
+            .chain([("k100000".to_string(), json!("end"))])
+            .collect();
+        let entries: Vec<(&str, &str, Value)> = links
+            .iter()
+            .map(|(key, value)| ("a", key.as_str(), value.clone()))
+            .collect();
+        let mut table = metadata_table(&entries);
+        interpolate_metadata(&mut table).unwrap();
+        assert_eq!(table.regions["a"].metadata["k0"].value, json!("end"));
+    }
+
+    #[test]
+    fn test_interpolate_metadata_budget() {
+        // Each value holds the next one twice, doubling with every link
+        let links: Vec<(String, Value)> = (0..64)
+            .map(|i| {
+                (
+                    format!("k{}", i),
+                    json!(format!("${{a:k{0}}}${{a:k{0}}}", i + 1)),
+                )
+            })
+            .chain([("k64".to_string(), json!("x"))])
+            .collect();
+        let entries: Vec<(&str, &str, Value)> = links
+            .iter()
+            .map(|(key, value)| ("a", key.as_str(), value.clone()))
+            .collect();
+        let mut table = metadata_table(&entries);
+        let mut budget = EvalBudget::new(1_000_000);
+        assert!(matches!(
+            interpolate_metadata_budgeted(&mut table, &mut budget),
+            Err(ParseError::EvaluationBudgetExceeded { limit: 1_000_000 })
+        ));
+
+        // A budget covering every byte written is enough
+        let mut table = metadata_table(&entries[54..]);
+        let mut budget = EvalBudget::new(2 * 1024);
+        interpolate_metadata_budgeted(&mut table, &mut budget).unwrap();
+        assert_eq!(
+            table.regions["a"].metadata["k54"].value,
+            json!("x".repeat(1024))
+        );
+        assert_eq!(budget.used(), 2046);
+    }
 }
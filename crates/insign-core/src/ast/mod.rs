@@ -1,3 +1,4 @@
+pub mod alias;
 pub mod eval;
 pub mod expr;
 pub mod geom;
@@ -6,6 +7,7 @@ pub mod meta;
 pub mod metadata;
 pub mod output;
 
+pub use alias::*;
 pub use eval::*;
 pub use expr::*;
 pub use geom::*;
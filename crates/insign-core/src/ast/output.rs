@@ -1,12 +1,18 @@
-use crate::{DslEntry, DslMap};
-use std::collections::BTreeMap;
+use crate::ast::eval::compute_difference;
+use crate::ast::EvaluatedRegionTable;
+use crate::boxutil;
+use crate::{
+    BoxPair, BoxPair64, CompileOptions, Coord, DslEntry, DslMap, DslMap64, OrderedDslMap,
+    OutputOrdering, ParseError,
+};
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Categories for region key ordering
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 enum RegionCategory {
     /// $global comes first
     Global,
-    /// Wildcard patterns (contain '*')
+    /// Wildcard patterns (contain '*' or '?')
     Wildcard(String),
     /// Regular named regions
     Region(String),
@@ -19,7 +25,7 @@ impl RegionCategory {
     fn from_region_name(name: &str) -> Self {
         if name == "$global" {
             RegionCategory::Global
-        } else if name.contains('*') {
+        } else if crate::ast::metadata::is_wildcard_pattern(name) {
             RegionCategory::Wildcard(name.to_string())
         } else if name.starts_with("__anon_") {
             RegionCategory::Anonymous(name.to_string())
@@ -50,9 +56,11 @@ fn compare_region_keys(a: &str, b: &str) -> std::cmp::Ordering {
 ///
 /// Order: $global first, then wildcards (lexicographic), then regions (lexicographic), then anonymous (lexicographic)
 /// Anonymous regions are included only if they have metadata
-pub fn apply_deterministic_ordering(dsl_map: BTreeMap<String, DslEntry>) -> DslMap {
+pub fn apply_deterministic_ordering<B>(
+    dsl_map: BTreeMap<String, DslEntry<B>>,
+) -> BTreeMap<String, DslEntry<B>> {
     // Extract entries and sort them with our custom comparison
-    let mut entries: Vec<(String, DslEntry)> = dsl_map.into_iter().collect();
+    let mut entries: Vec<(String, DslEntry<B>)> = dsl_map.into_iter().collect();
     entries.sort_by(|a, b| compare_region_keys(&a.0, &b.0));
 
     // Build the final ordered map
@@ -71,29 +79,622 @@ pub fn apply_deterministic_ordering(dsl_map: BTreeMap<String, DslEntry>) -> DslM
     ordered_map
 }
 
+/// Number of blocks in an inclusive box
+pub fn box_volume(box_pair: BoxPair) -> u128 {
+    boxutil::volume(box_pair)
+}
+
+/// Arrange a compiled map into an [`OrderedDslMap`] following `ordering`.
+///
+/// Volume is the sum of each entry's box volumes (overlaps count twice);
+/// entries without boxes count as zero. Ties are broken by name.
+pub fn order_dsl_map(dsl_map: DslMap, ordering: OutputOrdering) -> OrderedDslMap {
+    let mut entries: Vec<(String, DslEntry)> = dsl_map.into_iter().collect();
+    match ordering {
+        // A DslMap is already sorted by name
+        OutputOrdering::Name => {}
+        OutputOrdering::Category => entries.sort_by(|a, b| compare_region_keys(&a.0, &b.0)),
+        OutputOrdering::VolumeDesc => {
+            let volume = |entry: &DslEntry| -> u128 {
+                entry
+                    .bounding_boxes
+                    .iter()
+                    .flatten()
+                    .map(|&box_pair| box_volume(box_pair))
+                    .sum()
+            };
+            entries.sort_by(|a, b| volume(&b.1).cmp(&volume(&a.1)).then_with(|| a.0.cmp(&b.0)));
+        }
+        OutputOrdering::BoxCountDesc => {
+            let count = |entry: &DslEntry| entry.bounding_boxes.as_ref().map_or(0, Vec::len);
+            entries.sort_by(|a, b| count(&b.1).cmp(&count(&a.1)).then_with(|| a.0.cmp(&b.0)));
+        }
+    }
+    OrderedDslMap::from_entries(entries)
+}
+
 /// Filter and shape a DslMap for final output
 ///
 /// - Anonymous regions without metadata are excluded
 /// - Keys are ordered deterministically
-pub fn shape_final_output(dsl_map: BTreeMap<String, DslEntry>) -> DslMap {
+pub fn shape_final_output<B>(
+    dsl_map: BTreeMap<String, DslEntry<B>>,
+) -> BTreeMap<String, DslEntry<B>> {
+    shape_final_output_with_options(dsl_map, &CompileOptions::default())
+}
+
+/// Filter and shape a DslMap for final output, honoring `options`
+///
+/// With `keep_empty_anonymous`, anonymous regions without metadata are kept.
+pub fn shape_final_output_with_options<B>(
+    dsl_map: BTreeMap<String, DslEntry<B>>,
+    options: &CompileOptions,
+) -> BTreeMap<String, DslEntry<B>> {
     // First filter out anonymous regions without metadata
-    let filtered_map: BTreeMap<String, DslEntry> = dsl_map
+    let filtered_map: BTreeMap<String, DslEntry<B>> = dsl_map
         .into_iter()
-        .filter(|(key, entry)| {
-            // Keep all non-anonymous regions
-            if !key.starts_with("__anon_") {
-                return true;
-            }
-
-            // Keep anonymous regions only if they have metadata
-            !entry.metadata.is_empty()
-        })
+        .filter(|(key, entry)| is_output_region(key, !entry.metadata.is_empty(), options))
         .collect();
 
     // Apply deterministic ordering
     apply_deterministic_ordering(filtered_map)
 }
 
+/// Narrow an i64 map to the i32 [`DslMap`], failing on the first out-of-range coordinate
+pub fn narrow_dsl_map(dsl_map: DslMap64) -> Result<DslMap, ParseError> {
+    dsl_map
+        .into_iter()
+        .map(|(region_name, entry)| {
+            let bounding_boxes = match entry.bounding_boxes {
+                Some(boxes) => Some(
+                    boxes
+                        .into_iter()
+                        .map(|(min, max)| Some((narrow_vec3(min)?, narrow_vec3(max)?)))
+                        .collect::<Option<Vec<BoxPair>>>()
+                        .ok_or_else(|| narrowing_error(&region_name))?,
+                ),
+                None => None,
+            };
+            let entry = DslEntry {
+                bounding_boxes,
+                box_labels: entry.box_labels,
+                metadata: entry.metadata,
+            };
+            Ok((region_name, entry))
+        })
+        .collect()
+}
+
+/// Whether the output keeps region `key`: anonymous regions only with metadata,
+/// unless `keep_empty_anonymous` is set
+fn is_output_region(key: &str, has_metadata: bool, options: &CompileOptions) -> bool {
+    !key.starts_with("__anon_") || options.keep_empty_anonymous || has_metadata
+}
+
+/// Check that every output region of `table` fits the i32 range, failing as
+/// [`narrow_dsl_map`] would without building the output map
+pub fn check_narrowable(
+    table: &EvaluatedRegionTable,
+    options: &CompileOptions,
+) -> Result<(), ParseError> {
+    for (region_name, data) in &table.regions {
+        if !is_output_region(region_name, !data.metadata.is_empty(), options) {
+            continue;
+        }
+        let fits = data
+            .boxes
+            .iter()
+            .flatten()
+            .all(|&(min, max)| narrow_vec3(min).is_some() && narrow_vec3(max).is_some());
+        if !fits {
+            return Err(narrowing_error(region_name));
+        }
+    }
+    Ok(())
+}
+
+/// The error for a region whose boxes do not fit the i32 range
+fn narrowing_error(region_name: &str) -> ParseError {
+    ParseError::Internal {
+        message: format!(
+            "Coordinate overflow: region '{}' exceeds the i32 range (use compile_wide)",
+            region_name
+        ),
+        position: 0,
+    }
+}
+
+/// Convert an i64 corner to [`Coord`], or None if any axis is out of range
+pub(crate) fn narrow_vec3(corner: [i64; 3]) -> Option<[Coord; 3]> {
+    Some([
+        Coord::try_from(corner[0]).ok()?,
+        Coord::try_from(corner[1]).ok()?,
+        Coord::try_from(corner[2]).ok()?,
+    ])
+}
+
+/// The parts of `within` not covered by any of `entry`'s boxes, as disjoint boxes.
+///
+/// A boxless entry leaves `within` whole.
+pub fn complement(entry: &DslEntry, within: BoxPair) -> Vec<BoxPair> {
+    let widen = |(min, max): BoxPair| -> BoxPair64 { (min.map(i64::from), max.map(i64::from)) };
+    let covered: Vec<BoxPair64> = entry
+        .bounding_boxes
+        .iter()
+        .flatten()
+        .map(|&box_pair| widen(box_pair))
+        .collect();
+
+    // Every piece lies inside `within`, so narrowing back to i32 is lossless
+    compute_difference(&[widen(within)], &covered)
+        .into_iter()
+        .filter_map(|(min, max)| Some((narrow_vec3(min)?, narrow_vec3(max)?)))
+        .collect()
+}
+
+/// Split a compiled map by the tuples that contributed to each entry.
+///
+/// `contributions` maps region names to contributing tuple indices. Every index in
+/// `0..tuple_count` gets a map, possibly empty; entries are cloned into each of
+/// their contributing tuples.
+pub fn group_by_tuple(
+    dsl_map: DslMap,
+    contributions: &BTreeMap<String, BTreeSet<usize>>,
+    tuple_count: usize,
+) -> BTreeMap<usize, DslMap> {
+    let mut grouped: BTreeMap<usize, DslMap> =
+        (0..tuple_count).map(|idx| (idx, DslMap::new())).collect();
+
+    for (region_name, entry) in dsl_map {
+        let Some(tuples) = contributions.get(&region_name) else {
+            continue;
+        };
+        for tuple_idx in tuples {
+            if let Some(tuple_map) = grouped.get_mut(tuple_idx) {
+                tuple_map.insert(region_name.clone(), entry.clone());
+            }
+        }
+    }
+
+    grouped
+}
+
+/// Maximum number of boxes stored in a single BVH leaf
+const BVH_LEAF_SIZE: usize = 4;
+
+/// Spatial index over the bounding boxes of a compiled `DslMap`.
+///
+/// Built as a bounding-volume hierarchy (median split on the longest axis), so
+/// point and box queries visit only the subtrees whose bounds they touch.
+/// Entries without boxes (like `$global` and wildcards) are not indexed.
+#[derive(Debug, Clone)]
+pub struct RegionIndex {
+    /// Region names in the map's key order
+    names: Vec<String>,
+    /// Indexed boxes paired with the index of their region in `names`
+    items: Vec<(BoxPair, usize)>,
+    /// BVH nodes; the root is `nodes[0]` when the index is non-empty
+    nodes: Vec<BvhNode>,
+}
+
+/// A node of the bounding-volume hierarchy
+#[derive(Debug, Clone)]
+struct BvhNode {
+    /// Bounds enclosing every box below this node
+    bounds: BoxPair,
+    kind: BvhNodeKind,
+}
+
+#[derive(Debug, Clone)]
+enum BvhNodeKind {
+    /// Range of `items` stored directly in this node
+    Leaf { start: usize, end: usize },
+    /// Indices of the two child nodes
+    Branch { left: usize, right: usize },
+}
+
+impl RegionIndex {
+    /// Regions with at least one box containing `point` (bounds are inclusive).
+    /// Results follow the map's key order.
+    pub fn regions_at(&self, point: [Coord; 3]) -> Vec<&str> {
+        self.query(|bounds| boxutil::contains_point(bounds, point))
+    }
+
+    /// Regions with at least one box overlapping `query` (touching counts).
+    /// Results follow the map's key order.
+    pub fn regions_in_box(&self, query: BoxPair) -> Vec<&str> {
+        self.query(|bounds| boxutil::intersects(bounds, query))
+    }
+
+    /// Traverse the hierarchy, descending only into nodes whose bounds match
+    fn query(&self, matches: impl Fn(BoxPair) -> bool) -> Vec<&str> {
+        let mut hits = BTreeSet::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(node_idx) = stack.pop() {
+            let node = &self.nodes[node_idx];
+            if !matches(node.bounds) {
+                continue;
+            }
+            match node.kind {
+                BvhNodeKind::Leaf { start, end } => {
+                    for (box_pair, region_idx) in &self.items[start..end] {
+                        if matches(*box_pair) {
+                            hits.insert(*region_idx);
+                        }
+                    }
+                }
+                BvhNodeKind::Branch { left, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        hits.into_iter()
+            .map(|idx| self.names[idx].as_str())
+            .collect()
+    }
+
+    /// Recursively build the node covering `items[start..end]`, returning its index
+    fn build_node(&mut self, start: usize, end: usize) -> usize {
+        let bounds = enclosing_box(self.items[start..end].iter().map(|(b, _)| *b));
+        let node_idx = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            kind: BvhNodeKind::Leaf { start, end },
+        });
+
+        if end - start <= BVH_LEAF_SIZE {
+            return node_idx;
+        }
+
+        // Split at the median box center along the longest axis
+        let axis = (0..3)
+            .max_by_key(|&axis| i128::from(bounds.1[axis]) - i128::from(bounds.0[axis]))
+            .unwrap_or(0);
+        self.items[start..end].sort_by_key(|((min, max), _)| {
+            (i128::from(min[axis]) + i128::from(max[axis]), *min, *max)
+        });
+        let mid = start + (end - start) / 2;
+
+        let left = self.build_node(start, mid);
+        let right = self.build_node(mid, end);
+        self.nodes[node_idx].kind = BvhNodeKind::Branch { left, right };
+        node_idx
+    }
+}
+
+/// Build a spatial index over all region boxes in `dsl_map`
+pub fn build_index(dsl_map: &DslMap) -> RegionIndex {
+    let mut index = RegionIndex {
+        names: Vec::new(),
+        items: Vec::new(),
+        nodes: Vec::new(),
+    };
+
+    for (name, entry) in dsl_map {
+        let Some(boxes) = &entry.bounding_boxes else {
+            continue;
+        };
+        if boxes.is_empty() {
+            continue;
+        }
+        let region_idx = index.names.len();
+        index.names.push(name.clone());
+        index
+            .items
+            .extend(boxes.iter().map(|box_pair| (*box_pair, region_idx)));
+    }
+
+    if !index.items.is_empty() {
+        index.build_node(0, index.items.len());
+    }
+
+    index
+}
+
+/// Which kinds of contact between boxes count as adjacency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdjacencyMode {
+    /// Boxes must share a face: touch on one axis and overlap on the other two
+    #[default]
+    FacesOnly,
+    /// Also count boxes that touch only along an edge or at a corner
+    IncludeDiagonal,
+}
+
+/// Face adjacency between regions; see [`adjacency_with_mode`]
+pub fn adjacency(dsl_map: &DslMap) -> BTreeMap<String, Vec<String>> {
+    adjacency_with_mode(dsl_map, AdjacencyMode::FacesOnly)
+}
+
+/// Report, for every region with boxes, the other regions it touches.
+///
+/// Boxes are inclusive block ranges, so `[0..1]` and `[2..3]` touch on that axis.
+/// Overlapping boxes are intersecting, not adjacent. Neighbor lists follow the
+/// map's key order. Candidates come from a [`RegionIndex`], so the cost is
+/// O(n log n) to build plus roughly O(log n + k) per box for k nearby boxes,
+/// rather than O(n²) over all box pairs.
+pub fn adjacency_with_mode(dsl_map: &DslMap, mode: AdjacencyMode) -> BTreeMap<String, Vec<String>> {
+    let index = build_index(dsl_map);
+    let mut report = BTreeMap::new();
+
+    for (name, entry) in dsl_map {
+        let Some(boxes) = entry.bounding_boxes.as_ref().filter(|b| !b.is_empty()) else {
+            continue;
+        };
+
+        let mut neighbors = BTreeSet::new();
+        for &box_pair in boxes {
+            for candidate in index.regions_in_box(expand_box(box_pair)) {
+                if candidate == name || neighbors.contains(candidate) {
+                    continue;
+                }
+                let mut candidate_boxes = dsl_map[candidate].bounding_boxes.iter().flatten();
+                if candidate_boxes.any(|&other| boxes_adjacent(box_pair, other, mode)) {
+                    neighbors.insert(candidate);
+                }
+            }
+        }
+
+        report.insert(
+            name.clone(),
+            neighbors.into_iter().map(str::to_string).collect(),
+        );
+    }
+
+    report
+}
+
+/// Index the regions of `dsl_map` by metadata: key, then value, then the
+/// regions with that value, e.g. `power` → `"low"` → `["cpu.*", "cpu.cache"]`.
+///
+/// JSON values are not `Ord`, so values are keyed by their canonical JSON text
+/// (`"\"low\""` for the string `low`, `4` for the number). Every level follows
+/// key order, and entries without metadata do not appear.
+pub fn index_by_metadata(dsl_map: &DslMap) -> BTreeMap<String, BTreeMap<String, Vec<String>>> {
+    let mut index: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for (name, entry) in dsl_map {
+        for (key, value) in &entry.metadata {
+            index
+                .entry(key.clone())
+                .or_default()
+                .entry(value.to_string())
+                .or_default()
+                .push(name.clone());
+        }
+    }
+    index
+}
+
+/// How per-axis gaps combine into a distance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Sum of the gaps on all three axes
+    Manhattan,
+    /// Largest gap on any axis
+    Chebyshev,
+}
+
+/// Minimum gap, in empty blocks, between any box of `a` and any box of `b`.
+///
+/// Boxes are inclusive block ranges, so overlapping or touching boxes are at
+/// distance 0 and `[0..1]` to `[4..5]` is 2. Returns `None` when either entry
+/// has no boxes. Every pair of boxes is compared: O(boxes of a × boxes of b).
+pub fn region_distance(a: &DslEntry, b: &DslEntry, metric: DistanceMetric) -> Option<i64> {
+    let boxes_a = a.bounding_boxes.as_deref()?;
+    let boxes_b = b.bounding_boxes.as_deref()?;
+
+    boxes_a
+        .iter()
+        .flat_map(|&box_a| {
+            boxes_b
+                .iter()
+                .map(move |&box_b| box_distance(box_a, box_b, metric))
+        })
+        .min()
+}
+
+/// Gap between two inclusive boxes under `metric`, saturating at `i64::MAX`
+fn box_distance((min1, max1): BoxPair, (min2, max2): BoxPair, metric: DistanceMetric) -> i64 {
+    let gaps = (0..3).map(|axis| {
+        let before = i128::from(min2[axis]) - i128::from(max1[axis]) - 1;
+        let after = i128::from(min1[axis]) - i128::from(max2[axis]) - 1;
+        before.max(after).max(0)
+    });
+
+    let distance: i128 = match metric {
+        DistanceMetric::Manhattan => gaps.sum(),
+        DistanceMetric::Chebyshev => gaps.max().unwrap_or(0),
+    };
+    i64::try_from(distance).unwrap_or(i64::MAX)
+}
+
+/// Translate `entry` so the min corner of its boxes lies at `[0,0,0]`.
+///
+/// Returns the original min corner, to place the copy back with, and the
+/// translated copy; metadata and box labels are kept as is. A boxless entry is
+/// returned unchanged with no origin. A box reaching more than [`Coord::MAX`]
+/// blocks past the origin on some axis cannot be translated, and fails with
+/// `ParseError::CoordinateLimitExceeded` naming `region`, the entry's ID.
+pub fn localize(
+    region: &str,
+    entry: &DslEntry,
+) -> Result<(Option<[Coord; 3]>, DslEntry), ParseError> {
+    let boxes = match entry.bounding_boxes.as_deref() {
+        Some(boxes) if !boxes.is_empty() => boxes,
+        _ => return Ok((None, entry.clone())),
+    };
+
+    let (origin, _) = enclosing_box(boxes.iter().copied());
+    let shift = |corner: [Coord; 3]| -> Result<[Coord; 3], ParseError> {
+        let mut shifted = [0; 3];
+        for axis in 0..3 {
+            // The offset always fits i128, but may not fit a coordinate
+            let offset = i128::from(corner[axis]) - i128::from(origin[axis]);
+            shifted[axis] =
+                Coord::try_from(offset).map_err(|_| ParseError::CoordinateLimitExceeded {
+                    region: region.to_string(),
+                    limit: Coord::MAX as u64,
+                })?;
+        }
+        Ok(shifted)
+    };
+
+    let localized = DslEntry {
+        bounding_boxes: Some(
+            boxes
+                .iter()
+                .map(|&(min, max)| Ok((shift(min)?, shift(max)?)))
+                .collect::<Result<_, ParseError>>()?,
+        ),
+        ..entry.clone()
+    };
+    Ok((Some(origin), localized))
+}
+
+/// First point where two compiled maps diverge; see [`semantic_diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SemanticDifference {
+    /// The region exists in only one map, the left one when `in_left`
+    Region { region: String, in_left: bool },
+    /// A metadata value differs; `None` means the key is absent on that side
+    Metadata {
+        region: String,
+        key: String,
+        left: Option<serde_json::Value>,
+        right: Option<serde_json::Value>,
+    },
+    /// `voxel` is covered on one side only, the left one when `in_left`
+    Geometry {
+        region: String,
+        voxel: [Coord; 3],
+        in_left: bool,
+    },
+}
+
+/// Check whether two compiled maps describe the same regions, metadata and
+/// covered blocks, regardless of how the blocks are split into boxes.
+pub fn semantically_equal(a: &DslMap, b: &DslMap) -> bool {
+    semantic_diff(a, b).is_none()
+}
+
+/// Report the first divergence between two compiled maps, or None if they are
+/// semantically equal.
+///
+/// Regions are visited by name and metadata by key. Geometry is compared by
+/// covered blocks: box order, splits and overlaps do not matter, and a boxless
+/// entry equals one with an empty box list. Box labels are not compared.
+pub fn semantic_diff(a: &DslMap, b: &DslMap) -> Option<SemanticDifference> {
+    let names: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    for name in names {
+        let (left, right) = match (a.get(name), b.get(name)) {
+            (Some(left), Some(right)) => (left, right),
+            (left, _) => {
+                return Some(SemanticDifference::Region {
+                    region: name.clone(),
+                    in_left: left.is_some(),
+                })
+            }
+        };
+
+        let keys: BTreeSet<&String> = left.metadata.keys().chain(right.metadata.keys()).collect();
+        for key in keys {
+            let (left_value, right_value) = (left.metadata.get(key), right.metadata.get(key));
+            if left_value != right_value {
+                return Some(SemanticDifference::Metadata {
+                    region: name.clone(),
+                    key: key.clone(),
+                    left: left_value.cloned(),
+                    right: right_value.cloned(),
+                });
+            }
+        }
+
+        if let Some((voxel, in_left)) = first_uncovered_voxel(left, right) {
+            return Some(SemanticDifference::Geometry {
+                region: name.clone(),
+                voxel,
+                in_left,
+            });
+        }
+    }
+    None
+}
+
+/// A block covered by only one of the entries, and whether it is the left one
+fn first_uncovered_voxel(left: &DslEntry, right: &DslEntry) -> Option<([Coord; 3], bool)> {
+    let widen = |entry: &DslEntry| -> Vec<BoxPair64> {
+        entry
+            .bounding_boxes
+            .iter()
+            .flatten()
+            .map(|&(min, max)| (min.map(i64::from), max.map(i64::from)))
+            .collect()
+    };
+    let (left, right) = (widen(left), widen(right));
+
+    // Difference pieces lie inside the original boxes, so their corners fit in Coord
+    for (from, subtract, in_left) in [(&left, &right, true), (&right, &left, false)] {
+        if let Some((min, _)) = compute_difference(from, subtract).into_iter().min() {
+            return Some((narrow_vec3(min)?, in_left));
+        }
+    }
+    None
+}
+
+/// Grow an inclusive box by one block on every side
+fn expand_box((min, max): BoxPair) -> BoxPair {
+    (
+        min.map(|c| c.saturating_sub(1)),
+        max.map(|c| c.saturating_add(1)),
+    )
+}
+
+/// Check whether two inclusive boxes touch without overlapping
+fn boxes_adjacent((min1, max1): BoxPair, (min2, max2): BoxPair, mode: AdjacencyMode) -> bool {
+    let mut touching_axes = 0;
+    for axis in 0..3 {
+        let (lo1, hi1) = (i128::from(min1[axis]), i128::from(max1[axis]));
+        let (lo2, hi2) = (i128::from(min2[axis]), i128::from(max2[axis]));
+        if hi1 + 1 == lo2 || hi2 + 1 == lo1 {
+            touching_axes += 1;
+        } else if lo1 > hi2 || lo2 > hi1 {
+            // Separated by a gap on this axis
+            return false;
+        }
+    }
+
+    match mode {
+        AdjacencyMode::FacesOnly => touching_axes == 1,
+        AdjacencyMode::IncludeDiagonal => touching_axes >= 1,
+    }
+}
+
+/// Smallest box enclosing all `boxes` (which must be non-empty)
+fn enclosing_box(boxes: impl Iterator<Item = BoxPair>) -> BoxPair {
+    boxes
+        .reduce(|(acc_min, acc_max), (min, max)| {
+            (
+                [
+                    acc_min[0].min(min[0]),
+                    acc_min[1].min(min[1]),
+                    acc_min[2].min(min[2]),
+                ],
+                [
+                    acc_max[0].max(max[0]),
+                    acc_max[1].max(max[1]),
+                    acc_max[2].max(max[2]),
+                ],
+            )
+        })
+        .expect("enclosing_box requires at least one box")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,17 +702,15 @@ mod tests {
     use std::collections::BTreeMap;
 
     /// Helper to create a DslEntry with optional metadata
-    fn make_entry(
-        boxes: Option<Vec<([i32; 3], [i32; 3])>>,
-        metadata: &[(&str, serde_json::Value)],
-    ) -> DslEntry {
-        let mut meta_map = BTreeMap::new();
+    fn make_entry(boxes: Option<Vec<BoxPair>>, metadata: &[(&str, serde_json::Value)]) -> DslEntry {
+        let mut meta_map = crate::Metadata::new();
         for (key, value) in metadata {
             meta_map.insert(key.to_string(), value.clone());
         }
 
         DslEntry {
             bounding_boxes: boxes,
+            box_labels: None,
             metadata: meta_map,
         }
     }
@@ -290,4 +889,401 @@ mod tests {
         // Verify __anon_0_0 was filtered out (no metadata)
         assert!(!shaped_map.contains_key("__anon_0_0"));
     }
+
+    /// Build a map of boxes-only regions for index tests
+    fn make_box_map(regions: &[(&str, Vec<BoxPair>)]) -> DslMap {
+        regions
+            .iter()
+            .map(|(name, boxes)| (name.to_string(), make_entry(Some(boxes.clone()), &[])))
+            .collect()
+    }
+
+    #[test]
+    fn test_index_point_queries() {
+        let mut dsl_map = make_box_map(&[
+            ("a", vec![([0, 0, 0], [3, 3, 3])]),
+            ("b", vec![([2, 2, 2], [5, 5, 5]), ([10, 0, 0], [10, 0, 0])]),
+        ]);
+        dsl_map.insert(
+            "$global".to_string(),
+            make_entry(None, &[("version", json!("1.0"))]),
+        );
+
+        let index = build_index(&dsl_map);
+
+        assert_eq!(index.regions_at([1, 1, 1]), vec!["a"]);
+        assert_eq!(index.regions_at([3, 3, 3]), vec!["a", "b"]); // inclusive corner
+        assert_eq!(index.regions_at([10, 0, 0]), vec!["b"]);
+        assert!(index.regions_at([6, 6, 6]).is_empty());
+    }
+
+    #[test]
+    fn test_index_box_queries() {
+        let dsl_map = make_box_map(&[
+            ("a", vec![([0, 0, 0], [3, 3, 3])]),
+            ("b", vec![([10, 10, 10], [12, 12, 12])]),
+        ]);
+        let index = build_index(&dsl_map);
+
+        assert_eq!(
+            index.regions_in_box(([3, 3, 3], [10, 10, 10])),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            index.regions_in_box(([4, 4, 4], [9, 9, 9])),
+            Vec::<&str>::new()
+        );
+        assert_eq!(index.regions_in_box(([11, 0, 11], [11, 20, 11])), vec!["b"]);
+    }
+
+    #[test]
+    fn test_adjacency_faces() {
+        let dsl_map = make_box_map(&[
+            ("cpu.cache", vec![([4, 0, 0], [5, 3, 3])]),
+            ("cpu.core", vec![([0, 0, 0], [3, 3, 3])]),
+            ("edge", vec![([4, 4, 0], [5, 5, 3])]),
+            ("far", vec![([20, 0, 0], [21, 1, 1])]),
+            ("inside", vec![([1, 1, 1], [2, 2, 2])]),
+        ]);
+
+        let report = adjacency(&dsl_map);
+
+        assert_eq!(report["cpu.core"], vec!["cpu.cache"]);
+        assert_eq!(report["cpu.cache"], vec!["cpu.core", "edge"]);
+        // Overlap is intersection, not adjacency
+        assert!(report["inside"].is_empty());
+        assert!(report["far"].is_empty());
+    }
+
+    #[test]
+    fn test_adjacency_diagonal_mode() {
+        let dsl_map = make_box_map(&[
+            ("a", vec![([0, 0, 0], [1, 1, 1])]),
+            ("corner", vec![([2, 2, 2], [3, 3, 3])]),
+            ("edge", vec![([2, 2, 0], [3, 3, 1])]),
+        ]);
+
+        let faces = adjacency(&dsl_map);
+        assert!(faces["a"].is_empty());
+
+        let diagonal = adjacency_with_mode(&dsl_map, AdjacencyMode::IncludeDiagonal);
+        assert_eq!(diagonal["a"], vec!["corner", "edge"]);
+        assert_eq!(diagonal["edge"], vec!["a", "corner"]);
+    }
+
+    #[test]
+    fn test_index_by_metadata() {
+        let units = vec![
+            (
+                [10, 64, 10],
+                "@rc([0,0,0],[3,2,1])\n#doc.label=\"Patch A\"".to_string(),
+            ),
+            ([0, 0, 0], "@cpu.cache=rc([0,0,0],[1,1,1])".to_string()),
+            ([0, 0, 0], "#cpu.*:power=\"low\"".to_string()),
+            ([0, 0, 0], "#$global:version=\"1.0\"".to_string()),
+        ];
+        let dsl_map = crate::compile(&units).unwrap();
+
+        let index = index_by_metadata(&dsl_map);
+
+        assert_eq!(
+            index.keys().collect::<Vec<_>>(),
+            vec!["doc.label", "power", "version"]
+        );
+        assert_eq!(
+            index["power"],
+            BTreeMap::from([(
+                "\"low\"".to_string(),
+                vec!["cpu.*".to_string(), "cpu.cache".to_string()]
+            )])
+        );
+        assert_eq!(index["doc.label"]["\"Patch A\""], vec!["__anon_0_0"]);
+        assert_eq!(index["version"]["\"1.0\""], vec!["$global"]);
+    }
+
+    #[test]
+    fn test_region_distance() {
+        let a = make_entry(Some(vec![([0, 0, 0], [1, 1, 1])]), &[]);
+        let b = make_entry(
+            Some(vec![([10, 10, 10], [11, 11, 11]), ([4, 0, 5], [5, 1, 6])]),
+            &[],
+        );
+
+        // Nearest box of b leaves gaps of 2 (x), 0 (y) and 3 (z)
+        assert_eq!(region_distance(&a, &b, DistanceMetric::Manhattan), Some(5));
+        assert_eq!(region_distance(&a, &b, DistanceMetric::Chebyshev), Some(3));
+        assert_eq!(region_distance(&b, &a, DistanceMetric::Manhattan), Some(5));
+
+        // Touching and overlapping regions are at distance 0
+        let touching = make_entry(Some(vec![([2, 2, 2], [3, 3, 3])]), &[]);
+        let overlapping = make_entry(Some(vec![([1, 1, 1], [2, 2, 2])]), &[]);
+        assert_eq!(
+            region_distance(&a, &touching, DistanceMetric::Manhattan),
+            Some(0)
+        );
+        assert_eq!(
+            region_distance(&a, &overlapping, DistanceMetric::Chebyshev),
+            Some(0)
+        );
+
+        let global = make_entry(None, &[("version", json!(1))]);
+        let empty = make_entry(Some(vec![]), &[]);
+        assert_eq!(
+            region_distance(&a, &global, DistanceMetric::Manhattan),
+            None
+        );
+        assert_eq!(region_distance(&empty, &a, DistanceMetric::Chebyshev), None);
+    }
+
+    #[test]
+    fn test_localize() {
+        let entry = make_entry(
+            Some(vec![([10, 64, -5], [12, 70, -3]), ([8, 66, 0], [9, 66, 2])]),
+            &[("name", json!("adder"))],
+        );
+
+        let (origin, local) = localize("r", &entry).unwrap();
+        assert_eq!(origin, Some([8, 64, -5]));
+        assert_eq!(
+            local.bounding_boxes,
+            Some(vec![([2, 0, 0], [4, 6, 2]), ([0, 2, 5], [1, 2, 7])])
+        );
+        assert_eq!(local.metadata, entry.metadata);
+
+        // Already at the origin: unchanged
+        assert_eq!(
+            localize("r", &local).unwrap(),
+            (Some([0, 0, 0]), local.clone())
+        );
+
+        let global = make_entry(None, &[("version", json!(1))]);
+        assert_eq!(localize("r", &global).unwrap(), (None, global.clone()));
+        let empty = make_entry(Some(vec![]), &[]);
+        assert_eq!(localize("r", &empty).unwrap(), (None, empty.clone()));
+
+        // Spanning the whole range fits, one block further does not
+        let widest = make_entry(Some(vec![([-1, 0, 0], [Coord::MAX - 1, 0, 0])]), &[]);
+        assert_eq!(
+            localize("r", &widest).unwrap().1.bounding_boxes,
+            Some(vec![([0, 0, 0], [Coord::MAX, 0, 0])])
+        );
+        let too_wide = make_entry(Some(vec![([-1, 0, 0], [Coord::MAX, 0, 0])]), &[]);
+        assert_eq!(
+            localize("r", &too_wide),
+            Err(ParseError::CoordinateLimitExceeded {
+                region: "r".to_string(),
+                limit: Coord::MAX as u64
+            })
+        );
+    }
+
+    #[test]
+    fn test_complement_within_volume() {
+        let within = ([0, 0, 0], [9, 9, 9]);
+        let entry = make_entry(
+            Some(vec![([0, 0, 0], [4, 9, 9]), ([5, 0, 0], [9, 4, 9])]),
+            &[],
+        );
+
+        let pieces = complement(&entry, within);
+        let volume: u128 = pieces.iter().map(|&pair| boxutil::volume(pair)).sum();
+        assert_eq!(volume, 5 * 5 * 10);
+        for point in [[5, 5, 0], [9, 9, 9]] {
+            assert!(pieces.iter().any(|&b| boxutil::contains_point(b, point)));
+        }
+        for point in [[0, 0, 0], [9, 4, 9]] {
+            assert!(!pieces.iter().any(|&b| boxutil::contains_point(b, point)));
+        }
+    }
+
+    #[test]
+    fn test_complement_of_empty_region() {
+        let within = ([-3, 0, 2], [3, 1, 5]);
+
+        assert_eq!(complement(&make_entry(None, &[]), within), vec![within]);
+        assert_eq!(
+            complement(
+                &make_entry(Some(vec![([20, 20, 20], [21, 21, 21])]), &[]),
+                within
+            ),
+            vec![within]
+        );
+        // Fully covered volume has an empty complement
+        assert!(complement(
+            &make_entry(Some(vec![([-5, -5, -5], [5, 5, 5])]), &[]),
+            within
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_index_empty_map() {
+        let index = build_index(&DslMap::new());
+        assert!(index.regions_at([0, 0, 0]).is_empty());
+        assert!(index.regions_in_box(([0, 0, 0], [1, 1, 1])).is_empty());
+    }
+
+    #[test]
+    fn test_index_matches_linear_scan() {
+        // Enough boxes to force several levels of the hierarchy
+        let regions: Vec<(String, Vec<BoxPair>)> = (0..40)
+            .map(|i| {
+                let x = (i % 8) * 5;
+                let z = (i / 8) * 7;
+                (
+                    format!("r{:02}", i),
+                    vec![([x, 0, z], [x + 3, 2, z + 4]), ([x, 10, z], [x + 6, 10, z])],
+                )
+            })
+            .collect();
+        let dsl_map: DslMap = regions
+            .iter()
+            .map(|(name, boxes)| (name.clone(), make_entry(Some(boxes.clone()), &[])))
+            .collect();
+        let index = build_index(&dsl_map);
+
+        for x in -1..42 {
+            for y in [0, 1, 5, 10] {
+                for z in [0, 3, 7, 30] {
+                    let point = [x, y, z];
+                    let expected: Vec<&str> = dsl_map
+                        .iter()
+                        .filter(|(_, entry)| {
+                            entry
+                                .bounding_boxes
+                                .as_ref()
+                                .unwrap()
+                                .iter()
+                                .any(|b| boxutil::contains_point(*b, point))
+                        })
+                        .map(|(name, _)| name.as_str())
+                        .collect();
+                    assert_eq!(index.regions_at(point), expected, "point {:?}", point);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_order_dsl_map_by_volume_and_box_count() {
+        let mut dsl_map = BTreeMap::new();
+        dsl_map.insert(
+            "big".to_string(),
+            make_entry(Some(vec![([0, 0, 0], [9, 9, 9])]), &[]),
+        );
+        dsl_map.insert(
+            "many".to_string(),
+            make_entry(
+                Some(vec![
+                    ([0, 0, 0], [0, 0, 0]),
+                    ([2, 0, 0], [2, 0, 0]),
+                    ([4, 0, 0], [4, 0, 0]),
+                ]),
+                &[],
+            ),
+        );
+        dsl_map.insert(
+            "also_big".to_string(),
+            make_entry(Some(vec![([0, 0, 0], [99, 9, 0])]), &[]),
+        );
+        dsl_map.insert("$global".to_string(), make_entry(None, &[("v", json!(1))]));
+
+        assert_eq!(box_volume(([0, 0, 0], [9, 9, 9])), 1000);
+        assert_eq!(
+            box_volume(([Coord::MIN, 0, 0], [Coord::MAX, 0, 0])),
+            1u128 << Coord::BITS
+        );
+
+        let by_volume = order_dsl_map(dsl_map.clone(), OutputOrdering::VolumeDesc);
+        assert_eq!(
+            by_volume.keys().collect::<Vec<_>>(),
+            vec!["also_big", "big", "many", "$global"]
+        );
+
+        let by_count = order_dsl_map(dsl_map.clone(), OutputOrdering::BoxCountDesc);
+        assert_eq!(
+            by_count.keys().collect::<Vec<_>>(),
+            vec!["many", "also_big", "big", "$global"]
+        );
+
+        // Name is the default, so bindings print in DslMap order
+        assert_eq!(OutputOrdering::default(), OutputOrdering::Name);
+        let by_name = order_dsl_map(dsl_map.clone(), OutputOrdering::Name);
+        assert!(by_name.keys().eq(dsl_map.keys().map(String::as_str)));
+
+        let by_category = order_dsl_map(dsl_map.clone(), OutputOrdering::Category);
+        assert_eq!(by_category.keys().next(), Some("$global"));
+        assert_eq!(by_category.into_map(), dsl_map);
+    }
+
+    #[test]
+    fn test_semantically_equal() {
+        let meta = [("name", json!("adder"))];
+        let split = BTreeMap::from([(
+            "cpu".to_string(),
+            make_entry(
+                Some(vec![([0, 0, 0], [1, 3, 3]), ([2, 0, 0], [3, 3, 3])]),
+                &meta,
+            ),
+        )]);
+        // Same blocks, split the other way and listed in reverse
+        let resplit = BTreeMap::from([(
+            "cpu".to_string(),
+            make_entry(
+                Some(vec![([0, 2, 0], [3, 3, 3]), ([0, 0, 0], [3, 1, 3])]),
+                &meta,
+            ),
+        )]);
+        assert_ne!(split, resplit);
+        assert!(semantically_equal(&split, &resplit));
+        assert_eq!(semantic_diff(&split, &resplit), None);
+
+        // One block missing on the right
+        let mut dented = resplit.clone();
+        dented.get_mut("cpu").unwrap().bounding_boxes = Some(vec![
+            ([0, 2, 0], [3, 3, 3]),
+            ([0, 0, 0], [3, 1, 2]),
+            ([0, 0, 3], [2, 1, 3]),
+        ]);
+        assert!(!semantically_equal(&split, &dented));
+        assert_eq!(
+            semantic_diff(&split, &dented),
+            Some(SemanticDifference::Geometry {
+                region: "cpu".to_string(),
+                voxel: [3, 0, 3],
+                in_left: true,
+            })
+        );
+
+        let mut relabeled = resplit.clone();
+        relabeled
+            .get_mut("cpu")
+            .unwrap()
+            .metadata
+            .insert("name".to_string(), json!("alu"));
+        assert_eq!(
+            semantic_diff(&split, &relabeled),
+            Some(SemanticDifference::Metadata {
+                region: "cpu".to_string(),
+                key: "name".to_string(),
+                left: Some(json!("adder")),
+                right: Some(json!("alu")),
+            })
+        );
+
+        let mut extra = resplit.clone();
+        extra.insert("$global".to_string(), make_entry(None, &[]));
+        assert_eq!(
+            semantic_diff(&split, &extra),
+            Some(SemanticDifference::Region {
+                region: "$global".to_string(),
+                in_left: false,
+            })
+        );
+
+        // Boxless and empty box lists cover the same nothing
+        let boxless = BTreeMap::from([("a".to_string(), make_entry(None, &[]))]);
+        let empty = BTreeMap::from([("a".to_string(), make_entry(Some(vec![]), &[]))]);
+        assert!(semantically_equal(&boxless, &empty));
+    }
 }
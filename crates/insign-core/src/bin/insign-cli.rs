@@ -1,88 +1,459 @@
+//! Command-line front-end for the Insign compiler.
+//!
+//! Exit codes are a stable contract for scripts:
+//!
+//! * `0` — compiled successfully (JSON on stdout, `--stats` summary on stderr),
+//!   or `--print-schema`
+//! * `1` — input error: unreadable file, malformed JSONL or front-matter, output
+//!   failure, `--pretty` with a line-based `--format`
+//! * `2` — compile error (diagnostic JSON on stderr); `0` with `--exit-zero-on-compile-error`
+//! * `3` — with `--fail-on-empty`, compiled to an empty map (JSON still on
+//!   stdout); empty input counts as empty output
+//!
+//! Several input files are concatenated into one unit list, in argument order,
+//! so regions may be defined in one file and referenced from another.
+//!
+//! With `--watch` the CLI never exits on its own: it recompiles on every change
+//! to the input files and keeps running through errors until interrupted. On a
+//! terminal the screen is cleared between runs; piped output gets one block
+//! per run.
+
 use clap::{Arg, Command};
+use insign::ast::SourceLocation;
+use insign::{CompileOptions, DslEntry, OrderedDslMap};
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Cursor, IsTerminal, Read, Write};
 use std::process;
+use std::thread;
+use std::time::Duration;
+
+/// Exit code for successful compilation
+const EXIT_SUCCESS: i32 = 0;
+/// Exit code for input, I/O and output errors
+const EXIT_INPUT_ERROR: i32 = 1;
+/// Exit code for DSL compile errors
+const EXIT_COMPILE_ERROR: i32 = 2;
+/// Exit code for an empty compiled map under `--fail-on-empty`
+const EXIT_EMPTY_OUTPUT: i32 = 3;
+
+/// How often `--watch` checks the input file for changes
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Prefix marking front-matter keys that set compile options
+const FRONT_MATTER_OPTION_PREFIX: &str = "options.";
 
 /// JSONL input format for CLI
 #[derive(Debug, Deserialize)]
 struct JsonlInput {
-    pos: [i32; 3],
+    /// Position of the sign; `--origin` supplies it when omitted
+    #[serde(default)]
+    pos: Option<[i32; 3]>,
     text: String,
 }
 
+/// How input files are read into units
+#[derive(Debug, Clone, Copy)]
+struct InputSettings {
+    /// Treat inputs as raw DSL text instead of JSONL
+    text_mode: bool,
+    /// Position of JSONL lines without `pos`
+    origin: Option<[i32; 3]>,
+    /// Offset added to every tuple position
+    shift: [i32; 3],
+}
+
+/// Key/value block at the head of a text-mode input, delimited by `---` lines
+#[derive(Debug, Default, PartialEq)]
+struct FrontMatter {
+    /// Entries destined for `$global` metadata
+    global_metadata: Vec<(String, serde_json::Value)>,
+    /// Entries under `options.` destined for `CompileOptions`
+    options: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Shape of the compiled output on stdout
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    /// The whole map as one JSON object; the only format `--pretty` applies to
+    Json,
+    /// The whole map as one JSON object on a single line
+    Jsonl,
+    /// One `{"id": ..., "entry": ...}` line per region
+    NdjsonEntries,
+}
+
+impl OutputFormat {
+    /// Values accepted by `--format`
+    const NAMES: [&'static str; 3] = ["json", "jsonl", "ndjson-entries"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "jsonl" => OutputFormat::Jsonl,
+            "ndjson-entries" => OutputFormat::NdjsonEntries,
+            _ => OutputFormat::Json,
+        }
+    }
+}
+
+/// What is printed after a successful compilation
+#[derive(Debug, Clone, Copy)]
+struct OutputSettings {
+    format: OutputFormat,
+    /// Pretty-print `--format json` output
+    pretty: bool,
+    /// Print a summary of the compiled map to stderr
+    stats: bool,
+    /// Treat an empty compiled map as a failure
+    fail_on_empty: bool,
+}
+
+/// One line of `--format ndjson-entries` output
+#[derive(Serialize)]
+struct NdjsonEntry<'a> {
+    id: &'a str,
+    entry: &'a DslEntry,
+}
+
+/// Compile units and options read from all inputs
+type Units = Vec<([i32; 3], String)>;
+
+/// Where the tuples of one input file landed in the merged unit list
+#[derive(Debug, PartialEq)]
+struct InputSource {
+    /// File name as given on the command line, or `<stdin>`
+    name: String,
+    /// Index of the file's first tuple in the merged unit list
+    first_tuple: usize,
+    /// 1-based line each of the file's tuples starts on
+    lines: Vec<usize>,
+}
+
 /// Enhanced error output for CLI
 #[derive(Debug, Serialize)]
 struct CliError {
     error: String,
     tuple_index: Option<usize>,
     statement_index: Option<usize>,
+    /// Input file the failing tuple came from
+    file: Option<String>,
+    /// Line in `file` the failing tuple starts on
+    line: Option<usize>,
 }
 
 fn main() {
     let matches = Command::new("insign-cli")
         .version("0.1.1")
         .about("Compiles Insign DSL from JSONL input to regions+metadata JSON")
+        .after_help(
+            "Exit codes:\n  0  success\n  1  input error (unreadable file, malformed input)\n  2  compile error (diagnostics on stderr)\n  3  empty output with --fail-on-empty",
+        )
         .arg(
             Arg::new("input")
-                .help("Input JSONL file (stdin if not provided)")
+                .help("Input JSONL files, compiled together in order (stdin if not provided)")
                 .value_name("FILE")
+                .num_args(1..)
+                .action(clap::ArgAction::Append)
                 .index(1),
         )
         .arg(
             Arg::new("pretty")
                 .long("pretty")
                 .short('p')
-                .help("Pretty-print JSON output")
+                .help("Pretty-print JSON output (only with --format json)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .short('f')
+                .help("Output format: one JSON object, the same on a single line, or one line per region")
+                .value_name("FORMAT")
+                .value_parser(OutputFormat::NAMES)
+                .default_value("json"),
+        )
+        .arg(
+            Arg::new("text")
+                .long("text")
+                .short('t')
+                .help(
+                    "Treat input as raw DSL text (one tuple at [0,0,0]) with optional front-matter",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("origin")
+                .long("origin")
+                .help("Position of JSONL lines without 'pos'")
+                .value_names(["X", "Y", "Z"])
+                .num_args(3)
+                .allow_negative_numbers(true)
+                .value_parser(clap::value_parser!(i32)),
+        )
+        .arg(
+            Arg::new("shift")
+                .long("shift")
+                .help("Add an offset to every tuple position before compiling")
+                .value_names(["X", "Y", "Z"])
+                .num_args(3)
+                .allow_negative_numbers(true)
+                .value_parser(clap::value_parser!(i32)),
+        )
+        .arg(
+            Arg::new("exit-zero-on-compile-error")
+                .long("exit-zero-on-compile-error")
+                .help("Exit with 0 on compile errors (diagnostics are still printed to stderr)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("fail-on-empty")
+                .long("fail-on-empty")
+                .help("Exit with 3 when the output has no entries, including for empty input")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("stats")
+                .long("stats")
+                .help("After compiling, print region, box and wildcard counts to stderr")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("print-schema")
+                .long("print-schema")
+                .help("Print the JSON Schema of the output and exit, without reading input")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .short('w')
+                .help("Recompile whenever a FILE changes, until interrupted with Ctrl-C")
+                .requires("input")
                 .action(clap::ArgAction::SetTrue),
         )
         .get_matches();
 
-    // Read input from file or stdin
-    let input_reader: Box<dyn BufRead> = match matches.get_one::<String>("input") {
-        Some(filename) => match File::open(filename) {
-            Ok(file) => Box::new(BufReader::new(file)),
-            Err(e) => {
-                eprintln!("Error reading file '{}': {}", filename, e);
-                process::exit(1);
-            }
-        },
-        None => Box::new(io::stdin().lock()),
+    if matches.get_flag("print-schema") {
+        // The alternate form of a JSON value's Display is pretty-printed
+        println!("{:#}", insign::output_json_schema());
+        process::exit(EXIT_SUCCESS);
+    }
+
+    let vec3 = |name: &str| {
+        matches
+            .get_many::<i32>(name)
+            .map(|values| <[i32; 3]>::try_from(values.copied().collect::<Vec<_>>()).unwrap())
     };
+    let settings = InputSettings {
+        text_mode: matches.get_flag("text"),
+        origin: vec3("origin"),
+        shift: vec3("shift").unwrap_or_default(),
+    };
+    let output = OutputSettings {
+        format: OutputFormat::from_name(matches.get_one::<String>("format").unwrap()),
+        pretty: matches.get_flag("pretty"),
+        stats: matches.get_flag("stats"),
+        fail_on_empty: matches.get_flag("fail-on-empty"),
+    };
+    if output.pretty && output.format != OutputFormat::Json {
+        eprintln!("--pretty only applies to --format json");
+        process::exit(EXIT_INPUT_ERROR);
+    }
+
+    let files: Vec<String> = matches
+        .get_many::<String>("input")
+        .map(|files| files.cloned().collect())
+        .unwrap_or_default();
+
+    if matches.get_flag("watch") {
+        watch(&files, settings, output);
+    }
 
-    // Parse JSONL input
-    let units = match parse_jsonl_input(input_reader) {
-        Ok(units) => units,
+    let (units, options, sources) = match read_inputs(&files, settings) {
+        Ok(inputs) => inputs,
         Err(e) => {
-            eprintln!("Error parsing JSONL input: {}", e);
-            process::exit(1);
+            eprintln!("{}", e);
+            process::exit(EXIT_INPUT_ERROR);
         }
     };
 
-    // Compile using the insign library
-    match insign::compile(&units) {
+    let code = compile_and_print(&units, &options, &sources, output);
+    if code == EXIT_COMPILE_ERROR && matches.get_flag("exit-zero-on-compile-error") {
+        process::exit(EXIT_SUCCESS);
+    }
+    process::exit(code);
+}
+
+/// Read and parse every file in `files` (stdin when empty) into one unit list.
+///
+/// Front-matter options from text-mode files are merged; setting one option
+/// to different values in two files is an error. `settings.shift` is added to
+/// every position.
+fn read_inputs(
+    files: &[String],
+    settings: InputSettings,
+) -> Result<(Units, CompileOptions, Vec<InputSource>), String> {
+    let mut inputs = Vec::new();
+    if files.is_empty() {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("Error reading stdin: {}", e))?;
+        inputs.push(("<stdin>".to_string(), text));
+    }
+    for filename in files {
+        let text = fs::read_to_string(filename)
+            .map_err(|e| format!("Error reading file '{}': {}", filename, e))?;
+        inputs.push((filename.clone(), text));
+    }
+
+    let mut units = Vec::new();
+    let mut sources = Vec::new();
+    let mut option_entries = serde_json::Map::new();
+    let mut option_origins = BTreeMap::new();
+
+    for (name, text) in inputs {
+        let (file_units, file_options) =
+            parse_input(Box::new(Cursor::new(text.as_str())), settings).map_err(|e| match files
+                .len()
+            {
+                0 | 1 => e,
+                _ => format!("{} (in '{}')", e, name),
+            })?;
+
+        for (option, value) in file_options {
+            if let Some(existing) = option_entries.get(&option) {
+                if *existing != value {
+                    return Err(format!(
+                        "Front-matter option '{}{}' is set to {} in '{}' but {} in '{}'",
+                        FRONT_MATTER_OPTION_PREFIX,
+                        option,
+                        existing,
+                        option_origins[&option],
+                        value,
+                        name
+                    ));
+                }
+                continue;
+            }
+            option_origins.insert(option.clone(), name.clone());
+            option_entries.insert(option, value);
+        }
+
+        // Text input is a single tuple (plus front-matter globals) starting on line 1
+        let lines = if settings.text_mode {
+            vec![1; file_units.len()]
+        } else {
+            jsonl_unit_lines(&text)
+        };
+        sources.push(InputSource {
+            name,
+            first_tuple: units.len(),
+            lines,
+        });
+        units.extend(file_units);
+    }
+
+    for (position, _) in &mut units {
+        *position = shift_position(*position, settings.shift)?;
+    }
+
+    let options = options_from_front_matter(option_entries)
+        .map_err(|e| format!("Error parsing text input: {}", e))?;
+    Ok((units, options, sources))
+}
+
+/// Add `shift` to `position`, failing when a component leaves the i32 range
+fn shift_position(position: [i32; 3], shift: [i32; 3]) -> Result<[i32; 3], String> {
+    let mut shifted = position;
+    for axis in 0..3 {
+        shifted[axis] = position[axis].checked_add(shift[axis]).ok_or_else(|| {
+            format!(
+                "Position {:?} shifted by {:?} is out of range",
+                position, shift
+            )
+        })?;
+    }
+    Ok(shifted)
+}
+
+/// Parse input as raw DSL text (`text_mode`) or JSONL, with an error message
+/// naming the format on failure. Returns the units and any front-matter
+/// `options.*` entries.
+fn parse_input(
+    reader: Box<dyn BufRead + '_>,
+    settings: InputSettings,
+) -> Result<(Units, serde_json::Map<String, serde_json::Value>), String> {
+    if settings.text_mode {
+        // Parse raw DSL text with optional front-matter
+        parse_text_input(reader).map_err(|e| format!("Error parsing text input: {}", e))
+    } else {
+        // Parse JSONL input
+        parse_jsonl_input(reader, settings.origin)
+            .map(|units| (units, serde_json::Map::new()))
+            .map_err(|e| format!("Error parsing JSONL input: {}", e))
+    }
+}
+
+/// 1-based line numbers of the non-empty lines of a JSONL input, one per unit
+fn jsonl_unit_lines(text: &str) -> Vec<usize> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, _)| idx + 1)
+        .collect()
+}
+
+/// Find the input file and line a tuple of the merged unit list came from
+fn locate_tuple(sources: &[InputSource], tuple_idx: usize) -> Option<(&str, usize)> {
+    let source = sources
+        .iter()
+        .rev()
+        .find(|source| source.first_tuple <= tuple_idx)?;
+    let line = source.lines.get(tuple_idx - source.first_tuple)?;
+    Some((&source.name, *line))
+}
+
+/// Compile `units`, print the JSON result to stdout or the diagnostic to
+/// stderr, and return the matching exit code
+fn compile_and_print(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+    sources: &[InputSource],
+    output: OutputSettings,
+) -> i32 {
+    // Compile using the insign library, in the entry order the options ask for
+    match insign::compile_ordered(units, options) {
         Ok(dsl_map) => {
             // Output compiled result to stdout
-            let json_output = if matches.get_flag("pretty") {
-                serde_json::to_string_pretty(&dsl_map)
-            } else {
-                serde_json::to_string(&dsl_map)
-            };
-
-            match json_output {
-                Ok(json) => {
-                    println!("{}", json);
-                    process::exit(0);
+            match format_output(&dsl_map, output.format, output.pretty) {
+                Ok(text) => {
+                    print!("{}", text);
+                    if output.stats {
+                        eprintln!("{}", format_stats(&dsl_map));
+                    }
+                    if output.fail_on_empty && dsl_map.is_empty() {
+                        if units.is_empty() {
+                            eprintln!("Output is empty: the input has no signs");
+                        } else {
+                            eprintln!(
+                                "Output is empty: no named regions, and no anonymous boxes with metadata"
+                            );
+                        }
+                        return EXIT_EMPTY_OUTPUT;
+                    }
+                    EXIT_SUCCESS
                 }
                 Err(e) => {
                     eprintln!("Error serializing output: {}", e);
-                    process::exit(1);
+                    EXIT_INPUT_ERROR
                 }
             }
         }
         Err(compile_error) => {
             // Format error with diagnostic information
-            let cli_error = format_compile_error(&compile_error);
+            let cli_error = format_compile_error(&compile_error, sources);
 
             // Output error as JSON to stderr for structured processing
             if let Ok(error_json) = serde_json::to_string_pretty(&cli_error) {
@@ -92,15 +463,102 @@ fn main() {
                 eprintln!("Compilation error: {}", compile_error);
             }
 
-            process::exit(2);
+            EXIT_COMPILE_ERROR
         }
     }
 }
 
+/// Render a compiled map in `format`, each line ending in a newline
+fn format_output(
+    dsl_map: &OrderedDslMap,
+    format: OutputFormat,
+    pretty: bool,
+) -> Result<String, serde_json::Error> {
+    let mut output = match format {
+        OutputFormat::Json if pretty => serde_json::to_string_pretty(dsl_map)?,
+        OutputFormat::Json | OutputFormat::Jsonl => serde_json::to_string(dsl_map)?,
+        OutputFormat::NdjsonEntries => {
+            let mut lines = String::new();
+            for (id, entry) in dsl_map.iter() {
+                lines.push_str(&serde_json::to_string(&NdjsonEntry { id, entry })?);
+                lines.push('\n');
+            }
+            return Ok(lines);
+        }
+    };
+    output.push('\n');
+    Ok(output)
+}
+
+/// Summarize a compiled map for `--stats`: wildcard entries and `$global` are
+/// not counted as regions
+fn format_stats(dsl_map: &OrderedDslMap) -> String {
+    // Keys with a glob character are wildcard metadata targets, as in the library
+    let wildcards = dsl_map.keys().filter(|id| id.contains(['*', '?'])).count();
+    let has_global = dsl_map.get("$global").is_some();
+    let regions = dsl_map.len() - wildcards - usize::from(has_global);
+    let boxes: usize = dsl_map
+        .iter()
+        .filter_map(|(_, entry)| entry.bounding_boxes.as_ref())
+        .map(Vec::len)
+        .sum();
+
+    format!(
+        "regions: {}, boxes: {}, wildcard entries: {}, $global: {}",
+        regions,
+        boxes,
+        wildcards,
+        if has_global { "yes" } else { "no" }
+    )
+}
+
+/// Recompile `files` every time the modification time or size of one of them
+/// changes. Errors are printed and watching continues; the loop only ends when
+/// the process is interrupted. A missing file counts as one more state, so it
+/// is reported once when it goes away and compiled again when it comes back.
+///
+/// The screen is only cleared when stdout is a terminal, so piped output has
+/// no escape sequences.
+fn watch(files: &[String], settings: InputSettings, output: OutputSettings) -> ! {
+    let mut last_seen = None;
+    let clear_screen = io::stdout().is_terminal();
+
+    loop {
+        let seen: Vec<_> = files
+            .iter()
+            .map(|file| {
+                fs::metadata(file)
+                    .and_then(|meta| Ok((meta.modified()?, meta.len())))
+                    .ok()
+            })
+            .collect();
+        if last_seen.as_ref() != Some(&seen) {
+            last_seen = Some(seen);
+
+            // Clear the terminal and move the cursor home between runs
+            if clear_screen {
+                print!("\x1b[2J\x1b[H");
+            }
+            match read_inputs(files, settings) {
+                Ok((units, options, sources)) => {
+                    compile_and_print(&units, &options, &sources, output);
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+            let names: Vec<String> = files.iter().map(|file| format!("'{}'", file)).collect();
+            eprintln!("Watching {} for changes (Ctrl-C to exit)", names.join(", "));
+            let _ = io::stdout().flush();
+        }
+
+        thread::sleep(WATCH_POLL_INTERVAL);
+    }
+}
+
 /// Parse JSONL input into units format expected by compiler
 #[allow(clippy::type_complexity)]
 fn parse_jsonl_input(
-    reader: Box<dyn BufRead>,
+    reader: Box<dyn BufRead + '_>,
+    origin: Option<[i32; 3]>,
 ) -> Result<Vec<([i32; 3], String)>, Box<dyn std::error::Error>> {
     let mut units = Vec::new();
 
@@ -116,36 +574,182 @@ fn parse_jsonl_input(
         let input: JsonlInput = serde_json::from_str(&line)
             .map_err(|e| format!("Line {}: Invalid JSON: {}", line_num + 1, e))?;
 
-        // Validate position array
-        if input.pos.len() != 3 {
-            return Err(format!(
-                "Line {}: 'pos' must be an array of exactly 3 integers",
+        let pos = input.pos.or(origin).ok_or_else(|| {
+            format!(
+                "Line {}: missing 'pos' (pass --origin to give lines a default)",
                 line_num + 1
             )
-            .into());
+        })?;
+        units.push((pos, input.text));
+    }
+
+    Ok(units)
+}
+
+/// Parse raw DSL text input into units and front-matter `options.*` entries
+///
+/// The whole body becomes a single tuple at `[0,0,0]`. Front-matter entries
+/// become `$global` metadata via an extra trailing tuple, so anonymous region
+/// IDs of the body are unaffected.
+#[allow(clippy::type_complexity)]
+fn parse_text_input(
+    mut reader: Box<dyn BufRead + '_>,
+) -> Result<(Units, serde_json::Map<String, serde_json::Value>), Box<dyn std::error::Error>> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+
+    let (front_matter, body) = split_front_matter(&input)?;
+    let mut units = vec![([0, 0, 0], body)];
+
+    if !front_matter.global_metadata.is_empty() {
+        let global_text = front_matter
+            .global_metadata
+            .iter()
+            .map(|(key, value)| format!("#$global:{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join("\n");
+        units.push(([0, 0, 0], global_text));
+    }
+
+    Ok((units, front_matter.options))
+}
+
+/// Split an optional `---` delimited front-matter block from the DSL body
+///
+/// Each front-matter line is `key: <json>` or `key = <json>`; blank lines and
+/// `;` comments are ignored. Front-matter lines are blanked in the returned
+/// body so DSL line numbers stay aligned with the file.
+fn split_front_matter(input: &str) -> Result<(FrontMatter, String), String> {
+    let lines: Vec<&str> = input.lines().collect();
+
+    let Some(open_idx) = lines.iter().position(|line| !line.trim().is_empty()) else {
+        return Ok((FrontMatter::default(), input.to_string()));
+    };
+    if lines[open_idx].trim() != "---" {
+        return Ok((FrontMatter::default(), input.to_string()));
+    }
+
+    let close_idx = lines[open_idx + 1..]
+        .iter()
+        .position(|line| line.trim() == "---")
+        .map(|offset| open_idx + 1 + offset)
+        .ok_or_else(|| {
+            format!(
+                "Front-matter opened on line {} is never closed with '---'",
+                open_idx + 1
+            )
+        })?;
+
+    let mut front_matter = FrontMatter::default();
+    for (line_idx, line) in lines.iter().enumerate().take(close_idx).skip(open_idx + 1) {
+        let line_num = line_idx + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
         }
 
-        units.push((input.pos, input.text));
+        let separator = trimmed
+            .find([':', '='])
+            .ok_or_else(|| format!("Front-matter line {}: expected 'key: value'", line_num))?;
+        let key = trimmed[..separator].trim();
+        let raw_value = trimmed[separator + 1..].trim();
+
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || ch == '_' || ch == '.')
+        {
+            return Err(format!(
+                "Front-matter line {}: invalid key '{}'",
+                line_num, key
+            ));
+        }
+        let value: serde_json::Value = serde_json::from_str(raw_value)
+            .map_err(|e| format!("Front-matter line {}: invalid JSON value: {}", line_num, e))?;
+
+        match key.strip_prefix(FRONT_MATTER_OPTION_PREFIX) {
+            Some(option) => {
+                front_matter.options.insert(option.to_string(), value);
+            }
+            None => front_matter.global_metadata.push((key.to_string(), value)),
+        }
     }
 
-    Ok(units)
+    // Blank out the front-matter lines to preserve line numbering
+    let body = lines
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| if idx <= close_idx { "" } else { *line })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok((front_matter, body))
+}
+
+/// Build compile options from front-matter `options.*` entries, rejecting unknown names
+fn options_from_front_matter(
+    entries: serde_json::Map<String, serde_json::Value>,
+) -> Result<CompileOptions, String> {
+    let known = CompileOptions::option_names();
+
+    if let Some(unknown) = entries.keys().find(|key| !known.contains(key)) {
+        return Err(format!(
+            "Unknown front-matter option '{}{}'. Valid options: {}",
+            FRONT_MATTER_OPTION_PREFIX,
+            unknown,
+            known.join(", ")
+        ));
+    }
+
+    CompileOptions::from_json(entries, true)
+        .map_err(|e| format!("Invalid front-matter option: {}", e))
 }
 
 /// Format compilation error for CLI output with enhanced diagnostics
-fn format_compile_error(error: &insign::Error) -> CliError {
+fn format_compile_error(error: &insign::Error, sources: &[InputSource]) -> CliError {
     use insign::Error::*;
 
+    let (message, location) = match error {
+        Parser(parse_err) => (
+            format!("Parse error: {}", parse_err),
+            error_location(parse_err),
+        ),
+        NotImplemented => ("Feature not implemented yet".to_string(), None),
+    };
+    let origin = location
+        .as_ref()
+        .and_then(|location| locate_tuple(sources, location.tuple_idx));
+
+    CliError {
+        error: message,
+        tuple_index: location.as_ref().map(|location| location.tuple_idx),
+        statement_index: location.map(|location| location.stmt_idx),
+        file: origin.map(|(file, _)| file.to_string()),
+        line: origin.map(|(_, line)| line),
+    }
+}
+
+/// The statement a compile error points at, when it carries one
+fn error_location(error: &insign::ParseError) -> Option<SourceLocation> {
+    use insign::ParseError::*;
+
     match error {
-        Parser(parse_err) => CliError {
-            error: format!("Parse error: {}", parse_err),
-            tuple_index: None,
-            statement_index: None,
-        },
-        NotImplemented => CliError {
-            error: "Feature not implemented yet".to_string(),
-            tuple_index: None,
-            statement_index: None,
-        },
+        UnknownRegion { source, .. }
+        | SelfReference { source, .. }
+        | NoCurrentRegion { source }
+        | PredefinedRegionRedefined { source, .. }
+        | DuplicateConstant { source, .. }
+        | UnknownInterpolationReference { source, .. } => Some(source.clone()),
+        MixedRegionMode(error) => Some(error.defined_source.clone()),
+        DuplicateRegionDefinition(error) => Some(error.duplicate_source.clone()),
+        MetadataConflict(error) => Some(error.conflict_source.clone()),
+        MetadataTypeMismatch(error) => Some(error.source.clone()),
+        #[cfg(feature = "namespace_schemas")]
+        UnknownSchemaVersion { source, .. } => Some(source.clone()),
+        #[cfg(feature = "namespace_schemas")]
+        SchemaViolation(error) => Some(error.source.clone()),
+        // Syntax errors are located by the compiler
+        _ => error.context().map(|context| context.source.clone()),
     }
 }
 
@@ -160,7 +764,7 @@ mod tests {
 {\"pos\": [0, 64, 0], \"text\": \"#doc.label=\\\"test\\\"\"}";
 
         let reader = Box::new(Cursor::new(input));
-        let result = parse_jsonl_input(reader).unwrap();
+        let result = parse_jsonl_input(reader, None).unwrap();
 
         assert_eq!(result.len(), 2);
         assert_eq!(result[0].0, [10, 64, 10]);
@@ -175,7 +779,7 @@ mod tests {
 invalid json line";
 
         let reader = Box::new(Cursor::new(input));
-        let result = parse_jsonl_input(reader);
+        let result = parse_jsonl_input(reader, None);
 
         assert!(result.is_err());
         assert!(result
@@ -184,14 +788,101 @@ invalid json line";
             .contains("Line 2: Invalid JSON"));
     }
 
+    #[test]
+    fn test_split_front_matter() {
+        let input = "---\nversion: \"1.0\"\n; a comment\noptions.keep_empty_anonymous = true\n---\n@rc([0,0,0],[1,1,1])";
+        let (front_matter, body) = split_front_matter(input).unwrap();
+
+        assert_eq!(
+            front_matter.global_metadata,
+            vec![("version".to_string(), serde_json::json!("1.0"))]
+        );
+        assert_eq!(
+            front_matter.options.get("keep_empty_anonymous"),
+            Some(&serde_json::json!(true))
+        );
+        // Front-matter lines are blanked so the body keeps its line numbers
+        assert_eq!(body, "\n\n\n\n\n@rc([0,0,0],[1,1,1])");
+    }
+
+    #[test]
+    fn test_split_front_matter_absent() {
+        let input = "@rc([0,0,0],[1,1,1])\n---";
+        let (front_matter, body) = split_front_matter(input).unwrap();
+
+        assert_eq!(front_matter, FrontMatter::default());
+        assert_eq!(body, input);
+    }
+
+    #[test]
+    fn test_split_front_matter_malformed() {
+        assert!(split_front_matter("---\nversion: 1.0")
+            .unwrap_err()
+            .contains("never closed"));
+        assert!(split_front_matter("---\nversion 1\n---")
+            .unwrap_err()
+            .contains("line 2"));
+        assert!(split_front_matter("---\nversion: not json\n---")
+            .unwrap_err()
+            .contains("invalid JSON value"));
+    }
+
+    #[test]
+    fn test_options_from_front_matter_unknown_option() {
+        let mut entries = serde_json::Map::new();
+        entries.insert("keep_empty_anonymus".to_string(), serde_json::json!(true));
+
+        let error = options_from_front_matter(entries).unwrap_err();
+        assert!(error.contains("options.keep_empty_anonymus"));
+        assert!(error.contains("keep_empty_anonymous"));
+    }
+
+    #[test]
+    fn test_parse_jsonl_input_origin() {
+        let input = "{\"text\": \"@rc([0,0,0],[1,1,1])\"}\n{\"pos\": [1, 2, 3], \"text\": \"\"}";
+
+        let result = parse_jsonl_input(Box::new(Cursor::new(input)), Some([5, 6, 7])).unwrap();
+        assert_eq!(result[0].0, [5, 6, 7]);
+        assert_eq!(result[1].0, [1, 2, 3]);
+
+        let error = parse_jsonl_input(Box::new(Cursor::new(input)), None).unwrap_err();
+        assert!(error.to_string().contains("Line 1: missing 'pos'"));
+    }
+
+    #[test]
+    fn test_shift_position() {
+        assert_eq!(shift_position([1, 2, 3], [10, -10, 0]), Ok([11, -8, 3]));
+        assert!(shift_position([i32::MAX, 0, 0], [1, 0, 0]).is_err());
+    }
+
     #[test]
     fn test_parse_jsonl_input_empty_lines() {
         let input = "{\"pos\": [10, 64, 10], \"text\": \"@rc([0,0,0],[3,2,1])\"}
 
 {\"pos\": [0, 64, 0], \"text\": \"#doc.label=\\\"test\\\"\"}";
         let reader = Box::new(Cursor::new(input));
-        let result = parse_jsonl_input(reader).unwrap();
+        let result = parse_jsonl_input(reader, None).unwrap();
 
         assert_eq!(result.len(), 2);
     }
+
+    #[test]
+    fn test_locate_tuple_across_files() {
+        let sources = vec![
+            InputSource {
+                name: "a.jsonl".to_string(),
+                first_tuple: 0,
+                lines: jsonl_unit_lines("{}\n\n{}"),
+            },
+            InputSource {
+                name: "b.jsonl".to_string(),
+                first_tuple: 2,
+                lines: jsonl_unit_lines("\n{}"),
+            },
+        ];
+
+        assert_eq!(locate_tuple(&sources, 1), Some(("a.jsonl", 3)));
+        assert_eq!(locate_tuple(&sources, 2), Some(("b.jsonl", 2)));
+        assert_eq!(locate_tuple(&sources, 3), None);
+    }
 }
@@ -0,0 +1,131 @@
+//! Math on inclusive boxes, shared by evaluation, queries and output shaping.
+//!
+//! A box is a `(min, max)` corner pair covering every block from `min` to `max`
+//! on each axis, so `([0,0,0],[0,0,0])` is a single block. The helpers are
+//! generic over the coordinate type and work on both [`crate::BoxPair`] and
+//! [`crate::BoxPair64`]. None of them depend on the `boolean_ops` feature.
+
+/// Order the corners of a box so `min <= max` on every axis
+pub fn normalize<C: Copy + Ord>(corner1: [C; 3], corner2: [C; 3]) -> ([C; 3], [C; 3]) {
+    (
+        [0, 1, 2].map(|axis| corner1[axis].min(corner2[axis])),
+        [0, 1, 2].map(|axis| corner1[axis].max(corner2[axis])),
+    )
+}
+
+/// Check whether two boxes share at least one block
+pub fn intersects<C: Copy + Ord>(
+    (min1, max1): ([C; 3], [C; 3]),
+    (min2, max2): ([C; 3], [C; 3]),
+) -> bool {
+    (0..3).all(|axis| min1[axis] <= max2[axis] && min2[axis] <= max1[axis])
+}
+
+/// The blocks two boxes share, or `None` if they are disjoint
+pub fn intersection<C: Copy + Ord>(
+    (min1, max1): ([C; 3], [C; 3]),
+    (min2, max2): ([C; 3], [C; 3]),
+) -> Option<([C; 3], [C; 3])> {
+    let min = [0, 1, 2].map(|axis| min1[axis].max(min2[axis]));
+    let max = [0, 1, 2].map(|axis| max1[axis].min(max2[axis]));
+    (0..3)
+        .all(|axis| min[axis] <= max[axis])
+        .then_some((min, max))
+}
+
+/// Number of blocks in a box; 0 if it is inverted on any axis. Saturates at
+/// `u128::MAX`, which only i64 boxes can reach.
+pub fn volume<C: Copy + Into<i128>>((min, max): ([C; 3], [C; 3])) -> u128 {
+    (0..3)
+        .map(|axis| {
+            let extent = max[axis].into() - min[axis].into() + 1;
+            u128::try_from(extent).unwrap_or(0)
+        })
+        .fold(1, u128::saturating_mul)
+}
+
+/// Check whether a box contains `point`
+pub fn contains_point<C: Copy + Ord>((min, max): ([C; 3], [C; 3]), point: [C; 3]) -> bool {
+    (0..3).all(|axis| min[axis] <= point[axis] && point[axis] <= max[axis])
+}
+
+/// Check whether `outer` contains every block of `inner`
+pub fn contains<C: Copy + Ord>(outer: ([C; 3], [C; 3]), (min, max): ([C; 3], [C; 3])) -> bool {
+    contains_point(outer, min) && contains_point(outer, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: ([i32; 3], [i32; 3]) = ([2, 2, 2], [2, 2, 2]);
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize([3, 0, -1], [1, 4, -5]), ([1, 0, -5], [3, 4, -1]));
+        assert_eq!(normalize(BLOCK.0, BLOCK.1), BLOCK);
+        assert_eq!(
+            normalize([i64::MAX, 0, 0], [i64::MIN, 0, 0]),
+            ([i64::MIN, 0, 0], [i64::MAX, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_intersects() {
+        let a = ([0, 0, 0], [3, 3, 3]);
+        assert!(intersects(a, ([3, 3, 3], [5, 5, 5])));
+        assert!(!intersects(a, ([4, 0, 0], [5, 3, 3])));
+        assert!(!intersects(a, ([0, 0, 4], [3, 3, 4])));
+
+        assert!(intersects(a, BLOCK));
+        assert!(intersects(BLOCK, BLOCK));
+        assert!(!intersects(BLOCK, ([3, 2, 2], [3, 2, 2])));
+    }
+
+    #[test]
+    fn test_intersection() {
+        let a = ([0, 0, 0], [3, 3, 3]);
+        assert_eq!(
+            intersection(a, ([2, -1, 1], [5, 1, 9])),
+            Some(([2, 0, 1], [3, 1, 3]))
+        );
+        assert_eq!(intersection(a, ([4, 0, 0], [5, 3, 3])), None);
+
+        assert_eq!(intersection(a, BLOCK), Some(BLOCK));
+        assert_eq!(intersection(BLOCK, BLOCK), Some(BLOCK));
+        assert_eq!(
+            intersection(([0i64, 0, 0], [3, 3, 3]), ([3, 3, 3], [9, 9, 9])),
+            Some(([3, 3, 3], [3, 3, 3]))
+        );
+    }
+
+    #[test]
+    fn test_volume() {
+        assert_eq!(volume(BLOCK), 1);
+        assert_eq!(volume(([0, 0, 0], [3, 1, 0])), 8);
+        assert_eq!(volume(([1, 0, 0], [0, 0, 0])), 0);
+        assert_eq!(volume(([i32::MIN; 3], [i32::MAX; 3])), (1u128 << 32).pow(3));
+        assert_eq!(volume(([i64::MIN, 0, 0], [i64::MAX, 0, 0])), 1u128 << 64);
+        assert_eq!(volume(([i64::MIN; 3], [i64::MAX; 3])), u128::MAX);
+    }
+
+    #[test]
+    fn test_contains_point() {
+        let a = ([0, 0, 0], [3, 3, 3]);
+        assert!(contains_point(a, [0, 0, 0]));
+        assert!(contains_point(a, [3, 3, 3]));
+        assert!(!contains_point(a, [4, 0, 0]));
+
+        assert!(contains_point(BLOCK, [2, 2, 2]));
+        assert!(!contains_point(BLOCK, [2, 2, 3]));
+    }
+
+    #[test]
+    fn test_contains() {
+        let a = ([0, 0, 0], [3, 3, 3]);
+        assert!(contains(a, a));
+        assert!(contains(a, BLOCK));
+        assert!(!contains(BLOCK, a));
+        assert!(!contains(a, ([2, 2, 2], [4, 3, 3])));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::ast::{EvaluatedRegionTable, GeomStmtWide, RegionEntry, RegionTable, SourceLocation};
+use crate::options::CoordinateStyleThresholds;
+use crate::parser::geom::{GeometryStatement, Vec3Wide};
+use crate::BoxPair64;
+use crate::UnitPosition;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Compilation succeeded, but the input is probably not what was meant
+    Warning,
+}
+
+/// A non-fatal message produced during compilation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Statement the diagnostic refers to, if any
+    pub source: Option<SourceLocation>,
+}
+
+impl Diagnostic {
+    /// Create a warning attached to a statement
+    pub fn warning(message: impl Into<String>, source: SourceLocation) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            source: Some(source),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self.severity {
+            Severity::Warning => "warning",
+        };
+        match &self.source {
+            Some(source) => write!(f, "{} at {}: {}", label, source, self.message),
+            None => write!(f, "{}: {}", label, self.message),
+        }
+    }
+}
+
+/// Flag `ac` boxes hugging their sign and `rc` boxes reaching far from it.
+///
+/// Heuristic: an `ac` box whose corners all lie within `absolute_near` blocks of a
+/// sign away from the origin was probably meant as `rc`; an `rc` box with any
+/// coordinate beyond `relative_far` was probably meant as `ac`.
+pub fn lint_coordinate_style(
+    geom_stmts: &[GeomStmtWide],
+    units: &[(impl UnitPosition, String)],
+    thresholds: &CoordinateStyleThresholds,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for stmt in geom_stmts {
+        let position = units
+            .get(stmt.tuple_idx)
+            .map(|(pos, _)| pos.to_wide())
+            .unwrap_or([0, 0, 0]);
+        let source = SourceLocation::new(stmt.tuple_idx, stmt.stmt_idx);
+
+        // A shell is linted by its outer box
+        let statement = match &stmt.statement {
+            GeometryStatement::Shell { outer, .. } => outer.as_ref(),
+            statement => statement,
+        };
+
+        match statement {
+            GeometryStatement::AbsoluteCoordinate { corners, .. } => {
+                let near = |corner: Vec3Wide| {
+                    (0..3).all(|axis| {
+                        corner[axis].abs_diff(position[axis]) <= thresholds.absolute_near
+                    })
+                };
+                if position != [0, 0, 0] && near(corners.0) && near(corners.1) {
+                    diagnostics.push(Diagnostic::warning(
+                        format!(
+                            "ac({:?},{:?}) lies within {} blocks of the sign at {:?}; did you mean rc(...)?",
+                            corners.0, corners.1, thresholds.absolute_near, position
+                        ),
+                        source,
+                    ));
+                }
+            }
+            GeometryStatement::RelativeCoordinate { corners, .. } => {
+                let far = |corner: Vec3Wide| {
+                    corner
+                        .iter()
+                        .any(|c| c.unsigned_abs() > thresholds.relative_far)
+                };
+                if far(corners.0) || far(corners.1) {
+                    diagnostics.push(Diagnostic::warning(
+                        format!(
+                            "rc({:?},{:?}) reaches more than {} blocks from the sign; did you mean ac(...)?",
+                            corners.0, corners.1, thresholds.relative_far
+                        ),
+                        source,
+                    ));
+                }
+            }
+            GeometryStatement::Expression { .. }
+            | GeometryStatement::Shell { .. }
+            | GeometryStatement::Sphere { .. }
+            | GeometryStatement::Const { .. }
+            | GeometryStatement::Alias { .. } => {}
+        }
+    }
+
+    diagnostics
+}
+
+/// Flag anonymous regions repeating the box of an earlier one in the same tuple.
+///
+/// Only regions without metadata count: such a repeat adds nothing and is
+/// usually a copy-paste slip. The warning is attached to the later statement.
+pub fn lint_duplicate_anonymous(
+    region_table: &RegionTable,
+    evaluated_table: &EvaluatedRegionTable,
+) -> Vec<Diagnostic> {
+    let mut first_seen: BTreeMap<(usize, BoxPair64), &SourceLocation> = BTreeMap::new();
+    let mut candidates = Vec::new();
+
+    for (region_name, entry) in &region_table.regions {
+        let RegionEntry::Anonymous { box_pair, source } = entry else {
+            continue;
+        };
+        let has_metadata = evaluated_table
+            .regions
+            .get(region_name)
+            .is_some_and(|data| !data.metadata.is_empty());
+        if has_metadata {
+            continue;
+        }
+        candidates.push((box_pair, source));
+    }
+
+    // Region names do not sort by statement (`__anon_0_10` < `__anon_0_2`)
+    candidates.sort_by_key(|(_, source)| (source.tuple_idx, source.stmt_idx));
+
+    let mut diagnostics = Vec::new();
+    for (box_pair, source) in candidates {
+        match first_seen.get(&(source.tuple_idx, *box_pair)) {
+            Some(first) => diagnostics.push(Diagnostic::warning(
+                format!(
+                    "anonymous box ({:?}, {:?}) repeats the one at {} and has no metadata",
+                    box_pair.0, box_pair.1, first
+                ),
+                source.clone(),
+            )),
+            None => {
+                first_seen.insert((source.tuple_idx, *box_pair), source);
+            }
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::geom::GeometryStatementWide;
+
+    fn make_stmt(tuple_idx: usize, statement: GeometryStatementWide) -> GeomStmtWide {
+        GeomStmtWide::new(tuple_idx, 0, statement)
+    }
+
+    #[test]
+    fn test_lint_absolute_near_sign() {
+        let units = vec![([100, 64, 100], String::new()), ([0, 0, 0], String::new())];
+        let ac = |corners| GeometryStatement::AbsoluteCoordinate {
+            region: None,
+            corners,
+            label: None,
+        };
+        let stmts = vec![
+            make_stmt(0, ac(([101, 64, 100], [103, 66, 102]))),
+            make_stmt(0, ac(([200, 64, 100], [203, 66, 102]))),
+            // Signs at the origin are not linted: every small box would match
+            make_stmt(1, ac(([1, 1, 1], [2, 2, 2]))),
+        ];
+
+        let diagnostics =
+            lint_coordinate_style(&stmts, &units, &CoordinateStyleThresholds::default());
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].source, Some(SourceLocation::new(0, 0)));
+        assert!(diagnostics[0].message.contains("did you mean rc"));
+    }
+
+    #[test]
+    fn test_lint_relative_far_from_sign() {
+        let units = vec![([0, 64, 0], String::new())];
+        let rc = |corners| GeometryStatement::RelativeCoordinate {
+            region: Some("bus".to_string()),
+            corners,
+            label: None,
+        };
+        let stmts = vec![
+            make_stmt(0, rc(([0, 0, 0], [31, 8, 15]))),
+            make_stmt(0, rc(([5000, 0, 0], [5001, 1, 1]))),
+        ];
+        let thresholds = CoordinateStyleThresholds {
+            relative_far: 10_000,
+            ..Default::default()
+        };
+
+        let diagnostics =
+            lint_coordinate_style(&stmts, &units, &CoordinateStyleThresholds::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("did you mean ac"));
+
+        assert!(lint_coordinate_style(&stmts, &units, &thresholds).is_empty());
+    }
+}
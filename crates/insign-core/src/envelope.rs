@@ -0,0 +1,198 @@
+//! JSON request format shared by the FFI and WASM bindings.
+//!
+//! A request is either a bare array of units, or an envelope object:
+//!
+//! ```json
+//! {
+//!   "units": [{ "pos": [0, 0, 0], "text": "@rc([0,0,0],[1,1,1])" }],
+//!   "options": { "keep_empty_anonymous": true },
+//!   "pretty": false,
+//!   "strict_options": true
+//! }
+//! ```
+//!
+//! Unknown envelope and option keys are ignored unless `strict_options` is set,
+//! in which case they are rejected with a message listing the valid keys.
+
+use crate::CompileOptions;
+use serde::de::Error as _;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Keys accepted at the top level of an envelope
+const ENVELOPE_KEYS: [&str; 4] = ["units", "options", "pretty", "strict_options"];
+
+/// One input unit: a sign position and its DSL text
+#[derive(Debug, Deserialize)]
+struct UnitInput {
+    pos: [i32; 3],
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    units: Vec<UnitInput>,
+    #[serde(default)]
+    options: Map<String, Value>,
+    #[serde(default)]
+    pretty: bool,
+}
+
+/// A decoded compile request
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileRequest {
+    pub units: Vec<([i32; 3], String)>,
+    pub options: CompileOptions,
+    /// Pretty-print the output JSON
+    pub pretty: bool,
+}
+
+/// Decode a request from JSON text, either a bare unit array or an envelope
+pub fn parse_compile_request(input: &str) -> Result<CompileRequest, serde_json::Error> {
+    let value: Value = serde_json::from_str(input)?;
+
+    let Value::Object(object) = value else {
+        let units: Vec<UnitInput> = serde_json::from_value(value)?;
+        return Ok(CompileRequest {
+            units: units
+                .into_iter()
+                .map(|unit| (unit.pos, unit.text))
+                .collect(),
+            options: CompileOptions::default(),
+            pretty: false,
+        });
+    };
+
+    let strict = match object.get("strict_options") {
+        None => false,
+        Some(flag) => flag
+            .as_bool()
+            .ok_or_else(|| serde_json::Error::custom("'strict_options' must be a boolean"))?,
+    };
+    if strict {
+        if let Some(unknown) = object
+            .keys()
+            .find(|key| !ENVELOPE_KEYS.contains(&key.as_str()))
+        {
+            return Err(serde_json::Error::custom(format!(
+                "unknown envelope key '{}'; valid keys: {}",
+                unknown,
+                ENVELOPE_KEYS.join(", ")
+            )));
+        }
+    }
+
+    let envelope: Envelope = serde_json::from_value(Value::Object(object))?;
+    Ok(CompileRequest {
+        units: envelope
+            .units
+            .into_iter()
+            .map(|unit| (unit.pos, unit.text))
+            .collect(),
+        options: CompileOptions::from_json(envelope.options, strict)?,
+        pretty: envelope.pretty,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bare_array_request() {
+        let request =
+            parse_compile_request(r#"[{"pos":[1,2,3],"text":"@a=rc([0,0,0],[1,1,1])"}]"#).unwrap();
+        assert_eq!(
+            request.units,
+            vec![([1, 2, 3], "@a=rc([0,0,0],[1,1,1])".to_string())]
+        );
+        assert_eq!(request.options, CompileOptions::default());
+        assert!(!request.pretty);
+    }
+
+    #[test]
+    fn test_envelope_lenient_ignores_unknown_keys() {
+        let request = parse_compile_request(
+            r#"{"units":[],"prety":true,"options":{"keep_empty_anonymous":true,"keep_empty_anonymus":true}}"#,
+        )
+        .unwrap();
+        assert!(request.options.keep_empty_anonymous);
+        assert!(!request.pretty);
+    }
+
+    #[test]
+    fn test_envelope_strict_rejects_unknown_keys() {
+        let error = parse_compile_request(r#"{"units":[],"prety":true,"strict_options":true}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("'prety'"));
+        assert!(error.contains("pretty"));
+
+        let error = parse_compile_request(
+            r#"{"units":[],"strict_options":true,"options":{"keep_empty_anonymus":true}}"#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("'keep_empty_anonymus'"));
+        assert!(error.contains("keep_empty_anonymous"));
+
+        let request = parse_compile_request(
+            r#"{"units":[],"pretty":true,"strict_options":true,"options":{"max_eval_steps":5}}"#,
+        )
+        .unwrap();
+        assert!(request.pretty);
+        assert_eq!(request.options.max_eval_steps, 5);
+    }
+
+    #[test]
+    fn test_envelope_strict_rejects_unknown_nested_keys() {
+        let error = parse_compile_request(
+            r#"{"units":[],"strict_options":true,"options":{"coordinate_style_thresholds":{"absolute_nera":4}}}"#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("'coordinate_style_thresholds.absolute_nera'"));
+        assert!(error.contains("absolute_near"));
+
+        // Keys of maps keyed by user names are not option names
+        let request = parse_compile_request(
+            r#"{"units":[],"strict_options":true,"options":{"metadata_schema":{"logic.clock_hz":"integer"},"coordinate_style_thresholds":{"absolute_near":4}}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.options.coordinate_style_thresholds.absolute_near, 4);
+        assert_eq!(request.options.metadata_schema.len(), 1);
+
+        #[cfg(feature = "namespace_schemas")]
+        {
+            let error = parse_compile_request(
+                r#"{"units":[],"strict_options":true,"options":{"namespace_schemas":{"logic":{"1":{"keys":{"clock_hz":"integer"},"allow_unknown_kyes":true}}}}}"#,
+            )
+            .unwrap_err()
+            .to_string();
+            assert!(error.contains("'namespace_schemas.logic.1.allow_unknown_kyes'"));
+            assert!(error.contains("allow_unknown_keys"));
+        }
+    }
+
+    #[test]
+    fn test_envelope_max_tuples() {
+        let request = parse_compile_request(r#"{"units":[],"options":{"max_tuples":16}}"#).unwrap();
+        assert_eq!(request.options.max_tuples, Some(16));
+
+        let request =
+            parse_compile_request(r#"{"units":[],"options":{"max_tuples":null}}"#).unwrap();
+        assert_eq!(request.options.max_tuples, None);
+    }
+
+    #[test]
+    fn test_envelope_position_axis_order() {
+        let request = parse_compile_request(
+            r#"{"units":[],"options":{"position_axis_order":["x","z","y"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.options.position_axis_order,
+            [crate::Axis::X, crate::Axis::Z, crate::Axis::Y]
+        );
+    }
+}
@@ -9,29 +9,58 @@ pub struct StatementSlice<'a> {
     pub end: usize,
 }
 
-/// Filter out comment lines from input text.
-/// Comments start with ';' and extend to the end of the line.
-/// This preserves line numbers by replacing comments with empty lines.
+/// Filter out comments from input text.
+/// Lines whose first non-whitespace is ';' or '//' are comments, and a '//'
+/// outside a JSON string starts a comment running to the end of the line.
+/// This preserves line numbers by replacing comment lines with empty lines
+/// and cutting trailing comments off their line.
 pub fn filter_comments(input: &str) -> String {
     input
         .lines()
         .map(|line| {
             let trimmed = line.trim_start();
-            if trimmed.starts_with(';') {
+            if trimmed.starts_with(';') || trimmed.starts_with("//") {
                 "" // Replace comment line with empty line
             } else {
-                line
+                strip_trailing_comment(line)
             }
         })
         .collect::<Vec<_>>()
         .join("\n")
 }
 
+/// The part of `line` before a trailing `//` comment, tracking JSON strings
+/// like [`split_statements`] so `"http://..."` is kept
+fn strip_trailing_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escape_next = false;
+
+    for (idx, ch) in line.char_indices() {
+        if in_string {
+            match ch {
+                _ if escape_next => escape_next = false,
+                '\\' => escape_next = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_string = true,
+            '/' if line[idx + 1..].starts_with('/') => return line[..idx].trim_end(),
+            _ => {}
+        }
+    }
+
+    line
+}
+
 /// Split input text into DSL statement slices.
 ///
 /// Statements start with '@' or '#' only when:
 /// - We're at depth 0 (not inside brackets/parentheses)
 /// - We're not inside a JSON string literal
+/// - The '#' is not a box label like `rc(...)#north` (see [`is_box_label`])
 ///
 /// # Arguments
 /// * `input` - The DSL text to split
@@ -74,7 +103,9 @@ pub fn split_statements(input: &str) -> Vec<StatementSlice<'_>> {
             ')' | ']' | '}' if !in_string => {
                 depth -= 1;
             }
-            '@' | '#' if depth == 0 && !in_string && char_idx > 0 => {
+            '@' | '#'
+                if depth == 0 && !in_string && char_idx > 0 && !is_box_label(&chars, char_idx) =>
+            {
                 // Found the start of a new statement
                 // End the previous statement at the current byte position
                 let text = &input[current_start..byte_pos];
@@ -106,6 +137,56 @@ pub fn split_statements(input: &str) -> Vec<StatementSlice<'_>> {
     statements
 }
 
+/// Split input text into DSL statement slices trimmed of surrounding whitespace.
+///
+/// Like [`split_statements`], but each slice's `start` and `end` are narrowed
+/// to the statement text itself, for tools that highlight statements.
+/// Whitespace-only slices are dropped, so unlike [`split_statements`] the
+/// slices do not cover the whole input: whitespace between statements
+/// belongs to none of them. `text` is always `&input[start..end]`.
+pub fn split_statements_trimmed(input: &str) -> Vec<StatementSlice<'_>> {
+    split_statements(input)
+        .into_iter()
+        .filter_map(|slice| {
+            let text = slice.text.trim();
+            if text.is_empty() {
+                return None;
+            }
+            let start = slice.start + (slice.text.len() - slice.text.trim_start().len());
+            Some(StatementSlice {
+                text,
+                start,
+                end: start + text.len(),
+            })
+        })
+        .collect()
+}
+
+/// Whether the '#' at `hash_idx` labels the box before it, as in `rc(...)#north`.
+///
+/// A label follows `)` directly and is a run of [A-Za-z0-9_] that is not
+/// continued, after optional whitespace, like a metadata key or target (`.`, `*`) or
+/// assignment (`=`, `?=`, `:`). So `rc(...)#key=1` still starts metadata.
+fn is_box_label(chars: &[char], hash_idx: usize) -> bool {
+    if chars[hash_idx] != '#' || hash_idx == 0 || chars[hash_idx - 1] != ')' {
+        return false;
+    }
+
+    let rest = &chars[hash_idx + 1..];
+    let label_len = rest
+        .iter()
+        .take_while(|ch| ch.is_ascii_alphanumeric() || **ch == '_')
+        .count();
+    if label_len == 0 {
+        return false;
+    }
+
+    !matches!(
+        rest[label_len..].iter().find(|ch| !ch.is_whitespace()),
+        Some('.' | '=' | '?' | ':' | '*')
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -196,6 +277,29 @@ mod tests {
         assert_eq!(statements.len(), 0);
     }
 
+    #[test]
+    fn test_box_label_stays_in_statement() {
+        let input = "@wall=rc([0,0,0],[9,0,9])#north\n#wall:kind=1";
+        let statements = split_statements(input);
+
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].text, "@wall=rc([0,0,0],[9,0,9])#north\n");
+
+        // Directly attached metadata is still split off
+        for metadata in [
+            "#key=1",
+            "#key ?= 1",
+            "#doc.label=1",
+            "#cpu*:k=1",
+            "#!key=1",
+        ] {
+            let input = format!("@wall=rc([0,0,0],[1,1,1]){}", metadata);
+            let statements = split_statements(&input);
+            assert_eq!(statements.len(), 2, "{}", input);
+            assert_eq!(statements[1].text, metadata);
+        }
+    }
+
     // M14: Comment Support Tests
 
     #[test]
@@ -249,6 +353,57 @@ mod tests {
         assert_eq!(statements[0].text, input);
     }
 
+    #[test]
+    fn test_filter_slash_comments() {
+        // Full-line comments, indented or not, become empty lines
+        let input = "// Header\n@rc([0,0,0],[1,1,1])\n  // Indented\n; Semicolon";
+        assert_eq!(filter_comments(input), "\n@rc([0,0,0],[1,1,1])\n\n");
+
+        // Trailing comments are cut off their line
+        let input = "@a=rc([0,0,0],[1,1,1]) // the hall\n#a:kind=\"room\"// trailing\n#b=1";
+        assert_eq!(
+            filter_comments(input),
+            "@a=rc([0,0,0],[1,1,1])\n#a:kind=\"room\"\n#b=1"
+        );
+
+        // '//' inside JSON strings, also after an escaped quote, is kept
+        let input = r#"#doc.url="http://example.com" // link"#;
+        assert_eq!(filter_comments(input), r#"#doc.url="http://example.com""#);
+        let input = r#"#doc.note="say \"hi\" // still text""#;
+        assert_eq!(filter_comments(input), input);
+
+        // A single slash is not a comment
+        let input = r#"#doc.path="a/b""#;
+        assert_eq!(filter_comments(input), input);
+    }
+
+    #[test]
+    fn test_split_statements_trimmed() {
+        let input = "\n  @rc([0,0,0],[1,1,1])\n";
+        let statements = split_statements_trimmed(input);
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].text, "@rc([0,0,0],[1,1,1])");
+        assert_eq!(statements[0].start, 3);
+        assert_eq!(statements[0].end, 23);
+        assert_eq!(
+            &input[statements[0].start..statements[0].end],
+            statements[0].text
+        );
+
+        // Whitespace between statements is left out; comments leave blank lines
+        let input = filter_comments("; header\n@a=rc([0,0,0],[1,1,1])\n\t#kind=\"x\"  \n");
+        let texts: Vec<_> = split_statements_trimmed(&input)
+            .iter()
+            .map(|slice| (slice.start, slice.text))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![(1, "@a=rc([0,0,0],[1,1,1])"), (25, "#kind=\"x\"")]
+        );
+
+        assert!(split_statements_trimmed(" \n\t").is_empty());
+    }
+
     #[test]
     fn test_empty_comment_lines() {
         let input = ";\n@rc([0,0,0],[1,1,1])\n;   \n#key=\"value\"";
@@ -338,6 +493,19 @@ mod tests {
             }
         }
 
+        #[test]
+        fn test_trimmed_spans_match_text(input in "[^\\x00-\\x08\\x0B\\x0C\\x0E-\\x1F\\x7F]*") {
+            let mut previous_end = 0;
+            for stmt in split_statements_trimmed(&input) {
+                // Each span is exactly its non-empty, trimmed text, in input order
+                prop_assert_eq!(&input[stmt.start..stmt.end], stmt.text);
+                prop_assert!(!stmt.text.is_empty());
+                prop_assert_eq!(stmt.text.trim(), stmt.text);
+                prop_assert!(stmt.start >= previous_end);
+                previous_end = stmt.end;
+            }
+        }
+
         #[test]
         fn test_lexer_no_panic(input in "[^\\x00-\\x08\\x0B\\x0C\\x0E-\\x1F\\x7F]*") {
             // Property: The lexer should never panic on any valid UTF-8 input
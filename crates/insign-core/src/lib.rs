@@ -3,30 +3,272 @@ use std::collections::BTreeMap;
 use thiserror::Error;
 
 pub mod ast;
+pub mod boxutil;
+pub mod diagnostics;
+pub mod envelope;
 pub mod lexer;
+pub mod options;
 pub mod parser;
 pub mod pipeline;
+pub mod query;
+#[cfg(feature = "namespace_schemas")]
+pub mod schema;
+
+pub use diagnostics::{Diagnostic, Severity};
+pub use options::{
+    Axis, CompileOptions, CoordWidth, GeomKind, MetadataMergePolicy, OutputOrdering,
+    PredefinedCollision, ValueKind,
+};
+pub use pipeline::{LazyCompiled, ParsedProgram};
+pub use query::DslMapQuery;
+#[cfg(feature = "namespace_schemas")]
+pub use schema::{NamespaceSchemas, Schema};
+
+/// Integer type of output coordinates: i32, or i64 with the `coords_i64` feature.
+#[cfg(not(feature = "coords_i64"))]
+pub type Coord = i32;
+/// Integer type of output coordinates: i32, or i64 with the `coords_i64` feature.
+#[cfg(feature = "coords_i64")]
+pub type Coord = i64;
 
 /// A pair of 3D coordinates representing an axis-aligned bounding box.
 /// Both corners are inclusive, and the box should be normalized (min <= max per axis).
-pub type BoxPair = ([i32; 3], [i32; 3]);
+pub type BoxPair = ([Coord; 3], [Coord; 3]);
+
+/// A [`BoxPair`] with i64 coordinates, produced by [`compile_wide`].
+/// The compiler evaluates geometry in this width internally.
+pub type BoxPair64 = ([i64; 3], [i64; 3]);
 
 /// Entry in the DSL output map, containing bounding boxes and metadata for a region.
+///
+/// Generic over the box type so the same shape serves [`DslMap`] and [`DslMap64`].
+///
+/// Non-exhaustive since `box_labels` was added after 0.1.1: outside this crate,
+/// build entries with [`DslEntry::builder`] instead of a struct literal.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct DslEntry {
+#[non_exhaustive]
+pub struct DslEntry<B = BoxPair> {
     /// Optional bounding boxes for this region. None for special entries like $global.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub bounding_boxes: Option<Vec<BoxPair>>,
-    /// Metadata key-value pairs attached to this region.
-    pub metadata: BTreeMap<String, serde_json::Value>,
+    pub bounding_boxes: Option<Vec<B>>,
+    /// Labels of the boxes, parallel to `bounding_boxes`. Present only for
+    /// accumulator regions with at least one labeled box (`rc(...)#north`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub box_labels: Option<Vec<Option<String>>>,
+    /// Metadata key-value pairs attached to this region, sorted by key unless
+    /// compiled with `preserve_metadata_order`.
+    pub metadata: Metadata,
+}
+
+/// Metadata of a [`DslEntry`]. Keys serialize in the map's order.
+pub type Metadata = indexmap::IndexMap<String, serde_json::Value>;
+
+impl<B> DslEntry<B> {
+    /// Start building an entry with no boxes and no metadata
+    pub fn builder() -> DslEntryBuilder<B> {
+        DslEntryBuilder {
+            entry: DslEntry {
+                bounding_boxes: None,
+                box_labels: None,
+                metadata: Metadata::new(),
+            },
+        }
+    }
+
+    /// Number of bounding boxes, `0` for entries without boxes
+    pub fn box_count(&self) -> usize {
+        self.bounding_boxes.as_ref().map_or(0, Vec::len)
+    }
+
+    /// The metadata value of `key`, if set
+    pub fn get_meta(&self, key: &str) -> Option<&serde_json::Value> {
+        self.metadata.get(key)
+    }
+}
+
+/// Builder for a [`DslEntry`], created by [`DslEntry::builder`].
+///
+/// An entry given no boxes has `bounding_boxes: None`, like `$global`.
+#[derive(Debug, Clone)]
+pub struct DslEntryBuilder<B = BoxPair> {
+    entry: DslEntry<B>,
+}
+
+impl<B> DslEntryBuilder<B> {
+    /// Append one bounding box (`box` itself is a Rust keyword)
+    pub fn bbox(mut self, bbox: B) -> Self {
+        self.entry
+            .bounding_boxes
+            .get_or_insert_with(Vec::new)
+            .push(bbox);
+        self
+    }
+
+    /// Append several bounding boxes
+    pub fn boxes(mut self, boxes: Vec<B>) -> Self {
+        self.entry
+            .bounding_boxes
+            .get_or_insert_with(Vec::new)
+            .extend(boxes);
+        self
+    }
+
+    /// Set the labels of the boxes, parallel to the boxes given so far
+    pub fn labels(mut self, labels: Vec<Option<String>>) -> Self {
+        self.entry.box_labels = Some(labels);
+        self
+    }
+
+    /// Set metadata `key` to `value`, replacing an earlier value
+    pub fn meta(mut self, key: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.entry.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Finish the entry, with its metadata keys sorted by name as compiled
+    /// entries have them unless `preserve_metadata_order` is set
+    pub fn build(mut self) -> DslEntry<B> {
+        self.entry.metadata.sort_keys();
+        self.entry
+    }
 }
 
 /// The complete DSL compilation output: a map from region IDs to their entries.
 /// Uses BTreeMap for deterministic ordering of keys.
 pub type DslMap = BTreeMap<String, DslEntry>;
 
+/// Where a metadata value in an [`AnnotatedEntry`] comes from.
+///
+/// Serializes as `"own"` or as the wildcard pattern, e.g. `"cpu.*"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataOrigin {
+    /// Set by a statement targeting the region itself
+    Own,
+    /// Inherited through the given wildcard pattern
+    Inherited(String),
+}
+
+impl Serialize for MetadataOrigin {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MetadataOrigin::Own => serializer.serialize_str("own"),
+            MetadataOrigin::Inherited(pattern) => serializer.serialize_str(pattern),
+        }
+    }
+}
+
+/// A metadata value together with its [`MetadataOrigin`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnnotatedValue {
+    pub value: serde_json::Value,
+    pub origin: MetadataOrigin,
+}
+
+/// Like [`DslEntry`], with each metadata value annotated with its origin
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnnotatedEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounding_boxes: Option<Vec<BoxPair>>,
+    pub metadata: BTreeMap<String, AnnotatedValue>,
+}
+
+/// Compilation output of [`compile_annotated`]
+pub type AnnotatedMap = BTreeMap<String, AnnotatedEntry>;
+
+/// Compilation output that keeps entries in a chosen order; see [`compile_ordered`].
+///
+/// Serializes as a JSON object whose keys appear in that order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OrderedDslMap {
+    entries: Vec<(String, DslEntry)>,
+}
+
+impl OrderedDslMap {
+    /// Wrap entries that are already in the desired order
+    pub fn from_entries(entries: Vec<(String, DslEntry)>) -> Self {
+        Self { entries }
+    }
+
+    /// Metadata keys of an entry in the order they serialize
+    pub fn metadata_keys(&self, name: &str) -> Option<Vec<&str>> {
+        Some(
+            self.get(name)?
+                .metadata
+                .keys()
+                .map(String::as_str)
+                .collect(),
+        )
+    }
+
+    /// Entries in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &DslEntry)> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    /// Entry names in order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Look up an entry by name
+    pub fn get(&self, name: &str) -> Option<&DslEntry> {
+        self.entries
+            .iter()
+            .find(|(entry_name, _)| entry_name == name)
+            .map(|(_, entry)| entry)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop the ordering and return a plain [`DslMap`]
+    pub fn into_map(self) -> DslMap {
+        self.entries.into_iter().collect()
+    }
+}
+
+impl Serialize for OrderedDslMap {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (name, entry) in &self.entries {
+            map.serialize_entry(name, entry)?;
+        }
+        map.end()
+    }
+}
+
+/// Compilation output with i64 coordinates; see [`compile_wide`].
+pub type DslMap64 = BTreeMap<String, DslEntry<BoxPair64>>;
+
+/// A tuple position accepted by the compiler, widened to i64 for evaluation.
+pub trait UnitPosition: Copy {
+    /// The position as i64 coordinates
+    fn to_wide(self) -> [i64; 3];
+}
+
+impl UnitPosition for [i32; 3] {
+    fn to_wide(self) -> [i64; 3] {
+        self.map(i64::from)
+    }
+}
+
+impl UnitPosition for [i64; 3] {
+    fn to_wide(self) -> [i64; 3] {
+        self
+    }
+}
+
 /// Errors that can occur during DSL compilation.
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq)]
 pub enum Error {
     #[error("Parser error: {0}")]
     Parser(#[from] ParseError),
@@ -35,42 +277,111 @@ pub enum Error {
     NotImplemented,
 }
 
+impl Error {
+    /// Stable snake_case identifier of the error kind, such as
+    /// `"unknown_region"`; see [`ParseError::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Parser(error) => error.code(),
+            Error::NotImplemented => "not_implemented",
+        }
+    }
+}
+
 /// Specific parse errors with location information.
-#[derive(Error, Debug)]
+///
+/// A `position` is a byte offset into the text of the failing statement.
+/// Errors with a `position` also have a `context`, which the compiler fills
+/// in with the statement's tuple, line, column and text; a parser used on its
+/// own leaves it `None`.
+#[derive(Error, Debug, PartialEq)]
 pub enum ParseError {
-    #[error("Expected {expected} at position {position}, but found '{found}'")]
+    #[error("Expected {expected} at position {position}, but found '{found}'{note}", note = context_note(.context))]
+    #[non_exhaustive]
     Expected {
         expected: &'static str,
         found: String,
         position: usize,
+        context: Option<Box<StatementContext>>,
     },
 
-    #[error("Expected {expected} at position {position}, but reached end of input")]
+    #[error("Expected {expected} at position {position}, but reached end of input{note}", note = context_note(.context))]
+    #[non_exhaustive]
     UnexpectedEnd {
         expected: &'static str,
         position: usize,
+        context: Option<Box<StatementContext>>,
     },
 
-    #[error("Invalid integer at position {position}: {source}")]
+    #[error("Invalid integer at position {position}: {source}{note}", note = context_note(.context))]
+    #[non_exhaustive]
     InvalidInteger {
         position: usize,
         source: std::num::ParseIntError,
+        context: Option<Box<StatementContext>>,
     },
 
-    #[error("Invalid vec3 at position {position}: {message}")]
-    InvalidVec3 { position: usize, message: String },
+    #[error("Invalid vec3 at position {position}: {message}{note}", note = context_note(.context))]
+    #[non_exhaustive]
+    InvalidVec3 {
+        position: usize,
+        message: String,
+        context: Option<Box<StatementContext>>,
+    },
 
-    #[error("Invalid box at position {position}: {message}")]
-    InvalidBox { position: usize, message: String },
+    #[error("Invalid box at position {position}: {message}{note}", note = context_note(.context))]
+    #[non_exhaustive]
+    InvalidBox {
+        position: usize,
+        message: String,
+        context: Option<Box<StatementContext>>,
+    },
+
+    #[error("Phase 0 supports only '+' operator at position {position}. Found '{operator}'{note}", note = context_note(.context))]
+    #[non_exhaustive]
+    UnsupportedOperator {
+        position: usize,
+        operator: String,
+        context: Option<Box<StatementContext>>,
+    },
+
+    /// Produced instead of `UnsupportedOperator` with `verbose_feature_errors`.
+    /// Non-exhaustive since `left` was added after 0.1.1: match it with `..`.
+    #[error("Boolean operator '{operator}' after '{left}' at position {position} requires the 'boolean_ops' feature; rebuild with `--features boolean_ops` to use '-', '&' and '^'{note}", note = context_note(.context))]
+    #[non_exhaustive]
+    FeatureGated {
+        position: usize,
+        operator: String,
+        /// The operand already read before the operator
+        left: String,
+        context: Option<Box<StatementContext>>,
+    },
 
-    #[error("Phase 0 supports only '+' operator at position {position}. Found '{operator}'")]
-    UnsupportedOperator { position: usize, operator: String },
+    #[error("Empty expression at position {position}{note}", note = context_note(.context))]
+    #[non_exhaustive]
+    EmptyExpression {
+        position: usize,
+        context: Option<Box<StatementContext>>,
+    },
 
-    #[error("Boolean operator '{operator}' at position {position} requires 'boolean_ops' feature. TODO: Full boolean operations not implemented yet.")]
-    FeatureGated { position: usize, operator: String },
+    /// A region name outside `[A-Za-z0-9_][A-Za-z0-9_.]*`, such as `cpu core`
+    /// or `$global`, which cannot have boxes
+    #[error("Invalid character '{found}' in region name '{name}' at position {position}; region names use letters, digits, '_' and '.'{note}", note = context_note(.context))]
+    #[non_exhaustive]
+    InvalidRegionName {
+        name: String,
+        found: char,
+        position: usize,
+        context: Option<Box<StatementContext>>,
+    },
 
-    #[error("Empty expression at position {position}")]
-    EmptyExpression { position: usize },
+    /// Geometry for a name the compiler uses itself: `$global`, a wildcard
+    /// pattern, or an anonymous region key such as `__anon_0_0`
+    #[error("Region name '{name}' at {source} is reserved and cannot have geometry")]
+    ReservedRegionName {
+        name: String,
+        source: crate::ast::SourceLocation,
+    },
 
     #[error("Region '{region}' cannot be both accumulator and defined. Accumulator sources: {accumulator_sources:?}, defined source: {defined_source:?}", region = .0.region, accumulator_sources = .0.accumulator_sources, defined_source = .0.defined_source)]
     MixedRegionMode(Box<MixedRegionModeError>),
@@ -81,18 +392,30 @@ pub enum ParseError {
     #[error("Internal error: {message}")]
     Internal { message: String, position: usize },
 
+    /// A metadata value whose JSON type differs from the one
+    /// `CompileOptions::metadata_schema` requires for its key
+    #[error("Metadata '{key}' of region '{region}' at {source} must be {expected}, found {found}", region = .0.region, key = .0.key, source = .0.source, expected = .0.expected, found = .0.found)]
+    MetadataTypeMismatch(Box<MetadataTypeMismatchError>),
+
     #[error("Unknown region '{region}' referenced in expression at {source:?}")]
     UnknownRegion {
         region: String,
         source: crate::ast::SourceLocation,
     },
 
+    /// A region looked up by name, e.g. with `LazyCompiled::region`, that no
+    /// statement defines; references from statements are `UnknownRegion`
+    #[error("Region '{region}' is not defined")]
+    RegionNotFound { region: String },
+
     #[error("Self-reference detected: region '{region}' references itself at {source:?}")]
     SelfReference {
         region: String,
         source: crate::ast::SourceLocation,
     },
 
+    /// Regions depending on each other, in dependency order and starting at
+    /// the smallest name: `["a", "b"]` for `@a=b` and `@b=a`
     #[error("Cycle detected in region dependencies: {cycle:?}")]
     CycleDetected { cycle: Vec<String> },
 
@@ -101,10 +424,241 @@ pub enum ParseError {
 
     #[error("No current region found for metadata statement at {source}. Hint: Metadata statements like '#key=value' must be placed after a geometry statement (@rc, @ac, or @region=expr) in the same tuple.")]
     NoCurrentRegion { source: crate::ast::SourceLocation },
+
+    #[error("Non-finite number '{found}' at position {position}: metadata numbers must be finite{note}", note = context_note(.context))]
+    #[non_exhaustive]
+    NonFiniteNumber {
+        found: String,
+        position: usize,
+        context: Option<Box<StatementContext>>,
+    },
+
+    #[error("Evaluation exceeded the budget of {limit} steps (box operations or interpolated metadata bytes)")]
+    EvaluationBudgetExceeded { limit: u64 },
+
+    #[error("Evaluating region '{region}' exceeded the maximum depth of {depth} nested references and sub-expressions")]
+    EvaluationDepthExceeded { region: String, depth: usize },
+
+    #[error("Input has {count} tuples, more than the limit of {limit}")]
+    TooManyTuples { count: usize, limit: usize },
+
+    #[error("Position axis order {order:?} must name each of X, Y and Z once")]
+    InvalidAxisOrder { order: [crate::Axis; 3] },
+
+    #[error("Region '{region}' at {source} redefines a predefined region")]
+    PredefinedRegionRedefined {
+        region: String,
+        source: crate::ast::SourceLocation,
+    },
+
+    #[error("Metadata interpolation cycle: {chain:?}")]
+    MetadataInterpolationCycle { chain: Vec<String> },
+
+    #[error("Metadata '{key}' of region '{region}' at {source} references unknown metadata '{reference}'")]
+    UnknownInterpolationReference {
+        region: String,
+        key: String,
+        reference: String,
+        source: crate::ast::SourceLocation,
+    },
+
+    #[error("Region '{region}' reaches beyond the coordinate limit of {limit}")]
+    CoordinateLimitExceeded { region: String, limit: u64 },
+
+    #[error("Unknown constant '{name}' at position {position}; constants must be declared with @const before use{note}", note = context_note(.context))]
+    #[non_exhaustive]
+    UnknownConstant {
+        name: String,
+        position: usize,
+        context: Option<Box<StatementContext>>,
+    },
+
+    #[error("Constant '{name}' at {source} is already defined")]
+    DuplicateConstant {
+        name: String,
+        source: crate::ast::SourceLocation,
+    },
+
+    #[cfg(feature = "namespace_schemas")]
+    #[error("Namespace '{namespace}' declares schema version '{version}' at {source}, but no such schema is registered")]
+    UnknownSchemaVersion {
+        namespace: String,
+        version: String,
+        source: crate::ast::SourceLocation,
+    },
+
+    #[cfg(feature = "namespace_schemas")]
+    #[error("Metadata '{key}' of region '{region}' at {source} violates schema {namespace} v{version}: {message}", region = .0.region, key = .0.key, source = .0.source, namespace = .0.namespace, version = .0.version, message = .0.message)]
+    SchemaViolation(Box<crate::schema::SchemaViolationError>),
+}
+
+impl ParseError {
+    /// Shift a byte `position` by `offset`, for errors raised by a parser
+    /// that only saw a suffix of the statement. Errors without a position are
+    /// returned unchanged.
+    pub(crate) fn offset_position(mut self, offset: usize) -> Self {
+        match &mut self {
+            ParseError::Expected { position, .. }
+            | ParseError::UnexpectedEnd { position, .. }
+            | ParseError::InvalidInteger { position, .. }
+            | ParseError::InvalidVec3 { position, .. }
+            | ParseError::InvalidBox { position, .. }
+            | ParseError::UnsupportedOperator { position, .. }
+            | ParseError::FeatureGated { position, .. }
+            | ParseError::EmptyExpression { position, .. }
+            | ParseError::InvalidRegionName { position, .. }
+            | ParseError::NonFiniteNumber { position, .. }
+            | ParseError::UnknownConstant { position, .. }
+            | ParseError::Internal { position, .. } => *position += offset,
+            _ => {}
+        }
+        self
+    }
+
+    /// The statement a syntax error was found in, once the compiler has
+    /// located it
+    pub fn context(&self) -> Option<&StatementContext> {
+        match self {
+            ParseError::Expected { context, .. }
+            | ParseError::UnexpectedEnd { context, .. }
+            | ParseError::InvalidInteger { context, .. }
+            | ParseError::InvalidVec3 { context, .. }
+            | ParseError::InvalidBox { context, .. }
+            | ParseError::UnsupportedOperator { context, .. }
+            | ParseError::FeatureGated { context, .. }
+            | ParseError::EmptyExpression { context, .. }
+            | ParseError::InvalidRegionName { context, .. }
+            | ParseError::NonFiniteNumber { context, .. }
+            | ParseError::UnknownConstant { context, .. } => context.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Attach the statement an error with a `position` was found in. Other
+    /// errors are returned unchanged.
+    pub(crate) fn with_context(mut self, statement_context: StatementContext) -> Self {
+        match &mut self {
+            ParseError::Expected { context, .. }
+            | ParseError::UnexpectedEnd { context, .. }
+            | ParseError::InvalidInteger { context, .. }
+            | ParseError::InvalidVec3 { context, .. }
+            | ParseError::InvalidBox { context, .. }
+            | ParseError::UnsupportedOperator { context, .. }
+            | ParseError::FeatureGated { context, .. }
+            | ParseError::EmptyExpression { context, .. }
+            | ParseError::InvalidRegionName { context, .. }
+            | ParseError::NonFiniteNumber { context, .. }
+            | ParseError::UnknownConstant { context, .. } => {
+                *context = Some(Box::new(statement_context));
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// The byte `position` within the statement, for errors that have one
+    pub(crate) fn position(&self) -> Option<usize> {
+        match self {
+            ParseError::Expected { position, .. }
+            | ParseError::UnexpectedEnd { position, .. }
+            | ParseError::InvalidInteger { position, .. }
+            | ParseError::InvalidVec3 { position, .. }
+            | ParseError::InvalidBox { position, .. }
+            | ParseError::UnsupportedOperator { position, .. }
+            | ParseError::FeatureGated { position, .. }
+            | ParseError::EmptyExpression { position, .. }
+            | ParseError::InvalidRegionName { position, .. }
+            | ParseError::NonFiniteNumber { position, .. }
+            | ParseError::UnknownConstant { position, .. }
+            | ParseError::Internal { position, .. } => Some(*position),
+            _ => None,
+        }
+    }
+
+    /// A [`ParseError::CycleDetected`] for the regions of `cycle`, in cycle
+    /// order. The cycle is rotated to start at its smallest name, so it reads
+    /// the same whichever region it was entered from.
+    pub(crate) fn cycle_detected(mut cycle: Vec<String>) -> Self {
+        if let Some(start) = (0..cycle.len()).min_by_key(|&idx| &cycle[idx]) {
+            cycle.rotate_left(start);
+        }
+        ParseError::CycleDetected { cycle }
+    }
+
+    /// Stable snake_case identifier of the error kind, for hosts that branch
+    /// on it: the variant name, e.g. `"unknown_region"` for
+    /// [`ParseError::UnknownRegion`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::Expected { .. } => "expected",
+            ParseError::UnexpectedEnd { .. } => "unexpected_end",
+            ParseError::InvalidInteger { .. } => "invalid_integer",
+            ParseError::InvalidVec3 { .. } => "invalid_vec3",
+            ParseError::InvalidBox { .. } => "invalid_box",
+            ParseError::UnsupportedOperator { .. } => "unsupported_operator",
+            ParseError::FeatureGated { .. } => "feature_gated",
+            ParseError::EmptyExpression { .. } => "empty_expression",
+            ParseError::InvalidRegionName { .. } => "invalid_region_name",
+            ParseError::ReservedRegionName { .. } => "reserved_region_name",
+            ParseError::MixedRegionMode(_) => "mixed_region_mode",
+            ParseError::DuplicateRegionDefinition(_) => "duplicate_region_definition",
+            ParseError::Internal { .. } => "internal",
+            ParseError::MetadataTypeMismatch(_) => "metadata_type_mismatch",
+            ParseError::UnknownRegion { .. } => "unknown_region",
+            ParseError::RegionNotFound { .. } => "region_not_found",
+            ParseError::SelfReference { .. } => "self_reference",
+            ParseError::CycleDetected { .. } => "cycle_detected",
+            ParseError::MetadataConflict(_) => "metadata_conflict",
+            ParseError::NoCurrentRegion { .. } => "no_current_region",
+            ParseError::NonFiniteNumber { .. } => "non_finite_number",
+            ParseError::EvaluationBudgetExceeded { .. } => "evaluation_budget_exceeded",
+            ParseError::EvaluationDepthExceeded { .. } => "evaluation_depth_exceeded",
+            ParseError::TooManyTuples { .. } => "too_many_tuples",
+            ParseError::InvalidAxisOrder { .. } => "invalid_axis_order",
+            ParseError::PredefinedRegionRedefined { .. } => "predefined_region_redefined",
+            ParseError::MetadataInterpolationCycle { .. } => "metadata_interpolation_cycle",
+            ParseError::UnknownInterpolationReference { .. } => "unknown_interpolation_reference",
+            ParseError::CoordinateLimitExceeded { .. } => "coordinate_limit_exceeded",
+            ParseError::UnknownConstant { .. } => "unknown_constant",
+            ParseError::DuplicateConstant { .. } => "duplicate_constant",
+            #[cfg(feature = "namespace_schemas")]
+            ParseError::UnknownSchemaVersion { .. } => "unknown_schema_version",
+            #[cfg(feature = "namespace_schemas")]
+            ParseError::SchemaViolation(_) => "schema_violation",
+        }
+    }
+}
+
+/// Where a syntax error lies, attached to it by the compiler; see
+/// [`ParseError::context`].
+///
+/// Displays as ` at tuple 2 statement 3, line 4 col 7 in: @a=rc([0,0,0],[1,x,1])`,
+/// the suffix of the error's message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementContext {
+    /// The failing statement, with the line and column of the error position
+    pub source: crate::ast::SourceLocation,
+    /// Text of the failing statement, on one line and shortened around the
+    /// error position when long
+    pub statement: String,
+}
+
+impl std::fmt::Display for StatementContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, " at {} in: {}", self.source, self.statement)
+    }
+}
+
+/// The message suffix locating an error, empty until the compiler sets it
+fn context_note(context: &Option<Box<StatementContext>>) -> String {
+    context
+        .as_ref()
+        .map(ToString::to_string)
+        .unwrap_or_default()
 }
 
 /// Large error struct for MixedRegionMode to reduce enum size.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct MixedRegionModeError {
     pub region: String,
     pub accumulator_sources: Vec<crate::ast::SourceLocation>,
@@ -112,15 +666,28 @@ pub struct MixedRegionModeError {
 }
 
 /// Large error struct for DuplicateRegionDefinition to reduce enum size.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct DuplicateRegionDefinitionError {
     pub region: String,
     pub first_source: crate::ast::SourceLocation,
     pub duplicate_source: crate::ast::SourceLocation,
 }
 
+/// Large error struct for MetadataTypeMismatch to reduce enum size.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MetadataTypeMismatchError {
+    pub region: String,
+    pub key: String,
+    pub expected: ValueKind,
+    pub found: serde_json::Value,
+    pub source: crate::ast::SourceLocation,
+}
+
 /// Large error struct for MetadataConflict to reduce enum size.
-#[derive(Debug)]
+///
+/// Serializes to a structured object so bindings can report both values and
+/// their source locations without parsing the error message.
+#[derive(Debug, PartialEq, Serialize)]
 pub struct MetadataConflictError {
     pub region: String,
     pub key: String,
@@ -141,6 +708,276 @@ pub fn compile(units: &[([i32; 3], String)]) -> Result<DslMap, Error> {
     pipeline::compile_pipeline(units)
 }
 
+/// Compile DSL input units read from an iterator, e.g. lines of a JSONL stream.
+///
+/// Units get their tuple index in iteration order, so the result is the same
+/// as [`compile`] on the collected slice. Each unit is parsed as it is read and
+/// its text dropped; only positions and parsed statements are kept until the
+/// input ends.
+pub fn compile_iter(units: impl Iterator<Item = ([i32; 3], String)>) -> Result<DslMap, Error> {
+    pipeline::compile_iter_pipeline(units, &CompileOptions::default())
+}
+
+/// Compile one DSL document as a single sign at `[0,0,0]`, without building
+/// the units yourself.
+pub fn compile_from_str(text: &str) -> Result<DslMap, Error> {
+    compile_from_str_at(text, [0, 0, 0])
+}
+
+/// Compile one DSL document as a single sign at `position`
+pub fn compile_from_str_at(text: &str, position: [i32; 3]) -> Result<DslMap, Error> {
+    compile(&[(position, text.to_string())])
+}
+
+/// Check that DSL input units compile, without building the output map.
+///
+/// Returns the same error as [`compile`]. This is not a syntax check: every
+/// region's geometry is still evaluated, which is what finds cycles, unknown
+/// regions and metadata conflicts. Only converting and shaping the output is
+/// skipped.
+pub fn validate(units: &[([i32; 3], String)]) -> Result<(), Error> {
+    validate_with_options(units, &CompileOptions::default())
+}
+
+/// Check that DSL input units compile with explicit [`CompileOptions`]; see
+/// [`validate`]
+pub fn validate_with_options(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<(), Error> {
+    pipeline::validate_pipeline(units, options)
+}
+
+/// Check that every statement of one sign's DSL text parses.
+///
+/// Lexes and parses only: no evaluation and no cross-tuple checks, so unknown
+/// regions or constants, duplicate constants and metadata conflicts are not
+/// reported. Returns the first syntax
+/// error, with positions relative to the failing statement as in [`compile`].
+pub fn validate_syntax(text: &str) -> Result<(), ParseError> {
+    validate_syntax_with_options(text, &CompileOptions::default())
+}
+
+/// Check that every statement of one sign's DSL text parses, honoring the
+/// parse-related settings in `options` such as `allow_trailing_commas`.
+pub fn validate_syntax_with_options(
+    text: &str,
+    options: &CompileOptions,
+) -> Result<(), ParseError> {
+    pipeline::validate_syntax_pipeline(text, options)
+}
+
+/// Parse DSL input units into their statements, without evaluating anything.
+///
+/// Meant for tooling such as editor outlines: only syntax errors and `@const`
+/// misuse are reported, so a program referring to an unknown region or with
+/// conflicting metadata still parses.
+pub fn parse_units(units: &[([i32; 3], String)]) -> Result<ParsedProgram, Error> {
+    parse_units_with_options(units, &CompileOptions::default())
+}
+
+/// Parse DSL input units into their statements, honoring the parse-related
+/// settings in `options` such as `allow_trailing_commas` and `max_tuples`.
+pub fn parse_units_with_options(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<ParsedProgram, Error> {
+    pipeline::parse_units_pipeline(units, options)
+}
+
+/// Compile DSL input units with explicit [`CompileOptions`].
+///
+/// `compile(units)` is equivalent to `compile_with_options(units, &CompileOptions::default())`.
+pub fn compile_with_options(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<DslMap, Error> {
+    pipeline::compile_pipeline_with_options(units, options)
+}
+
+/// Compile DSL input units with default options, also returning non-fatal
+/// diagnostics; see [`compile_verbose`].
+pub fn compile_with_diagnostics(
+    units: &[([i32; 3], String)],
+) -> Result<(DslMap, Vec<Diagnostic>), Error> {
+    compile_verbose(units, &CompileOptions::default())
+}
+
+/// Compile DSL input units, also returning non-fatal diagnostics.
+///
+/// Anonymous boxes repeated within a tuple without metadata are always
+/// reported; other warnings come from opt-in lints such as
+/// `options.warn_coordinate_style`. The map is the same as
+/// [`compile_with_options`] would return.
+pub fn compile_verbose(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<(DslMap, Vec<Diagnostic>), Error> {
+    pipeline::compile_pipeline_verbose(units, options)
+}
+
+/// Compile DSL input units, returning diagnostics on success and on failure.
+///
+/// Success and failure are the same as [`compile_verbose`]; on failure, the
+/// warnings gathered before the error are returned together with it.
+pub fn try_compile(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<(DslMap, Vec<Diagnostic>), (Error, Vec<Diagnostic>)> {
+    pipeline::compile_pipeline_try(units, options)
+}
+
+/// Compile DSL input units, reporting every error found instead of the first.
+///
+/// Meant for editor problem lists. Tuples with syntax errors are skipped and
+/// the rest are still checked, so independent errors in different tuples and
+/// metadata conflicts are all returned. Geometry is only evaluated when every
+/// tuple parsed. On success, the map is the same as [`compile`] returns.
+pub fn compile_collect(units: &[([i32; 3], String)]) -> Result<DslMap, Vec<Error>> {
+    compile_collect_with_options(units, &CompileOptions::default())
+}
+
+/// Compile DSL input units with explicit [`CompileOptions`], reporting every
+/// error found; see [`compile_collect`].
+pub fn compile_collect_with_options(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<DslMap, Vec<Error>> {
+    if units.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    pipeline::compile_collect_pipeline(units, options)
+}
+
+/// Parse and assemble DSL input units, deferring geometry evaluation.
+///
+/// The returned handle evaluates regions on demand through
+/// [`LazyCompiled::region`] or [`LazyCompiled::materialize`], caching results.
+/// Syntax and assembly errors are reported here; evaluation errors such as
+/// unknown regions or cycles are reported when the affected region is accessed.
+pub fn compile_lazy(units: &[([i32; 3], String)]) -> Result<LazyCompiled, Error> {
+    LazyCompiled::new(units, &CompileOptions::default())
+}
+
+/// Compile DSL input units and group the result by originating tuple index.
+///
+/// Each tuple maps to the entries it contributed geometry or metadata to. Regions
+/// built from several tuples appear, in full, under each of them.
+pub fn compile_by_tuple(units: &[([i32; 3], String)]) -> Result<BTreeMap<usize, DslMap>, Error> {
+    pipeline::compile_by_tuple_pipeline(units, &CompileOptions::default())
+}
+
+/// Compile DSL input units against host-provided regions.
+///
+/// Each `predefined` region behaves like an accumulator the user could have
+/// written: expressions may reference it, metadata may target it, and it is
+/// included in the output. A user statement that defines a predefined name
+/// is an error; see [`compile_with_predefined_verbose`] to downgrade it.
+pub fn compile_with_predefined(
+    units: &[([i32; 3], String)],
+    predefined: &BTreeMap<String, Vec<BoxPair>>,
+) -> Result<DslMap, Error> {
+    compile_with_predefined_verbose(units, predefined, &CompileOptions::default())
+        .map(|(dsl_map, _diagnostics)| dsl_map)
+}
+
+/// Compile DSL input units against host-provided regions, returning diagnostics.
+///
+/// With `options.predefined_collision` set to [`PredefinedCollision::Warn`],
+/// a user definition replaces the predefined region and a warning is reported.
+pub fn compile_with_predefined_verbose(
+    units: &[([i32; 3], String)],
+    predefined: &BTreeMap<String, Vec<BoxPair>>,
+    options: &CompileOptions,
+) -> Result<(DslMap, Vec<Diagnostic>), Error> {
+    pipeline::compile_pipeline_predefined(units, predefined, options)
+}
+
+/// Compile DSL input units, recording whether each metadata value is the
+/// region's own or inherited through a wildcard.
+///
+/// Regions and values are the same as [`compile_with_options`] would return.
+pub fn compile_annotated(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<AnnotatedMap, Error> {
+    pipeline::compile_annotated_pipeline(units, options)
+}
+
+/// Compile DSL input units into a map ordered by `options.ordering`, with
+/// metadata keys in statement order if `options.preserve_metadata_order` is set.
+///
+/// Entries are the same as [`compile_with_options`] would return.
+pub fn compile_ordered(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<OrderedDslMap, Error> {
+    pipeline::compile_ordered_pipeline(units, options)
+}
+
+/// Compile DSL input units with i64 positions into an i64-coordinate map.
+///
+/// Geometry is always evaluated in i64, so this needs no build feature; i32
+/// positions can be widened with `pos.map(i64::from)`.
+///
+/// Set `options.coordinate_width` to [`CoordWidth::I64`] to also accept i64
+/// literals in `rc`/`ac`; with the default [`CoordWidth::I32`], literals must
+/// still fit in i32, though tuple positions may carry results beyond it.
+pub fn compile_wide(
+    units: &[([i64; 3], String)],
+    options: &CompileOptions,
+) -> Result<DslMap64, Error> {
+    pipeline::compile_wide_pipeline(units, options)
+}
+
+/// JSON Schema (draft 2020-12) of a serialized [`DslMap`].
+///
+/// Describes the map of region IDs to entries, each with optional
+/// `bounding_boxes` (pairs of inclusive i32 corners) and `box_labels`, and a
+/// `metadata` object holding any JSON values.
+pub fn output_json_schema() -> serde_json::Value {
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Insign region map",
+        "description": "Compiled Insign output: region IDs mapped to their boxes and metadata",
+        "type": "object",
+        "additionalProperties": { "$ref": "#/$defs/DslEntry" },
+        "$defs": {
+            "Vec3": {
+                "type": "array",
+                "items": { "type": "integer", "minimum": i32::MIN, "maximum": i32::MAX },
+                "minItems": 3,
+                "maxItems": 3
+            },
+            "BoxPair": {
+                "description": "Min and max corner of a box, both inclusive",
+                "type": "array",
+                "prefixItems": [{ "$ref": "#/$defs/Vec3" }, { "$ref": "#/$defs/Vec3" }],
+                "items": false,
+                "minItems": 2
+            },
+            "DslEntry": {
+                "type": "object",
+                "properties": {
+                    "bounding_boxes": {
+                        "description": "Absent for entries without geometry, like $global and wildcards",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/BoxPair" }
+                    },
+                    "box_labels": {
+                        "description": "Labels parallel to bounding_boxes, null for unlabeled boxes",
+                        "type": "array",
+                        "items": { "type": ["string", "null"] }
+                    },
+                    "metadata": { "type": "object" }
+                },
+                "required": ["metadata"],
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,7 +1001,8 @@ mod tests {
         // Add a minimal entry
         let entry = DslEntry {
             bounding_boxes: Some(vec![([0, 0, 0], [1, 1, 1])]),
-            metadata: BTreeMap::new(),
+            box_labels: None,
+            metadata: Metadata::new(),
         };
         map.insert("test_region".to_string(), entry);
 
@@ -175,6 +1013,56 @@ mod tests {
         assert_eq!(map, deserialized);
     }
 
+    #[test]
+    fn test_dsl_entry_builder() {
+        let manual = DslEntry {
+            bounding_boxes: Some(vec![([0, 0, 0], [1, 1, 1]), ([4, 0, 0], [5, 1, 1])]),
+            box_labels: None,
+            metadata: Metadata::from([
+                ("power".to_string(), json!("low")),
+                ("tier".to_string(), json!(2)),
+            ]),
+        };
+        let built = DslEntry::builder()
+            .bbox(([0, 0, 0], [1, 1, 1]))
+            .boxes(vec![([4, 0, 0], [5, 1, 1])])
+            .meta("power", "low")
+            .meta("tier", 2)
+            .build();
+        assert_eq!(built, manual);
+        assert_eq!(built.box_count(), 2);
+        assert_eq!(built.get_meta("power"), Some(&json!("low")));
+        assert_eq!(built.get_meta("missing"), None);
+
+        let labeled: DslEntry = DslEntry::builder()
+            .bbox(([0, 0, 0], [1, 1, 1]))
+            .labels(vec![Some("north".to_string())])
+            .build();
+        assert_eq!(labeled.box_labels, Some(vec![Some("north".to_string())]));
+
+        // Without boxes the entry is metadata-only
+        let global: DslEntry = DslEntry::builder().meta("v", true).build();
+        assert_eq!(global.bounding_boxes, None);
+        assert_eq!(global.box_count(), 0);
+
+        // Keys are sorted like compiled output, so the JSON matches too
+        let units = vec![([0, 0, 0], "@r=rc([0,0,0],[1,1,1])\n#z=1\n#a=2".to_string())];
+        let compiled = &compile(&units).unwrap()["r"];
+        let built = DslEntry::builder()
+            .bbox(([0, 0, 0], [1, 1, 1]))
+            .meta("z", 1)
+            .meta("a", 2)
+            .build();
+        assert_eq!(
+            built.metadata.keys().collect::<Vec<_>>(),
+            compiled.metadata.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            serde_json::to_string(&built).unwrap(),
+            serde_json::to_string(compiled).unwrap()
+        );
+    }
+
     #[test]
     fn test_deterministic_key_ordering() {
         let mut map = DslMap::new();
@@ -184,7 +1072,8 @@ mod tests {
         for key in keys {
             let entry = DslEntry {
                 bounding_boxes: None,
-                metadata: BTreeMap::new(),
+                box_labels: None,
+                metadata: Metadata::new(),
             };
             map.insert(key.to_string(), entry);
         }
@@ -196,6 +1085,37 @@ mod tests {
         assert!(json.find("banana").unwrap() < json.find("zebra").unwrap());
     }
 
+    #[test]
+    fn test_metadata_conflict_serializes_structured() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@r=rc([0,0,0],[1,1,1])\n#label=\"First\"".to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "@r=rc([2,2,2],[3,3,3])\n#label=\"Second\"".to_string(),
+            ),
+        ];
+
+        let conflict = match compile(&units).unwrap_err() {
+            Error::Parser(ParseError::MetadataConflict(conflict)) => conflict,
+            other => panic!("Expected MetadataConflict, got {:?}", other),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&conflict).unwrap(),
+            json!({
+                "region": "r",
+                "key": "label",
+                "first_value": "First",
+                "first_source": {"tuple_idx": 0, "stmt_idx": 1},
+                "conflict_value": "Second",
+                "conflict_source": {"tuple_idx": 1, "stmt_idx": 1},
+            })
+        );
+    }
+
     #[test]
     fn test_compile_empty_input() {
         let result = compile(&[]);
@@ -298,6 +1218,572 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_compile_wide_beyond_i32() {
+        let units = vec![(
+            [5_000_000_000, 0, 0],
+            "@far=rc([0,0,0],[1,1,1])\n#far:label=\"distant\"".to_string(),
+        )];
+
+        let dsl_map = compile_wide(&units, &CompileOptions::default()).unwrap();
+        assert_eq!(
+            dsl_map["far"].bounding_boxes.as_ref().unwrap()[0],
+            ([5_000_000_000, 0, 0], [5_000_000_001, 1, 1])
+        );
+
+        // i64 literals need the widened coordinate mode (or the coords_i64 feature)
+        let units = vec![([0, 0, 0], "@far=ac([3000000000,0,0],[0,0,0])".to_string())];
+        let narrow = CompileOptions {
+            coordinate_width: CoordWidth::I32,
+            ..Default::default()
+        };
+        assert_eq!(
+            compile_wide(&units, &narrow).is_err(),
+            CoordWidth::COORD == CoordWidth::I32
+        );
+
+        let options = CompileOptions {
+            coordinate_width: CoordWidth::I64,
+            ..Default::default()
+        };
+        let dsl_map = compile_wide(&units, &options).unwrap();
+        let json = serde_json::to_value(&dsl_map).unwrap();
+        assert_eq!(
+            json["far"]["bounding_boxes"],
+            json!([[[0, 0, 0], [3_000_000_000_i64, 0, 0]]])
+        );
+    }
+
+    #[test]
+    fn test_compile_offset_overflow_is_error() {
+        // Offsets beyond the coordinate range are reported instead of overflowing
+        let text = format!("@edge=rc([{},0,0],[{},0,0])", Coord::MAX - 1, Coord::MAX);
+        let units = vec![([1, 0, 0], text)];
+
+        let error = compile(&units).unwrap_err();
+        assert!(error.to_string().contains("x axis"), "{}", error);
+
+        let options = CompileOptions {
+            coordinate_width: CoordWidth::I64,
+            ..Default::default()
+        };
+        assert!(compile_with_options(&units, &options).is_err());
+    }
+
+    #[test]
+    fn test_compile_wide_offset_overflow_names_axis() {
+        let units = vec![([0, i64::MAX, 0], "@edge=rc([0,1,0],[0,2,0])".to_string())];
+
+        match compile_wide(&units, &CompileOptions::default()).unwrap_err() {
+            Error::Parser(ParseError::InvalidBox { message, .. }) => {
+                assert!(message.contains("overflows the y axis"), "{}", message);
+                assert!(message.contains("tuple 0 statement 0"), "{}", message);
+            }
+            other => panic!("expected InvalidBox, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_compile_offset_beyond_i32_names_axis() {
+        for name in ["@edge=", "@"] {
+            let text = format!("{}rc([0,0,0],[{},0,0])", name, Coord::MAX - 4);
+            let units = vec![([5, 0, 0], text.clone())];
+            match compile(&units).unwrap_err() {
+                Error::Parser(ParseError::InvalidBox {
+                    position, message, ..
+                }) => {
+                    assert_eq!(position, 0);
+                    assert!(message.contains("the x axis"), "{}", message);
+                    assert!(message.contains("tuple 0 statement 0"), "{}", message);
+                }
+                other => panic!("expected InvalidBox for {}, got {:?}", text, other),
+            }
+        }
+        // The same box is fine where i64 output is allowed
+        let units = vec![(
+            [i64::from(i32::MAX), 0, 0],
+            "@edge=rc([0,0,0],[5,0,0])".to_string(),
+        )];
+        assert!(compile_wide(&units, &CompileOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_compile_wide_near_i32_max() {
+        // i32 positions from existing sources widen losslessly; the box near
+        // i32::MAX plus a positive offset is no overflow in i64
+        let units: Vec<([i32; 3], String)> = vec![(
+            [i32::MAX - 1, 0, 0],
+            "@edge=rc([1,0,0],[2,0,0])\n@origin=ac([0,0,0],[0,0,0])\n@wide=edge+origin".to_string(),
+        )];
+        // Only i64 output holds it
+        assert_eq!(
+            compile(&units).is_ok(),
+            CoordWidth::COORD == CoordWidth::I64
+        );
+
+        let wide_units: Vec<([i64; 3], String)> = units
+            .iter()
+            .map(|(pos, text)| (pos.map(i64::from), text.clone()))
+            .collect();
+        let dsl_map = compile_wide(&wide_units, &CompileOptions::default()).unwrap();
+        let max = i64::from(i32::MAX);
+        assert_eq!(
+            dsl_map["edge"].bounding_boxes,
+            Some(vec![([max, 0, 0], [max + 1, 0, 0])])
+        );
+        assert_eq!(dsl_map["wide"].bounding_boxes.as_ref().unwrap().len(), 2);
+    }
+
+    #[cfg(feature = "coords_i64")]
+    #[test]
+    fn test_coords_i64_near_i32_max() {
+        // With i64 output, a box near i32::MAX plus a positive offset is no overflow
+        let units = vec![(
+            [i32::MAX, 0, 0],
+            "@edge=rc([0,0,0],[5,0,0])\n@origin=ac([0,0,0],[0,0,0])\n@wide=edge+origin".to_string(),
+        )];
+        let dsl_map = compile(&units).unwrap();
+        let max = i64::from(i32::MAX);
+        assert_eq!(
+            dsl_map["edge"].bounding_boxes,
+            Some(vec![([max, 0, 0], [max + 5, 0, 0])])
+        );
+        assert_eq!(dsl_map["wide"].bounding_boxes.as_ref().unwrap().len(), 2);
+        assert!(validate(&units).is_ok());
+
+        // Literals are i64 by default too
+        let units = vec![([0, 0, 0], "@far=ac([3000000000,0,0],[0,0,0])".to_string())];
+        assert_eq!(
+            compile(&units).unwrap()["far"].bounding_boxes,
+            Some(vec![([0, 0, 0], [3_000_000_000, 0, 0])])
+        );
+    }
+
+    #[test]
+    fn test_coordinate_width_serialization() {
+        let options: CompileOptions =
+            serde_json::from_value(json!({ "coordinate_width": "i64" })).unwrap();
+        assert_eq!(options.coordinate_width, CoordWidth::I64);
+        assert_eq!(
+            serde_json::to_value(CompileOptions::default()).unwrap()["coordinate_width"],
+            json!("i32")
+        );
+    }
+
+    #[test]
+    fn test_compile_ordered_serializes_in_order() {
+        let units = vec![(
+            [0, 0, 0],
+            "@a=rc([0,0,0],[0,0,0])\n@b=rc([0,0,0],[4,4,4])\n@c=rc([0,0,0],[1,1,1])".to_string(),
+        )];
+        let options = CompileOptions {
+            ordering: OutputOrdering::VolumeDesc,
+            ..Default::default()
+        };
+
+        let ordered = compile_ordered(&units, &options).unwrap();
+        assert_eq!(ordered.keys().collect::<Vec<_>>(), vec!["b", "c", "a"]);
+        assert_eq!(ordered.get("c"), compile(&units).unwrap().get("c"));
+
+        let json = serde_json::to_string(&ordered).unwrap();
+        let positions: Vec<usize> = ["\"b\"", "\"c\"", "\"a\""]
+            .iter()
+            .map(|key| json.find(key).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_preserve_metadata_order() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@cpu=rc([0,0,0],[1,1,1])\n#zeta=1\n#alpha=2\n#mid=3".to_string(),
+            ),
+            ([0, 0, 0], "@alias cpu2=cpu\n#cpu2:beta=4".to_string()),
+        ];
+        let json = |options: &CompileOptions| {
+            serde_json::to_string(&compile_with_options(&units, options).unwrap()["cpu"]).unwrap()
+        };
+
+        let sorted = json(&CompileOptions::default());
+        assert!(sorted.contains(r#""metadata":{"alpha":2,"mid":3,"zeta":1}"#));
+
+        let options = CompileOptions {
+            preserve_metadata_order: true,
+            ..Default::default()
+        };
+        assert!(json(&options).contains(r#""metadata":{"zeta":1,"alpha":2,"mid":3}"#));
+        // Compiler keys follow the user's
+        let with_volume = CompileOptions {
+            compute_volume: true,
+            ..options.clone()
+        };
+        assert!(
+            json(&with_volume).contains(r#""metadata":{"zeta":1,"alpha":2,"mid":3,"__volume":8}"#)
+        );
+
+        let ordered = compile_ordered(&units, &options).unwrap();
+        assert_eq!(
+            ordered.metadata_keys("cpu").unwrap(),
+            vec!["zeta", "alpha", "mid"]
+        );
+        // Only the order differs
+        assert_eq!(ordered.into_map(), compile(&units).unwrap());
+
+        let mut lazy = LazyCompiled::new(&units, &options).unwrap();
+        let keys = |entry: DslEntry| entry.metadata.into_keys().collect::<Vec<_>>();
+        assert_eq!(
+            keys(lazy.region("cpu").unwrap()),
+            vec!["zeta", "alpha", "mid"]
+        );
+        assert_eq!(
+            keys(lazy.region("cpu2").unwrap()),
+            vec!["zeta", "alpha", "mid", "beta"]
+        );
+    }
+
+    #[test]
+    fn test_compile_with_diagnostics_duplicate_anonymous() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@rc([0,0,0],[1,1,1])\n@rc([0,0,0],[1,1,1])".to_string(),
+            ),
+            // Same box in another tuple, and a repeat that carries metadata
+            (
+                [0, 0, 0],
+                "@rc([0,0,0],[1,1,1])\n@rc([0,0,0],[1,1,1])\n#doc.label=\"kept\"".to_string(),
+            ),
+        ];
+
+        let (dsl_map, diagnostics) = compile_with_diagnostics(&units).unwrap();
+        assert_eq!(dsl_map, compile(&units).unwrap());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].source, Some(ast::SourceLocation::new(0, 1)));
+        assert!(diagnostics[0]
+            .message
+            .contains("repeats the one at tuple 0 statement 0"));
+    }
+
+    #[test]
+    fn test_compile_default_metadata() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@cpu.core=rc([0,0,0],[1,1,1])\n#power=\"high\"".to_string(),
+            ),
+            (
+                [10, 0, 0],
+                "@cpu.cache=rc([0,0,0],[1,1,1])\n#cpu.*:power?=\"low\"".to_string(),
+            ),
+        ];
+
+        let dsl_map = compile(&units).unwrap();
+        assert_eq!(dsl_map["cpu.core"].metadata["power"], json!("high"));
+        assert_eq!(dsl_map["cpu.cache"].metadata["power"], json!("low"));
+
+        // Without `?=` the group assignment conflicts with the region's own value
+        let units = vec![(
+            [0, 0, 0],
+            "@cpu.core=rc([0,0,0],[1,1,1])\n#power=\"high\"\n#cpu.*:power=\"low\"".to_string(),
+        )];
+        assert!(compile(&units).is_err());
+    }
+
+    #[test]
+    fn test_compile_all_regions_metadata() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "#*:power=\"low\"\n@a=rc([0,0,0],[1,1,1])\n#a:power=\"high\"".to_string(),
+            ),
+            (
+                [10, 0, 0],
+                "@b=rc([0,0,0],[1,1,1])\n@c=rc([4,4,4],[5,5,5])\n@rc([2,2,2],[3,3,3])\n#$global:x=1".to_string(),
+            ),
+        ];
+
+        let dsl_map = compile(&units).unwrap();
+        // A specific value overrides the fallback without a conflict
+        assert_eq!(dsl_map["a"].metadata["power"], json!("high"));
+        assert_eq!(dsl_map["b"].metadata["power"], json!("low"));
+        assert_eq!(dsl_map["c"].metadata["power"], json!("low"));
+        // No `*` entry, and neither `$global` nor bare anonymous boxes get the value
+        assert!(!dsl_map.contains_key("*"));
+        assert!(!dsl_map["$global"].metadata.contains_key("power"));
+        assert_eq!(dsl_map.len(), 4);
+    }
+
+    #[test]
+    fn test_validate_syntax() {
+        assert!(validate_syntax("@a=rc([0,0,0],[1,1,1])\n#label=\"ok\"\n@b=a+missing").is_ok());
+        // Semantic errors are left to compilation
+        assert!(validate_syntax("#orphan=1").is_ok());
+
+        assert!(matches!(
+            validate_syntax("@a=rc([0,0,0],[1,1,1])\n@b=rc([0,0,0],[1,1])"),
+            Err(ParseError::Expected { .. } | ParseError::InvalidVec3 { .. })
+        ));
+        assert!(validate_syntax("#label=not_json").is_err());
+
+        let trailing = "@a=rc([0,0,0,],[1,1,1])";
+        assert!(validate_syntax(trailing).is_err());
+        let options = CompileOptions {
+            allow_trailing_commas: true,
+            ..Default::default()
+        };
+        assert!(validate_syntax_with_options(trailing, &options).is_ok());
+    }
+
+    #[test]
+    fn test_output_json_schema_describes_output() {
+        let schema = output_json_schema();
+        let entry_schema = &schema["$defs"]["DslEntry"];
+        assert_eq!(entry_schema["required"], json!(["metadata"]));
+
+        let dsl_map = compile(&[(
+            [0, 0, 0],
+            "@hall=rc([0,0,0],[1,1,1])#door\n@hall=rc([4,0,0],[5,1,1])\n\
+             #hall:kind=\"room\"\n#$global:world=\"test\""
+                .to_string(),
+        )])
+        .unwrap();
+        let output = serde_json::to_value(&dsl_map).unwrap();
+        assert_eq!(schema["type"], "object");
+
+        // Every field of every entry is described, with the declared types
+        for entry in output.as_object().unwrap().values() {
+            let entry = entry.as_object().unwrap();
+            for required in entry_schema["required"].as_array().unwrap() {
+                assert!(entry.contains_key(required.as_str().unwrap()));
+            }
+            for (field, value) in entry {
+                let property = &entry_schema["properties"][field];
+                assert!(property.is_object(), "field {} not in schema", field);
+                if let Some(kind) = property["type"].as_str() {
+                    let matches = match kind {
+                        "array" => value.is_array(),
+                        "object" => value.is_object(),
+                        _ => false,
+                    };
+                    assert!(matches, "field {} is not {}", field, kind);
+                }
+            }
+            let boxes = entry
+                .get("bounding_boxes")
+                .and_then(|boxes| boxes.as_array());
+            for bounds in boxes.into_iter().flatten() {
+                let corners = bounds.as_array().unwrap();
+                assert_eq!(corners.len(), 2);
+                for corner in corners {
+                    assert_eq!(corner.as_array().unwrap().len(), 3);
+                    assert!(corner.as_array().unwrap().iter().all(|v| v.is_i64()));
+                }
+            }
+        }
+        assert!(output["hall"]["box_labels"].is_array());
+        assert!(output["$global"].get("bounding_boxes").is_none());
+    }
+
+    #[test]
+    fn test_parse_units() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@a=rc([0,0,0],[1,1,1])\n#label=\"a\"".to_string(),
+            ),
+            (
+                [5, 0, 0],
+                "@const o=[1,1,1]\n@b=a+missing\n#b:kind=2".to_string(),
+            ),
+        ];
+
+        // The unknown region is an evaluation error, so parsing still succeeds
+        assert!(matches!(
+            compile(&units),
+            Err(Error::Parser(ParseError::UnknownRegion { .. }))
+        ));
+        let program = parse_units(&units).unwrap();
+
+        let geom: Vec<_> = program
+            .geom_stmts
+            .iter()
+            .map(|stmt| (stmt.tuple_idx, stmt.stmt_idx, stmt.statement.region()))
+            .collect();
+        assert_eq!(geom, vec![(0, 0, Some("a")), (1, 1, Some("b"))]);
+        let meta: Vec<_> = program
+            .meta_stmts
+            .iter()
+            .map(|stmt| (stmt.tuple_idx, stmt.stmt_idx))
+            .collect();
+        assert_eq!(meta, vec![(0, 1), (1, 2)]);
+        let constants: Vec<_> = program
+            .constants
+            .iter()
+            .map(|stmt| (stmt.tuple_idx, stmt.stmt_idx))
+            .collect();
+        assert_eq!(constants, vec![(1, 0)]);
+
+        let broken = vec![([0, 0, 0], "@a=rc([0,0,0],[1,1".to_string())];
+        assert!(matches!(
+            parse_units(&broken),
+            Err(Error::Parser(ParseError::Expected { .. }))
+        ));
+    }
+
+    /// Minimized inputs that used to panic, mostly multibyte characters where the
+    /// parsers sliced their input by character positions
+    const CRASH_CORPUS: &[&str] = &[
+        "#é?",
+        "#é=1",
+        "#é:key=1",
+        "#cpu.é=1",
+        "#日本=\"x\"",
+        "@café=rc([0,0,0],[1,1,1])",
+        "@é=rc([0,0,0],[1,1,1])\n#label=1",
+        "@a=rc([0,0,0],[1,1,1])#é",
+        "@a=é+rc([0,0,0],[1,1,1])",
+        "@a=rc([0,0,0],[1,1,é])",
+        "@a=ac(é",
+        "@a=rc([0,0,0],[1,1,1])\n#label=\"é\"é",
+        "@a=rc([0,0,0],[1,1,1])\n#label=[\"é\",é]",
+        "@a=rc([0,0,0],[1,1,1])\n#label={\"é\":1}é",
+        "@a=rc([0,0,0],[1,1,1])\n#label=-é",
+        "@a=rc([0,0,0],[1,1,1])\n#label=é",
+        "@a=shell(é",
+        "@\u{0}=rc([0,0,0],[1,1,1])\n#\u{0}=\"\u{0}\"",
+        "@a=rc([2147483647,0,0],[2147483647,0,0])",
+        "@a=((((rc([0,0,0],[1,1,1])",
+        "@a=rc([0,0,0],[1,1,1]))))\n#k=1",
+    ];
+
+    #[test]
+    fn test_error_codes() {
+        let code = |text: &str| compile_from_str(text).unwrap_err().code();
+
+        assert_eq!(code("@a=missing"), "unknown_region");
+        assert_eq!(code("@a=b\n@b=a"), "cycle_detected");
+        assert_eq!(code("#label=1"), "no_current_region");
+        assert_eq!(
+            compile(&[
+                ([0, 0, 0], "@a=rc([0,0,0],[1,1,1])\n#kind=1".to_string()),
+                ([0, 0, 0], "#a:kind=2".to_string()),
+            ])
+            .unwrap_err()
+            .code(),
+            "metadata_conflict"
+        );
+        assert_eq!(code("@a=rc([0,0,0],[1,1"), "expected");
+        assert_eq!(code("@cpu core=rc([0,0,0],[1,1,1])"), "invalid_region_name");
+        assert_eq!(Error::NotImplemented.code(), "not_implemented");
+    }
+
+    #[test]
+    fn test_compile_from_str() {
+        let text = "@test=rc([0,0,0],[1,1,1])\n#test:label=\"Test Region\"";
+
+        let dsl_map = compile_from_str_at(text, [10, 20, 30]).unwrap();
+        assert_eq!(dsl_map.len(), 1);
+        let entry = &dsl_map["test"];
+        assert_eq!(
+            entry.bounding_boxes,
+            Some(vec![([10, 20, 30], [11, 21, 31])])
+        );
+        assert_eq!(entry.metadata["label"], json!("Test Region"));
+
+        let at_origin = compile_from_str(text).unwrap();
+        assert_eq!(
+            at_origin["test"].bounding_boxes,
+            Some(vec![([0, 0, 0], [1, 1, 1])])
+        );
+        assert_eq!(
+            at_origin,
+            compile(&[([0, 0, 0], text.to_string())]).unwrap()
+        );
+        assert!(compile_from_str("@a=missing").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = vec![
+            ([0, 0, 0], "@a=rc([0,0,0],[1,1,1])\n@b=a".to_string()),
+            ([5, 0, 0], "#b:label=\"copy\"".to_string()),
+        ];
+        assert!(validate(&valid).is_ok());
+        assert!(validate(&[]).is_ok());
+
+        let cycle = vec![([0, 0, 0], "@a=b\n@b=a".to_string())];
+        assert!(matches!(
+            validate(&cycle),
+            Err(Error::Parser(ParseError::CycleDetected { .. }))
+        ));
+
+        // Boxes beyond the coordinate range fail validation just as they
+        // fail compilation, whatever the literal width
+        let options = CompileOptions {
+            coordinate_width: CoordWidth::I64,
+            ..Default::default()
+        };
+        let beyond = vec![([5, 0, 0], format!("@a=rc([0,0,0],[{},0,0])", Coord::MAX))];
+        assert!(compile_with_options(&beyond, &options).is_err());
+        assert!(validate_with_options(&beyond, &options).is_err());
+        assert!(compile(&beyond).is_err());
+        assert!(validate(&beyond).is_err());
+    }
+
+    #[test]
+    fn test_compile_iter_matches_compile() {
+        let lines = [
+            r#"{"pos":[0,0,0],"text":"@base=ac([0,0,0],[1,1,1])\n#doc=1"}"#,
+            r#"{"pos":[10,0,0],"text":"@base=rc([0,0,0],[2,2,2])"}"#,
+            r#"{"pos":[20,0,0],"text":"@all=base\n#all:k=\"v\""}"#,
+        ];
+        let units = lines.iter().map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            let pos: [i32; 3] = serde_json::from_value(value["pos"].clone()).unwrap();
+            (pos, value["text"].as_str().unwrap().to_string())
+        });
+        let collected: Vec<_> = units.clone().collect();
+
+        let streamed = compile_iter(units).unwrap();
+        assert_eq!(streamed, compile(&collected).unwrap());
+        // Accumulated boxes follow the iteration order
+        assert_eq!(
+            streamed["base"].bounding_boxes,
+            Some(vec![([0, 0, 0], [1, 1, 1]), ([10, 0, 0], [12, 2, 2])])
+        );
+    }
+
+    #[test]
+    fn test_compile_iter_stops_reading_at_syntax_error() {
+        let read = std::cell::Cell::new(0);
+        let units = (0..100).map(|i| {
+            read.set(read.get() + 1);
+            let text = if i == 1 {
+                "@bad=ac("
+            } else {
+                "@r=rc([0,0,0],[1,1,1])"
+            };
+            ([i, 0, 0], text.to_string())
+        });
+
+        assert!(compile_iter(units).is_err());
+        assert_eq!(read.get(), 2);
+    }
+
+    #[test]
+    fn test_crash_corpus_does_not_panic() {
+        let positions = [[0, 0, 0], [i32::MAX, i32::MIN, i32::MAX], [i32::MIN; 3]];
+        for text in CRASH_CORPUS {
+            for position in positions {
+                let _ = compile(&[(position, text.to_string())]);
+            }
+            let _ = validate_syntax(text);
+        }
+    }
 }
 
 // M12: Feature Gate for Booleans (Phase 1 stub)
@@ -337,6 +1823,43 @@ mod m12_feature_gate {
         assert!(!error_message.contains("TODO"));
     }
 
+    #[test]
+    #[cfg(not(feature = "boolean_ops"))]
+    fn test_verbose_feature_errors() {
+        let options = crate::CompileOptions {
+            verbose_feature_errors: true,
+            ..Default::default()
+        };
+
+        for (text, operator, left, position) in [
+            ("@result=a-b", "-", "a", 9),
+            ("@result=(a+b)&c", "&", "(a + b)", 13),
+            ("@result=a+b^c", "^", "(a + b)", 11),
+        ] {
+            let units = vec![([0, 0, 0], text.to_string())];
+            match crate::compile_with_options(&units, &options) {
+                Err(crate::Error::Parser(crate::ParseError::FeatureGated {
+                    position: p,
+                    operator: o,
+                    left: l,
+                    ..
+                })) => {
+                    assert_eq!((p, o.as_str(), l.as_str()), (position, operator, left));
+                }
+                other => panic!("Expected FeatureGated for {}, got {:?}", text, other),
+            }
+        }
+
+        let units = vec![([0, 0, 0], "@result=a-b".to_string())];
+        let error_message = format!(
+            "{}",
+            crate::compile_with_options(&units, &options).unwrap_err()
+        );
+        assert!(error_message.contains("after 'a'"));
+        assert!(error_message.contains("--features boolean_ops"));
+        assert!(!error_message.contains("TODO"));
+    }
+
     #[test]
     #[cfg(not(feature = "boolean_ops"))]
     fn test_boolean_ops_disabled_by_default_xor() {
@@ -386,6 +1909,24 @@ mod m9_diagnostics {
         assert_snapshot!("unknown_region_error", error_message);
     }
 
+    #[test]
+    fn test_unknown_region_location() {
+        let mut units: Vec<([i32; 3], String)> = (0..5)
+            .map(|i| ([i, 0, 0], format!("@part{i}=rc([0,0,0],[1,1,1])")))
+            .collect();
+        units[3]
+            .1
+            .push_str("\n#part3:doc=1\n@combined=part0+typo+part4");
+
+        match compile(&units) {
+            Err(crate::Error::Parser(crate::ParseError::UnknownRegion { region, source })) => {
+                assert_eq!(region, "typo");
+                assert_eq!(source, crate::ast::SourceLocation::new(3, 2));
+            }
+            other => panic!("expected UnknownRegion, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_no_current_region_error() {
         // Test case: metadata without prior geometry in tuple
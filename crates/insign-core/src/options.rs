@@ -0,0 +1,461 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Options controlling how DSL input is compiled.
+///
+/// The `Default` value reproduces the behavior of [`crate::compile`].
+/// Serializes as a flat object keyed by field name; missing keys take their default.
+///
+/// New options may be added in any release, so outside this crate start from
+/// `CompileOptions::default()` and set fields on it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct CompileOptions {
+    /// Tolerate a single trailing comma before the closing `]` of a vec3 or the
+    /// closing `)` of a box, e.g. `rc([0,0,0,],[1,1,1],)`. Default: strict.
+    pub allow_trailing_commas: bool,
+    /// Reject an `rc`/`ac` box whose first corner exceeds the second on any
+    /// axis with `ParseError::InvalidBox`, naming the axis, instead of
+    /// swapping the corners. Default: corners are normalized.
+    pub strict_box_order: bool,
+    /// Only `@r+=` adds boxes to a region: a second `@r=` box statement for the
+    /// same region fails with `ParseError::DuplicateRegionDefinition`.
+    /// Default: repeating `@r=` accumulates like `@r+=`.
+    pub strict_region_assignment: bool,
+    /// Keep anonymous regions that have geometry but no metadata in the output.
+    /// Default: such regions are dropped.
+    pub keep_empty_anonymous: bool,
+    /// Bind current-region metadata (`#key=value`) by position: to the nearest
+    /// geometry statement before it in the tuple or, when it precedes all of
+    /// them, to the next one. Default: binds to the last geometry in the tuple.
+    pub current_region_binds_forward: bool,
+    /// Integer width of coordinate literals and evaluated boxes. With
+    /// [`CoordWidth::I64`], use [`crate::compile_wide`] to get results that
+    /// exceed i32. With the `coords_i64` feature, whose [`crate::BoxPair`] is
+    /// i64, coordinates are i64 everywhere and this has no effect. Default:
+    /// [`CoordWidth::I32`].
+    pub coordinate_width: CoordWidth,
+    /// Warn when `ac` boxes hug their sign or `rc` boxes reach far from it,
+    /// a common mix-up between absolute and relative coordinates. Warnings are
+    /// reported by [`crate::compile_verbose`]. Default: off.
+    pub warn_coordinate_style: bool,
+    /// Distances used by `warn_coordinate_style`
+    pub coordinate_style_thresholds: CoordinateStyleThresholds,
+    /// What to do when user geometry defines a region passed to
+    /// [`crate::compile_with_predefined`]. Default: [`PredefinedCollision::Error`].
+    pub predefined_collision: PredefinedCollision,
+    /// Maximum box operations performed while evaluating expressions, counted
+    /// across all regions including intermediate results. Metadata
+    /// interpolation separately gets the same limit in bytes of text written.
+    /// Exceeding either fails with `ParseError::EvaluationBudgetExceeded`.
+    /// Default: 10,000,000.
+    pub max_eval_steps: u64,
+    /// Maximum nesting while evaluating a region, counting every region
+    /// reference followed and every parenthesized or function sub-expression
+    /// entered: a chain `@b=a`, `@c=b`, … adds a level per link and
+    /// `expand((a+b), 1)` a few, while a flat `a+b+c+…` of any length takes
+    /// one level plus its operands. Regions evaluated before are not entered
+    /// again.
+    /// Deeper input fails with `ParseError::EvaluationDepthExceeded` instead
+    /// of overflowing the stack. Default: 256.
+    pub max_eval_depth: usize,
+    /// Simplify region expressions with [`crate::ast::BooleanExpr::simplify`]
+    /// before evaluating them, so `a + a` yields `a`'s boxes once. Default: off.
+    pub simplify_exprs: bool,
+    /// Also accept `RC(`, `Ac(` and other capitalizations of the geometry
+    /// keywords, and of the aliases below. Default: lowercase only.
+    pub geometry_keywords_case_insensitive: bool,
+    /// Extra keywords for the geometry primitives, e.g. `"box"` for `rc`.
+    /// Default: none.
+    pub geometry_keyword_aliases: BTreeMap<String, GeomKind>,
+    /// Entry order of [`crate::compile_ordered`] output, and of the JSON the
+    /// CLI, FFI and WASM bindings print. Functions returning a
+    /// [`crate::DslMap`] always order by name. Default: [`OutputOrdering::Name`];
+    /// up to 0.1.1 it was [`OutputOrdering::Category`], which must now be set
+    /// explicitly to keep that order.
+    pub ordering: OutputOrdering,
+    /// List each entry's metadata keys in the order of the statements that set
+    /// them rather than by name, in every compiled [`crate::DslEntry`] and so in
+    /// its JSON. Keys added by the compiler, like `__volume`, follow. Default:
+    /// sorted by name.
+    pub preserve_metadata_order: bool,
+    /// Maximum number of input tuples (signs). More fail compilation with
+    /// `ParseError::TooManyTuples` before anything is parsed. Default: no limit.
+    pub max_tuples: Option<usize>,
+    /// Axis stored at each index of incoming tuple positions and predefined
+    /// boxes, e.g. `[X, Z, Y]` for tools that emit `[x, z, y]`. They are
+    /// reordered to XYZ before compiling; coordinates written in the DSL and all
+    /// output stay XYZ. Must name each axis once. Default: `[X, Y, Z]`.
+    pub position_axis_order: [Axis; 3],
+    /// Replace each region's boxes with the fewest boxes a greedy sweep finds
+    /// covering the same blocks (see [`crate::ast::minimize_boxes`]). Regions
+    /// with box labels keep their boxes. Default: boxes as evaluated.
+    pub minimize_boxes: bool,
+    /// Merge boxes of a region whose union is exactly one box: duplicates,
+    /// boxes inside others, and boxes sharing a full face (see
+    /// [`crate::ast::coalesce_boxes`]). Other boxes are kept as they are, so
+    /// this never splits a box. Regions with box labels keep their boxes.
+    /// Default: boxes as evaluated.
+    pub coalesce_boxes: bool,
+    /// Sort each region's boxes by min corner, then max corner, so the output
+    /// does not depend on evaluation order: `a+b` and `b+a` emit the same list,
+    /// as do differences whose fragments come out in another order. Regions
+    /// with box labels keep their order. Default: boxes as evaluated.
+    pub sort_boxes: bool,
+    /// Largest absolute coordinate an evaluated box may reach, e.g. a world
+    /// border. A region beyond it fails with
+    /// `ParseError::CoordinateLimitExceeded`. Default: only the coordinate
+    /// width limits coordinates.
+    pub max_abs_coordinate: Option<u64>,
+    /// Add each region's block count as `__volume` metadata, summed over its
+    /// boxes so overlaps count twice unless `minimize_boxes` is also set; see
+    /// [`crate::ast::add_volume_metadata`]. Default: off.
+    pub compute_volume: bool,
+    /// Replace `${region:key}` in string metadata values with that region's
+    /// value for `key`; see [`crate::ast::interpolate_metadata`]. Default:
+    /// strings are kept as written.
+    pub interpolate_metadata: bool,
+    /// Without the `boolean_ops` feature, report `-`, `&` and `^` as
+    /// `ParseError::FeatureGated`, naming the operand before the operator and
+    /// the feature to enable. Default: `ParseError::UnsupportedOperator`.
+    pub verbose_feature_errors: bool,
+    /// How a second, different value for a region's metadata key is combined
+    /// with the first. Default: [`MetadataMergePolicy::Conflict`].
+    pub metadata_merge_policy: MetadataMergePolicy,
+    /// Required JSON type of metadata keys, e.g. `{"logic.clock_hz": "integer"}`.
+    /// Assigning a value of another type is a `ParseError::MetadataTypeMismatch`.
+    /// Default: none.
+    pub metadata_schema: BTreeMap<String, ValueKind>,
+    /// Schemas for metadata namespaces, keyed by `(namespace, version)`. A
+    /// namespace is checked against the version declared with
+    /// `#$global:_schema.<namespace>="<version>"`; see
+    /// [`crate::schema::validate_namespace_schemas`]. Serializes as
+    /// `{namespace: {version: schema}}`. Default: none.
+    #[cfg(feature = "namespace_schemas")]
+    #[serde(
+        serialize_with = "crate::schema::serialize_nested",
+        deserialize_with = "crate::schema::deserialize_nested"
+    )]
+    pub namespace_schemas: crate::schema::NamespaceSchemas,
+}
+
+impl CompileOptions {
+    /// Names of all options, as used in serialized form
+    pub fn option_names() -> Vec<String> {
+        match serde_json::to_value(Self::default()) {
+            Ok(serde_json::Value::Object(fields)) => fields.keys().cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Build options from a JSON object; missing keys take their default.
+    ///
+    /// Unknown keys are ignored, or with `strict` rejected with an error
+    /// naming the key by its dotted path and listing the valid names beside
+    /// it. Nested objects with fixed fields, like `coordinate_style_thresholds`
+    /// and each namespace schema, are checked too; maps keyed by user names,
+    /// like `metadata_schema`, are not.
+    pub fn from_json(
+        fields: serde_json::Map<String, serde_json::Value>,
+        strict: bool,
+    ) -> Result<Self, serde_json::Error> {
+        if strict {
+            if let Some((path, names)) = Self::find_unknown_option(&fields) {
+                return Err(serde::de::Error::custom(format!(
+                    "unknown option '{}'; valid options: {}",
+                    path,
+                    names.join(", ")
+                )));
+            }
+        }
+        serde_json::from_value(serde_json::Value::Object(fields))
+    }
+
+    /// The first key of `fields` that no option or nested option field has
+    fn find_unknown_option(
+        fields: &serde_json::Map<String, Value>,
+    ) -> Option<(String, Vec<String>)> {
+        let template = match serde_json::to_value(Self::default()) {
+            Ok(Value::Object(template)) => template,
+            _ => return None,
+        };
+        if let Some(unknown) = find_unknown_key(fields, &template) {
+            return Some(unknown);
+        }
+
+        // Schemas sit below two levels of user names: `{namespace: {version: schema}}`
+        #[cfg(feature = "namespace_schemas")]
+        if let (Some(Value::Object(namespaces)), Ok(Value::Object(schema_template))) = (
+            fields.get("namespace_schemas"),
+            serde_json::to_value(crate::schema::Schema::default()),
+        ) {
+            for (namespace, versions) in namespaces {
+                let Value::Object(versions) = versions else {
+                    continue;
+                };
+                for (version, schema) in versions {
+                    let Value::Object(schema) = schema else {
+                        continue;
+                    };
+                    if let Some((path, names)) = find_unknown_key(schema, &schema_template) {
+                        let path = format!("namespace_schemas.{}.{}.{}", namespace, version, path);
+                        return Some((path, names));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Find a key of `fields` missing from `template`, a serialized default,
+/// descending into nested objects the template has fields for. Returns the
+/// key's dotted path and the keys valid beside it. Objects the template
+/// leaves empty are maps keyed by user names, whose keys are not checked.
+fn find_unknown_key(
+    fields: &serde_json::Map<String, Value>,
+    template: &serde_json::Map<String, Value>,
+) -> Option<(String, Vec<String>)> {
+    for (key, value) in fields {
+        let Some(expected) = template.get(key) else {
+            return Some((key.clone(), template.keys().cloned().collect()));
+        };
+        if let (Value::Object(nested), Value::Object(nested_template)) = (value, expected) {
+            if nested_template.is_empty() {
+                continue;
+            }
+            if let Some((path, names)) = find_unknown_key(nested, nested_template) {
+                return Some((format!("{}.{}", key, path), names));
+            }
+        }
+    }
+    None
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            allow_trailing_commas: false,
+            strict_box_order: false,
+            strict_region_assignment: false,
+            keep_empty_anonymous: false,
+            current_region_binds_forward: false,
+            coordinate_width: CoordWidth::default(),
+            warn_coordinate_style: false,
+            coordinate_style_thresholds: CoordinateStyleThresholds::default(),
+            predefined_collision: PredefinedCollision::default(),
+            max_eval_steps: 10_000_000,
+            max_eval_depth: crate::ast::DEFAULT_MAX_EVAL_DEPTH,
+            simplify_exprs: false,
+            geometry_keywords_case_insensitive: false,
+            geometry_keyword_aliases: BTreeMap::new(),
+            ordering: OutputOrdering::default(),
+            preserve_metadata_order: false,
+            max_tuples: None,
+            position_axis_order: Axis::XYZ,
+            minimize_boxes: false,
+            coalesce_boxes: false,
+            sort_boxes: false,
+            max_abs_coordinate: None,
+            compute_volume: false,
+            interpolate_metadata: false,
+            verbose_feature_errors: false,
+            metadata_merge_policy: MetadataMergePolicy::default(),
+            metadata_schema: BTreeMap::new(),
+            #[cfg(feature = "namespace_schemas")]
+            namespace_schemas: BTreeMap::new(),
+        }
+    }
+}
+
+/// Distances (in blocks) for the coordinate style lint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoordinateStyleThresholds {
+    /// An `ac` box with every corner within this distance of its sign is flagged
+    pub absolute_near: u64,
+    /// An `rc` box with any coordinate beyond this distance is flagged
+    pub relative_far: u64,
+}
+
+impl Default for CoordinateStyleThresholds {
+    fn default() -> Self {
+        Self {
+            absolute_near: 8,
+            relative_far: 1024,
+        }
+    }
+}
+
+/// A coordinate axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    /// Minecraft's axis order, used throughout compilation and output
+    pub const XYZ: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    /// Check that `order` names each axis exactly once
+    pub fn is_permutation(order: [Axis; 3]) -> bool {
+        Axis::XYZ.iter().all(|axis| order.contains(axis))
+    }
+
+    /// Reorder `values`, stored in `order`, to XYZ. `order` must be a permutation.
+    pub fn to_xyz<T: Copy>(order: [Axis; 3], values: [T; 3]) -> [T; 3] {
+        let mut xyz = values;
+        for (value, axis) in values.into_iter().zip(order) {
+            xyz[axis as usize] = value;
+        }
+        xyz
+    }
+}
+
+/// Order of entries in an [`crate::OrderedDslMap`].
+///
+/// The default is `Name` so that bindings printing in this order match a
+/// [`crate::DslMap`]; up to 0.1.1 it was `Category`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputOrdering {
+    /// By region ID, as in a [`crate::DslMap`]
+    #[default]
+    Name,
+    /// `$global`, then wildcards, named regions and anonymous regions, each by name
+    Category,
+    /// Largest total box volume first
+    VolumeDesc,
+    /// Most boxes first
+    BoxCountDesc,
+}
+
+/// The geometry primitive a keyword stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeomKind {
+    /// `rc`: coordinates relative to the sign
+    Relative,
+    /// `ac`: absolute world coordinates
+    Absolute,
+}
+
+/// Handling of user statements that redefine a predefined region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PredefinedCollision {
+    /// Fail compilation
+    #[default]
+    Error,
+    /// Let the user definition replace the predefined region and report a warning
+    Warn,
+}
+
+/// JSON type a metadata value must have
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueKind {
+    String,
+    Number,
+    /// A number without fractional part
+    Integer,
+    #[serde(alias = "bool")]
+    Boolean,
+    Array,
+    Object,
+    /// Any JSON value, including `null`
+    Any,
+}
+
+impl ValueKind {
+    /// Check whether `value` has this kind
+    pub fn matches(self, value: &Value) -> bool {
+        match self {
+            ValueKind::String => value.is_string(),
+            ValueKind::Number => value.is_number(),
+            ValueKind::Integer => value.is_i64() || value.is_u64(),
+            ValueKind::Boolean => value.is_boolean(),
+            ValueKind::Array => value.is_array(),
+            ValueKind::Object => value.is_object(),
+            ValueKind::Any => true,
+        }
+    }
+}
+
+impl std::fmt::Display for ValueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ValueKind::String => "string",
+            ValueKind::Number => "number",
+            ValueKind::Integer => "integer",
+            ValueKind::Boolean => "boolean",
+            ValueKind::Array => "array",
+            ValueKind::Object => "object",
+            ValueKind::Any => "any",
+        })
+    }
+}
+
+/// Handling of different values assigned to the same metadata key of a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataMergePolicy {
+    /// Fail with `ParseError::MetadataConflict`
+    #[default]
+    Conflict,
+    /// Keep the value and source of the statement applied last
+    LastWins,
+    /// Append the elements of two arrays, keeping the first of any equal
+    /// elements; other values conflict
+    MergeArrays,
+    /// Merge two objects key by key, recursing into nested objects; differing
+    /// non-object values, at any depth, conflict
+    DeepMergeObjects,
+}
+
+/// Integer width used for coordinates during compilation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoordWidth {
+    /// Minecraft's range: literals and results must fit in i32
+    #[default]
+    I32,
+    /// Literals and results may use the full i64 range
+    I64,
+}
+
+impl CoordWidth {
+    /// The width of [`crate::Coord`]
+    pub(crate) const COORD: CoordWidth = if cfg!(feature = "coords_i64") {
+        CoordWidth::I64
+    } else {
+        CoordWidth::I32
+    };
+
+    /// The width coordinates are handled in: this one, or i64 with the
+    /// `coords_i64` feature
+    pub(crate) fn resolved(self) -> CoordWidth {
+        Ord::max(self, CoordWidth::COORD)
+    }
+
+    /// Smallest representable coordinate
+    pub fn min(self) -> i64 {
+        match self {
+            CoordWidth::I32 => i64::from(i32::MIN),
+            CoordWidth::I64 => i64::MIN,
+        }
+    }
+
+    /// Largest representable coordinate
+    pub fn max(self) -> i64 {
+        match self {
+            CoordWidth::I32 => i64::from(i32::MAX),
+            CoordWidth::I64 => i64::MAX,
+        }
+    }
+}
@@ -1,25 +1,39 @@
-use crate::ast::BooleanExpr;
-use crate::{BoxPair, ParseError};
+use crate::ast::output::narrow_vec3;
+use crate::ast::{compute_difference, BooleanExpr};
+use crate::parser::take_chars;
+use crate::{BoxPair, BoxPair64, CompileOptions, CoordWidth, GeomKind, ParseError, UnitPosition};
+use std::collections::BTreeMap;
 
 /// A 3D vector coordinate
-pub type Vec3 = [i32; 3];
+pub type Vec3 = [crate::Coord; 3];
+
+/// A 3D vector coordinate in i64, whatever the configured [`CoordWidth`]
+pub type Vec3Wide = [i64; 3];
+
+/// A [`GeometryStatement`] with i64 coordinates, as parsed by
+/// [`GeometryParser::parse_wide`]
+pub type GeometryStatementWide = GeometryStatement<Vec3Wide>;
 
 /// Types of geometry statements
 #[derive(Debug, Clone, PartialEq)]
-pub enum GeometryStatement {
+pub enum GeometryStatement<V = Vec3> {
     /// Relative coordinate box: rc([x1,y1,z1],[x2,y2,z2])
     RelativeCoordinate {
         /// The region name if this is a named region (e.g., @region=rc(...))
         region: Option<String>,
         /// The two corner coordinates
-        corners: (Vec3, Vec3),
+        corners: (V, V),
+        /// Label of this box within its region (e.g., @wall=rc(...)#north)
+        label: Option<String>,
     },
     /// Absolute coordinate box: ac([x1,y1,z1],[x2,y2,z2])
     AbsoluteCoordinate {
         /// The region name if this is a named region (e.g., @region=ac(...))
         region: Option<String>,
         /// The two corner coordinates
-        corners: (Vec3, Vec3),
+        corners: (V, V),
+        /// Label of this box within its region (e.g., @wall=ac(...)#north)
+        label: Option<String>,
     },
     /// Boolean expression: @region=expr
     Expression {
@@ -28,50 +42,355 @@ pub enum GeometryStatement {
         /// The boolean expression
         expr: BooleanExpr,
     },
+    /// Hollow box: shell(rc(...), thickness=n) or shell(ac(...), thickness=n)
+    Shell {
+        /// The region name if this is a named region (e.g., @wall=shell(...))
+        region: Option<String>,
+        /// The outer box, an unnamed `rc`/`ac` statement
+        outer: Box<GeometryStatement<V>>,
+        /// Wall thickness in blocks, at least 1
+        thickness: u64,
+    },
+    /// Sphere around a center relative to the tuple: sp([x,y,z], r)
+    Sphere {
+        /// The region name if this is a named region (e.g., @dome=sp(...))
+        region: Option<String>,
+        /// The center block, offset by the tuple position like `rc`
+        center: V,
+        /// Blocks within this Euclidean distance of the center are covered
+        radius: u64,
+    },
+    /// Named coordinate constant: @const name=[x,y,z]
+    Const {
+        /// The constant's name, usable in place of a vec3 in later statements
+        name: String,
+        /// The coordinates it stands for, used as written in `rc` and `ac`
+        value: V,
+    },
+    /// Region alias: @alias name=target, a copy of another region's boxes and
+    /// metadata under a new name
+    Alias {
+        /// The new region name
+        name: String,
+        /// The region it copies
+        target: String,
+    },
 }
 
 impl GeometryStatement {
     /// Convert this geometry statement to a normalized BoxPair.
     /// For RelativeCoordinate, applies the offset to make it absolute.
-    /// Note: Expression variants don't have direct box pairs - they need evaluation first.
+    /// A sphere yields its bounding cube.
+    /// Note: Expression variants don't have direct box pairs - they need evaluation first,
+    /// so they return None, as do constants and a box the offset pushes out of range.
     pub fn to_box_pair(&self, offset: Vec3) -> Option<BoxPair> {
+        let (min, max) = self.widen().to_box_pair_wide(offset.to_wide())?;
+        Some((narrow_vec3(min)?, narrow_vec3(max)?))
+    }
+
+    /// Convert this statement to the disjoint boxes it covers, see
+    /// [`GeometryStatementWide::to_boxes_wide`]. Returns None where
+    /// [`Self::to_box_pair`] does.
+    pub fn to_boxes(&self, offset: Vec3) -> Option<Vec<BoxPair>> {
+        self.widen()
+            .to_boxes_wide(offset.to_wide())?
+            .into_iter()
+            .map(|(min, max)| Some((narrow_vec3(min)?, narrow_vec3(max)?)))
+            .collect()
+    }
+
+    /// This statement with i64 coordinates
+    pub fn widen(&self) -> GeometryStatementWide {
+        self.clone()
+            .try_map(&mut |corner: Vec3| Some(corner.to_wide()))
+            .expect("widening cannot fail")
+    }
+}
+
+impl GeometryStatementWide {
+    /// [`GeometryStatement::to_box_pair`] in i64, returning None only where
+    /// the offset overflows i64
+    pub fn to_box_pair_wide(&self, offset: Vec3Wide) -> Option<BoxPair64> {
         match self {
             GeometryStatement::RelativeCoordinate { corners, .. } => {
                 let (c1, c2) = *corners;
-                let corner1 = [c1[0] + offset[0], c1[1] + offset[1], c1[2] + offset[2]];
-                let corner2 = [c2[0] + offset[0], c2[1] + offset[1], c2[2] + offset[2]];
-                Some(normalize_box(corner1, corner2))
+                let corner1 = offset_vec3(c1, offset)?;
+                let corner2 = offset_vec3(c2, offset)?;
+                Some(normalize_box_wide(corner1, corner2))
             }
             GeometryStatement::AbsoluteCoordinate { corners, .. } => {
-                Some(normalize_box(corners.0, corners.1))
+                Some(normalize_box_wide(corners.0, corners.1))
             }
             GeometryStatement::Expression { .. } => None, // Expressions need evaluation
+            GeometryStatement::Const { .. } | GeometryStatement::Alias { .. } => None,
+            GeometryStatement::Shell { outer, .. } => outer.to_box_pair_wide(offset),
+            GeometryStatement::Sphere { center, radius, .. } => {
+                let center = offset_vec3(*center, offset)?;
+                let radius = i64::try_from(*radius).ok()?;
+                Some((
+                    [
+                        center[0].checked_sub(radius)?,
+                        center[1].checked_sub(radius)?,
+                        center[2].checked_sub(radius)?,
+                    ],
+                    offset_vec3(center, [radius; 3])?,
+                ))
+            }
+        }
+    }
+
+    /// Find the first axis (0 = x, 1 = y, 2 = z) on which applying `offset`
+    /// leaves the i64 range, i.e. why [`Self::to_box_pair_wide`] returned None
+    /// for a box statement. A sphere radius beyond i64 reports the x axis.
+    pub fn overflowing_axis(&self, offset: Vec3Wide) -> Option<usize> {
+        match self {
+            GeometryStatement::RelativeCoordinate { corners, .. } => (0..3).find(|&axis| {
+                corners.0[axis].checked_add(offset[axis]).is_none()
+                    || corners.1[axis].checked_add(offset[axis]).is_none()
+            }),
+            GeometryStatement::Shell { outer, .. } => outer.overflowing_axis(offset),
+            GeometryStatement::Sphere { center, radius, .. } => {
+                let Ok(radius) = i64::try_from(*radius) else {
+                    return Some(0);
+                };
+                (0..3).find(|&axis| match center[axis].checked_add(offset[axis]) {
+                    Some(c) => c.checked_sub(radius).is_none() || c.checked_add(radius).is_none(),
+                    None => true,
+                })
+            }
+            GeometryStatement::AbsoluteCoordinate { .. }
+            | GeometryStatement::Expression { .. }
+            | GeometryStatement::Const { .. }
+            | GeometryStatement::Alias { .. } => None,
+        }
+    }
+
+    /// Convert this statement to the disjoint boxes it covers.
+    /// A shell yields its walls, or the solid outer box when the thickness
+    /// leaves no interior; a sphere yields one box per run of equal z-extent;
+    /// other box statements yield one box. Returns None where
+    /// [`Self::to_box_pair_wide`] does.
+    pub fn to_boxes_wide(&self, offset: Vec3Wide) -> Option<Vec<BoxPair64>> {
+        let outer = self.to_box_pair_wide(offset)?;
+        let thickness = match self {
+            GeometryStatement::Shell { thickness, .. } => thickness,
+            GeometryStatement::Sphere { center, radius, .. } => {
+                return Some(sphere_boxes(offset_vec3(*center, offset)?, *radius));
+            }
+            _ => return Some(vec![outer]),
+        };
+
+        let inset = i64::try_from(*thickness).unwrap_or(i64::MAX);
+        let inner = (
+            outer.0.map(|c| c.saturating_add(inset)),
+            outer.1.map(|c| c.saturating_sub(inset)),
+        );
+        if (0..3).any(|axis| inner.0[axis] > inner.1[axis]) {
+            return Some(vec![outer]);
         }
+        Some(compute_difference(&[outer], &[inner]))
     }
+}
 
+impl<V> GeometryStatement<V> {
     /// Get the region name if this is a named geometry statement
     pub fn region(&self) -> Option<&str> {
         match self {
             GeometryStatement::RelativeCoordinate { region, .. } => region.as_deref(),
             GeometryStatement::AbsoluteCoordinate { region, .. } => region.as_deref(),
             GeometryStatement::Expression { region, .. } => Some(region),
+            GeometryStatement::Shell { region, .. } => region.as_deref(),
+            GeometryStatement::Sphere { region, .. } => region.as_deref(),
+            GeometryStatement::Const { .. } => None,
+            GeometryStatement::Alias { name, .. } => Some(name),
+        }
+    }
+
+    /// Get the box label, if this is a labeled `rc`/`ac` box
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            GeometryStatement::RelativeCoordinate { label, .. }
+            | GeometryStatement::AbsoluteCoordinate { label, .. } => label.as_deref(),
+            GeometryStatement::Expression { .. }
+            | GeometryStatement::Shell { .. }
+            | GeometryStatement::Sphere { .. }
+            | GeometryStatement::Const { .. }
+            | GeometryStatement::Alias { .. } => None,
         }
     }
+
+    /// This statement with `f` applied to every coordinate, or None if `f`
+    /// returns None for any of them
+    fn try_map<W>(self, f: &mut impl FnMut(V) -> Option<W>) -> Option<GeometryStatement<W>> {
+        Some(match self {
+            GeometryStatement::RelativeCoordinate {
+                region,
+                corners,
+                label,
+            } => GeometryStatement::RelativeCoordinate {
+                region,
+                corners: (f(corners.0)?, f(corners.1)?),
+                label,
+            },
+            GeometryStatement::AbsoluteCoordinate {
+                region,
+                corners,
+                label,
+            } => GeometryStatement::AbsoluteCoordinate {
+                region,
+                corners: (f(corners.0)?, f(corners.1)?),
+                label,
+            },
+            GeometryStatement::Expression { region, expr } => {
+                GeometryStatement::Expression { region, expr }
+            }
+            GeometryStatement::Shell {
+                region,
+                outer,
+                thickness,
+            } => GeometryStatement::Shell {
+                region,
+                outer: Box::new(outer.try_map(f)?),
+                thickness,
+            },
+            GeometryStatement::Sphere {
+                region,
+                center,
+                radius,
+            } => GeometryStatement::Sphere {
+                region,
+                center: f(center)?,
+                radius,
+            },
+            GeometryStatement::Const { name, value } => GeometryStatement::Const {
+                name,
+                value: f(value)?,
+            },
+            GeometryStatement::Alias { name, target } => GeometryStatement::Alias { name, target },
+        })
+    }
 }
 
+/// Keyword and opening paren of the hollow-box form
+const SHELL_KEYWORD: &str = "shell(";
+
+/// Keyword of the constant declaration, followed by whitespace
+const CONST_KEYWORD: &str = "const";
+
+/// Keyword of the region alias statement, followed by whitespace
+const ALIAS_KEYWORD: &str = "alias";
+
+/// Keyword and opening paren of the sphere form
+const SPHERE_KEYWORD: &str = "sp(";
+
+/// Largest accepted sphere radius; a sphere takes O(radius²) boxes
+pub const MAX_SPHERE_RADIUS: u64 = 256;
+
+/// Opening of the expansion term, `expand(expr, n)`
+const EXPAND_KEYWORD: &str = "expand(";
+
+/// Opening of the inset term, `inset(expr, n)`
+const INSET_KEYWORD: &str = "inset(";
+
+/// Opening of the translation term, `translate(expr, [dx,dy,dz])`
+const TRANSLATE_KEYWORD: &str = "translate(";
+
+/// Opening of a metadata group term, `group_by(key == value)`
+#[cfg(feature = "metadata_groups")]
+const GROUP_BY_KEYWORD: &str = "group_by(";
+
 /// Parser for geometry statements
 pub struct GeometryParser<'a> {
     input: &'a str,
     position: usize,
+    allow_trailing_commas: bool,
+    strict_box_order: bool,
+    coordinate_width: CoordWidth,
+    keywords_case_insensitive: bool,
+    keyword_aliases: BTreeMap<String, GeomKind>,
+    #[cfg_attr(feature = "boolean_ops", allow(dead_code))] // Operators are never gated then
+    verbose_feature_errors: bool,
+    constants: Option<&'a BTreeMap<String, Vec3Wide>>,
+    accept_unknown_constants: bool,
+    disabled: bool,
+    narrow: bool,
 }
 
 impl<'a> GeometryParser<'a> {
     pub fn new(input: &'a str) -> Self {
-        Self { input, position: 0 }
+        Self {
+            input,
+            position: 0,
+            allow_trailing_commas: false,
+            strict_box_order: false,
+            coordinate_width: CoordWidth::COORD,
+            keywords_case_insensitive: false,
+            keyword_aliases: BTreeMap::new(),
+            verbose_feature_errors: false,
+            constants: None,
+            accept_unknown_constants: false,
+            disabled: false,
+            narrow: false,
+        }
     }
 
-    /// Parse a geometry statement from the input
+    /// Create a parser honoring the parse-related settings in `options`
+    pub fn with_options(input: &'a str, options: &CompileOptions) -> Self {
+        Self {
+            allow_trailing_commas: options.allow_trailing_commas,
+            strict_box_order: options.strict_box_order,
+            coordinate_width: options.coordinate_width.resolved(),
+            keywords_case_insensitive: options.geometry_keywords_case_insensitive,
+            keyword_aliases: options.geometry_keyword_aliases.clone(),
+            verbose_feature_errors: options.verbose_feature_errors,
+            ..Self::new(input)
+        }
+    }
+
+    /// Resolve constant names in place of vec3 literals using `constants`
+    pub fn with_constants(self, constants: &'a BTreeMap<String, Vec3Wide>) -> Self {
+        Self {
+            constants: Some(constants),
+            ..self
+        }
+    }
+
+    /// Read any constant name as `[0,0,0]` instead of failing, for syntax checks
+    /// that cannot see declarations in other tuples
+    pub fn accepting_unknown_constants(self) -> Self {
+        Self {
+            accept_unknown_constants: true,
+            ..self
+        }
+    }
+
+    /// Parse a geometry statement from the input. Coordinates, including
+    /// substituted constants, must fit [`Vec3`] as well as the configured
+    /// [`CoordWidth`].
     pub fn parse(&mut self) -> Result<GeometryStatement, ParseError> {
+        self.narrow = true;
+        let statement = self.parse_statement();
+        self.narrow = false;
+        Ok(statement?
+            .0
+            .try_map(&mut narrow_vec3)
+            .expect("narrow parsing keeps coordinates within Coord"))
+    }
+
+    /// Parse a geometry statement from the input, with coordinates in the
+    /// range of the configured [`CoordWidth`]
+    pub fn parse_wide(&mut self) -> Result<GeometryStatementWide, ParseError> {
+        self.parse_wide_with_append()
+            .map(|(statement, _append)| statement)
+    }
+
+    /// Like [`Self::parse_wide`], also returning whether the statement was
+    /// written `@region+=`, explicitly adding boxes to the region
+    pub fn parse_wide_with_append(&mut self) -> Result<(GeometryStatementWide, bool), ParseError> {
+        self.parse_statement()
+    }
+
+    fn parse_statement(&mut self) -> Result<(GeometryStatementWide, bool), ParseError> {
         self.skip_whitespace();
 
         // Expect '@' at the start
@@ -80,11 +399,29 @@ impl<'a> GeometryParser<'a> {
                 expected: "'@'",
                 found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
+                context: None,
             });
         }
 
+        // '@!' marks a disabled statement: still parsed, but excluded from compilation
+        self.disabled = self.consume_char('!');
+
+        let after_keyword = self.rest().strip_prefix(CONST_KEYWORD);
+        if after_keyword.is_some_and(|rest| rest.starts_with(char::is_whitespace)) {
+            self.position += CONST_KEYWORD.len();
+            return Ok((self.parse_const()?, false));
+        }
+        let after_keyword = self.rest().strip_prefix(ALIAS_KEYWORD);
+        if after_keyword.is_some_and(|rest| rest.starts_with(char::is_whitespace)) {
+            self.position += ALIAS_KEYWORD.len();
+            return Ok((self.parse_alias()?, false));
+        }
+
         // Check if this is a named region (contains '=')
-        let region_name = self.parse_optional_region_name()?;
+        let (region_name, append) = match self.parse_optional_region_name()? {
+            Some((name, append)) => (Some(name), append),
+            None => (None, false),
+        };
 
         if region_name.is_some() {
             // Named region, expect '=' after the region name
@@ -94,84 +431,376 @@ impl<'a> GeometryParser<'a> {
                     expected: "'='",
                     found: self.current_char().unwrap_or('\0').to_string(),
                     position: self.position,
+                    context: None,
                 });
             }
             self.skip_whitespace();
         }
 
+        // `+=` appends boxes, so it cannot introduce an expression
+        if append
+            && self.peek_geometry_keyword().is_none()
+            && !self.rest().starts_with(SHELL_KEYWORD)
+            && !self.rest().starts_with(SPHERE_KEYWORD)
+        {
+            return Err(ParseError::Expected {
+                expected: "'rc(', 'ac(', 'shell(' or 'sp(' after '+='",
+                found: self.peek_str(10).to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+
         // Parse the geometry function call
-        if self.consume_str("rc(") {
-            let corners = self.parse_box()?;
+        let statement = if self.consume_str(SHELL_KEYWORD) {
+            self.parse_shell(region_name)
+        } else if self.consume_str(SPHERE_KEYWORD) {
+            self.parse_sphere(region_name)
+        } else if let Some((kind, len)) = self.peek_geometry_keyword() {
+            self.position += len;
+            self.parse_box_call(kind, region_name, true)
+        } else if let Some(region) = region_name {
+            // If we have a region name but no rc( or ac(, try parsing an expression
+            let expr = self.parse_expression()?;
             self.skip_whitespace();
-            if !self.consume_char(')') {
+            if self.current_char() == Some('#') {
                 return Err(ParseError::Expected {
-                    expected: "')'",
-                    found: self.current_char().unwrap_or('\0').to_string(),
+                    expected: "end of expression (labels only follow rc/ac boxes)",
+                    found: self.peek_str(10).to_string(),
                     position: self.position,
+                    context: None,
                 });
             }
-            Ok(GeometryStatement::RelativeCoordinate {
-                region: region_name,
-                corners,
+            Ok(GeometryStatement::Expression { region, expr })
+        } else {
+            Err(ParseError::Expected {
+                expected: "'rc(' or 'ac(' or expression",
+                found: self.peek_str(10).to_string(),
+                position: self.position,
+                context: None,
             })
-        } else if self.consume_str("ac(") {
-            let corners = self.parse_box()?;
+        };
+        Ok((statement?, append))
+    }
+
+    /// Parse the rest of an `rc(`/`ac(` call, after the keyword and `(`, and
+    /// with `labeled` an optional `#label` suffix
+    fn parse_box_call(
+        &mut self,
+        kind: GeomKind,
+        region: Option<String>,
+        labeled: bool,
+    ) -> Result<GeometryStatementWide, ParseError> {
+        let corners = self.parse_box()?;
+        self.expect_closing_paren()?;
+
+        let label_position = self.position;
+        let label = if labeled {
+            self.parse_box_label()?
+        } else {
+            None
+        };
+        if label.is_some() && region.is_none() {
+            return Err(ParseError::InvalidBox {
+                position: label_position,
+                message: "box labels need a named region, e.g. @wall=rc(...)#north".to_string(),
+                context: None,
+            });
+        }
+
+        Ok(match kind {
+            GeomKind::Relative => GeometryStatement::RelativeCoordinate {
+                region,
+                corners,
+                label,
+            },
+            GeomKind::Absolute => GeometryStatement::AbsoluteCoordinate {
+                region,
+                corners,
+                label,
+            },
+        })
+    }
+
+    /// Parse an optional `#label` directly after a box: [A-Za-z0-9_]+
+    fn parse_box_label(&mut self) -> Result<Option<String>, ParseError> {
+        if !self.consume_char('#') {
+            return Ok(None);
+        }
+
+        let start_pos = self.position;
+        while self
+            .current_char()
+            .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        {
+            self.advance();
+        }
+        // Labels are ASCII; stopping at "ü" in "#süd" must not leave a short label
+        if self.position == start_pos || self.current_char().is_some_and(char::is_alphanumeric) {
+            return Err(ParseError::Expected {
+                expected: "box label",
+                found: self.current_char().unwrap_or('\0').to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+
+        Ok(Some(self.slice(start_pos, self.position).to_string()))
+    }
+
+    /// Parse the rest of a `shell(` call: a box and an optional `thickness=n` (default 1)
+    fn parse_shell(&mut self, region: Option<String>) -> Result<GeometryStatementWide, ParseError> {
+        self.skip_whitespace();
+        let Some((kind, len)) = self.peek_geometry_keyword() else {
+            return Err(ParseError::Expected {
+                expected: "'rc(' or 'ac(' inside shell(...)",
+                found: self.peek_str(10).to_string(),
+                position: self.position,
+                context: None,
+            });
+        };
+        self.position += len;
+        let outer = self.parse_box_call(kind, None, false)?;
+
+        self.skip_whitespace();
+        let mut thickness = 1;
+        if self.consume_char(',') {
             self.skip_whitespace();
-            if !self.consume_char(')') {
+            if !self.consume_str("thickness") {
                 return Err(ParseError::Expected {
-                    expected: "')'",
+                    expected: "'thickness='",
+                    found: self.peek_str(10).to_string(),
+                    position: self.position,
+                    context: None,
+                });
+            }
+            self.skip_whitespace();
+            if !self.consume_char('=') {
+                return Err(ParseError::Expected {
+                    expected: "'='",
                     found: self.current_char().unwrap_or('\0').to_string(),
                     position: self.position,
+                    context: None,
                 });
             }
-            Ok(GeometryStatement::AbsoluteCoordinate {
-                region: region_name,
-                corners,
-            })
-        } else if let Some(region) = region_name {
-            // If we have a region name but no rc( or ac(, try parsing an expression
-            let expr = self.parse_expression()?;
-            Ok(GeometryStatement::Expression { region, expr })
-        } else {
-            Err(ParseError::Expected {
-                expected: "'rc(' or 'ac(' or expression",
+            self.skip_whitespace();
+            let position = self.position;
+            thickness = u64::try_from(self.parse_integer()?)
+                .ok()
+                .filter(|&t| t >= 1)
+                .ok_or_else(|| ParseError::InvalidBox {
+                    position,
+                    message: "shell thickness must be at least 1".to_string(),
+                    context: None,
+                })?;
+        }
+        self.expect_closing_paren()?;
+
+        Ok(GeometryStatement::Shell {
+            region,
+            outer: Box::new(outer),
+            thickness,
+        })
+    }
+
+    /// Parse the rest of a `const` declaration: `name=[x,y,z]`
+    fn parse_const(&mut self) -> Result<GeometryStatementWide, ParseError> {
+        self.skip_whitespace();
+        let start_pos = self.position;
+        let name = self.parse_identifier();
+        if name.is_empty() {
+            return Err(ParseError::Expected {
+                expected: "constant name",
                 found: self.peek_str(10).to_string(),
+                position: start_pos,
+                context: None,
+            });
+        }
+        self.skip_whitespace();
+        if !self.consume_char('=') {
+            return Err(ParseError::Expected {
+                expected: "'='",
+                found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
-            })
+                context: None,
+            });
         }
+        let value = self.parse_vec3()?;
+
+        Ok(GeometryStatement::Const {
+            name: name.to_string(),
+            value,
+        })
     }
 
-    /// Parse an optional region name (up to '=')
-    fn parse_optional_region_name(&mut self) -> Result<Option<String>, ParseError> {
+    /// Parse the rest of an `alias` statement: `name=target`
+    fn parse_alias(&mut self) -> Result<GeometryStatementWide, ParseError> {
+        self.skip_whitespace();
+        let name_pos = self.position;
+        let Some((name, append)) = self.parse_optional_region_name()? else {
+            return Err(ParseError::Expected {
+                expected: "alias name followed by '='",
+                found: self.peek_str(10).to_string(),
+                position: name_pos,
+                context: None,
+            });
+        };
+        if append {
+            return Err(ParseError::Expected {
+                expected: "'=' after the alias name",
+                found: "+=".to_string(),
+                position: name_pos,
+                context: None,
+            });
+        }
+        self.consume_char('=');
+        self.skip_whitespace();
+
+        let target_pos = self.position;
+        let quoted = self.current_char() == Some('"');
+        let target = self.parse_region_ref()?;
+        self.skip_whitespace();
+        if self.current_char().is_some() {
+            return Err(ParseError::Expected {
+                expected: "end of alias (the target is a single region name)",
+                found: self.peek_str(10).to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+        let target = target.as_region_ref().unwrap_or_default().to_string();
+        if !quoted {
+            validate_region_name(&target, target_pos)?;
+        }
+
+        Ok(GeometryStatement::Alias { name, target })
+    }
+
+    /// Consume a `[A-Za-z_][A-Za-z0-9_]*` identifier, or nothing
+    fn parse_identifier(&mut self) -> &'a str {
         let start_pos = self.position;
+        if self
+            .current_char()
+            .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        {
+            while self
+                .current_char()
+                .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+            {
+                self.advance();
+            }
+        }
+        self.slice(start_pos, self.position)
+    }
+
+    /// Parse the rest of an `sp(` call: a center and a radius
+    fn parse_sphere(
+        &mut self,
+        region: Option<String>,
+    ) -> Result<GeometryStatementWide, ParseError> {
+        let center = self.parse_vec3()?;
+        self.skip_whitespace();
+        if !self.consume_char(',') {
+            return Err(ParseError::Expected {
+                expected: "','",
+                found: self.current_char().unwrap_or('\0').to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+        self.skip_whitespace();
+        let position = self.position;
+        let radius = self.parse_integer()?;
+        let radius = u64::try_from(radius)
+            .ok()
+            .filter(|&r| r <= MAX_SPHERE_RADIUS)
+            .ok_or_else(|| ParseError::InvalidBox {
+                position,
+                message: if radius < 0 {
+                    "sphere radius must not be negative".to_string()
+                } else {
+                    format!("sphere radius must be at most {}", MAX_SPHERE_RADIUS)
+                },
+                context: None,
+            })?;
+        self.skip_trailing_comma();
+        self.expect_closing_paren()?;
+
+        Ok(GeometryStatement::Sphere {
+            region,
+            center,
+            radius,
+        })
+    }
+
+    /// Skip whitespace and consume a `)`
+    fn expect_closing_paren(&mut self) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if !self.consume_char(')') {
+            return Err(ParseError::Expected {
+                expected: "')'",
+                found: self.current_char().unwrap_or('\0').to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the last parsed statement used the `@!` disable prefix
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Parse an optional region name up to '=' or '+=', and whether it is
+    /// followed by '+='. The '+' is consumed, the '=' is not.
+    fn parse_optional_region_name(&mut self) -> Result<Option<(String, bool)>, ParseError> {
+        let start_pos = self.position;
+
+        if self.current_char() == Some('"') {
+            let name = self.parse_quoted_region_name()?;
+            self.skip_whitespace();
+            let append = self.consume_char('+');
+            if self.current_char() != Some('=') {
+                return Err(ParseError::Expected {
+                    expected: "'=' after the quoted region name",
+                    found: self.current_char().unwrap_or('\0').to_string(),
+                    position: self.position,
+                    context: None,
+                });
+            }
+            return Ok(Some((name, append)));
+        }
 
         // Look for characters that could be part of a region name
         while let Some(ch) = self.current_char() {
             if ch == '=' {
                 // Found '=', so we have a region name
-                let name = self.input[start_pos..self.position].trim().to_string();
+                let mut raw = self.slice(start_pos, self.position);
+                let name_start = start_pos + raw.len() - raw.trim_start().len();
+                // `+=` is one token: `@bus + =` leaves '+' in the name
+                let append = raw.ends_with('+');
+                if append {
+                    raw = &raw[..raw.len() - 1];
+                }
+                let name = raw.trim();
                 if name.is_empty() {
                     return Err(ParseError::Expected {
                         expected: "region name",
                         found: "empty string".to_string(),
                         position: start_pos,
+                        context: None,
                     });
                 }
-                return Ok(Some(name));
-            } else if ch == 'r' && self.position == start_pos {
-                // Check if this is the very start and looks like "rc("
-                if self.peek_str(3) == "rc(" {
-                    // This looks like a function call, not a region name
-                    self.position = start_pos; // Reset position
-                    return Ok(None);
-                }
-            } else if ch == 'a' && self.position == start_pos {
-                // Check if this is the very start and looks like "ac("
-                if self.peek_str(3) == "ac(" {
-                    // This looks like a function call, not a region name
-                    self.position = start_pos; // Reset position
-                    return Ok(None);
-                }
+                validate_region_name(name, name_start)?;
+                return Ok(Some((name.to_string(), append)));
+            } else if self.position == start_pos
+                && (self.peek_geometry_keyword().is_some()
+                    || self.rest().starts_with(SHELL_KEYWORD)
+                    || self.rest().starts_with(SPHERE_KEYWORD))
+            {
+                // Starts like "rc(", "shell(", "sp(" or an alias: a function call, not a region name
+                return Ok(None);
             }
             self.advance();
         }
@@ -181,79 +810,188 @@ impl<'a> GeometryParser<'a> {
         Ok(None)
     }
 
-    /// Parse a box: two vec3 coordinates separated by comma
-    fn parse_box(&mut self) -> Result<(Vec3, Vec3), ParseError> {
+    /// Recognize a geometry keyword and its `(` at the current position.
+    ///
+    /// Returns the primitive it stands for and the length to consume. `rc`/`ac`
+    /// always match in lowercase; other spellings need the case-insensitive
+    /// option or an entry in the alias table.
+    fn peek_geometry_keyword(&self) -> Option<(GeomKind, usize)> {
+        let rest = self.rest();
+        let word_len = rest
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+            .unwrap_or(rest.len());
+        if word_len == 0 || !rest[word_len..].starts_with('(') {
+            return None;
+        }
+
+        let word = &rest[..word_len];
+        let matches = |keyword: &str| {
+            word == keyword
+                || (self.keywords_case_insensitive && word.eq_ignore_ascii_case(keyword))
+        };
+        let kind = if matches("rc") {
+            GeomKind::Relative
+        } else if matches("ac") {
+            GeomKind::Absolute
+        } else {
+            *self
+                .keyword_aliases
+                .iter()
+                .find(|(alias, _)| matches(alias))?
+                .1
+        };
+        Some((kind, word_len + 1))
+    }
+
+    /// Parse a box: two vec3 coordinates separated by comma, or a single vec3
+    /// directly followed by `)` for the one block at that point.
+    /// With `strict_box_order`, the first corner must not exceed the second.
+    fn parse_box(&mut self) -> Result<(Vec3Wide, Vec3Wide), ParseError> {
         self.skip_whitespace();
+        let start_pos = self.position;
+        // Unknown constants accepted by a syntax check have no real value
+        let mut placeholder = self.accept_unknown_constants && self.current_char() != Some('[');
         let vec1 = self.parse_vec3()?;
         self.skip_whitespace();
 
+        // `rc([5,5,5],)` stays an incomplete box, even with trailing commas
+        if self.current_char() == Some(')') {
+            return Ok((vec1, vec1));
+        }
+
         if !self.consume_char(',') {
             return Err(ParseError::Expected {
                 expected: "','",
                 found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
+                context: None,
             });
         }
 
         self.skip_whitespace();
+        placeholder |= self.accept_unknown_constants && self.current_char() != Some('[');
         let vec2 = self.parse_vec3()?;
+        self.skip_trailing_comma();
+
+        if self.strict_box_order && !placeholder {
+            if let Some(axis) = (0..3).find(|&axis| vec1[axis] > vec2[axis]) {
+                return Err(ParseError::InvalidBox {
+                    position: start_pos,
+                    message: format!(
+                        "first corner exceeds the second on the {} axis ({} > {})",
+                        ["x", "y", "z"][axis],
+                        vec1[axis],
+                        vec2[axis]
+                    ),
+                    context: None,
+                });
+            }
+        }
 
         Ok((vec1, vec2))
     }
 
-    /// Parse a vec3: [x,y,z]
-    fn parse_vec3(&mut self) -> Result<Vec3, ParseError> {
+    /// Parse a vec3: [x,y,z], or the name of a constant
+    fn parse_vec3(&mut self) -> Result<Vec3Wide, ParseError> {
         self.skip_whitespace();
 
+        let start_pos = self.position;
+        let name = self.parse_identifier();
+        if !name.is_empty() {
+            let value = self
+                .constants
+                .and_then(|constants| constants.get(name))
+                .copied()
+                .or(self.accept_unknown_constants.then_some([0, 0, 0]))
+                .ok_or_else(|| ParseError::UnknownConstant {
+                    name: name.to_string(),
+                    position: start_pos,
+                    context: None,
+                })?;
+            let width = self.literal_width();
+            if value.iter().any(|&c| c < width.min() || c > width.max()) {
+                return Err(ParseError::InvalidVec3 {
+                    position: start_pos,
+                    message: format!("constant '{}' does not fit {:?} coordinates", name, width),
+                    context: None,
+                });
+            }
+            return Ok(value);
+        }
+
         if !self.consume_char('[') {
             return Err(ParseError::Expected {
                 expected: "'['",
                 found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
+                context: None,
             });
         }
 
-        self.skip_whitespace();
-        let x = self.parse_integer()?;
-        self.skip_whitespace();
-
-        if !self.consume_char(',') {
-            return Err(ParseError::Expected {
-                expected: "','",
-                found: self.current_char().unwrap_or('\0').to_string(),
-                position: self.position,
-            });
+        let mut components = [0; 3];
+        for (idx, component) in components.iter_mut().enumerate() {
+            self.skip_whitespace();
+            if idx > 0 {
+                if self.current_char() == Some(']') {
+                    return Err(ParseError::InvalidVec3 {
+                        position: self.position,
+                        message: format!("expected 3 components, found {}", idx),
+                        context: None,
+                    });
+                }
+                if !self.consume_char(',') {
+                    return Err(ParseError::Expected {
+                        expected: "','",
+                        found: self.current_char().unwrap_or('\0').to_string(),
+                        position: self.position,
+                        context: None,
+                    });
+                }
+                self.skip_whitespace();
+            }
+            if idx == 0 && self.current_char() == Some(']') {
+                return Err(ParseError::InvalidVec3 {
+                    position: self.position,
+                    message: "expected 3 components, found 0".to_string(),
+                    context: None,
+                });
+            }
+            *component = self.parse_integer()?;
         }
 
+        // A fourth value gets its own message, pointing at the value
         self.skip_whitespace();
-        let y = self.parse_integer()?;
-        self.skip_whitespace();
-
-        if !self.consume_char(',') {
-            return Err(ParseError::Expected {
-                expected: "','",
-                found: self.current_char().unwrap_or('\0').to_string(),
-                position: self.position,
-            });
+        let after_z = self.position;
+        if self.consume_char(',') {
+            self.skip_whitespace();
+            if self
+                .current_char()
+                .is_some_and(|ch| ch.is_ascii_digit() || ch == '-')
+            {
+                return Err(ParseError::InvalidVec3 {
+                    position: self.position,
+                    message: "expected 3 components, found more".to_string(),
+                    context: None,
+                });
+            }
+            self.position = after_z;
         }
-
-        self.skip_whitespace();
-        let z = self.parse_integer()?;
-        self.skip_whitespace();
+        self.skip_trailing_comma();
 
         if !self.consume_char(']') {
             return Err(ParseError::Expected {
                 expected: "']'",
                 found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
+                context: None,
             });
         }
 
-        Ok([x, y, z])
+        Ok(components)
     }
 
-    /// Parse an integer (potentially negative)
-    fn parse_integer(&mut self) -> Result<i32, ParseError> {
+    /// Parse an integer (potentially negative) within the configured coordinate width
+    fn parse_integer(&mut self) -> Result<i64, ParseError> {
         let start_pos = self.position;
 
         // Handle negative sign
@@ -264,6 +1002,7 @@ impl<'a> GeometryParser<'a> {
                 expected: "digit",
                 found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
+                context: None,
             });
         }
 
@@ -272,13 +1011,34 @@ impl<'a> GeometryParser<'a> {
             self.advance();
         }
 
-        let num_str = &self.input[start_pos..self.position];
-        num_str
-            .parse::<i32>()
-            .map_err(|e| ParseError::InvalidInteger {
-                position: start_pos,
-                source: e,
-            })
+        let num_str = self.slice(start_pos, self.position);
+        let parsed = match self.literal_width() {
+            CoordWidth::I32 => num_str.parse::<i32>().map(i64::from),
+            CoordWidth::I64 => num_str.parse::<i64>(),
+        };
+        parsed.map_err(|e| ParseError::InvalidInteger {
+            position: start_pos,
+            source: e,
+            context: None,
+        })
+    }
+
+    /// Range of coordinate literals: the configured width, within [`Vec3`]'s
+    /// when parsing a [`GeometryStatement`]
+    fn literal_width(&self) -> CoordWidth {
+        if self.narrow {
+            Ord::min(self.coordinate_width, CoordWidth::COORD)
+        } else {
+            self.coordinate_width
+        }
+    }
+
+    /// Consume a single trailing comma (and following whitespace) when lenient mode is on
+    fn skip_trailing_comma(&mut self) {
+        self.skip_whitespace();
+        if self.allow_trailing_commas && self.consume_char(',') {
+            self.skip_whitespace();
+        }
     }
 
     /// Skip whitespace characters
@@ -288,15 +1048,25 @@ impl<'a> GeometryParser<'a> {
         }
     }
 
+    /// The input between two byte positions reached by the cursor
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        &self.input[start..end]
+    }
+
+    /// The input from the current position on
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
     /// Get the current character
     fn current_char(&self) -> Option<char> {
-        self.input.chars().nth(self.position)
+        self.rest().chars().next()
     }
 
     /// Advance the position by one character
     fn advance(&mut self) {
-        if self.position < self.input.len() {
-            self.position += 1;
+        if let Some(ch) = self.current_char() {
+            self.position += ch.len_utf8();
         }
     }
 
@@ -312,7 +1082,7 @@ impl<'a> GeometryParser<'a> {
 
     /// Consume a specific string if it matches
     fn consume_str(&mut self, expected: &str) -> bool {
-        if self.input[self.position..].starts_with(expected) {
+        if self.rest().starts_with(expected) {
             self.position += expected.len();
             true
         } else {
@@ -320,10 +1090,9 @@ impl<'a> GeometryParser<'a> {
         }
     }
 
-    /// Peek at a string of the given length starting from current position
+    /// Peek at up to `len` characters starting from current position
     fn peek_str(&self, len: usize) -> &str {
-        let end = (self.position + len).min(self.input.len());
-        &self.input[self.position..end]
+        take_chars(self.rest(), len)
     }
 
     /// Parse a boolean expression with proper precedence
@@ -353,10 +1122,7 @@ impl<'a> GeometryParser<'a> {
 
                 #[cfg(not(feature = "boolean_ops"))]
                 {
-                    return Err(ParseError::UnsupportedOperator {
-                        position: self.position,
-                        operator: "^".to_string(),
-                    });
+                    return Err(self.gated_operator_error('^', &left));
                 }
             } else {
                 break;
@@ -386,10 +1152,7 @@ impl<'a> GeometryParser<'a> {
 
                 #[cfg(not(feature = "boolean_ops"))]
                 {
-                    return Err(ParseError::UnsupportedOperator {
-                        position: self.position,
-                        operator: "-".to_string(),
-                    });
+                    return Err(self.gated_operator_error('-', &left));
                 }
             } else {
                 break;
@@ -399,6 +1162,26 @@ impl<'a> GeometryParser<'a> {
         Ok(left)
     }
 
+    /// Error for a boolean operator other than `+` without the `boolean_ops`
+    /// feature, naming the operand before it when `verbose_feature_errors` is set
+    #[cfg(not(feature = "boolean_ops"))]
+    fn gated_operator_error(&self, operator: char, left: &BooleanExpr) -> ParseError {
+        if self.verbose_feature_errors {
+            ParseError::FeatureGated {
+                position: self.position,
+                operator: operator.to_string(),
+                left: left.to_string(),
+                context: None,
+            }
+        } else {
+            ParseError::UnsupportedOperator {
+                position: self.position,
+                operator: operator.to_string(),
+                context: None,
+            }
+        }
+    }
+
     /// Parse union expressions
     fn parse_union(&mut self) -> Result<BooleanExpr, ParseError> {
         let mut left = self.parse_intersection()?;
@@ -438,10 +1221,7 @@ impl<'a> GeometryParser<'a> {
 
                 #[cfg(not(feature = "boolean_ops"))]
                 {
-                    return Err(ParseError::UnsupportedOperator {
-                        position: self.position,
-                        operator: "&".to_string(),
-                    });
+                    return Err(self.gated_operator_error('&', &left));
                 }
             } else {
                 break;
@@ -451,58 +1231,191 @@ impl<'a> GeometryParser<'a> {
         Ok(left)
     }
 
-    /// Parse a term: region_ref | ( expression )
+    /// Parse a term: region_ref | ( expression ) | expand(...) | inset(...) | translate(...)
     fn parse_term(&mut self) -> Result<BooleanExpr, ParseError> {
         self.skip_whitespace();
 
-        if self.consume_char('(') {
-            // Parenthesized expression
-            self.skip_whitespace();
-            let expr = self.parse_expression()?;
-            self.skip_whitespace();
+        if self.consume_char('(') {
+            // Parenthesized expression
+            self.skip_whitespace();
+            let expr = self.parse_expression()?;
+            self.skip_whitespace();
+
+            if !self.consume_char(')') {
+                return Err(ParseError::Expected {
+                    expected: "')'",
+                    found: self.current_char().unwrap_or('\0').to_string(),
+                    position: self.position,
+                    context: None,
+                });
+            }
+
+            Ok(expr)
+        } else {
+            if self.consume_str(EXPAND_KEYWORD) {
+                let (expr, amount) = self.parse_offset_args("expand")?;
+                return Ok(BooleanExpr::expand(expr, amount));
+            }
+            if self.consume_str(INSET_KEYWORD) {
+                let (expr, amount) = self.parse_offset_args("inset")?;
+                return Ok(BooleanExpr::inset(expr, amount));
+            }
+            if self.consume_str(TRANSLATE_KEYWORD) {
+                return self.parse_translate_args();
+            }
+
+            #[cfg(feature = "metadata_groups")]
+            if self.consume_str(GROUP_BY_KEYWORD) {
+                return self.parse_group_by();
+            }
+
+            // Region reference
+            self.parse_region_ref()
+        }
+    }
+
+    /// Parse the rest of an `expand(` or `inset(` term: an expression, a
+    /// non-negative amount and the closing `)`
+    fn parse_offset_args(&mut self, keyword: &str) -> Result<(BooleanExpr, u64), ParseError> {
+        self.skip_whitespace();
+        let expr = self.parse_expression()?;
+        self.skip_whitespace();
+        if !self.consume_char(',') {
+            return Err(ParseError::Expected {
+                expected: "','",
+                found: self.current_char().unwrap_or('\0').to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+        self.skip_whitespace();
+        let position = self.position;
+        let amount = self.parse_integer()?;
+        let amount = u64::try_from(amount).map_err(|_| ParseError::InvalidBox {
+            position,
+            message: format!("{} amount must not be negative", keyword),
+            context: None,
+        })?;
+        self.skip_trailing_comma();
+        self.expect_closing_paren()?;
+        Ok((expr, amount))
+    }
+
+    /// Parse the rest of a `translate(` term: an expression, a vec3 offset
+    /// and the closing `)`
+    fn parse_translate_args(&mut self) -> Result<BooleanExpr, ParseError> {
+        self.skip_whitespace();
+        let expr = self.parse_expression()?;
+        self.skip_whitespace();
+        if !self.consume_char(',') {
+            return Err(ParseError::Expected {
+                expected: "','",
+                found: self.current_char().unwrap_or('\0').to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+        let offset = self.parse_vec3()?;
+        self.skip_trailing_comma();
+        self.expect_closing_paren()?;
+        Ok(BooleanExpr::translate(expr, offset))
+    }
+
+    /// Parse the rest of a `group_by(` term: `key == <json>` and the closing `)`
+    #[cfg(feature = "metadata_groups")]
+    fn parse_group_by(&mut self) -> Result<BooleanExpr, ParseError> {
+        self.skip_whitespace();
+        let start_pos = self.position;
+        while self
+            .current_char()
+            .is_some_and(|ch| ch.is_alphanumeric() || ch == '_' || ch == '.')
+        {
+            self.advance();
+        }
+        let key = self.slice(start_pos, self.position).to_string();
+        if key.is_empty() {
+            return Err(ParseError::Expected {
+                expected: "metadata key",
+                found: self.current_char().unwrap_or('\0').to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+
+        self.skip_whitespace();
+        if !self.consume_str("==") {
+            return Err(ParseError::Expected {
+                expected: "'=='",
+                found: self.peek_str(2).to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+        self.skip_whitespace();
+
+        let mut json_parser = crate::parser::JsonValueParser::new(self.rest());
+        let value = json_parser
+            .parse()
+            .map_err(|e| e.offset_position(self.position))?;
+        self.position += json_parser.position();
 
-            if !self.consume_char(')') {
-                return Err(ParseError::Expected {
-                    expected: "')'",
-                    found: self.current_char().unwrap_or('\0').to_string(),
-                    position: self.position,
-                });
-            }
+        self.expect_closing_paren()?;
+        Ok(BooleanExpr::group_by(key, value))
+    }
 
-            Ok(expr)
-        } else {
-            // Region reference
-            self.parse_region_ref()
+    /// Parse a region name written as a JSON string, e.g. `"My Region"`.
+    /// Any characters are allowed; the name is the unescaped string.
+    fn parse_quoted_region_name(&mut self) -> Result<String, ParseError> {
+        let start_pos = self.position;
+        let mut json_parser = crate::parser::JsonValueParser::new(self.rest());
+        let name = json_parser
+            .parse_string()
+            .map_err(|e| e.offset_position(start_pos))?;
+        self.position += json_parser.position();
+        if name.is_empty() {
+            return Err(ParseError::Expected {
+                expected: "region name",
+                found: "empty string".to_string(),
+                position: start_pos,
+                context: None,
+            });
         }
+        Ok(name)
     }
 
-    /// Parse a region reference (identifier)
+    /// Parse a region reference: an identifier or a quoted name
     fn parse_region_ref(&mut self) -> Result<BooleanExpr, ParseError> {
         let start_pos = self.position;
 
+        if self.current_char() == Some('"') {
+            return Ok(BooleanExpr::region_ref(self.parse_quoted_region_name()?));
+        }
+
         // Parse identifier: [A-Za-z0-9_.]+
         if !self
             .current_char()
-            .is_some_and(|ch| ch.is_alphanumeric() || ch == '_')
+            .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_')
         {
             return Err(ParseError::Expected {
                 expected: "region name",
                 found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
+                context: None,
             });
         }
 
         while self
             .current_char()
-            .is_some_and(|ch| ch.is_alphanumeric() || ch == '_' || ch == '.')
+            .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '.')
         {
             self.advance();
         }
 
-        let name = self.input[start_pos..self.position].to_string();
+        let name = self.slice(start_pos, self.position).to_string();
         if name.is_empty() {
             return Err(ParseError::EmptyExpression {
                 position: start_pos,
+                context: None,
             });
         }
 
@@ -512,19 +1425,84 @@ impl<'a> GeometryParser<'a> {
 
 /// Normalize a box by ensuring min <= max for each axis
 pub fn normalize_box(corner1: Vec3, corner2: Vec3) -> BoxPair {
-    let min_x = corner1[0].min(corner2[0]);
-    let max_x = corner1[0].max(corner2[0]);
-    let min_y = corner1[1].min(corner2[1]);
-    let max_y = corner1[1].max(corner2[1]);
-    let min_z = corner1[2].min(corner2[2]);
-    let max_z = corner1[2].max(corner2[2]);
-
-    ([min_x, min_y, min_z], [max_x, max_y, max_z])
+    crate::boxutil::normalize(corner1, corner2)
+}
+
+/// [`normalize_box`] for i64 corners
+pub fn normalize_box_wide(corner1: Vec3Wide, corner2: Vec3Wide) -> BoxPair64 {
+    crate::boxutil::normalize(corner1, corner2)
+}
+
+/// Blocks within `radius` of `center` as boxes spanning whole z-runs, with
+/// neighbouring rows of equal extent along y merged. The caller checks that
+/// the bounding cube fits in i64.
+fn sphere_boxes(center: Vec3Wide, radius: u64) -> Vec<BoxPair64> {
+    let r = radius as i64;
+    let mut boxes = Vec::new();
+    for dx in -r..=r {
+        // Current run of rows: first dy and half z-extent
+        let mut run: Option<(i64, i64)> = None;
+        for dy in -r..=r + 1 {
+            let rest = r * r - dx * dx - dy * dy;
+            let half = (dy <= r && rest >= 0).then(|| integer_sqrt(rest as u64) as i64);
+            if let Some((start, run_half)) = run {
+                if half == Some(run_half) {
+                    continue;
+                }
+                boxes.push((
+                    [center[0] + dx, center[1] + start, center[2] - run_half],
+                    [center[0] + dx, center[1] + dy - 1, center[2] + run_half],
+                ));
+            }
+            run = half.map(|half| (dy, half));
+        }
+    }
+    boxes
+}
+
+/// Floor of the square root of `n`, as `u64::isqrt` computes on newer toolchains
+fn integer_sqrt(n: u64) -> u64 {
+    let mut root = (n as f64).sqrt() as u64;
+    // The float estimate can be off by one either way for large `n`
+    while !matches!(root.checked_mul(root), Some(square) if square <= n) {
+        root -= 1;
+    }
+    while matches!((root + 1).checked_mul(root + 1), Some(square) if square <= n) {
+        root += 1;
+    }
+    root
+}
+
+/// Check a region name against `[A-Za-z0-9_][A-Za-z0-9_.]*`, the names region
+/// references accept; `position` is where the name starts
+fn validate_region_name(name: &str, position: usize) -> Result<(), ParseError> {
+    for (offset, ch) in name.char_indices() {
+        let allowed = ch.is_ascii_alphanumeric() || ch == '_' || (ch == '.' && offset > 0);
+        if !allowed {
+            return Err(ParseError::InvalidRegionName {
+                name: name.to_string(),
+                found: ch,
+                position: position + offset,
+                context: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Add `offset` to `corner`, or None on i64 overflow
+fn offset_vec3(corner: Vec3Wide, offset: Vec3Wide) -> Option<Vec3Wide> {
+    Some([
+        corner[0].checked_add(offset[0])?,
+        corner[1].checked_add(offset[1])?,
+        corner[2].checked_add(offset[2])?,
+    ])
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeSet;
 
     #[test]
     fn test_parse_simple_rc() {
@@ -532,7 +1510,9 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            GeometryStatement::RelativeCoordinate { region, corners } => {
+            GeometryStatement::RelativeCoordinate {
+                region, corners, ..
+            } => {
                 assert_eq!(region, None);
                 assert_eq!(corners, ([0, 1, 2], [3, 4, 5]));
             }
@@ -540,13 +1520,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_single_point_box() {
+        for (input, corners) in [
+            ("@rc([5,5,5])", ([5, 5, 5], [5, 5, 5])),
+            ("@ac( [-1, 2, 3] )", ([-1, 2, 3], [-1, 2, 3])),
+            ("@rc([0,0,0],[1,2,3])", ([0, 0, 0], [1, 2, 3])),
+        ] {
+            let stmt = GeometryParser::new(input).parse().unwrap();
+            assert_eq!(stmt.to_box_pair([0, 0, 0]), Some(corners), "{}", input);
+        }
+
+        let constants = BTreeMap::from([("origin".to_string(), [10, 64, 10])]);
+        let mut parser = GeometryParser::new("@spot=ac(origin)").with_constants(&constants);
+        assert_eq!(
+            parser.parse().unwrap().to_box_pair([0, 0, 0]),
+            Some(([10, 64, 10], [10, 64, 10]))
+        );
+
+        let options = CompileOptions {
+            allow_trailing_commas: true,
+            ..Default::default()
+        };
+        let mut parser = GeometryParser::with_options("@rc([5,5,5],)", &options);
+        assert!(parser.parse().is_err());
+    }
+
     #[test]
     fn test_parse_simple_ac() {
         let mut parser = GeometryParser::new("@ac([10,-5,0],[20,15,10])");
         let result = parser.parse().unwrap();
 
         match result {
-            GeometryStatement::AbsoluteCoordinate { region, corners } => {
+            GeometryStatement::AbsoluteCoordinate {
+                region, corners, ..
+            } => {
                 assert_eq!(region, None);
                 assert_eq!(corners, ([10, -5, 0], [20, 15, 10]));
             }
@@ -554,13 +1562,76 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_disabled_prefix() {
+        let mut parser = GeometryParser::new("@!region=rc([0,0,0],[1,1,1])");
+        let result = parser.parse().unwrap();
+
+        assert!(parser.is_disabled());
+        assert_eq!(result.region(), Some("region"));
+
+        let mut parser = GeometryParser::new("@rc([0,0,0],[1,1,1])");
+        parser.parse().unwrap();
+        assert!(!parser.is_disabled());
+    }
+
+    #[test]
+    fn test_invalid_region_names() {
+        let error_at = |input: &str| match GeometryParser::new(input).parse() {
+            Err(ParseError::InvalidRegionName {
+                found, position, ..
+            }) => (found, position),
+            other => panic!("expected InvalidRegionName for {}, got {:?}", input, other),
+        };
+
+        assert_eq!(error_at("@cpu core=rc([0,0,0],[1,1,1])"), (' ', 4));
+        assert_eq!(error_at("@ .cpu=rc([0,0,0],[1,1,1])"), ('.', 2));
+        // `$global` holds metadata only and cannot get boxes
+        assert_eq!(error_at("@$global=rc([0,0,0],[1,1,1])"), ('$', 1));
+        assert_eq!(error_at("@cpu.*=rc([0,0,0],[1,1,1])"), ('*', 5));
+
+        // Leading digits are allowed, as in region references
+        let mut parser = GeometryParser::new("@1st.floor_2 += rc([0,0,0],[1,1,1])");
+        assert_eq!(parser.parse().unwrap().region(), Some("1st.floor_2"));
+    }
+
+    #[test]
+    fn test_parse_append() {
+        let mut parser = GeometryParser::new("@bus += rc([0,0,0],[1,1,1])");
+        let (result, append) = parser.parse_wide_with_append().unwrap();
+        assert!(append);
+        assert_eq!(result.region(), Some("bus"));
+
+        let mut parser = GeometryParser::new("@bus=rc([0,0,0],[1,1,1])");
+        assert!(!parser.parse_wide_with_append().unwrap().1);
+
+        // `+=` is a single token
+        let mut parser = GeometryParser::new("@bus + = rc([0,0,0],[1,1,1])");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::InvalidRegionName { ref name, .. }) if name == "bus +"
+        ));
+
+        // Appending needs boxes, not an expression
+        let mut parser = GeometryParser::new("@bus+=a+b");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::Expected { position: 6, .. })
+        ));
+        assert!(GeometryParser::new("@+=rc([0,0,0],[1,1,1])")
+            .parse()
+            .is_err());
+    }
+
     #[test]
     fn test_parse_named_region() {
         let mut parser = GeometryParser::new("@dataloop=rc([0,0,0],[31,7,15])");
         let result = parser.parse().unwrap();
 
         match result {
-            GeometryStatement::RelativeCoordinate { region, corners } => {
+            GeometryStatement::RelativeCoordinate {
+                region, corners, ..
+            } => {
                 assert_eq!(region, Some("dataloop".to_string()));
                 assert_eq!(corners, ([0, 0, 0], [31, 7, 15]));
             }
@@ -575,7 +1646,9 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            GeometryStatement::AbsoluteCoordinate { region, corners } => {
+            GeometryStatement::AbsoluteCoordinate {
+                region, corners, ..
+            } => {
                 assert_eq!(region, Some("region".to_string()));
                 assert_eq!(corners, ([-10, -20, -30], [10, 20, 30]));
             }
@@ -589,11 +1662,44 @@ mod tests {
         assert_eq!(box_pair, ([0, 5, 10], [5, 10, 15]));
     }
 
+    #[test]
+    fn test_strict_box_order() {
+        let input = "@rc([5,0,0],[0,0,0])";
+        let statement = GeometryParser::new(input).parse().unwrap();
+        assert_eq!(
+            statement.to_box_pair([0, 0, 0]),
+            Some(([0, 0, 0], [5, 0, 0]))
+        );
+
+        let options = CompileOptions {
+            strict_box_order: true,
+            ..Default::default()
+        };
+        match GeometryParser::with_options(input, &options).parse() {
+            Err(ParseError::InvalidBox {
+                position, message, ..
+            }) => {
+                assert_eq!(position, 4);
+                assert!(message.contains("x axis (5 > 0)"), "{}", message);
+            }
+            other => panic!("expected InvalidBox, got {:?}", other),
+        }
+
+        let parse = |input| GeometryParser::with_options(input, &options).parse();
+        assert!(matches!(
+            parse("@r=ac([0,3,0], [1,2,1])"),
+            Err(ParseError::InvalidBox { message, .. }) if message.contains("y axis")
+        ));
+        // Equal corners are a valid single block
+        assert!(parse("@r=ac([1,2,3],[1,2,3])").is_ok());
+    }
+
     #[test]
     fn test_to_box_pair_relative() {
         let stmt = GeometryStatement::RelativeCoordinate {
             region: None,
             corners: ([0, 0, 0], [3, 2, 1]),
+            label: None,
         };
         let box_pair = stmt.to_box_pair([10, 64, 10]).unwrap();
         assert_eq!(box_pair, ([10, 64, 10], [13, 66, 11]));
@@ -604,6 +1710,7 @@ mod tests {
         let stmt = GeometryStatement::AbsoluteCoordinate {
             region: None,
             corners: ([100, 70, -20], [104, 72, -18]),
+            label: None,
         };
         let box_pair = stmt.to_box_pair([0, 0, 0]).unwrap(); // Offset ignored for absolute
         assert_eq!(box_pair, ([100, 70, -20], [104, 72, -18]));
@@ -630,6 +1737,145 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_vec3_component_count() {
+        for (input, position, message) in [
+            ("@rc([0,1],[3,4,5])", 8, "expected 3 components, found 2"),
+            (
+                "@rc([0, 1, 2 ,3],[3,4,5])",
+                14,
+                "expected 3 components, found more",
+            ),
+            ("@rc([0,0,0],[ ])", 14, "expected 3 components, found 0"),
+        ] {
+            match GeometryParser::new(input).parse() {
+                Err(ParseError::InvalidVec3 {
+                    position: p,
+                    message: m,
+                    ..
+                }) => assert_eq!((p, m.as_str()), (position, message), "{}", input),
+                other => panic!("Expected InvalidVec3 for {}, got {:?}", input, other),
+            }
+        }
+
+        // Also with trailing commas allowed, which must not swallow the extra value
+        let options = CompileOptions {
+            allow_trailing_commas: true,
+            ..Default::default()
+        };
+        let mut parser = GeometryParser::with_options("@rc([0,1,2,3],[3,4,5])", &options);
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::InvalidVec3 { position: 11, .. })
+        ));
+        let mut parser = GeometryParser::with_options("@rc([0,1,2,],[3,4,5])", &options);
+        assert!(parser.parse().is_ok());
+    }
+
+    #[test]
+    fn test_parse_integer_respects_coordinate_width() {
+        let input = "@ac([3000000000,0,0],[3000000001,1,1])";
+
+        // i32 unless the coords_i64 feature makes coordinates i64 throughout
+        let narrow_default = CoordWidth::COORD == CoordWidth::I32;
+        let mut parser = GeometryParser::new(input);
+        assert_eq!(
+            matches!(parser.parse(), Err(ParseError::InvalidInteger { .. })),
+            narrow_default
+        );
+
+        let options = CompileOptions {
+            coordinate_width: CoordWidth::I64,
+            ..Default::default()
+        };
+        let mut parser = GeometryParser::with_options(input, &options);
+        let result = parser.parse_wide().unwrap();
+        assert_eq!(
+            result.to_box_pair_wide([0, 0, 0]),
+            Some(([3_000_000_000, 0, 0], [3_000_000_001, 1, 1]))
+        );
+
+        // A GeometryStatement holds what fits its Vec3
+        let narrow = GeometryParser::with_options(input, &options).parse();
+        assert_eq!(
+            narrow.is_ok(),
+            crate::Coord::try_from(3_000_000_000i64).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_parse_narrow_checks_constants() {
+        let constants = BTreeMap::from([("far".to_string(), [i64::MAX, 0, 0])]);
+        let options = CompileOptions {
+            coordinate_width: CoordWidth::I32,
+            ..Default::default()
+        };
+        let input = "@rc(far,[0,0,0])";
+
+        let narrow_default = CoordWidth::COORD == CoordWidth::I32;
+        let mut parser = GeometryParser::with_options(input, &options).with_constants(&constants);
+        assert_eq!(
+            matches!(
+                parser.parse(),
+                Err(ParseError::InvalidVec3 { position: 4, .. })
+            ),
+            narrow_default
+        );
+        let mut parser = GeometryParser::new(input).with_constants(&constants);
+        assert_eq!(parser.parse_wide().is_err(), narrow_default);
+
+        let options = CompileOptions {
+            coordinate_width: CoordWidth::I64,
+            ..Default::default()
+        };
+        let mut parser = GeometryParser::with_options(input, &options).with_constants(&constants);
+        assert!(matches!(
+            parser.parse_wide(),
+            Ok(GeometryStatement::RelativeCoordinate {
+                corners: ([i64::MAX, 0, 0], _),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_narrow_and_wide_box_pairs() {
+        let stmt = GeometryParser::new("@rc([0,0,0],[1,2,3])").parse().unwrap();
+        assert_eq!(stmt.to_box_pair([10, 0, 0]), Some(([10, 0, 0], [11, 2, 3])));
+        assert_eq!(
+            stmt.widen().to_box_pair_wide([10, 0, 0]),
+            Some(([10, 0, 0], [11, 2, 3]))
+        );
+        assert_eq!(stmt.to_boxes([0, 0, 0]), Some(vec![([0, 0, 0], [1, 2, 3])]));
+
+        // Past Coord's range only the wide statement has a box
+        assert_eq!(stmt.to_box_pair([crate::Coord::MAX, 0, 0]), None);
+        assert_eq!(
+            stmt.widen().to_box_pair_wide([1 << 40, 0, 0]),
+            Some(([1 << 40, 0, 0], [(1 << 40) + 1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn test_to_box_pair_offset_overflow() {
+        let stmt = GeometryStatementWide::RelativeCoordinate {
+            region: None,
+            corners: ([i64::MAX, 0, 0], [0, 0, 0]),
+            label: None,
+        };
+        assert_eq!(stmt.to_box_pair_wide([1, 0, 0]), None);
+        assert_eq!(stmt.overflowing_axis([1, 0, 0]), Some(0));
+        assert_eq!(stmt.overflowing_axis([0, 1, 0]), None);
+
+        let sphere = GeometryStatementWide::Sphere {
+            region: None,
+            center: [0, 0, i64::MIN + 2],
+            radius: 3,
+        };
+        assert_eq!(sphere.to_box_pair_wide([0, 0, 0]), None);
+        assert_eq!(sphere.overflowing_axis([0, 0, 0]), Some(2));
+    }
+
     #[test]
     fn test_parse_error_malformed_integer() {
         let mut parser = GeometryParser::new("@rc([0,not_a_number,2],[3,4,5])");
@@ -671,6 +1917,104 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_expand_and_inset() {
+        let mut parser = GeometryParser::new("@padded=expand( core , 2 )+inset(a+b,1)");
+        match parser.parse().unwrap() {
+            GeometryStatement::Expression { region, expr } => {
+                assert_eq!(region, "padded");
+                let expected = BooleanExpr::union(
+                    BooleanExpr::expand(BooleanExpr::region_ref("core"), 2),
+                    BooleanExpr::inset(
+                        BooleanExpr::union(
+                            BooleanExpr::region_ref("a"),
+                            BooleanExpr::region_ref("b"),
+                        ),
+                        1,
+                    ),
+                );
+                assert_eq!(expr, expected);
+                assert_eq!(expr.region_refs(), vec!["core", "a", "b"]);
+                assert_eq!(expr.to_string(), "(expand(core, 2) + inset((a + b), 1))");
+            }
+            _ => panic!("Expected Expression"),
+        }
+
+        // Without a paren the keyword is an ordinary region name
+        let mut parser = GeometryParser::new("@a=expander+inset");
+        assert!(parser.parse().is_ok());
+
+        match GeometryParser::new("@a=expand(core,-1)").parse() {
+            Err(ParseError::InvalidBox {
+                position, message, ..
+            }) => {
+                assert_eq!(position, 15);
+                assert!(message.contains("must not be negative"));
+            }
+            other => panic!("Expected InvalidBox, got {:?}", other),
+        }
+        for input in ["@a=expand(core)", "@a=inset(core,1", "@a=inset(,1)"] {
+            assert!(GeometryParser::new(input).parse().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_translate() {
+        let mut parser = GeometryParser::new("@copy=translate( tpl , [16,0,-8] )+base");
+        match parser.parse().unwrap() {
+            GeometryStatement::Expression { expr, .. } => {
+                assert_eq!(
+                    expr,
+                    BooleanExpr::union(
+                        BooleanExpr::translate(BooleanExpr::region_ref("tpl"), [16, 0, -8]),
+                        BooleanExpr::region_ref("base"),
+                    )
+                );
+                assert_eq!(expr.to_string(), "(translate(tpl, [16,0,-8]) + base)");
+            }
+            other => panic!("expected expression, got {:?}", other),
+        }
+
+        for input in [
+            "@a=translate(tpl)",
+            "@a=translate(tpl,[1,2])",
+            "@a=translate(tpl,[1,2,3]",
+        ] {
+            assert!(GeometryParser::new(input).parse().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "metadata_groups")]
+    fn test_parse_group_by() {
+        use serde_json::json;
+
+        let mut parser = GeometryParser::new(r#"@low=group_by( power == "low" )+base"#);
+        match parser.parse().unwrap() {
+            GeometryStatement::Expression { region, expr } => {
+                assert_eq!(region, "low");
+                let expected = BooleanExpr::union(
+                    BooleanExpr::group_by("power", json!("low")),
+                    BooleanExpr::region_ref("base"),
+                );
+                assert_eq!(expr, expected);
+            }
+            _ => panic!("Expected Expression"),
+        }
+
+        let mut parser = GeometryParser::new("@g=group_by(logic.tier==[1,2])");
+        assert!(parser.parse().is_ok());
+
+        for input in [
+            "@g=group_by(==1)",
+            "@g=group_by(power=1)",
+            "@g=group_by(power==)",
+            r#"@g=group_by(power=="low""#,
+        ] {
+            assert!(GeometryParser::new(input).parse().is_err(), "{}", input);
+        }
+    }
+
     #[test]
     fn test_parse_parenthesized_expression() {
         let mut parser = GeometryParser::new("@result=(a+b)+c");
@@ -774,7 +2118,9 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            GeometryStatement::RelativeCoordinate { region, corners } => {
+            GeometryStatement::RelativeCoordinate {
+                region, corners, ..
+            } => {
                 assert_eq!(region, Some("cpu.cache".to_string()));
                 assert_eq!(corners, ([0, 0, 0], [1, 1, 1]));
             }
@@ -782,6 +2128,83 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_trailing_comma_rejected_by_default() {
+        assert!(GeometryParser::new("@rc([0,0,0,],[1,1,1])")
+            .parse()
+            .is_err());
+        assert!(GeometryParser::new("@rc([0,0,0],[1,1,1],)")
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_trailing_comma_inside_vec3() {
+        let options = CompileOptions {
+            allow_trailing_commas: true,
+            ..Default::default()
+        };
+        let mut parser = GeometryParser::with_options("@rc([0,0,0,],[1,1,1 , ])", &options);
+
+        match parser.parse().unwrap() {
+            GeometryStatement::RelativeCoordinate { corners, .. } => {
+                assert_eq!(corners, ([0, 0, 0], [1, 1, 1]));
+            }
+            _ => panic!("Expected RelativeCoordinate"),
+        }
+    }
+
+    #[test]
+    fn test_trailing_comma_after_box_corners() {
+        let options = CompileOptions {
+            allow_trailing_commas: true,
+            ..Default::default()
+        };
+        let mut parser = GeometryParser::with_options("@r=ac([0,0,0],[1,1,1],)", &options);
+
+        match parser.parse().unwrap() {
+            GeometryStatement::AbsoluteCoordinate {
+                region, corners, ..
+            } => {
+                assert_eq!(region, Some("r".to_string()));
+                assert_eq!(corners, ([0, 0, 0], [1, 1, 1]));
+            }
+            _ => panic!("Expected AbsoluteCoordinate"),
+        }
+    }
+
+    #[test]
+    fn test_trailing_comma_only_single_trailing() {
+        let options = CompileOptions {
+            allow_trailing_commas: true,
+            ..Default::default()
+        };
+
+        // Multiple trailing commas
+        assert!(
+            GeometryParser::with_options("@rc([0,0,0,,],[1,1,1])", &options)
+                .parse()
+                .is_err()
+        );
+        assert!(
+            GeometryParser::with_options("@rc([0,0,0],[1,1,1],,)", &options)
+                .parse()
+                .is_err()
+        );
+
+        // Leading commas
+        assert!(
+            GeometryParser::with_options("@rc([,0,0,0],[1,1,1])", &options)
+                .parse()
+                .is_err()
+        );
+        assert!(
+            GeometryParser::with_options("@rc(,[0,0,0],[1,1,1])", &options)
+                .parse()
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_parse_region_name_with_ac_substring() {
         // This should parse as a region name "cpu.cache" followed by ac(...)
@@ -789,11 +2212,421 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            GeometryStatement::AbsoluteCoordinate { region, corners } => {
+            GeometryStatement::AbsoluteCoordinate {
+                region, corners, ..
+            } => {
                 assert_eq!(region, Some("cpu.cache".to_string()));
                 assert_eq!(corners, ([0, 0, 0], [1, 1, 1]));
             }
             _ => panic!("Expected AbsoluteCoordinate"),
         }
     }
+
+    #[test]
+    fn test_geometry_keyword_case_and_aliases() {
+        let input = "@RC([0,0,0],[1,1,1])";
+        assert!(GeometryParser::new(input).parse().is_err());
+
+        let options = CompileOptions {
+            geometry_keywords_case_insensitive: true,
+            geometry_keyword_aliases: [
+                ("box".to_string(), GeomKind::Relative),
+                ("abs".to_string(), GeomKind::Absolute),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let parse = |input| GeometryParser::with_options(input, &options).parse();
+
+        assert!(matches!(
+            parse(input),
+            Ok(GeometryStatement::RelativeCoordinate { region: None, .. })
+        ));
+        assert!(matches!(
+            parse("@Box([0,0,0],[1,1,1])"),
+            Ok(GeometryStatement::RelativeCoordinate { region: None, .. })
+        ));
+        assert!(matches!(
+            parse("@cpu=ABS([0,0,0],[1,1,1])"),
+            Ok(GeometryStatement::AbsoluteCoordinate {
+                region: Some(_),
+                ..
+            })
+        ));
+
+        // Without '(' an alias is just a region name
+        assert!(matches!(
+            parse("@r=box+abs"),
+            Ok(GeometryStatement::Expression { .. })
+        ));
+
+        // Without the case-insensitive option, aliases match exactly
+        let options = CompileOptions {
+            geometry_keyword_aliases: [("box".to_string(), GeomKind::Relative)].into(),
+            ..Default::default()
+        };
+        let mut parser = GeometryParser::with_options("@Box([0,0,0],[1,1,1])", &options);
+        assert!(parser.parse().is_err());
+        let mut parser = GeometryParser::with_options("@box([0,0,0],[1,1,1])", &options);
+        assert!(parser.parse().is_ok());
+    }
+
+    #[test]
+    fn test_parse_shell() {
+        let mut parser = GeometryParser::new("@wall=shell(rc([0,0,0],[9,9,9]), thickness=2)");
+        let stmt = parser.parse().unwrap();
+        match &stmt {
+            GeometryStatement::Shell {
+                region,
+                outer,
+                thickness,
+            } => {
+                assert_eq!(region.as_deref(), Some("wall"));
+                assert!(matches!(
+                    outer.as_ref(),
+                    GeometryStatement::RelativeCoordinate { region: None, .. }
+                ));
+                assert_eq!(*thickness, 2);
+            }
+            _ => panic!("Expected Shell statement"),
+        }
+
+        // Anonymous, with the default thickness of 1
+        let mut parser = GeometryParser::new("@shell(ac([0,0,0],[2,2,2]))");
+        assert!(matches!(
+            parser.parse().unwrap(),
+            GeometryStatement::Shell {
+                region: None,
+                thickness: 1,
+                ..
+            }
+        ));
+
+        for input in [
+            "@shell(rc([0,0,0],[2,2,2]), thickness=0)",
+            "@shell(rc([0,0,0],[2,2,2]), depth=1)",
+            "@shell(a+b)",
+        ] {
+            assert!(GeometryParser::new(input).parse().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_shell_to_boxes() {
+        let volume = |boxes: &[BoxPair64]| -> i64 {
+            boxes
+                .iter()
+                .map(|(min, max)| {
+                    (0..3)
+                        .map(|axis| max[axis] - min[axis] + 1)
+                        .product::<i64>()
+                })
+                .sum()
+        };
+        let shell = |thickness| GeometryStatementWide::Shell {
+            region: None,
+            outer: Box::new(GeometryStatement::RelativeCoordinate {
+                region: None,
+                corners: ([0, 0, 0], [9, 9, 9]),
+                label: None,
+            }),
+            thickness,
+        };
+
+        let walls = shell(1).to_boxes_wide([100, 0, 0]).unwrap();
+        assert_eq!(volume(&walls), 1000 - 8 * 8 * 8);
+        assert!(walls.iter().all(|(min, _)| min[0] >= 100));
+        assert_eq!(
+            volume(&shell(4).to_boxes_wide([0, 0, 0]).unwrap()),
+            1000 - 2 * 2 * 2
+        );
+
+        // No interior left: the solid box
+        assert_eq!(
+            shell(5).to_boxes_wide([0, 0, 0]).unwrap(),
+            vec![([0, 0, 0], [9, 9, 9])]
+        );
+    }
+
+    #[test]
+    fn test_parse_sphere() {
+        let mut parser = GeometryParser::new("@dome=sp([1,2,3], 4)");
+        assert_eq!(
+            parser.parse().unwrap(),
+            GeometryStatement::Sphere {
+                region: Some("dome".to_string()),
+                center: [1, 2, 3],
+                radius: 4,
+            }
+        );
+        let mut parser = GeometryParser::new("@sp([0,0,0],0)");
+        assert!(matches!(
+            parser.parse().unwrap(),
+            GeometryStatement::Sphere { region: None, .. }
+        ));
+
+        for (input, message) in [
+            ("@sp([0,0,0], -1)", "sphere radius must not be negative"),
+            ("@sp([0,0,0], 257)", "sphere radius must be at most 256"),
+        ] {
+            match GeometryParser::new(input).parse() {
+                Err(ParseError::InvalidBox {
+                    position,
+                    message: m,
+                    ..
+                }) => {
+                    assert_eq!(position, 13);
+                    assert_eq!(m, message);
+                }
+                other => panic!("Expected InvalidBox for {}, got {:?}", input, other),
+            }
+        }
+        assert!(GeometryParser::new("@sp([0,0,0])").parse().is_err());
+    }
+
+    #[test]
+    fn test_sphere_to_boxes() {
+        let sphere = |radius| GeometryStatementWide::Sphere {
+            region: None,
+            center: [0, 0, 0],
+            radius,
+        };
+        let blocks = |boxes: &[BoxPair64]| -> BTreeSet<Vec3Wide> {
+            let mut blocks = BTreeSet::new();
+            for &(min, max) in boxes {
+                for x in min[0]..=max[0] {
+                    for y in min[1]..=max[1] {
+                        for z in min[2]..=max[2] {
+                            assert!(blocks.insert([x, y, z]), "boxes overlap");
+                        }
+                    }
+                }
+            }
+            blocks
+        };
+
+        assert_eq!(
+            sphere(0).to_boxes_wide([5, 6, 7]).unwrap(),
+            vec![([5, 6, 7], [5, 6, 7])]
+        );
+        assert_eq!(
+            blocks(&sphere(1).to_boxes_wide([0, 0, 0]).unwrap()).len(),
+            7
+        );
+        assert_eq!(
+            sphere(3).to_box_pair_wide([10, 0, 0]),
+            Some(([7, -3, -3], [13, 3, 3]))
+        );
+
+        let offset = [100, -50, 3];
+        let covered = blocks(&sphere(5).to_boxes_wide(offset).unwrap());
+        let mut expected = BTreeSet::new();
+        for x in -5i64..=5 {
+            for y in -5i64..=5 {
+                for z in -5i64..=5 {
+                    if x * x + y * y + z * z <= 25 {
+                        expected.insert([x + 100, y - 50, z + 3]);
+                    }
+                }
+            }
+        }
+        assert_eq!(covered, expected);
+
+        assert_eq!(sphere(1).to_boxes_wide([i64::MAX, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_integer_sqrt() {
+        for n in 0u64..=10_000 {
+            let root = integer_sqrt(n);
+            assert!(
+                root * root <= n && (root + 1) * (root + 1) > n,
+                "sqrt of {}",
+                n
+            );
+        }
+        // Large squares where the float estimate rounds the wrong way
+        let big = u32::MAX as u64;
+        assert_eq!(integer_sqrt(big * big), big);
+        assert_eq!(integer_sqrt(big * big - 1), big - 1);
+        assert_eq!(integer_sqrt(u64::MAX), big);
+        assert_eq!(integer_sqrt(i64::MAX as u64), 3_037_000_499);
+    }
+
+    #[test]
+    fn test_parse_alias() {
+        let mut parser = GeometryParser::new("@alias core2 = cpu.core");
+        assert_eq!(
+            parser.parse().unwrap(),
+            GeometryStatement::Alias {
+                name: "core2".to_string(),
+                target: "cpu.core".to_string(),
+            }
+        );
+        // A region may still be called `alias`
+        let mut parser = GeometryParser::new("@alias=rc([0,0,0],[1,1,1])");
+        assert_eq!(parser.parse().unwrap().region(), Some("alias"));
+
+        for input in ["@alias a", "@alias a+=b", "@alias a=b+c", "@alias a="] {
+            let mut parser = GeometryParser::new(input);
+            assert!(parser.parse().is_err(), "{} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_quoted_region_names() {
+        let mut parser = GeometryParser::new("@\"My Region\"=rc([0,0,0],[1,1,1])");
+        assert_eq!(parser.parse().unwrap().region(), Some("My Region"));
+
+        let mut parser = GeometryParser::new("@\"say \\\"hi\\\": now\" += rc([0,0,0],[1,1,1])");
+        let (result, append) = parser.parse_wide_with_append().unwrap();
+        assert_eq!(result.region(), Some("say \"hi\": now"));
+        assert!(append);
+
+        let mut parser = GeometryParser::new("@combined=\"My Region\"+other");
+        match parser.parse().unwrap() {
+            GeometryStatement::Expression { region, expr } => {
+                assert_eq!(region, "combined");
+                assert_eq!(
+                    expr,
+                    BooleanExpr::union(
+                        BooleanExpr::region_ref("My Region"),
+                        BooleanExpr::region_ref("other")
+                    )
+                );
+            }
+            other => panic!("Expected Expression, got {:?}", other),
+        }
+
+        let mut parser = GeometryParser::new("@alias \"a b\"=\"c:d\"");
+        assert_eq!(
+            parser.parse().unwrap(),
+            GeometryStatement::Alias {
+                name: "a b".to_string(),
+                target: "c:d".to_string(),
+            }
+        );
+
+        for input in [
+            "@\"\"=rc([0,0,0],[1,1,1])",
+            "@\"open=rc([0,0,0],[1,1,1])",
+            "@\"a\" rc([0,0,0],[1,1,1])",
+        ] {
+            assert!(GeometryParser::new(input).parse().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_const() {
+        let mut parser = GeometryParser::new("@const origin = [10,64,10]");
+        assert_eq!(
+            parser.parse().unwrap(),
+            GeometryStatement::Const {
+                name: "origin".to_string(),
+                value: [10, 64, 10],
+            }
+        );
+        // A region may still be called `const`
+        let mut parser = GeometryParser::new("@const=rc([0,0,0],[1,1,1])");
+        assert_eq!(parser.parse().unwrap().region(), Some("const"));
+
+        let constants = BTreeMap::from([("origin".to_string(), [10, 64, 10])]);
+        let mut parser = GeometryParser::new("@ac(origin, [3,2,1])").with_constants(&constants);
+        assert_eq!(
+            parser.parse().unwrap().to_box_pair([0, 0, 0]),
+            Some(([3, 2, 1], [10, 64, 10]))
+        );
+
+        let mut parser = GeometryParser::new("@rc(origin,corner)").with_constants(&constants);
+        match parser.parse() {
+            Err(ParseError::UnknownConstant { name, position, .. }) => {
+                assert_eq!(name, "corner");
+                assert_eq!(position, 11);
+            }
+            other => panic!("Expected UnknownConstant, got {:?}", other),
+        }
+        let mut parser = GeometryParser::new("@rc(origin,corner)").accepting_unknown_constants();
+        assert!(parser.parse().is_ok());
+
+        for input in ["@const =[1,2,3]", "@const 1x=[1,2,3]", "@const a [1,2,3]"] {
+            assert!(GeometryParser::new(input).parse().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_box_label() {
+        let mut parser = GeometryParser::new("@wall=ac([0,0,0],[9,0,9])#north");
+        let stmt = parser.parse().unwrap();
+        assert_eq!(stmt.label(), Some("north"));
+        assert_eq!(stmt.region(), Some("wall"));
+
+        let mut parser = GeometryParser::new("@wall=rc([0,0,0],[9,0,9])");
+        assert_eq!(parser.parse().unwrap().label(), None);
+
+        for input in [
+            "@wall=rc([0,0,0],[9,0,9])#",
+            // Labels identify boxes within a named region
+            "@rc([0,0,0],[9,0,9])#north",
+            "@wall=(a+b)#north",
+        ] {
+            assert!(GeometryParser::new(input).parse().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_non_ascii_region_name() {
+        // Bare names are ASCII; other names must be quoted
+        let mut parser = GeometryParser::new("@café=rc([0,0,0],[1,1,1])");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::InvalidRegionName {
+                found: 'é',
+                position: 4,
+                ..
+            })
+        ));
+
+        let mut parser = GeometryParser::new("@\"café\"=rc([0,0,0],[1,1,1])#sued");
+        match parser.parse().unwrap() {
+            GeometryStatement::RelativeCoordinate { region, label, .. } => {
+                assert_eq!(region.as_deref(), Some("café"));
+                assert_eq!(label.as_deref(), Some("sued"));
+            }
+            other => panic!("Expected RelativeCoordinate, got {:?}", other),
+        }
+
+        // Box labels are ASCII even when the region name is quoted
+        let mut parser = GeometryParser::new("@\"café\"=rc([0,0,0],[1,1,1])#süd");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::Expected {
+                expected: "box label",
+                position: 30,
+                ..
+            })
+        ));
+
+        // Positions are byte offsets: "é" takes two bytes
+        let mut parser = GeometryParser::new("@\"é\"=rc([0,0,x],[1,1,1])");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::Expected { position: 14, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_long_statement() {
+        // A union of a few thousand single-block boxes; parsing must stay linear
+        let terms: Vec<String> = (0..2000)
+            .map(|i| format!("ac([{i},0,0],[{i},0,0])"))
+            .collect();
+        let input = format!("@long=base\n  + {}", terms.join("\n  + "));
+        assert!(input.len() > 40_000);
+
+        let mut parser = GeometryParser::new(&input);
+        match parser.parse().unwrap() {
+            GeometryStatement::Expression { region, .. } => assert_eq!(region, "long"),
+            other => panic!("Expected Expression, got {:?}", other),
+        }
+    }
 }
@@ -1,6 +1,10 @@
+use crate::parser::take_chars;
 use crate::ParseError;
 use serde_json::Value;
 
+/// Non-finite number spellings that are not JSON but are emitted by some tools
+const NON_FINITE_LITERALS: [&str; 4] = ["-Infinity", "Infinity", "-NaN", "NaN"];
+
 /// Parser for strict JSON values in metadata
 pub struct JsonValueParser<'a> {
     input: &'a str,
@@ -12,6 +16,11 @@ impl<'a> JsonValueParser<'a> {
         Self { input, position: 0 }
     }
 
+    /// Bytes consumed so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
     /// Parse a JSON value from the input
     pub fn parse(&mut self) -> Result<Value, ParseError> {
         self.skip_whitespace();
@@ -19,7 +28,7 @@ impl<'a> JsonValueParser<'a> {
 
         // Find the end of the JSON value by parsing it with serde_json
         // We'll try parsing increasingly longer substrings until we find a valid JSON value
-        let remaining_input = &self.input[self.position..];
+        let remaining_input = self.rest();
 
         // Try to parse the JSON value
         match serde_json::from_str::<Value>(remaining_input) {
@@ -36,11 +45,40 @@ impl<'a> JsonValueParser<'a> {
         }
     }
 
+    /// Parse a JSON string at the current position, returning it unescaped
+    pub fn parse_string(&mut self) -> Result<String, ParseError> {
+        if self.current_char() != Some('"') {
+            return Err(ParseError::Expected {
+                expected: "'\"'",
+                found: self.current_char().unwrap_or('\0').to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+        match self.parse_string_value()? {
+            Value::String(text) => Ok(text),
+            _ => unreachable!("a quoted JSON value is a string"),
+        }
+    }
+
     /// Parse JSON value by finding its boundary
     fn parse_json_value_boundary(&mut self) -> Result<Value, ParseError> {
         self.skip_whitespace();
         let _start_pos = self.position;
 
+        // Reject the non-standard spellings some JSON emitters produce
+        let rest = self.rest();
+        if let Some(literal) = NON_FINITE_LITERALS.iter().find(|literal| {
+            rest.strip_prefix(*literal)
+                .is_some_and(|tail| !tail.starts_with(|ch: char| ch.is_alphanumeric() || ch == '_'))
+        }) {
+            return Err(ParseError::NonFiniteNumber {
+                found: literal.to_string(),
+                position: self.position,
+                context: None,
+            });
+        }
+
         match self.current_char() {
             Some('"') => self.parse_string_value(),
             Some(ch) if ch.is_ascii_digit() || ch == '-' => self.parse_number_value(),
@@ -52,10 +90,12 @@ impl<'a> JsonValueParser<'a> {
                 expected: "JSON value",
                 found: ch.to_string(),
                 position: self.position,
+                context: None,
             }),
             None => Err(ParseError::UnexpectedEnd {
                 expected: "JSON value",
                 position: self.position,
+                context: None,
             }),
         }
     }
@@ -73,11 +113,12 @@ impl<'a> JsonValueParser<'a> {
                 escaped = true;
             } else if ch == '"' {
                 self.advance(); // Skip closing quote
-                let json_str = &self.input[start_pos..self.position];
+                let json_str = self.slice(start_pos, self.position);
                 return serde_json::from_str(json_str).map_err(|_| ParseError::Expected {
                     expected: "valid JSON string",
                     found: json_str.to_string(),
                     position: start_pos,
+                    context: None,
                 });
             }
             self.advance();
@@ -86,6 +127,7 @@ impl<'a> JsonValueParser<'a> {
         Err(ParseError::UnexpectedEnd {
             expected: "closing quote for JSON string",
             position: self.position,
+            context: None,
         })
     }
 
@@ -105,12 +147,9 @@ impl<'a> JsonValueParser<'a> {
             self.advance();
         }
 
-        let json_str = &self.input[start_pos..self.position];
-        serde_json::from_str(json_str).map_err(|_| ParseError::Expected {
-            expected: "valid JSON number",
-            found: json_str.to_string(),
-            position: start_pos,
-        })
+        let json_str = self.slice(start_pos, self.position);
+        serde_json::from_str(json_str)
+            .map_err(|_| invalid_json("valid JSON number", json_str, start_pos))
     }
 
     /// Parse a JSON boolean value
@@ -126,6 +165,7 @@ impl<'a> JsonValueParser<'a> {
                 expected: "'true' or 'false'",
                 found: self.peek_str(5).to_string(),
                 position: start_pos,
+                context: None,
             })
         }
     }
@@ -141,6 +181,7 @@ impl<'a> JsonValueParser<'a> {
                 expected: "'null'",
                 found: self.peek_str(4).to_string(),
                 position: start_pos,
+                context: None,
             })
         }
     }
@@ -152,17 +193,17 @@ impl<'a> JsonValueParser<'a> {
 
         while let Some(ch) = self.current_char() {
             match ch {
-                '[' => depth += 1,
+                '[' => {
+                    depth += 1;
+                    self.advance();
+                }
                 ']' => {
                     depth -= 1;
                     self.advance();
                     if depth == 0 {
-                        let json_str = &self.input[start_pos..self.position];
-                        return serde_json::from_str(json_str).map_err(|_| ParseError::Expected {
-                            expected: "valid JSON array",
-                            found: json_str.to_string(),
-                            position: start_pos,
-                        });
+                        let json_str = self.slice(start_pos, self.position);
+                        return serde_json::from_str(json_str)
+                            .map_err(|_| invalid_json("valid JSON array", json_str, start_pos));
                     }
                 }
                 '"' => {
@@ -176,6 +217,7 @@ impl<'a> JsonValueParser<'a> {
         Err(ParseError::UnexpectedEnd {
             expected: "closing ']' for JSON array",
             position: self.position,
+            context: None,
         })
     }
 
@@ -186,17 +228,17 @@ impl<'a> JsonValueParser<'a> {
 
         while let Some(ch) = self.current_char() {
             match ch {
-                '{' => depth += 1,
+                '{' => {
+                    depth += 1;
+                    self.advance();
+                }
                 '}' => {
                     depth -= 1;
                     self.advance();
                     if depth == 0 {
-                        let json_str = &self.input[start_pos..self.position];
-                        return serde_json::from_str(json_str).map_err(|_| ParseError::Expected {
-                            expected: "valid JSON object",
-                            found: json_str.to_string(),
-                            position: start_pos,
-                        });
+                        let json_str = self.slice(start_pos, self.position);
+                        return serde_json::from_str(json_str)
+                            .map_err(|_| invalid_json("valid JSON object", json_str, start_pos));
                     }
                 }
                 '"' => {
@@ -210,6 +252,7 @@ impl<'a> JsonValueParser<'a> {
         Err(ParseError::UnexpectedEnd {
             expected: "closing '}' for JSON object",
             position: self.position,
+            context: None,
         })
     }
 
@@ -236,21 +279,31 @@ impl<'a> JsonValueParser<'a> {
         }
     }
 
+    /// The input between two byte positions reached by the cursor
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        &self.input[start..end]
+    }
+
+    /// The input from the current position on
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
     /// Get the current character
     fn current_char(&self) -> Option<char> {
-        self.input.chars().nth(self.position)
+        self.rest().chars().next()
     }
 
     /// Advance the position by one character
     fn advance(&mut self) {
-        if self.position < self.input.len() {
-            self.position += 1;
+        if let Some(ch) = self.current_char() {
+            self.position += ch.len_utf8();
         }
     }
 
     /// Consume a specific string if it matches
     fn consume_str(&mut self, expected: &str) -> bool {
-        if self.input[self.position..].starts_with(expected) {
+        if self.rest().starts_with(expected) {
             self.position += expected.len();
             true
         } else {
@@ -258,13 +311,67 @@ impl<'a> JsonValueParser<'a> {
         }
     }
 
-    /// Peek at a string of the given length starting from current position
+    /// Peek at up to `len` characters starting from current position
     fn peek_str(&self, len: usize) -> &str {
-        let end = (self.position + len).min(self.input.len());
-        &self.input[self.position..end]
+        take_chars(self.rest(), len)
     }
 }
 
+/// Map a serde_json failure to a parse error, singling out numbers too large
+/// to represent (e.g. `1e400`), which would otherwise become infinite
+fn invalid_json(expected: &'static str, json_str: &str, position: usize) -> ParseError {
+    if has_out_of_range_number(json_str) {
+        ParseError::NonFiniteNumber {
+            found: json_str.to_string(),
+            position,
+            context: None,
+        }
+    } else {
+        ParseError::Expected {
+            expected,
+            found: json_str.to_string(),
+            position,
+            context: None,
+        }
+    }
+}
+
+/// Whether a number literal outside strings in `json_str` overflows an f64
+fn has_out_of_range_number(json_str: &str) -> bool {
+    let is_number_char =
+        |ch: char| ch.is_ascii_digit() || matches!(ch, '.' | 'e' | 'E' | '+' | '-');
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = json_str.char_indices().peekable();
+    while let Some((offset, ch)) = chars.next() {
+        if in_string {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_string = true,
+            '-' | '0'..='9' => {
+                let mut end = offset + 1;
+                while let Some(&(next, _)) = chars.peek().filter(|&&(_, ch)| is_number_char(ch)) {
+                    end = next + 1;
+                    chars.next();
+                }
+                let literal = &json_str[offset..end];
+                if literal.parse::<f64>().is_ok_and(f64::is_infinite) {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -319,4 +426,50 @@ mod tests {
         let result = parser.parse().unwrap();
         assert_eq!(result, json!({"key": "value", "num": 42}));
     }
+
+    #[test]
+    fn test_reject_non_finite_numbers() {
+        for input in [
+            "NaN",
+            "Infinity",
+            "-Infinity",
+            "1e400",
+            "-1e400",
+            "NaN trailing",
+        ] {
+            let mut parser = JsonValueParser::new(input);
+            assert!(
+                matches!(
+                    parser.parse(),
+                    Err(ParseError::NonFiniteNumber { position: 0, .. })
+                ),
+                "{} should be rejected as non-finite",
+                input
+            );
+        }
+
+        // Nested out-of-range numbers are caught too
+        let mut parser = JsonValueParser::new("[1, 1e400]");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::NonFiniteNumber { .. })
+        ));
+        let mut parser = JsonValueParser::new("{\"a\": \"1e400\", \"b\": -2e999}");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::NonFiniteNumber { .. })
+        ));
+        // Out-of-range text inside a string does not mask another error
+        let mut parser = JsonValueParser::new("[\"1e400\" 2]");
+        assert!(matches!(parser.parse(), Err(ParseError::Expected { .. })));
+        let mut parser = JsonValueParser::new("{\"scale\": NaN}");
+        assert!(parser.parse().is_err());
+
+        // Large but finite values and look-alike strings are fine
+        assert_eq!(JsonValueParser::new("1e300").parse().unwrap(), json!(1e300));
+        assert_eq!(
+            JsonValueParser::new("\"NaN\"").parse().unwrap(),
+            json!("NaN")
+        );
+    }
 }
@@ -11,6 +11,8 @@ pub enum MetadataStatement {
         key: String,
         /// The JSON value
         value: Value,
+        /// Written `?=`: only applied where the key is not set otherwise
+        default: bool,
     },
     /// Targeted metadata: #<target>:key=<json>
     Targeted {
@@ -20,6 +22,15 @@ pub enum MetadataStatement {
         key: String,
         /// The JSON value
         value: Value,
+        /// Written `?=`: only applied where the key is not set otherwise
+        default: bool,
+    },
+    /// Key deletion: #<target>:-key
+    Delete {
+        /// The target (region ID, wildcard, or $global)
+        target: String,
+        /// The metadata key to remove
+        key: String,
     },
 }
 
@@ -27,11 +38,21 @@ pub enum MetadataStatement {
 pub struct MetadataParser<'a> {
     input: &'a str,
     position: usize,
+    disabled: bool,
 }
 
 impl<'a> MetadataParser<'a> {
     pub fn new(input: &'a str) -> Self {
-        Self { input, position: 0 }
+        Self {
+            input,
+            position: 0,
+            disabled: false,
+        }
+    }
+
+    /// Whether the last parsed statement used the `#!` disable prefix
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
     }
 
     /// Parse a metadata statement from the input
@@ -44,9 +65,13 @@ impl<'a> MetadataParser<'a> {
                 expected: "'#'",
                 found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
+                context: None,
             });
         }
 
+        // '#!' marks a disabled statement: still parsed, but excluded from compilation
+        self.disabled = self.consume_char('!');
+
         // Check if this is targeted metadata (contains ':')
         let _checkpoint = self.position;
         let target = self.parse_optional_target()?;
@@ -59,51 +84,84 @@ impl<'a> MetadataParser<'a> {
                     expected: "':'",
                     found: self.current_char().unwrap_or('\0').to_string(),
                     position: self.position,
+                    context: None,
                 });
             }
             self.skip_whitespace();
 
+            // '-' before the key removes it instead of assigning a value
+            if self.consume_char('-') {
+                let key = self.parse_key()?;
+                self.skip_whitespace();
+                if let Some(ch) = self.current_char() {
+                    return Err(ParseError::Expected {
+                        expected: "end of statement after deleted key",
+                        found: ch.to_string(),
+                        position: self.position,
+                        context: None,
+                    });
+                }
+                return Ok(MetadataStatement::Delete { target, key });
+            }
+
             // Parse key
             let key = self.parse_key()?;
 
-            // Expect '='
+            // Expect '=' or '?='
             self.skip_whitespace();
+            let default = self.consume_char('?');
             if !self.consume_char('=') {
                 return Err(ParseError::Expected {
                     expected: "'='",
                     found: self.current_char().unwrap_or('\0').to_string(),
                     position: self.position,
+                    context: None,
                 });
             }
             self.skip_whitespace();
 
             // Parse JSON value
-            let remaining_input = &self.input[self.position..];
+            let remaining_input = self.rest();
             let mut json_parser = JsonValueParser::new(remaining_input);
-            let value = json_parser.parse()?;
-
-            Ok(MetadataStatement::Targeted { target, key, value })
+            let value = json_parser
+                .parse()
+                .map_err(|e| e.offset_position(self.position))?;
+
+            Ok(MetadataStatement::Targeted {
+                target,
+                key,
+                value,
+                default,
+            })
         } else {
             // Current region metadata
             let key = self.parse_key()?;
 
-            // Expect '='
+            // Expect '=' or '?='
             self.skip_whitespace();
+            let default = self.consume_char('?');
             if !self.consume_char('=') {
                 return Err(ParseError::Expected {
                     expected: "'='",
                     found: self.current_char().unwrap_or('\0').to_string(),
                     position: self.position,
+                    context: None,
                 });
             }
             self.skip_whitespace();
 
             // Parse JSON value
-            let remaining_input = &self.input[self.position..];
+            let remaining_input = self.rest();
             let mut json_parser = JsonValueParser::new(remaining_input);
-            let value = json_parser.parse()?;
-
-            Ok(MetadataStatement::Current { key, value })
+            let value = json_parser
+                .parse()
+                .map_err(|e| e.offset_position(self.position))?;
+
+            Ok(MetadataStatement::Current {
+                key,
+                value,
+                default,
+            })
         }
     }
 
@@ -115,12 +173,13 @@ impl<'a> MetadataParser<'a> {
         while let Some(ch) = self.current_char() {
             if ch == ':' {
                 // Found ':', so we have a target
-                let target = self.input[start_pos..self.position].trim().to_string();
+                let target = self.slice(start_pos, self.position).trim().to_string();
                 if target.is_empty() {
                     return Err(ParseError::Expected {
                         expected: "target name",
                         found: "empty string".to_string(),
                         position: start_pos,
+                        context: None,
                     });
                 }
                 return Ok(Some(target));
@@ -150,6 +209,7 @@ impl<'a> MetadataParser<'a> {
                 expected: "metadata key",
                 found: self.current_char().unwrap_or('\0').to_string(),
                 position: self.position,
+                context: None,
             });
         }
 
@@ -160,12 +220,13 @@ impl<'a> MetadataParser<'a> {
             self.advance();
         }
 
-        let key = self.input[start_pos..self.position].to_string();
+        let key = self.slice(start_pos, self.position).to_string();
         if key.is_empty() {
             return Err(ParseError::Expected {
                 expected: "metadata key",
                 found: "empty string".to_string(),
                 position: start_pos,
+                context: None,
             });
         }
 
@@ -179,15 +240,25 @@ impl<'a> MetadataParser<'a> {
         }
     }
 
+    /// The input between two byte positions reached by the cursor
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        &self.input[start..end]
+    }
+
+    /// The input from the current position on
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
     /// Get the current character
     fn current_char(&self) -> Option<char> {
-        self.input.chars().nth(self.position)
+        self.rest().chars().next()
     }
 
     /// Advance the position by one character
     fn advance(&mut self) {
-        if self.position < self.input.len() {
-            self.position += 1;
+        if let Some(ch) = self.current_char() {
+            self.position += ch.len_utf8();
         }
     }
 
@@ -213,7 +284,7 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            MetadataStatement::Current { key, value } => {
+            MetadataStatement::Current { key, value, .. } => {
                 assert_eq!(key, "doc.label");
                 assert_eq!(value, json!("Patch A"));
             }
@@ -227,7 +298,7 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            MetadataStatement::Current { key, value } => {
+            MetadataStatement::Current { key, value, .. } => {
                 assert_eq!(key, "logic.clock_hz");
                 assert_eq!(value, json!(4));
             }
@@ -241,7 +312,9 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            MetadataStatement::Targeted { target, key, value } => {
+            MetadataStatement::Targeted {
+                target, key, value, ..
+            } => {
                 assert_eq!(target, "cpu.core");
                 assert_eq!(key, "logic.clock_hz");
                 assert_eq!(value, json!(4));
@@ -250,13 +323,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_disabled_prefix() {
+        let mut parser = MetadataParser::new(r#"#!cpu.core:logic.clock_hz=4"#);
+        let result = parser.parse().unwrap();
+
+        assert!(parser.is_disabled());
+        assert!(matches!(result, MetadataStatement::Targeted { .. }));
+    }
+
     #[test]
     fn test_parse_wildcard_metadata() {
         let mut parser = MetadataParser::new(r#"#cpu.*:power.budget="low""#);
         let result = parser.parse().unwrap();
 
         match result {
-            MetadataStatement::Targeted { target, key, value } => {
+            MetadataStatement::Targeted {
+                target, key, value, ..
+            } => {
                 assert_eq!(target, "cpu.*");
                 assert_eq!(key, "power.budget");
                 assert_eq!(value, json!("low"));
@@ -271,7 +355,9 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            MetadataStatement::Targeted { target, key, value } => {
+            MetadataStatement::Targeted {
+                target, key, value, ..
+            } => {
                 assert_eq!(target, "$global");
                 assert_eq!(key, "io.bus_width");
                 assert_eq!(value, json!(8));
@@ -286,7 +372,9 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            MetadataStatement::Targeted { target, key, value } => {
+            MetadataStatement::Targeted {
+                target, key, value, ..
+            } => {
                 assert_eq!(target, "cpu.core");
                 assert_eq!(key, "logic.clock_hz");
                 assert_eq!(value, json!(4));
@@ -301,7 +389,7 @@ mod tests {
         let result = parser.parse().unwrap();
 
         match result {
-            MetadataStatement::Current { key, value } => {
+            MetadataStatement::Current { key, value, .. } => {
                 assert_eq!(key, "config");
                 assert_eq!(value, json!({"enabled": true, "count": 42}));
             }
@@ -329,4 +417,89 @@ mod tests {
         let result = parser.parse();
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_json_error_position_is_relative_to_statement() {
+        let mut parser = MetadataParser::new("#key=invalid_json");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::Expected { position: 5, .. })
+        ));
+
+        let mut parser = MetadataParser::new("#cpu:label= \"unterminated");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::UnexpectedEnd { position: 25, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_error_non_finite_number() {
+        for input in [
+            "#scale=Infinity",
+            "#scale=NaN",
+            "#scale=1e400",
+            "#scale=[1e400, 2]",
+        ] {
+            let mut parser = MetadataParser::new(input);
+            assert!(
+                matches!(
+                    parser.parse(),
+                    Err(ParseError::NonFiniteNumber { position: 7, .. })
+                ),
+                "{} should be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_delete_metadata() {
+        let mut parser = MetadataParser::new("#cpu.core: -power ");
+        assert_eq!(
+            parser.parse().unwrap(),
+            MetadataStatement::Delete {
+                target: "cpu.core".to_string(),
+                key: "power".to_string(),
+            }
+        );
+
+        // A deletion takes no value
+        let mut parser = MetadataParser::new("#cpu.core:-power=1");
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::Expected { position: 16, .. })
+        ));
+        let mut parser = MetadataParser::new("#cpu.core:-");
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_default_metadata() {
+        let mut parser = MetadataParser::new(r#"#cpu.*:power ?= "low""#);
+        assert_eq!(
+            parser.parse().unwrap(),
+            MetadataStatement::Targeted {
+                target: "cpu.*".to_string(),
+                key: "power".to_string(),
+                value: json!("low"),
+                default: true,
+            }
+        );
+
+        let mut parser = MetadataParser::new("#power?=1");
+        assert!(matches!(
+            parser.parse().unwrap(),
+            MetadataStatement::Current { default: true, .. }
+        ));
+
+        let mut parser = MetadataParser::new("#power=1");
+        assert!(matches!(
+            parser.parse().unwrap(),
+            MetadataStatement::Current { default: false, .. }
+        ));
+
+        let mut parser = MetadataParser::new("#power?1");
+        assert!(parser.parse().is_err());
+    }
 }
@@ -5,3 +5,15 @@ pub mod meta;
 pub use geom::*;
 pub use json_value::*;
 pub use meta::*;
+
+/// The first `count` characters of `input`, or all of it if shorter.
+///
+/// The parsers track byte positions, so a character count must not be added
+/// to them directly: that could land inside a multibyte character.
+pub(crate) fn take_chars(input: &str, count: usize) -> &str {
+    let end = input
+        .char_indices()
+        .nth(count)
+        .map_or(input.len(), |(idx, _)| idx);
+    &input[..end]
+}
@@ -1,24 +1,42 @@
 use crate::ast::{
-    apply_metadata_pass, assemble_region_table, evaluate_geometry, shape_final_output,
-    EvaluatedRegionTable, GeomStmt, MetaStmt,
+    add_volume_metadata, alias_chain, apply_aliases, apply_metadata_statements,
+    assemble_region_table_within, check_narrowable, collect_aliases, group_by_tuple,
+    narrow_dsl_map, order_dsl_map, order_metadata, output_boxes, shape_final_output_with_options,
+    AliasTable, EvaluatedRegionTable, Evaluator, GeomStmt, GeomStmtWide, MetaStmt, RegionEntry,
+    RegionTable, SourceLocation, SourceSpan,
 };
-use crate::lexer::{filter_comments, split_statements};
-use crate::parser::geom::GeometryParser;
+use crate::diagnostics::{lint_coordinate_style, lint_duplicate_anonymous};
+use crate::lexer::{filter_comments, split_statements, StatementSlice};
+use crate::parser::geom::{GeometryParser, GeometryStatement, Vec3Wide};
 use crate::parser::meta::MetadataParser;
-use crate::{DslMap, Error, ParseError};
-use std::collections::BTreeMap;
+#[cfg(feature = "namespace_schemas")]
+use crate::schema::validate_namespace_schemas;
+use crate::{
+    AnnotatedEntry, AnnotatedMap, AnnotatedValue, Axis, BoxPair, BoxPair64, CompileOptions,
+    CoordWidth, Diagnostic, DslEntry, DslMap, DslMap64, Error, MetadataOrigin, OrderedDslMap,
+    ParseError, PredefinedCollision, StatementContext, UnitPosition,
+};
+use std::collections::{BTreeMap, BTreeSet};
 
-/// Parse all statements from a tuple's text into geometry and metadata statements
+/// Parse all statements from a tuple's text into geometry and metadata statements.
+///
+/// `@const` declarations are added to `constants`, which carries them on to
+/// the statements and tuples that follow, and are returned apart from the
+/// geometry they apply to. Without `constants`, as in a syntax check of a
+/// single tuple, any constant name is accepted and declarations are skipped.
 fn parse_tuple_statements(
     tuple_idx: usize,
     text: &str,
-) -> Result<(Vec<GeomStmt>, Vec<MetaStmt>), ParseError> {
+    options: &CompileOptions,
+    mut constants: Option<&mut BTreeMap<String, Vec3Wide>>,
+) -> Result<ParsedProgram, ParseError> {
     // Filter out comments before processing
     let filtered_text = filter_comments(text);
     let statement_slices = split_statements(&filtered_text);
 
     let mut geom_stmts = Vec::new();
     let mut meta_stmts = Vec::new();
+    let mut const_stmts = Vec::new();
 
     for (stmt_idx, statement_slice) in statement_slices.iter().enumerate() {
         let stmt_text = statement_slice.text.trim();
@@ -28,65 +46,1029 @@ fn parse_tuple_statements(
 
         if stmt_text.starts_with('@') {
             // Geometry statement
-            let mut geom_parser = GeometryParser::new(stmt_text);
-            let parsed_stmt = geom_parser.parse()?;
-            geom_stmts.push(GeomStmt::new(tuple_idx, stmt_idx, parsed_stmt));
+            let mut geom_parser = GeometryParser::with_options(stmt_text, options);
+            geom_parser = match &constants {
+                Some(constants) => geom_parser.with_constants(constants),
+                None => geom_parser.accepting_unknown_constants(),
+            };
+            let (parsed_stmt, append) = geom_parser.parse_wide_with_append().map_err(|e| {
+                locate_syntax_error(
+                    e,
+                    tuple_idx,
+                    stmt_idx,
+                    text,
+                    &filtered_text,
+                    statement_slice,
+                )
+            })?;
+            if geom_parser.is_disabled() {
+                continue;
+            }
+            if let GeometryStatement::Const { name, value } = &parsed_stmt {
+                let Some(constants) = constants.as_deref_mut() else {
+                    continue;
+                };
+                if constants.insert(name.clone(), *value).is_some() {
+                    return Err(ParseError::DuplicateConstant {
+                        name: name.clone(),
+                        source: SourceLocation::new(tuple_idx, stmt_idx),
+                    });
+                }
+                const_stmts.push(GeomStmt::new(tuple_idx, stmt_idx, parsed_stmt));
+                continue;
+            }
+            let mut geom_stmt = GeomStmt::new(tuple_idx, stmt_idx, parsed_stmt);
+            geom_stmt.append = append;
+            geom_stmts.push(geom_stmt);
         } else if stmt_text.starts_with('#') {
             // Metadata statement
             let mut meta_parser = MetadataParser::new(stmt_text);
-            let parsed_stmt = meta_parser.parse()?;
+            let parsed_stmt = meta_parser.parse().map_err(|e| {
+                locate_syntax_error(
+                    e,
+                    tuple_idx,
+                    stmt_idx,
+                    text,
+                    &filtered_text,
+                    statement_slice,
+                )
+            })?;
+            if meta_parser.is_disabled() {
+                continue;
+            }
             meta_stmts.push(MetaStmt::new(tuple_idx, stmt_idx, parsed_stmt));
         }
         // Skip any other statements (shouldn't happen with proper lexer)
     }
 
-    Ok((geom_stmts, meta_stmts))
+    Ok(ParsedProgram {
+        geom_stmts,
+        meta_stmts,
+        constants: const_stmts,
+    })
+}
+
+/// Attach the statement a parse error was found in, with the line and
+/// column of its position in the tuple `text` (`filtered` is `text` after
+/// comment filtering)
+fn locate_syntax_error(
+    error: ParseError,
+    tuple_idx: usize,
+    stmt_idx: usize,
+    text: &str,
+    filtered: &str,
+    slice: &StatementSlice<'_>,
+) -> ParseError {
+    let position = error.position().unwrap_or(0);
+    let span = statement_span(text, filtered, slice, position);
+    let context = StatementContext {
+        source: SourceLocation::new(tuple_idx, stmt_idx).with_span(span),
+        statement: statement_snippet(slice.text.trim(), position),
+    };
+    error.with_context(context)
+}
+
+/// Where byte `position` of the statement in `slice` lies in the tuple `text`
+fn statement_span(
+    text: &str,
+    filtered: &str,
+    slice: &StatementSlice<'_>,
+    position: usize,
+) -> SourceSpan {
+    // Parsers see the statement without leading whitespace
+    let leading = slice.text.len() - slice.text.trim_start().len();
+    let offset = (slice.start + leading + position).min(filtered.len());
+
+    // Comment filtering only blanks whole lines, so lines and columns match
+    // `text`; only byte offsets need translating
+    let before = &filtered[..offset];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    let line = before.matches('\n').count() + 1;
+    let column = before[line_start..].chars().count() + 1;
+    let text_line_start: usize = text
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum();
+
+    SourceSpan {
+        offset: text_line_start + (offset - line_start),
+        line,
+        column,
+    }
+}
+
+/// Longest statement shown whole in a syntax error, in characters
+const STATEMENT_SNIPPET_CHARS: usize = 60;
+
+/// `statement` on one line, cut to a window around byte `position` when longer
+/// than [`STATEMENT_SNIPPET_CHARS`]
+fn statement_snippet(statement: &str, position: usize) -> String {
+    let chars: Vec<char> = statement.chars().collect();
+    let mut start = 0;
+    let mut end = chars.len();
+    if chars.len() > STATEMENT_SNIPPET_CHARS {
+        let position = statement
+            .get(..position.min(statement.len()))
+            .map_or(0, |before| before.chars().count());
+        start = position
+            .saturating_sub(STATEMENT_SNIPPET_CHARS / 2)
+            .min(chars.len() - STATEMENT_SNIPPET_CHARS);
+        end = start + STATEMENT_SNIPPET_CHARS;
+    }
+
+    let window: String = chars[start..end].iter().collect();
+    let mut snippet = window.split_whitespace().collect::<Vec<_>>().join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "...");
+    }
+    if end < chars.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+/// Parse every statement of one tuple's text, discarding the result
+pub fn validate_syntax_pipeline(text: &str, options: &CompileOptions) -> Result<(), ParseError> {
+    parse_tuple_statements(0, text, options, None).map(|_| ())
 }
 
 /// Complete compilation pipeline from input units to final DslMap
 pub fn compile_pipeline(units: &[([i32; 3], String)]) -> Result<DslMap, Error> {
+    compile_pipeline_with_options(units, &CompileOptions::default())
+}
+
+/// Complete compilation pipeline with explicit compile options
+pub fn compile_pipeline_with_options(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<DslMap, Error> {
+    compile_pipeline_verbose(units, options).map(|(dsl_map, _diagnostics)| dsl_map)
+}
+
+/// Complete compilation pipeline, also returning non-fatal diagnostics
+pub fn compile_pipeline_verbose(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<(DslMap, Vec<Diagnostic>), Error> {
+    compile_pipeline_predefined(units, &BTreeMap::new(), options)
+}
+
+/// Complete compilation pipeline with host-provided regions seeded into the table
+pub fn compile_pipeline_predefined(
+    units: &[([i32; 3], String)],
+    predefined: &BTreeMap<String, Vec<BoxPair>>,
+    options: &CompileOptions,
+) -> Result<(DslMap, Vec<Diagnostic>), Error> {
+    let mut diagnostics = Vec::new();
+    let dsl_map = compile_into(units, predefined, options, &mut diagnostics)?;
+    Ok((dsl_map, diagnostics))
+}
+
+/// Complete compilation pipeline that keeps the diagnostics gathered before an
+/// error alongside it
+pub fn compile_pipeline_try(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<(DslMap, Vec<Diagnostic>), (Error, Vec<Diagnostic>)> {
+    let mut diagnostics = Vec::new();
+    match compile_into(units, &BTreeMap::new(), options, &mut diagnostics) {
+        Ok(dsl_map) => Ok((dsl_map, diagnostics)),
+        Err(error) => Err((error, diagnostics)),
+    }
+}
+
+/// Complete compilation pipeline over units read from an iterator.
+///
+/// Each unit is parsed as soon as it is read and its text dropped, so only
+/// positions and parsed statements are kept. `max_tuples` is checked as units
+/// arrive, so a syntax error in an earlier unit is reported before it.
+pub fn compile_iter_pipeline(
+    units: impl Iterator<Item = ([i32; 3], String)>,
+    options: &CompileOptions,
+) -> Result<DslMap, Error> {
+    // Positions are brought to XYZ as they are read, as `assemble_units` does
+    let order = options.position_axis_order;
+    if !Axis::is_permutation(order) {
+        return Err(ParseError::InvalidAxisOrder { order }.into());
+    }
+    let limit = options.max_tuples.unwrap_or(usize::MAX);
+    let mut units = units.map(|(position, text)| (Axis::to_xyz(order, position.to_wide()), text));
+
+    let mut diagnostics = Vec::new();
+    let (positions, program) = parse_unit_stream(
+        units.by_ref().take(limit),
+        options,
+        &mut diagnostics,
+        &mut Err,
+    )?;
+    if units.next().is_some() {
+        // Count the rest without parsing it, for the error
+        check_tuple_count(positions.len() + 1 + units.count(), options)?;
+    }
+    if positions.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let options = CompileOptions {
+        position_axis_order: Axis::XYZ,
+        ..options.clone()
+    };
+    let width = options.coordinate_width.resolved();
+    let assembled = assemble_program(
+        &positions,
+        program,
+        &BTreeMap::new(),
+        &options,
+        width,
+        &mut diagnostics,
+        &mut Err,
+    )?;
+    let (_region_table, evaluated_table) =
+        evaluate_assembled(assembled, true, &options, width, &mut diagnostics, &mut Err)?;
+    Ok(narrow_dsl_map(finish_output(evaluated_table, &options))?)
+}
+
+/// Complete compilation pipeline that reports every error it can find.
+///
+/// Runs the same steps as [`compile_pipeline`], but a failing tuple, metadata
+/// statement or region is recorded and skipped rather than ending the
+/// compilation; see [`evaluate_units`]. Limits, assembly errors and an
+/// exhausted evaluation budget still stop early.
+pub fn compile_collect_pipeline(
+    units: &[(impl UnitPosition, String)],
+    options: &CompileOptions,
+) -> Result<DslMap, Vec<Error>> {
     if units.is_empty() {
         return Ok(BTreeMap::new());
     }
 
-    // Step 1: Parse all statements from all tuples
-    let mut all_geom_stmts = Vec::new();
-    let mut all_meta_stmts = Vec::new();
+    let mut errors: Vec<ParseError> = Vec::new();
+    let result = evaluate_units(
+        units,
+        &BTreeMap::new(),
+        options,
+        options.coordinate_width.resolved(),
+        &mut Vec::new(),
+        &mut |error| {
+            // Regions built on a failing one fail with the same error
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+            Ok(())
+        },
+    );
+    match result {
+        Ok((_region_table, evaluated_table)) if errors.is_empty() => {
+            narrow_dsl_map(finish_output(evaluated_table, options))
+                .map_err(|error| vec![error.into()])
+        }
+        Ok(_) => Err(errors.into_iter().map(Error::from).collect()),
+        Err(error) => {
+            let mut errors: Vec<Error> = errors.into_iter().map(Error::from).collect();
+            errors.push(error);
+            Err(errors)
+        }
+    }
+}
 
-    for (tuple_idx, (_position, text)) in units.iter().enumerate() {
-        let (geom_stmts, meta_stmts) = parse_tuple_statements(tuple_idx, text)?;
-        all_geom_stmts.extend(geom_stmts);
-        all_meta_stmts.extend(meta_stmts);
+/// Compile to the final i32 map, pushing warnings into `diagnostics` as they
+/// are found so they survive a later error
+fn compile_into(
+    units: &[([i32; 3], String)],
+    predefined: &BTreeMap<String, Vec<BoxPair>>,
+    options: &CompileOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<DslMap, Error> {
+    if units.is_empty() && predefined.is_empty() {
+        return Ok(BTreeMap::new());
     }
 
-    // Step 2: Assemble RegionTable from geometry statements
-    let region_table =
-        assemble_region_table(all_geom_stmts.clone(), all_meta_stmts.clone(), units)?;
+    let (_region_table, evaluated_table) = evaluate_units(
+        units,
+        predefined,
+        options,
+        options.coordinate_width.resolved(),
+        diagnostics,
+        &mut Err,
+    )?;
+    Ok(narrow_dsl_map(finish_output(evaluated_table, options))?)
+}
+
+/// Run the compilation pipeline up to evaluated regions and metadata, reporting
+/// the error [`compile_into`] would, without building the output map.
+///
+/// The i32 range is checked on the evaluated boxes: tuple offsets are added
+/// in i64, so even i32 literals can land outside it.
+pub fn validate_pipeline(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<(), Error> {
+    if units.is_empty() {
+        return Ok(());
+    }
+
+    let (_region_table, evaluated_table) = evaluate_units(
+        units,
+        &BTreeMap::new(),
+        options,
+        options.coordinate_width.resolved(),
+        &mut Vec::new(),
+        &mut Err,
+    )?;
+    Ok(check_narrowable(&evaluated_table, options)?)
+}
+
+/// Complete compilation pipeline producing i64 coordinates
+pub fn compile_wide_pipeline(
+    units: &[([i64; 3], String)],
+    options: &CompileOptions,
+) -> Result<DslMap64, Error> {
+    if units.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let (_region_table, evaluated_table) = evaluate_units(
+        units,
+        &BTreeMap::new(),
+        options,
+        CoordWidth::I64,
+        &mut Vec::new(),
+        &mut Err,
+    )?;
+    Ok(finish_output(evaluated_table, options))
+}
+
+/// Compile pipeline with the output grouped by originating tuple index.
+///
+/// Every tuple index appears in the result. A region is listed, with its full
+/// compiled entry, under every tuple that contributed geometry or metadata to it
+/// (including metadata applied through wildcards), so regions spanning several
+/// tuples are repeated rather than moved to a shared bucket.
+pub fn compile_by_tuple_pipeline(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<BTreeMap<usize, DslMap>, Error> {
+    if units.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let (region_table, evaluated_table) = evaluate_units(
+        units,
+        &BTreeMap::new(),
+        options,
+        options.coordinate_width.resolved(),
+        &mut Vec::new(),
+        &mut Err,
+    )?;
+
+    // Trace which tuples contributed to each region
+    let mut contributions: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+    for (region_name, entry) in &region_table.regions {
+        contributions
+            .entry(region_name.clone())
+            .or_default()
+            .extend(entry.sources().iter().map(|source| source.tuple_idx));
+    }
+    for (region_name, data) in &evaluated_table.regions {
+        contributions
+            .entry(region_name.clone())
+            .or_default()
+            .extend(data.metadata.values().map(|a| a.source.tuple_idx));
+    }
+
+    let final_map = narrow_dsl_map(finish_output(evaluated_table, options))?;
+    Ok(group_by_tuple(final_map, &contributions, units.len()))
+}
+
+/// Complete compilation pipeline annotating each metadata value with its origin
+pub fn compile_annotated_pipeline(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<AnnotatedMap, Error> {
+    if units.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let (_region_table, evaluated_table) = evaluate_units(
+        units,
+        &BTreeMap::new(),
+        options,
+        options.coordinate_width.resolved(),
+        &mut Vec::new(),
+        &mut Err,
+    )?;
+
+    // Capture origins before the table is consumed by output shaping
+    let origins: BTreeMap<(String, String), MetadataOrigin> = evaluated_table
+        .regions
+        .iter()
+        .flat_map(|(region_name, data)| {
+            data.metadata.iter().map(move |(key, assignment)| {
+                let origin = match &assignment.via {
+                    Some(pattern) => MetadataOrigin::Inherited(pattern.clone()),
+                    None => MetadataOrigin::Own,
+                };
+                ((region_name.clone(), key.clone()), origin)
+            })
+        })
+        .collect();
+
+    let final_map = narrow_dsl_map(finish_output(evaluated_table, options))?;
+    Ok(final_map
+        .into_iter()
+        .map(|(region_name, entry)| {
+            let metadata = entry
+                .metadata
+                .into_iter()
+                .map(|(key, value)| {
+                    let origin = origins
+                        .get(&(region_name.clone(), key.clone()))
+                        .cloned()
+                        .unwrap_or(MetadataOrigin::Own);
+                    (key, AnnotatedValue { value, origin })
+                })
+                .collect();
+            let annotated = AnnotatedEntry {
+                bounding_boxes: entry.bounding_boxes,
+                metadata,
+            };
+            (region_name, annotated)
+        })
+        .collect())
+}
 
-    // Step 3: Evaluate geometry to get bounding boxes
-    let evaluated_boxes = evaluate_geometry(&region_table)?;
+/// Complete compilation pipeline arranging entries by `options.ordering`
+pub fn compile_ordered_pipeline(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<OrderedDslMap, Error> {
+    if units.is_empty() {
+        return Ok(OrderedDslMap::default());
+    }
+
+    let (_region_table, evaluated_table) = evaluate_units(
+        units,
+        &BTreeMap::new(),
+        options,
+        options.coordinate_width.resolved(),
+        &mut Vec::new(),
+        &mut Err,
+    )?;
+
+    let final_map = narrow_dsl_map(finish_output(evaluated_table, options))?;
+    Ok(order_dsl_map(final_map, options.ordering))
+}
+
+/// Run parsing, assembly, evaluation and the metadata pass over all units,
+/// pushing any warnings into `diagnostics`.
+///
+/// Evaluated boxes must stay within `evaluation_width`, which is the width of
+/// the output rather than `options.coordinate_width`: that one only limits
+/// literals, and i64 output may go beyond i32 whatever the literals are.
+///
+/// Errors a later step can work around are handed to `report`, which either
+/// stops the compilation by returning the error or records it and continues:
+/// a tuple that fails to parse is left out, a failing metadata statement is
+/// skipped, and a failing region keeps no boxes. Regions are only evaluated
+/// when assembly reported nothing, since references to the regions of a
+/// skipped tuple would otherwise show up as unknown.
+fn evaluate_units(
+    units: &[(impl UnitPosition, String)],
+    predefined: &BTreeMap<String, Vec<BoxPair>>,
+    options: &CompileOptions,
+    evaluation_width: CoordWidth,
+    diagnostics: &mut Vec<Diagnostic>,
+    report: &mut dyn FnMut(ParseError) -> Result<(), ParseError>,
+) -> Result<(RegionTable, EvaluatedRegionTable), Error> {
+    let mut assembled_all = true;
+    let assembled = assemble_units(
+        units,
+        predefined,
+        options,
+        evaluation_width,
+        diagnostics,
+        &mut |error| {
+            assembled_all = false;
+            report(error)
+        },
+    )?;
+    evaluate_assembled(
+        assembled,
+        assembled_all,
+        options,
+        evaluation_width,
+        diagnostics,
+        report,
+    )
+}
 
-    // Step 4: Build EvaluatedRegionTable with boxes
+/// Evaluate an assembled region table and apply metadata and aliases, as
+/// [`evaluate_units`] does once assembly is done. `assembled_all` tells
+/// whether assembly went through without reporting any error.
+fn evaluate_assembled(
+    (region_table, all_geom_stmts, all_meta_stmts): (RegionTable, Vec<GeomStmtWide>, Vec<MetaStmt>),
+    assembled_all: bool,
+    options: &CompileOptions,
+    evaluation_width: CoordWidth,
+    diagnostics: &mut Vec<Diagnostic>,
+    report: &mut dyn FnMut(ParseError) -> Result<(), ParseError>,
+) -> Result<(RegionTable, EvaluatedRegionTable), Error> {
     let mut evaluated_table = EvaluatedRegionTable::new();
-    for (region_name, boxes) in evaluated_boxes {
-        evaluated_table.set_region_boxes(region_name, Some(boxes));
+    for region_name in region_table.regions.keys() {
+        evaluated_table.set_region_boxes(region_name.clone(), None);
+    }
+    let mut evaluator = Evaluator::with_options(options).with_coordinate_width(evaluation_width);
+
+    // With metadata groups, metadata is applied before geometry so `group_by`
+    // terms can match on it. Metadata only depends on which regions exist.
+    #[cfg(feature = "metadata_groups")]
+    {
+        apply_metadata(
+            &mut evaluated_table,
+            &all_geom_stmts,
+            &all_meta_stmts,
+            options,
+            &mut *report,
+        )?;
+        evaluator.set_group_metadata(&evaluated_table);
+    }
+
+    // Step 3: Evaluate geometry into the table
+    if assembled_all {
+        for region_name in region_table.regions.keys() {
+            match evaluator.evaluate_region(&region_table, region_name) {
+                Ok(boxes) => set_evaluated_boxes(
+                    &mut evaluated_table,
+                    &region_table,
+                    region_name.clone(),
+                    boxes,
+                ),
+                // The budget is shared, so no other region can be evaluated
+                Err(error @ ParseError::EvaluationBudgetExceeded { .. }) => {
+                    return Err(error.into())
+                }
+                Err(error) => report(error)?,
+            }
+        }
+    }
+
+    // Step 4: Apply metadata pass
+    #[cfg(not(feature = "metadata_groups"))]
+    apply_metadata(
+        &mut evaluated_table,
+        &all_geom_stmts,
+        &all_meta_stmts,
+        options,
+        &mut *report,
+    )?;
+
+    if assembled_all {
+        if let Err(error) = apply_aliases(&mut evaluated_table, &region_table, &all_geom_stmts) {
+            report(error)?;
+        }
+    }
+    diagnostics.extend(lint_duplicate_anonymous(&region_table, &evaluated_table));
+    Ok((region_table, evaluated_table))
+}
+
+/// Apply all metadata statements to `table` and check namespace schemas,
+/// handing each error to `report`
+fn apply_metadata(
+    table: &mut EvaluatedRegionTable,
+    geom_stmts: &[GeomStmtWide],
+    meta_stmts: &[MetaStmt],
+    options: &CompileOptions,
+    report: &mut dyn FnMut(ParseError) -> Result<(), ParseError>,
+) -> Result<(), ParseError> {
+    apply_metadata_statements(table, geom_stmts, meta_stmts, options, &mut *report)?;
+    #[cfg(feature = "namespace_schemas")]
+    if let Err(error) = validate_namespace_schemas(table, &options.namespace_schemas) {
+        report(error)?;
+    }
+    Ok(())
+}
+
+/// Apply all metadata statements to a table listing every region, without boxes
+fn metadata_table(
+    region_table: &RegionTable,
+    geom_stmts: &[GeomStmtWide],
+    meta_stmts: &[MetaStmt],
+    options: &CompileOptions,
+) -> Result<EvaluatedRegionTable, ParseError> {
+    let mut table = EvaluatedRegionTable::new();
+    for region_name in region_table.regions.keys() {
+        table.set_region_boxes(region_name.clone(), None);
+    }
+    apply_metadata(&mut table, geom_stmts, meta_stmts, options, &mut Err)?;
+    Ok(table)
+}
+
+/// Record a region's evaluated boxes, along with its box labels if it has any
+fn set_evaluated_boxes(
+    evaluated_table: &mut EvaluatedRegionTable,
+    region_table: &RegionTable,
+    region_name: String,
+    boxes: Vec<BoxPair64>,
+) {
+    let labels = region_table.box_labels(&region_name).map(<[_]>::to_vec);
+    evaluated_table.set_region_boxes(region_name.clone(), Some(boxes));
+    if let Some(labels) = labels {
+        evaluated_table.set_region_box_labels(&region_name, labels);
+    }
+}
+
+/// The statements of all units, parsed but neither assembled nor evaluated.
+///
+/// Returned by [`crate::parse_units`]. Each statement carries its tuple and
+/// statement index. Fields may be added in any release.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ParsedProgram {
+    /// Geometry statements in input order, without `@const` declarations
+    pub geom_stmts: Vec<GeomStmtWide>,
+    /// Metadata statements in input order
+    pub meta_stmts: Vec<MetaStmt>,
+    /// `@const` declarations in input order, already substituted into the
+    /// geometry statements that use them
+    pub constants: Vec<GeomStmtWide>,
+}
+
+/// Parse the statements of all units, without assembling or evaluating them
+pub fn parse_units_pipeline(
+    units: &[([i32; 3], String)],
+    options: &CompileOptions,
+) -> Result<ParsedProgram, Error> {
+    check_tuple_count(units.len(), options)?;
+    parse_units_linted(units, options, &mut Vec::new(), &mut Err)
+}
+
+/// Reject inputs with more tuples than `options.max_tuples` allows
+fn check_tuple_count(count: usize, options: &CompileOptions) -> Result<(), ParseError> {
+    match options.max_tuples {
+        Some(limit) if count > limit => Err(ParseError::TooManyTuples { count, limit }),
+        _ => Ok(()),
+    }
+}
+
+/// Parse the statements of all units, pushing lint warnings into `diagnostics`.
+/// A tuple that fails to parse is handed to `report`, and left out if it
+/// returns `Ok`.
+fn parse_units_linted(
+    units: &[(impl UnitPosition, String)],
+    options: &CompileOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+    report: &mut dyn FnMut(ParseError) -> Result<(), ParseError>,
+) -> Result<ParsedProgram, Error> {
+    let units = units
+        .iter()
+        .map(|(position, text)| (*position, text.as_str()));
+    let (_positions, program) = parse_unit_stream(units, options, diagnostics, report)?;
+    Ok(program)
+}
+
+/// Parse units one at a time as [`parse_units_linted`] does, taking each
+/// unit's text only while it is parsed.
+///
+/// Returns the parsed statements along with every unit's position, paired with
+/// an empty text so the assembly steps can look positions up by tuple index.
+fn parse_unit_stream<P: UnitPosition>(
+    units: impl Iterator<Item = (P, impl AsRef<str>)>,
+    options: &CompileOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+    report: &mut dyn FnMut(ParseError) -> Result<(), ParseError>,
+) -> Result<(Vec<(P, String)>, ParsedProgram), Error> {
+    let mut positions = Vec::new();
+    let mut program = ParsedProgram {
+        geom_stmts: Vec::new(),
+        meta_stmts: Vec::new(),
+        constants: Vec::new(),
+    };
+    let mut constants = BTreeMap::new();
+
+    for (tuple_idx, (position, text)) in units.enumerate() {
+        positions.push((position, String::new()));
+        let parsed =
+            match parse_tuple_statements(tuple_idx, text.as_ref(), options, Some(&mut constants)) {
+                Ok(parsed) => parsed,
+                Err(error) => {
+                    report(error)?;
+                    continue;
+                }
+            };
+
+        // Lint as we go, so warnings for earlier tuples survive a later error
+        if options.warn_coordinate_style {
+            diagnostics.extend(lint_coordinate_style(
+                &parsed.geom_stmts,
+                &positions,
+                &options.coordinate_style_thresholds,
+            ));
+        }
+
+        program.geom_stmts.extend(parsed.geom_stmts);
+        program.meta_stmts.extend(parsed.meta_stmts);
+        program.constants.extend(parsed.constants);
+    }
+
+    Ok((positions, program))
+}
+
+/// Parse all units and assemble the RegionTable, without evaluating geometry.
+/// Box statements must fit `width` once offset by their tuple's position.
+fn assemble_units(
+    units: &[(impl UnitPosition, String)],
+    predefined: &BTreeMap<String, Vec<BoxPair>>,
+    options: &CompileOptions,
+    width: CoordWidth,
+    diagnostics: &mut Vec<Diagnostic>,
+    report: &mut dyn FnMut(ParseError) -> Result<(), ParseError>,
+) -> Result<(RegionTable, Vec<GeomStmtWide>, Vec<MetaStmt>), Error> {
+    // Check the input size before copying anything
+    check_tuple_count(units.len(), options)?;
+
+    // Step 0: Bring positions in a foreign axis order to XYZ
+    let order = options.position_axis_order;
+    if order != Axis::XYZ {
+        if !Axis::is_permutation(order) {
+            return Err(ParseError::InvalidAxisOrder { order }.into());
+        }
+        let units: Vec<([i64; 3], String)> = units
+            .iter()
+            .map(|(position, text)| (Axis::to_xyz(order, position.to_wide()), text.clone()))
+            .collect();
+        let predefined: BTreeMap<String, Vec<BoxPair>> = predefined
+            .iter()
+            .map(|(name, boxes)| {
+                let boxes = boxes
+                    .iter()
+                    .map(|&(min, max)| (Axis::to_xyz(order, min), Axis::to_xyz(order, max)))
+                    .collect();
+                (name.clone(), boxes)
+            })
+            .collect();
+        let options = CompileOptions {
+            position_axis_order: Axis::XYZ,
+            ..options.clone()
+        };
+        return assemble_units(&units, &predefined, &options, width, diagnostics, report);
     }
 
-    // Step 5: Apply metadata pass
-    apply_metadata_pass(&mut evaluated_table, &all_geom_stmts, &all_meta_stmts)?;
+    // Step 1: Parse all statements from all tuples
+    let program = parse_units_linted(units, options, diagnostics, &mut *report)?;
+    assemble_program(
+        units,
+        program,
+        predefined,
+        options,
+        width,
+        diagnostics,
+        report,
+    )
+}
+
+/// Assemble the RegionTable from parsed statements, as [`assemble_units`] does
+/// once parsing is done. Only the positions of `units` are used, which must
+/// already be in XYZ order.
+fn assemble_program(
+    units: &[(impl UnitPosition, String)],
+    program: ParsedProgram,
+    predefined: &BTreeMap<String, Vec<BoxPair>>,
+    options: &CompileOptions,
+    width: CoordWidth,
+    diagnostics: &mut Vec<Diagnostic>,
+    report: &mut dyn FnMut(ParseError) -> Result<(), ParseError>,
+) -> Result<(RegionTable, Vec<GeomStmtWide>, Vec<MetaStmt>), Error> {
+    let ParsedProgram {
+        geom_stmts: all_geom_stmts,
+        meta_stmts: all_meta_stmts,
+        ..
+    } = program;
+
+    // Step 2: Assemble RegionTable from geometry statements
+    let mut region_table = assemble_region_table_within(&all_geom_stmts, units, options, width)?;
+
+    // Step 2b: Seed host-provided regions the user did not define
+    for (region_name, boxes) in predefined {
+        if let Some(entry) = region_table.regions.get(region_name) {
+            let source = entry.sources()[0].clone();
+            match options.predefined_collision {
+                PredefinedCollision::Error => {
+                    report(ParseError::PredefinedRegionRedefined {
+                        region: region_name.clone(),
+                        source,
+                    })?;
+                    continue;
+                }
+                PredefinedCollision::Warn => {
+                    diagnostics.push(Diagnostic::warning(
+                        format!("region '{}' replaces a predefined region", region_name),
+                        source,
+                    ));
+                    continue;
+                }
+            }
+        }
+        region_table.regions.insert(
+            region_name.clone(),
+            RegionEntry::Accumulator {
+                boxes: boxes
+                    .iter()
+                    .map(|&(min, max)| (min.to_wide(), max.to_wide()))
+                    .collect(),
+                labels: vec![None; boxes.len()],
+                sources: Vec::new(),
+            },
+        );
+    }
+
+    Ok((region_table, all_geom_stmts, all_meta_stmts))
+}
+
+/// A parsed and assembled compilation whose geometry is evaluated on demand.
+///
+/// Created by [`crate::compile_lazy`]. Evaluated boxes are cached, so each
+/// region is computed at most once however it is reached. Evaluation errors
+/// (unknown regions, cycles, overflow) are reported by the call that hits them.
+#[derive(Debug)]
+pub struct LazyCompiled {
+    table: RegionTable,
+    geom_stmts: Vec<GeomStmtWide>,
+    meta_stmts: Vec<MetaStmt>,
+    aliases: AliasTable,
+    options: CompileOptions,
+    evaluator: Evaluator,
+    /// Metadata for every region, built on first access
+    metadata: Option<EvaluatedRegionTable>,
+}
+
+impl LazyCompiled {
+    /// Parse and assemble `units` without evaluating any geometry
+    pub fn new(units: &[([i32; 3], String)], options: &CompileOptions) -> Result<Self, Error> {
+        let (table, geom_stmts, meta_stmts) = assemble_units(
+            units,
+            &BTreeMap::new(),
+            options,
+            options.coordinate_width.resolved(),
+            &mut Vec::new(),
+            &mut Err,
+        )?;
+        let aliases = collect_aliases(&geom_stmts, &table)?;
+        Ok(Self {
+            table,
+            geom_stmts,
+            meta_stmts,
+            aliases,
+            options: options.clone(),
+            evaluator: Evaluator::with_options(options),
+            metadata: None,
+        })
+    }
+
+    /// Names of all regions that have geometry, in lexicographic order
+    pub fn region_names(&self) -> impl Iterator<Item = &str> {
+        self.table.regions.keys().map(String::as_str)
+    }
+
+    /// Evaluate a single region and return its entry with metadata applied.
+    ///
+    /// Only the region and the regions it references are evaluated. Metadata-only
+    /// entries such as `$global` or `cpu.*` are returned with no boxes.
+    pub fn region(&mut self, name: &str) -> Result<DslEntry, Error> {
+        // An alias is its final target's entry, with the aliases' own metadata on top
+        if self.aliases.contains_key(name) {
+            let chain = alias_chain(&self.aliases, &self.table, name)?;
+            let (target, links) = chain.split_last().expect("chain is not empty");
+            let mut entry = self.region(target)?;
+            self.metadata()?;
+            let table = self.metadata.as_ref().expect("metadata was just built");
+            for link in links.iter().rev() {
+                if let Some(data) = table.regions.get(link) {
+                    for (key, assignment) in &data.metadata {
+                        entry.metadata.insert(key.clone(), assignment.value.clone());
+                    }
+                }
+            }
+            // A key's statement is on the nearest link that sets it
+            order_metadata(
+                &mut entry.metadata,
+                |key| {
+                    chain
+                        .iter()
+                        .filter_map(|link| table.regions.get(link)?.metadata.get(key))
+                        .map(|assignment| &assignment.source)
+                        .next()
+                },
+                &self.options,
+            );
+            return Ok(entry);
+        }
+
+        // Groups need metadata before any geometry is evaluated
+        #[cfg(feature = "metadata_groups")]
+        self.metadata()?;
+
+        let boxes = if self.table.regions.contains_key(name)
+            || !self.metadata()?.regions.contains_key(name)
+        {
+            Some(self.evaluator.evaluate_region(&self.table, name)?)
+        } else {
+            None
+        };
+
+        self.metadata()?;
+        let assignments = self
+            .metadata
+            .as_ref()
+            .expect("metadata was just built")
+            .regions
+            .get(name)
+            .map(|data| &data.metadata);
+        let metadata = assignments
+            .into_iter()
+            .flatten()
+            .map(|(key, assignment)| (key.clone(), assignment.value.clone()))
+            .collect();
+
+        let box_labels = self.table.box_labels(name).map(<[_]>::to_vec);
+        let mut entry = DslEntry {
+            bounding_boxes: output_boxes(boxes, &box_labels, &self.options),
+            box_labels,
+            metadata,
+        };
+        add_volume_metadata(&mut entry, &self.options);
+        order_metadata(
+            &mut entry.metadata,
+            |key| Some(&assignments?.get(key)?.source),
+            &self.options,
+        );
+        let narrowed = narrow_dsl_map(BTreeMap::from([(name.to_string(), entry)]))?;
+        Ok(narrowed
+            .into_values()
+            .next()
+            .expect("narrowing keeps every entry"))
+    }
+
+    /// Evaluate every region and return the full map, identical to [`crate::compile_with_options`]
+    pub fn materialize(&mut self) -> Result<DslMap, Error> {
+        #[cfg(feature = "metadata_groups")]
+        self.metadata()?;
+
+        let mut evaluated_table = EvaluatedRegionTable::new();
+        for region_name in self.table.regions.keys() {
+            let boxes = self.evaluator.evaluate_region(&self.table, region_name)?;
+            set_evaluated_boxes(
+                &mut evaluated_table,
+                &self.table,
+                region_name.clone(),
+                boxes,
+            );
+        }
+
+        for (region_name, data) in &self.metadata()?.regions {
+            for (key, assignment) in &data.metadata {
+                evaluated_table.add_metadata(
+                    region_name.clone(),
+                    key.clone(),
+                    assignment.clone(),
+                )?;
+            }
+        }
+        apply_aliases(&mut evaluated_table, &self.table, &self.geom_stmts)?;
+
+        narrow_dsl_map(finish_output(evaluated_table, &self.options)).map_err(Error::from)
+    }
+
+    /// Run the metadata pass once over all statements, against every known region
+    fn metadata(&mut self) -> Result<&EvaluatedRegionTable, Error> {
+        if self.metadata.is_none() {
+            let table = metadata_table(
+                &self.table,
+                &self.geom_stmts,
+                &self.meta_stmts,
+                &self.options,
+            )?;
+            #[cfg(feature = "metadata_groups")]
+            self.evaluator.set_group_metadata(&table);
+            self.metadata = Some(table);
+        }
+        Ok(self.metadata.as_ref().expect("metadata was just built"))
+    }
+}
 
+/// Convert an evaluated table into the final, shaped (i64) DslMap
+fn finish_output(evaluated_table: EvaluatedRegionTable, options: &CompileOptions) -> DslMap64 {
     // Step 6: Convert to DslMap format
-    let dsl_map = crate::ast::metadata::build_dsl_map(evaluated_table);
+    let dsl_map = crate::ast::metadata::build_dsl_map_with_options(evaluated_table, options);
 
     // Step 7: Apply final output shaping (ordering and filtering)
-    let final_map = shape_final_output(dsl_map);
-
-    Ok(final_map)
+    shape_final_output_with_options(dsl_map, options)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ValueKind;
     use serde_json::json;
 
     #[test]
@@ -240,103 +1222,947 @@ mod tests {
     }
 
     #[test]
-    fn test_empty_compilation() {
-        let units = vec![];
-        let result = compile_pipeline(&units);
-        assert!(result.is_ok());
+    fn test_compilation_with_trailing_commas_option() {
+        let units = vec![(
+            [0, 0, 0],
+            "@test=rc([0,0,0,],[1,1,1],)\n#label=\"lenient\"".to_string(),
+        )];
 
-        let dsl_map = result.unwrap();
-        assert!(dsl_map.is_empty());
+        assert!(compile_pipeline(&units).is_err());
+
+        let options = CompileOptions {
+            allow_trailing_commas: true,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+
+        let boxes = dsl_map["test"].bounding_boxes.as_ref().unwrap();
+        assert_eq!(boxes[0], ([0, 0, 0], [1, 1, 1]));
     }
 
     #[test]
-    fn test_compilation_error_propagation() {
-        // Test with conflicting metadata
+    fn test_keep_empty_anonymous_option() {
+        let units = vec![
+            ([5, 5, 5], "@rc([0,0,0],[2,2,2])".to_string()),
+            ([0, 0, 0], "@named=ac([10,10,10],[11,11,11])".to_string()),
+        ];
+
+        // Default: anonymous region without metadata is dropped
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert!(!dsl_map.contains_key("__anon_0_0"));
+
+        let options = CompileOptions {
+            keep_empty_anonymous: true,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+
+        let anon_entry = dsl_map.get("__anon_0_0").unwrap();
+        assert_eq!(
+            anon_entry.bounding_boxes.as_ref().unwrap()[0],
+            ([5, 5, 5], [7, 7, 7])
+        );
+        assert!(anon_entry.metadata.is_empty());
+        assert!(dsl_map.contains_key("named"));
+    }
+
+    #[test]
+    fn test_compile_collect_reports_every_error() {
         let units = vec![
+            ([0, 0, 0], "@a=rc([0,0,0],[1,1".to_string()),
             (
                 [0, 0, 0],
-                "@test=rc([0,0,0],[1,1,1])\n#label=\"First\"".to_string(),
+                "@b=rc([0,0,0],[1,1,1])\n#b:kind=\"x\"".to_string(),
             ),
-            (
-                [5, 5, 5],
-                "@test=rc([2,2,2],[3,3,3])\n#label=\"Second\"".to_string(),
-            ), // Conflict!
+            ([0, 0, 0], "@c=rc([0,0,0],[1,1,1])\n#label=nope".to_string()),
+            ([0, 0, 0], "#b:kind=\"y\"".to_string()),
         ];
 
-        let result = compile_pipeline(&units);
-        assert!(result.is_err());
+        let errors = compile_collect_pipeline(&units, &CompileOptions::default()).unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                Error::Parser(ParseError::MetadataConflict(conflict)) => {
+                    ("conflict", conflict.conflict_source.tuple_idx)
+                }
+                Error::Parser(error) => ("syntax", error.context().unwrap().source.tuple_idx),
+                other => panic!("unexpected error {:?}", other),
+            })
+            .collect();
+        assert_eq!(kinds, vec![("syntax", 0), ("syntax", 2), ("conflict", 3)]);
 
-        match result.unwrap_err() {
-            Error::Parser(ParseError::MetadataConflict { .. }) => {
-                // Expected error type
-            }
-            other => panic!("Unexpected error type: {:?}", other),
-        }
+        // Fail-fast compilation stops at the first of them
+        assert!(matches!(
+            compile_pipeline(&units),
+            Err(Error::Parser(ParseError::Expected { .. }))
+        ));
     }
 
     #[test]
-    fn test_comment_filtering_in_pipeline() {
-        // Test that comments are properly filtered out during compilation
+    fn test_compile_collect_reports_every_metadata_conflict() {
         let units = vec![
             (
                 [0, 0, 0],
-                "; This is a comment\n@test=rc([0,0,0],[1,1,1])\n; Another comment".to_string(),
+                "@r=rc([0,0,0],[1,1,1])\n#kind=\"x\"\n#tier=1\n#doc=\"ok\"".to_string(),
             ),
             (
                 [0, 0, 0],
-                "; Comment before metadata\n#test:label=\"Test Region\"\n; Comment after"
-                    .to_string(),
+                "#r:kind=\"y\"\n#r:doc=\"ok\"\n#r:tier=2".to_string(),
             ),
         ];
 
-        let result = compile_pipeline(&units);
-        assert!(result.is_ok());
+        let errors = compile_collect_pipeline(&units, &CompileOptions::default()).unwrap_err();
+        let conflicts: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                Error::Parser(ParseError::MetadataConflict(conflict)) => (
+                    conflict.region.as_str(),
+                    conflict.key.as_str(),
+                    conflict.conflict_source.stmt_idx,
+                ),
+                other => panic!("unexpected error {:?}", other),
+            })
+            .collect();
+        assert_eq!(conflicts, vec![("r", "kind", 0), ("r", "tier", 2)]);
+    }
 
-        let dsl_map = result.unwrap();
-        assert_eq!(dsl_map.len(), 1);
+    #[test]
+    fn test_compile_collect_evaluation_errors() {
+        let units = vec![(
+            [0, 0, 0],
+            "@a=rc([0,0,0],[1,1,1])\n@b=a+missing\n@c=b\n@d=a+gone\n@x=y+a\n@y=x\n@self=self"
+                .to_string(),
+        )];
 
-        let test_entry = dsl_map.get("test").unwrap();
+        let errors = compile_collect_pipeline(&units, &CompileOptions::default()).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|error| error.to_string()).collect();
+        // `c` fails through `b`, and the cycle is found from both its regions;
+        // each is reported once
+        assert_eq!(messages.len(), 4, "{:?}", messages);
+        assert!(messages.iter().any(|message| message.contains("missing")));
+        assert!(messages.iter().any(|message| message.contains("gone")));
+        assert!(errors
+            .iter()
+            .any(|error| matches!(error, Error::Parser(ParseError::CycleDetected { .. }))));
+        assert!(errors
+            .iter()
+            .any(|error| matches!(error, Error::Parser(ParseError::SelfReference { .. }))));
 
-        // Should have the box
-        let boxes = test_entry.bounding_boxes.as_ref().unwrap();
-        assert_eq!(boxes.len(), 1);
-        assert_eq!(boxes[0], ([0, 0, 0], [1, 1, 1]));
+        let valid = vec![
+            ([0, 0, 0], "@a=rc([0,0,0],[1,1,1])\n#kind=1".to_string()),
+            ([3, 0, 0], "@b=ac([0,0,0],[1,1,1])+a".to_string()),
+        ];
+        assert_eq!(
+            compile_collect_pipeline(&valid, &CompileOptions::default()).unwrap(),
+            compile_pipeline(&valid).unwrap()
+        );
+    }
 
-        // Should have the metadata
-        assert_eq!(test_entry.metadata["label"], json!("Test Region"));
+    #[test]
+    fn test_strict_box_order_option() {
+        let units = vec![([0, 0, 0], "@a=rc([5,0,0],[0,0,0])".to_string())];
+        assert_eq!(
+            compile_pipeline(&units).unwrap()["a"].bounding_boxes,
+            Some(vec![([0, 0, 0], [5, 0, 0])])
+        );
+
+        let options = CompileOptions {
+            strict_box_order: true,
+            ..Default::default()
+        };
+        match compile_pipeline_with_options(&units, &options) {
+            Err(Error::Parser(error @ ParseError::InvalidBox { .. })) => {
+                assert_eq!(
+                    error
+                        .context()
+                        .unwrap()
+                        .source
+                        .span
+                        .as_deref()
+                        .unwrap()
+                        .column,
+                    7
+                );
+            }
+            other => panic!("expected InvalidBox, got {:?}", other),
+        }
     }
 
     #[test]
-    fn test_mixed_statements_with_comments() {
-        // Test complex mixing of statements and comments
+    fn test_sort_boxes_option() {
         let units = vec![
+            ([0, 0, 0], "@b=ac([5,0,0],[5,0,0])\n@hall=b+a".to_string()),
             (
                 [0, 0, 0],
-                "; Define a base region\n@base=rc([0,0,0],[1,1,1])\n; End base definition"
-                    .to_string(),
-            ),
-            (
-                [5, 5, 5],
-                "; Another region\n@ext=rc([0,0,0],[2,2,2])\n; Extension complete".to_string(),
-            ),
-            (
-                [0, 0, 0],
-                "; Combine regions\n@combined=base+ext\n; Combined region created".to_string(),
-            ),
-            (
-                [0, 0, 0],
-                "; Add metadata\n#combined:type=\"union\"\n; Metadata added".to_string(),
+                "@a=ac([-3,0,0],[-3,0,0])\n@a=ac([1,0,0],[1,0,0])".to_string(),
             ),
         ];
 
-        let result = compile_pipeline(&units);
-        assert!(result.is_ok());
-
-        let dsl_map = result.unwrap();
-        assert_eq!(dsl_map.len(), 3); // base, ext, combined
+        let evaluated = compile_pipeline(&units).unwrap()["hall"]
+            .bounding_boxes
+            .clone()
+            .unwrap();
+        assert_eq!(evaluated[0], ([5, 0, 0], [5, 0, 0]));
 
-        // Check combined region has union of boxes
-        let combined_entry = dsl_map.get("combined").unwrap();
+        let options = CompileOptions {
+            sort_boxes: true,
+            ..Default::default()
+        };
+        let sorted = compile_pipeline_with_options(&units, &options).unwrap()["hall"]
+            .bounding_boxes
+            .clone()
+            .unwrap();
+        assert_eq!(
+            sorted,
+            vec![
+                ([-3, 0, 0], [-3, 0, 0]),
+                ([1, 0, 0], [1, 0, 0]),
+                ([5, 0, 0], [5, 0, 0])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_boxes_union_order_independent() {
+        let options = CompileOptions {
+            sort_boxes: true,
+            ..Default::default()
+        };
+        let hall = |expr: &str| {
+            let units = vec![(
+                [0, 0, 0],
+                format!("@a=ac([4,0,0],[6,2,2])\n@b=ac([0,0,0],[1,1,1])\n@hall={expr}"),
+            )];
+            compile_pipeline_with_options(&units, &options).unwrap()["hall"]
+                .bounding_boxes
+                .clone()
+                .unwrap()
+        };
+
+        assert_eq!(hall("a+b"), hall("b+a"));
+        assert_eq!(hall("a+b")[0], ([0, 0, 0], [1, 1, 1]));
+    }
+
+    #[test]
+    fn test_max_abs_coordinate_option() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@spawn=rc([-100,0,-100],[100,255,100])".to_string(),
+            ),
+            ([5000, 0, 0], "@outpost=rc([0,0,0],[9,9,9])".to_string()),
+        ];
+        let options = CompileOptions {
+            max_abs_coordinate: Some(1000),
+            ..Default::default()
+        };
+
+        match compile_pipeline_with_options(&units, &options) {
+            Err(Error::Parser(ParseError::CoordinateLimitExceeded { region, limit })) => {
+                assert_eq!(region, "outpost");
+                assert_eq!(limit, 1000);
+            }
+            other => panic!("Expected CoordinateLimitExceeded, got {:?}", other),
+        }
+        assert!(compile_pipeline_with_options(&units[..1], &options).is_ok());
+    }
+
+    #[test]
+    fn test_compute_volume_option() {
+        let units = vec![(
+            [0, 0, 0],
+            "@a=rc([0,0,0],[9,0,0])\n@b=rc([0,5,0],[4,5,0])\n@both=a+b\n@rc([0,0,0],[1,1,1])"
+                .to_string(),
+        )];
+        let options = CompileOptions {
+            compute_volume: true,
+            ..Default::default()
+        };
+
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(dsl_map["a"].metadata["__volume"], json!(10));
+        assert_eq!(dsl_map["both"].metadata["__volume"], json!(15));
+        // The count alone does not keep an anonymous region
+        assert!(!dsl_map.contains_key("__anon_0_3"));
+
+        let mut lazy = LazyCompiled::new(&units, &options).unwrap();
+        assert_eq!(lazy.region("both").unwrap().metadata["__volume"], json!(15));
+    }
+
+    #[test]
+    fn test_interpolate_metadata_option() {
+        let units = vec![(
+            [0, 0, 0],
+            "@cpu.core=rc([0,0,0],[1,1,1])\n#logic.clock_hz=4\n#summary=\"clock ${cpu.core:logic.clock_hz}\""
+                .to_string(),
+        )];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(
+            dsl_map["cpu.core"].metadata["summary"],
+            json!("clock ${cpu.core:logic.clock_hz}")
+        );
+
+        let options = CompileOptions {
+            interpolate_metadata: true,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(dsl_map["cpu.core"].metadata["summary"], json!("clock 4"));
+
+        // Values doubling each other run into the evaluation budget
+        let mut text = "@r=rc([0,0,0],[1,1,1])\n".to_string();
+        for i in 0..64 {
+            text.push_str(&format!("#k{0}=\"${{r:k{1}}}${{r:k{1}}}\"\n", i, i + 1));
+        }
+        text.push_str("#k64=\"x\"");
+        assert!(matches!(
+            compile_pipeline_with_options(&[([0, 0, 0], text)], &options),
+            Err(Error::Parser(ParseError::EvaluationBudgetExceeded {
+                limit: 10_000_000
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_metadata_merge_policy_option() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@r=rc([0,0,0],[1,1,1])\n#tags=[\"a\"]".to_string(),
+            ),
+            ([5, 0, 0], "#r:tags=[\"b\",\"a\"]".to_string()),
+        ];
+
+        assert!(matches!(
+            compile_pipeline(&units),
+            Err(Error::Parser(ParseError::MetadataConflict(_)))
+        ));
+
+        let options = CompileOptions {
+            metadata_merge_policy: crate::MetadataMergePolicy::MergeArrays,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(dsl_map["r"].metadata["tags"], json!(["a", "b"]));
+
+        let options = CompileOptions::from_json(
+            serde_json::from_str(r#"{"metadata_merge_policy": "last_wins"}"#).unwrap(),
+            true,
+        )
+        .unwrap();
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(dsl_map["r"].metadata["tags"], json!(["b", "a"]));
+    }
+
+    #[test]
+    fn test_current_region_binds_forward_option() {
+        let units = vec![(
+            [0, 0, 0],
+            "#label=\"first\"\n@a=rc([0,0,0],[1,1,1])\n#size=1\n@b=rc([2,2,2],[3,3,3])".to_string(),
+        )];
+
+        // Default: current-region metadata binds to the last geometry in the tuple
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert!(dsl_map["a"].metadata.is_empty());
+        assert_eq!(dsl_map["b"].metadata.len(), 2);
+
+        let options = CompileOptions {
+            current_region_binds_forward: true,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+
+        // Metadata before any geometry binds forward, later metadata binds backward
+        assert_eq!(dsl_map["a"].metadata["label"], serde_json::json!("first"));
+        assert_eq!(dsl_map["a"].metadata["size"], serde_json::json!(1));
+        assert!(dsl_map["b"].metadata.is_empty());
+
+        // A tuple with no geometry still has no current region
+        let units = vec![([0, 0, 0], "#label=\"orphan\"".to_string())];
+        assert!(matches!(
+            compile_pipeline_with_options(&units, &options),
+            Err(Error::Parser(ParseError::NoCurrentRegion { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_coordinate_style_warnings() {
+        let units = vec![(
+            [100, 64, 100],
+            "@near=ac([101,64,101],[102,65,102])\n@ok=rc([0,0,0],[3,3,3])".to_string(),
+        )];
+
+        // Off by default
+        let (_, diagnostics) =
+            compile_pipeline_verbose(&units, &CompileOptions::default()).unwrap();
+        assert!(diagnostics.is_empty());
+
+        let options = CompileOptions {
+            warn_coordinate_style: true,
+            ..Default::default()
+        };
+        let (dsl_map, diagnostics) = compile_pipeline_verbose(&units, &options).unwrap();
+
+        // Warnings never change the compiled result
+        assert_eq!(dsl_map, compile_pipeline(&units).unwrap());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].source,
+            Some(crate::ast::SourceLocation::new(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_try_compile_keeps_warnings_on_error() {
+        let options = CompileOptions {
+            warn_coordinate_style: true,
+            ..Default::default()
+        };
+        let near = (
+            [100, 64, 100],
+            "@near=ac([101,64,101],[102,65,102])".to_string(),
+        );
+
+        let (_, diagnostics) = compile_pipeline_try(std::slice::from_ref(&near), &options).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+
+        // Warnings from before a parse error and before an evaluation error survive
+        for broken in ["@bad=rc([0,0,0]", "@bad=near+missing"] {
+            let units = vec![near.clone(), ([0, 0, 0], broken.to_string())];
+            let (error, diagnostics) = compile_pipeline_try(&units, &options).unwrap_err();
+            assert!(matches!(error, Error::Parser(_)));
+            assert_eq!(diagnostics.len(), 1);
+            assert!(diagnostics[0].message.contains("did you mean rc"));
+        }
+    }
+
+    #[test]
+    fn test_translate_with_constant_offset() {
+        let units = vec![(
+            [0, 0, 0],
+            "@const STEP=[8,0,0]\n@tpl=rc([0,0,0],[1,1,1])\n@tpl+=rc([3,0,0],[3,2,0])\n@copy=translate(tpl,STEP)".to_string(),
+        )];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(
+            dsl_map["copy"].bounding_boxes,
+            Some(vec![([8, 0, 0], [9, 1, 1]), ([11, 0, 0], [11, 2, 0])])
+        );
+    }
+
+    #[test]
+    fn test_simplify_exprs_option() {
+        let units = vec![(
+            [0, 0, 0],
+            "@a=rc([0,0,0],[1,1,1])\n@b=rc([4,0,0],[5,1,1])\n@c=(a+b)+(a)".to_string(),
+        )];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(dsl_map["c"].bounding_boxes.as_ref().unwrap().len(), 3);
+
+        let options = CompileOptions {
+            simplify_exprs: true,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(
+            dsl_map["c"].bounding_boxes,
+            Some(vec![([0, 0, 0], [1, 1, 1]), ([4, 0, 0], [5, 1, 1])])
+        );
+    }
+
+    #[test]
+    fn test_metadata_on_expression_before_its_operands() {
+        // `a` and `b` are only defined in later tuples
+        let units = vec![
+            ([0, 0, 0], "@c=a+b\n#label=\"x\"".to_string()),
+            ([0, 0, 0], "@a=rc([0,0,0],[1,1,1])".to_string()),
+            (
+                [5, 0, 0],
+                "@b=rc([0,0,0],[1,1,1])\n#label=\"b\"".to_string(),
+            ),
+        ];
+
+        for current_region_binds_forward in [false, true] {
+            let options = CompileOptions {
+                current_region_binds_forward,
+                ..Default::default()
+            };
+            let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+            assert_eq!(dsl_map["c"].metadata["label"], json!("x"));
+            assert_eq!(dsl_map["b"].metadata["label"], json!("b"));
+            assert_eq!(dsl_map["c"].bounding_boxes.as_ref().map(Vec::len), Some(2));
+
+            let mut lazy = LazyCompiled::new(&units, &options).unwrap();
+            assert_eq!(lazy.region("c").unwrap(), dsl_map["c"]);
+        }
+
+        // Attaching does not depend on the operands resolving at all
+        let mut units = units;
+        units.truncate(1);
+        let errors = compile_collect_pipeline(&units, &CompileOptions::default()).unwrap_err();
+        assert!(
+            errors
+                .iter()
+                .all(|error| matches!(error, Error::Parser(ParseError::UnknownRegion { .. }))),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_eval_depth_limit() {
+        // r000 references r001, ..., r298 references r299, which has the box
+        let mut text: String = (0..299)
+            .map(|i| format!("@r{:03}=r{:03}\n", i, i + 1))
+            .collect();
+        text.push_str("@r299=rc([0,0,0],[1,1,1])");
+        let units = vec![([0, 0, 0], text)];
+
+        match compile_pipeline(&units) {
+            Err(Error::Parser(ParseError::EvaluationDepthExceeded { region, depth })) => {
+                assert_eq!(depth, 256);
+                assert!(region.starts_with('r'), "{}", region);
+            }
+            other => panic!("expected EvaluationDepthExceeded, got {:?}", other),
+        }
+
+        let options = CompileOptions {
+            max_eval_depth: 400,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(
+            dsl_map["r000"].bounding_boxes,
+            Some(vec![([0, 0, 0], [1, 1, 1])])
+        );
+
+        // Sub-expressions count as well: `c`, its union, then the reference `a`
+        let options = CompileOptions {
+            max_eval_depth: 2,
+            ..Default::default()
+        };
+        let units = vec![(
+            [0, 0, 0],
+            "@a=rc([0,0,0],[1,1,1])\n@b=rc([4,0,0],[5,1,1])\n@c=a+b".to_string(),
+        )];
+        assert!(matches!(
+            compile_pipeline_with_options(&units, &options),
+            Err(Error::Parser(ParseError::EvaluationDepthExceeded {
+                depth: 2,
+                ..
+            }))
+        ));
+
+        // A flat union takes one level however many operands it has
+        let mut text: String = (0..300)
+            .map(|i| format!("@r{:03}=rc([{},0,0],[{},0,0])\n", i, i * 2, i * 2))
+            .collect();
+        let operands: Vec<String> = (0..300).map(|i| format!("r{:03}", i)).collect();
+        text.push_str(&format!("@all={}", operands.join("+")));
+        let dsl_map = compile_pipeline(&[([0, 0, 0], text)]).unwrap();
+        let boxes = dsl_map["all"].bounding_boxes.as_ref().unwrap();
+        assert_eq!(boxes.len(), 300);
+        assert_eq!(boxes[0], ([0, 0, 0], [0, 0, 0]));
+        assert_eq!(boxes[299], ([598, 0, 0], [598, 0, 0]));
+    }
+
+    #[test]
+    fn test_flat_union_budget_is_linear() {
+        // Charging the accumulated boxes at every step would need about
+        // 12,500,000 steps here, beyond the default budget
+        let mut text: String = (0..5000)
+            .map(|i| format!("@r{}=rc([{},0,0],[{},0,0])\n", i, i * 2, i * 2))
+            .collect();
+        let operands: Vec<String> = (0..5000).map(|i| format!("r{}", i)).collect();
+        text.push_str(&format!("@all={}", operands.join("+")));
+        let dsl_map = compile_pipeline(&[([0, 0, 0], text)]).unwrap();
+        assert_eq!(dsl_map["all"].bounding_boxes.as_ref().unwrap().len(), 5000);
+    }
+
+    #[test]
+    fn test_compile_huge_union_chain() {
+        // Long chains are left-nested; copying, printing and dropping them
+        // must not recurse once per operand
+        let mut text = "@a=rc([0,0,0],[1,1,1])\n@all=a".to_string();
+        text.push_str(&"+a".repeat(99_999));
+        let units = vec![([0, 0, 0], text)];
+        let options = CompileOptions {
+            max_eval_steps: u64::MAX,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(
+            dsl_map["all"].bounding_boxes.as_ref().unwrap().len(),
+            100_000
+        );
+
+        let options = CompileOptions {
+            simplify_exprs: true,
+            ..Default::default()
+        };
+        let dsl_map = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(dsl_map["all"].bounding_boxes.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_metadata_schema_option() {
+        let options = CompileOptions {
+            metadata_schema: BTreeMap::from([("logic.clock_hz".to_string(), ValueKind::Integer)]),
+            ..Default::default()
+        };
+        let compile_text =
+            |text: &str| compile_pipeline_with_options(&[([0, 0, 0], text.to_string())], &options);
+
+        let dsl_map = compile_text("@cpu=rc([0,0,0],[1,1,1])\n#logic.clock_hz=4").unwrap();
+        assert_eq!(dsl_map["cpu"].metadata["logic.clock_hz"], json!(4));
+
+        match compile_text("@cpu=rc([0,0,0],[1,1,1])\n#cpu:logic.clock_hz=\"4\"") {
+            Err(Error::Parser(ParseError::MetadataTypeMismatch(mismatch))) => {
+                assert_eq!(mismatch.region, "cpu");
+                assert_eq!(mismatch.key, "logic.clock_hz");
+                assert_eq!(mismatch.expected, ValueKind::Integer);
+                assert_eq!(mismatch.found, json!("4"));
+                assert_eq!(mismatch.source, SourceLocation::new(0, 1));
+            }
+            other => panic!("expected MetadataTypeMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_single_point_boxes() {
+        let units = vec![(
+            [10, 20, 30],
+            "@spot=rc([5,5,5])\n@spot+=ac([1,2,3])\n@wide=rc([0,0,0],[1,1,1])".to_string(),
+        )];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(
+            dsl_map["spot"].bounding_boxes,
+            Some(vec![([15, 25, 35], [15, 25, 35]), ([1, 2, 3], [1, 2, 3])])
+        );
+        assert_eq!(
+            dsl_map["wide"].bounding_boxes,
+            Some(vec![([10, 20, 30], [11, 21, 31])])
+        );
+    }
+
+    #[test]
+    fn test_region_append_syntax() {
+        let units = vec![
+            ([0, 0, 0], "@bus=rc([0,0,0],[1,1,1])".to_string()),
+            ([10, 0, 0], "@bus+=rc([0,0,0],[1,1,1])".to_string()),
+        ];
+        let strict = CompileOptions {
+            strict_region_assignment: true,
+            ..Default::default()
+        };
+
+        // `+=` accumulates like a repeated `=`, in both modes
+        let dsl_map = compile_pipeline_with_options(&units, &strict).unwrap();
+        assert_eq!(
+            dsl_map["bus"].bounding_boxes,
+            Some(vec![([0, 0, 0], [1, 1, 1]), ([10, 0, 0], [11, 1, 1])])
+        );
+        assert_eq!(dsl_map, compile_pipeline(&units).unwrap());
+
+        // Appending to a defined region is still mixing modes
+        let mixed = vec![(
+            [0, 0, 0],
+            "@a=rc([0,0,0],[1,1,1])\n@bus=a\n@bus+=rc([2,2,2],[3,3,3])".to_string(),
+        )];
+        assert!(matches!(
+            compile_pipeline(&mixed),
+            Err(Error::Parser(ParseError::MixedRegionMode(_)))
+        ));
+    }
+
+    #[test]
+    fn test_strict_region_assignment_rejects_double_assign() {
+        let units = vec![
+            ([0, 0, 0], "@bus=rc([0,0,0],[1,1,1])".to_string()),
+            ([10, 0, 0], "@bus=rc([0,0,0],[1,1,1])".to_string()),
+        ];
+        assert!(compile_pipeline(&units).is_ok());
+
+        let strict = CompileOptions {
+            strict_region_assignment: true,
+            ..Default::default()
+        };
+        match compile_pipeline_with_options(&units, &strict) {
+            Err(Error::Parser(ParseError::DuplicateRegionDefinition(error))) => {
+                assert_eq!(error.region, "bus");
+                assert_eq!(error.first_source, SourceLocation::new(0, 0));
+                assert_eq!(error.duplicate_source, SourceLocation::new(1, 0));
+            }
+            other => panic!("expected DuplicateRegionDefinition, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_disabled_statements_excluded() {
+        let units = vec![(
+            [0, 0, 0],
+            "@a=rc([0,0,0],[1,1,1])\n#a:label=\"kept\"\n@!b=rc([2,2,2],[3,3,3])\n#!a:label=\"conflict\"".to_string(),
+        )];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+
+        // Disabled geometry produces no region, disabled metadata does not conflict
+        assert!(dsl_map.contains_key("a"));
+        assert!(!dsl_map.contains_key("b"));
+        assert_eq!(dsl_map["a"].metadata["label"], serde_json::json!("kept"));
+    }
+
+    #[test]
+    fn test_syntax_error_line_and_column() {
+        let text = "; wiring\n@bus=rc([0,0,0],[1,1,1])\n  #doc=1 @cpu=rc([0,0,0],[1,x,1])";
+        let units = vec![
+            ([0, 0, 0], "#$global:v=1".to_string()),
+            ([0, 0, 0], text.to_string()),
+        ];
+
+        let error = match compile_pipeline(&units) {
+            Err(Error::Parser(error @ ParseError::Expected { .. })) => error,
+            other => panic!("expected a syntax error, got {:?}", other),
+        };
+        let source = &error.context().unwrap().source;
+        assert_eq!((source.tuple_idx, source.stmt_idx), (1, 3));
+        let span = source.span.as_deref().unwrap();
+        assert_eq!((span.line, span.column), (3, 29));
+        assert_eq!(&text[span.offset..span.offset + 1], "x");
+        assert!(error
+            .to_string()
+            .ends_with("at tuple 1 statement 3, line 3 col 29 in: @cpu=rc([0,0,0],[1,x,1])"));
+    }
+
+    #[test]
+    fn test_syntax_error_statement_snippet() {
+        let units = vec![(
+            [0, 0, 0],
+            "@ok=rc([0,0,0],[1,1,1])\n@bad=rc([0,0,0],\n    [1,1])".to_string(),
+        )];
+        let error = match compile_pipeline(&units) {
+            Err(Error::Parser(error)) => error,
+            other => panic!("expected a syntax error, got {:?}", other),
+        };
+        assert_eq!(
+            error.context().unwrap().statement,
+            "@bad=rc([0,0,0], [1,1])"
+        );
+        assert!(error.to_string().ends_with(" in: @bad=rc([0,0,0], [1,1])"));
+
+        // Long statements are cut around the error
+        let long = format!(
+            "@long=rc([0,0,0],[1,1,1]){0}+rc([0,0,0],[1,x,1]){0}",
+            "+rc([0,0,0],[1,1,1])".repeat(3)
+        );
+        let snippet = statement_snippet(&long, long.find('x').unwrap());
+        assert!(snippet.starts_with("...") && snippet.ends_with("..."));
+        assert!(snippet.contains("[1,x,1]"));
+        assert_eq!(snippet.chars().count(), STATEMENT_SNIPPET_CHARS + 6);
+    }
+
+    #[test]
+    fn test_disabled_statements_still_validated() {
+        let units = vec![([0, 0, 0], "@!b=rc([0,0,0],[1,1])".to_string())];
+        assert!(matches!(
+            compile_pipeline(&units),
+            Err(Error::Parser(ParseError::InvalidVec3 { .. }))
+        ));
+
+        let units = vec![([0, 0, 0], "#!label=not_json".to_string())];
+        assert!(compile_pipeline(&units).is_err());
+    }
+
+    #[test]
+    fn test_compile_by_tuple_pipeline() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@bus=rc([0,0,0],[1,1,1])\n@rc([0,0,0],[1,1,1])\n#doc=1".to_string(),
+            ),
+            (
+                [10, 0, 0],
+                "@bus=rc([0,0,0],[1,1,1])\n#$global:version=2".to_string(),
+            ),
+            ([20, 0, 0], "#bus:label=\"shared\"".to_string()),
+            ([30, 0, 0], "@rc([0,0,0],[1,1,1])".to_string()),
+        ];
+        let options = CompileOptions::default();
+
+        let grouped = compile_by_tuple_pipeline(&units, &options).unwrap();
+        let full = compile_pipeline(&units).unwrap();
+
+        assert_eq!(grouped.len(), 4);
+        let keys = |idx: usize| grouped[&idx].keys().cloned().collect::<Vec<_>>();
+        assert_eq!(keys(0), vec!["__anon_0_1", "bus"]);
+        assert_eq!(keys(1), vec!["$global", "bus"]);
+        assert_eq!(keys(2), vec!["bus"]);
+        // Dropped anonymous regions leave their tuple empty
+        assert!(grouped[&3].is_empty());
+
+        // Shared regions carry their full compiled entry under each tuple
+        assert_eq!(grouped[&0]["bus"], full["bus"]);
+        assert_eq!(grouped[&2]["bus"], full["bus"]);
+    }
+
+    #[test]
+    fn test_empty_compilation() {
+        let units = vec![];
+        let result = compile_pipeline(&units);
+        assert!(result.is_ok());
+
+        let dsl_map = result.unwrap();
+        assert!(dsl_map.is_empty());
+    }
+
+    #[test]
+    fn test_compilation_error_propagation() {
+        // Test with conflicting metadata
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@test=rc([0,0,0],[1,1,1])\n#label=\"First\"".to_string(),
+            ),
+            (
+                [5, 5, 5],
+                "@test=rc([2,2,2],[3,3,3])\n#label=\"Second\"".to_string(),
+            ), // Conflict!
+        ];
+
+        let result = compile_pipeline(&units);
+        assert!(result.is_err());
+
+        match result.unwrap_err() {
+            Error::Parser(ParseError::MetadataConflict { .. }) => {
+                // Expected error type
+            }
+            other => panic!("Unexpected error type: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_delete_metadata_in_pipeline() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@cpu.core=rc([0,0,0],[1,1,1])\n@cpu.cache=rc([2,0,0],[3,1,1])".to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "#cpu.core:-power\n#cpu.*:power=\"low\"\n#cpu.core:tier=1".to_string(),
+            ),
+        ];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(
+            dsl_map["cpu.core"].metadata,
+            crate::Metadata::from([("tier".to_string(), json!(1))])
+        );
+        assert_eq!(dsl_map["cpu.cache"].metadata["power"], json!("low"));
+    }
+
+    #[test]
+    fn test_slash_comments_in_pipeline() {
+        let units = vec![(
+            [0, 0, 0],
+            "// Main hall\n@hall=rc([0,0,0],[3,3,3]) // ground floor\n\
+             #hall:doc.url=\"https://example.com//hall\" // link\n@door=rc([0,0,0],[0,1,0]"
+                .to_string(),
+        )];
+
+        // Lines and columns still refer to the original text
+        match compile_pipeline(&units) {
+            Err(Error::Parser(error)) => {
+                assert_eq!(
+                    error
+                        .context()
+                        .unwrap()
+                        .source
+                        .span
+                        .as_deref()
+                        .unwrap()
+                        .line,
+                    4
+                )
+            }
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+
+        let mut units = units;
+        units[0].1.push(')');
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(
+            dsl_map["hall"].metadata["doc.url"],
+            json!("https://example.com//hall")
+        );
+    }
+
+    #[test]
+    fn test_comment_filtering_in_pipeline() {
+        // Test that comments are properly filtered out during compilation
+        let units = vec![
+            (
+                [0, 0, 0],
+                "; This is a comment\n@test=rc([0,0,0],[1,1,1])\n; Another comment".to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "; Comment before metadata\n#test:label=\"Test Region\"\n; Comment after"
+                    .to_string(),
+            ),
+        ];
+
+        let result = compile_pipeline(&units);
+        assert!(result.is_ok());
+
+        let dsl_map = result.unwrap();
+        assert_eq!(dsl_map.len(), 1);
+
+        let test_entry = dsl_map.get("test").unwrap();
+
+        // Should have the box
+        let boxes = test_entry.bounding_boxes.as_ref().unwrap();
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(boxes[0], ([0, 0, 0], [1, 1, 1]));
+
+        // Should have the metadata
+        assert_eq!(test_entry.metadata["label"], json!("Test Region"));
+    }
+
+    #[test]
+    fn test_mixed_statements_with_comments() {
+        // Test complex mixing of statements and comments
+        let units = vec![
+            (
+                [0, 0, 0],
+                "; Define a base region\n@base=rc([0,0,0],[1,1,1])\n; End base definition"
+                    .to_string(),
+            ),
+            (
+                [5, 5, 5],
+                "; Another region\n@ext=rc([0,0,0],[2,2,2])\n; Extension complete".to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "; Combine regions\n@combined=base+ext\n; Combined region created".to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "; Add metadata\n#combined:type=\"union\"\n; Metadata added".to_string(),
+            ),
+        ];
+
+        let result = compile_pipeline(&units);
+        assert!(result.is_ok());
+
+        let dsl_map = result.unwrap();
+        assert_eq!(dsl_map.len(), 3); // base, ext, combined
+
+        // Check combined region has union of boxes
+        let combined_entry = dsl_map.get("combined").unwrap();
         let boxes = combined_entry.bounding_boxes.as_ref().unwrap();
         assert_eq!(boxes.len(), 2); // Union of base + ext
         assert_eq!(combined_entry.metadata["type"], json!("union"));
@@ -369,4 +2195,625 @@ mod tests {
         assert_eq!(boxes.len(), 1);
         assert_eq!(boxes[0], ([0, 0, 0], [1, 1, 1]));
     }
+
+    #[test]
+    fn test_lazy_compiled_matches_eager() {
+        let units = vec![
+            ([0, 0, 0], "@base=rc([0,0,0],[1,1,1])".to_string()),
+            ([5, 5, 5], "@ext=rc([0,0,0],[2,2,2])".to_string()),
+            (
+                [0, 0, 0],
+                "@combined=base+ext\n#combined:type=\"union\"\n#$global:v=1".to_string(),
+            ),
+        ];
+        let eager = compile_pipeline(&units).unwrap();
+
+        let mut lazy = LazyCompiled::new(&units, &CompileOptions::default()).unwrap();
+        assert_eq!(
+            lazy.region_names().collect::<Vec<_>>(),
+            vec!["base", "combined", "ext"]
+        );
+        assert_eq!(lazy.region("combined").unwrap(), eager["combined"]);
+        assert_eq!(lazy.region("$global").unwrap(), eager["$global"]);
+
+        assert_eq!(lazy.materialize().unwrap(), eager);
+    }
+
+    #[test]
+    fn test_region_alias() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@cpu=rc([0,0,0],[1,1,1])\n#power=\"low\"\n#label=\"cpu\"".to_string(),
+            ),
+            ([10, 0, 0], "@alias core=cpu\n#label=\"core\"".to_string()),
+            ([20, 0, 0], "@alias core2=core".to_string()),
+        ];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(
+            dsl_map["core"].bounding_boxes,
+            dsl_map["cpu"].bounding_boxes
+        );
+        // The target's metadata comes along; the alias's own values win
+        assert_eq!(dsl_map["core"].metadata["power"], json!("low"));
+        assert_eq!(dsl_map["core"].metadata["label"], json!("core"));
+        assert_eq!(dsl_map["core2"].metadata["label"], json!("core"));
+        assert_eq!(dsl_map["cpu"].metadata["label"], json!("cpu"));
+
+        let mut lazy = LazyCompiled::new(&units, &CompileOptions::default()).unwrap();
+        assert_eq!(lazy.region("core2").unwrap(), dsl_map["core2"]);
+        assert_eq!(lazy.materialize().unwrap(), dsl_map);
+
+        // Aliases are collected once, so a duplicate fails up front
+        let mut duplicate = units.clone();
+        duplicate.push(([0, 0, 0], "@alias core=cpu".to_string()));
+        assert!(matches!(
+            LazyCompiled::new(&duplicate, &CompileOptions::default()),
+            Err(Error::Parser(ParseError::DuplicateRegionDefinition(_)))
+        ));
+    }
+
+    #[test]
+    fn test_quoted_region_names() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@\"My Region\"=rc([0,0,0],[1,1,1])\n#power=\"low\"".to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "@\"say \\\"hi\\\"\"=rc([4,0,0],[5,1,1])\n\
+             @combined=\"My Region\"+\"say \\\"hi\\\"\""
+                    .to_string(),
+            ),
+        ];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(dsl_map["My Region"].metadata["power"], json!("low"));
+        assert_eq!(
+            dsl_map["say \"hi\""].bounding_boxes,
+            Some(vec![([4, 0, 0], [5, 1, 1])])
+        );
+        assert_eq!(
+            dsl_map["combined"].bounding_boxes,
+            Some(vec![([0, 0, 0], [1, 1, 1]), ([4, 0, 0], [5, 1, 1])])
+        );
+
+        // Quoting does not get around reserved names
+        assert!(matches!(
+            compile_pipeline(&[([0, 0, 0], "@\"$global\"=rc([0,0,0],[1,1,1])".to_string())]),
+            Err(Error::Parser(ParseError::ReservedRegionName { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_reserved_region_names_rejected() {
+        let compile_text = |text: &str| compile_pipeline(&[([0, 0, 0], text.to_string())]);
+
+        assert!(matches!(
+            compile_text("@__anon_0_0=rc([0,0,0],[1,1,1])"),
+            Err(Error::Parser(ParseError::ReservedRegionName { .. }))
+        ));
+        assert!(matches!(
+            compile_text("@r=rc([0,0,0],[1,1,1])\n@alias __anon_1_0=r"),
+            Err(Error::Parser(ParseError::ReservedRegionName { .. }))
+        ));
+        // `$`, `*` and non-ASCII letters never make it past the parser
+        for text in [
+            "@$global=rc([0,0,0],[1,1,1])",
+            "@cpu.*=rc([0,0,0],[1,1,1])",
+            "@café=rc([0,0,0],[1,1,1])",
+        ] {
+            match compile_text(text) {
+                Err(Error::Parser(ParseError::InvalidRegionName { .. })) => {}
+                other => panic!("Expected InvalidRegionName for {}, got {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_region_alias_errors() {
+        let compile_text = |text: &str| compile_pipeline(&[([0, 0, 0], text.to_string())]);
+
+        match compile_text("@alias a=missing") {
+            Err(Error::Parser(ParseError::UnknownRegion { region, source })) => {
+                assert_eq!(region, "missing");
+                assert_eq!(source, SourceLocation::new(0, 0));
+            }
+            other => panic!("Expected UnknownRegion, got {:?}", other),
+        }
+        assert!(matches!(
+            compile_text("@r=rc([0,0,0],[1,1,1])\n@alias a=b\n@alias b=a"),
+            Err(Error::Parser(ParseError::CycleDetected { .. }))
+        ));
+        assert!(matches!(
+            compile_text("@r=rc([0,0,0],[1,1,1])\n@alias r=r"),
+            Err(Error::Parser(ParseError::DuplicateRegionDefinition(_)))
+        ));
+    }
+
+    #[test]
+    fn test_lazy_compiled_errors_on_access() {
+        let units = vec![(
+            [0, 0, 0],
+            "@ok=rc([0,0,0],[1,1,1])\n@a=b\n@b=a\n@broken=missing+ok".to_string(),
+        )];
+
+        // Eager compilation fails, but the handle is still created
+        assert!(compile_pipeline(&units).is_err());
+        let mut lazy = LazyCompiled::new(&units, &CompileOptions::default()).unwrap();
+
+        assert!(lazy.region("ok").is_ok());
+        assert!(matches!(
+            lazy.region("a"),
+            Err(Error::Parser(ParseError::CycleDetected { .. }))
+        ));
+        assert!(matches!(
+            lazy.region("broken"),
+            Err(Error::Parser(ParseError::UnknownRegion { .. }))
+        ));
+        assert!(matches!(
+            lazy.region("nope"),
+            Err(Error::Parser(ParseError::RegionNotFound { .. }))
+        ));
+        assert!(lazy.materialize().is_err());
+
+        // Syntax errors are still reported up front
+        let units = vec![([0, 0, 0], "@bad=rc([0,0,0]".to_string())];
+        assert!(LazyCompiled::new(&units, &CompileOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_wildcard_applies_to_regions_defined_in_later_tuples() {
+        let mut units = vec![([0, 0, 0], "#cpu.*:power=\"low\"".to_string())];
+        for _ in 1..5 {
+            units.push(([0, 0, 0], "; filler".to_string()));
+        }
+        units.push(([10, 0, 0], "@cpu.core=rc([0,0,0],[1,1,1])".to_string()));
+        units.push((
+            [20, 0, 0],
+            "@cpu.cache=rc([0,0,0],[1,1,1])\n#size=2".to_string(),
+        ));
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(dsl_map["cpu.core"].metadata["power"], json!("low"));
+        assert_eq!(dsl_map["cpu.cache"].metadata["power"], json!("low"));
+        assert_eq!(dsl_map["cpu.cache"].metadata["size"], json!(2));
+
+        // Same tuple, wildcard statement before the definition
+        let units = vec![(
+            [0, 0, 0],
+            "#cpu.*:power=\"low\"\n@cpu.alu=rc([0,0,0],[1,1,1])".to_string(),
+        )];
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(dsl_map["cpu.alu"].metadata["power"], json!("low"));
+    }
+
+    #[test]
+    fn test_predefined_regions() {
+        let predefined = BTreeMap::from([("spawn".to_string(), vec![([0, 64, 0], [3, 66, 3])])]);
+        let units = vec![(
+            [100, 0, 0],
+            "@base=rc([0,0,0],[1,1,1])\n@mine=spawn+base\n#spawn:doc.label=\"Spawn\"".to_string(),
+        )];
+
+        let (dsl_map, diagnostics) =
+            compile_pipeline_predefined(&units, &predefined, &CompileOptions::default()).unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            dsl_map["mine"].bounding_boxes,
+            Some(vec![([0, 64, 0], [3, 66, 3]), ([100, 0, 0], [101, 1, 1])])
+        );
+        assert_eq!(dsl_map["spawn"].metadata["doc.label"], json!("Spawn"));
+
+        // Without the predefined region the reference is unknown
+        assert!(compile_pipeline(&units).is_err());
+    }
+
+    #[test]
+    fn test_predefined_region_collision() {
+        let predefined = BTreeMap::from([("spawn".to_string(), vec![([0, 64, 0], [3, 66, 3])])]);
+        let units = vec![([0, 0, 0], "@spawn=rc([0,0,0],[1,1,1])".to_string())];
+
+        assert!(matches!(
+            compile_pipeline_predefined(&units, &predefined, &CompileOptions::default()),
+            Err(Error::Parser(ParseError::PredefinedRegionRedefined { .. }))
+        ));
+
+        let options = CompileOptions {
+            predefined_collision: PredefinedCollision::Warn,
+            ..Default::default()
+        };
+        let (dsl_map, diagnostics) =
+            compile_pipeline_predefined(&units, &predefined, &options).unwrap();
+        assert_eq!(
+            dsl_map["spawn"].bounding_boxes,
+            Some(vec![([0, 0, 0], [1, 1, 1])])
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("predefined"));
+    }
+
+    #[test]
+    fn test_annotated_metadata_origins() {
+        let units = vec![(
+            [0, 0, 0],
+            "@cpu.core=rc([0,0,0],[1,1,1])\n#cpu.core:clock=4\n#cpu.*:power=\"low\"\n#cpu.core:power=\"low\"\n#cpu.*:tier=1"
+                .to_string(),
+        )];
+
+        let annotated = compile_annotated_pipeline(&units, &CompileOptions::default()).unwrap();
+        let plain = compile_pipeline(&units).unwrap();
+        assert_eq!(
+            annotated.keys().collect::<Vec<_>>(),
+            plain.keys().collect::<Vec<_>>()
+        );
+
+        let core = &annotated["cpu.core"].metadata;
+        assert_eq!(core["clock"].origin, MetadataOrigin::Own);
+        // Set on the region as well as through the wildcard: own wins
+        assert_eq!(core["power"].origin, MetadataOrigin::Own);
+        assert_eq!(
+            core["tier"].origin,
+            MetadataOrigin::Inherited("cpu.*".to_string())
+        );
+        assert_eq!(
+            annotated["cpu.*"].metadata["tier"].origin,
+            MetadataOrigin::Own
+        );
+
+        let serialized = serde_json::to_value(&annotated["cpu.core"]).unwrap();
+        assert_eq!(
+            serialized["metadata"]["tier"],
+            json!({"value": 1, "origin": "cpu.*"})
+        );
+        assert_eq!(serialized["metadata"]["clock"]["origin"], json!("own"));
+    }
+
+    #[test]
+    fn test_compile_shell_regions() {
+        let units = vec![(
+            [10, 0, 0],
+            "@wall=shell(rc([0,0,0],[4,4,4]))\n@wall=rc([20,0,0],[20,0,0])\n@shell(ac([0,0,0],[2,2,2]))\n#kind=\"hollow\""
+                .to_string(),
+        )];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        // 5x5x5 minus the 3x3x3 interior is covered by six disjoint boxes, plus the extra box
+        let wall_boxes = dsl_map["wall"].bounding_boxes.as_ref().unwrap();
+        assert_eq!(wall_boxes.len(), 7);
+        assert!(!wall_boxes.iter().any(|(min, max)| {
+            (0..3).all(|axis| min[axis] <= [12, 2, 2][axis] && [12, 2, 2][axis] <= max[axis])
+        }));
+
+        // Metadata binds to the anonymous shell, which keeps all its boxes
+        let anon = &dsl_map["__anon_0_2"];
+        assert_eq!(anon.metadata["kind"], json!("hollow"));
+        assert_eq!(anon.bounding_boxes.as_ref().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_compile_sphere_regions() {
+        let units = vec![(
+            [10, 0, 0],
+            "@dome=sp([0,0,0],2)\n@sp([0,20,0],1)\n#kind=\"ball\"".to_string(),
+        )];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        let volume: u128 = dsl_map["dome"]
+            .bounding_boxes
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|&pair| crate::boxutil::volume(pair))
+            .sum();
+        assert_eq!(volume, 33);
+
+        // Metadata binds to the anonymous sphere, which keeps all its boxes
+        let anon = &dsl_map["__anon_0_1"];
+        assert_eq!(anon.metadata["kind"], json!("ball"));
+        assert!(anon
+            .bounding_boxes
+            .as_ref()
+            .unwrap()
+            .contains(&([10, 20, -1], [10, 20, 1])));
+    }
+
+    #[test]
+    fn test_compile_constants() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@const origin=[10,64,10]\n@const size=[3,2,1]\n@hall=ac(origin,[12,66,12])"
+                    .to_string(),
+            ),
+            ([100, 0, 0], "@hall=rc([0,0,0],size)".to_string()),
+        ];
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(
+            dsl_map["hall"].bounding_boxes,
+            Some(vec![
+                ([10, 64, 10], [12, 66, 12]),
+                ([100, 0, 0], [103, 2, 1])
+            ])
+        );
+        // Declarations are not regions
+        assert_eq!(dsl_map.len(), 1);
+
+        let redefined = vec![
+            ([0, 0, 0], "@const origin=[0,0,0]".to_string()),
+            ([0, 0, 0], "@const origin=[1,1,1]".to_string()),
+        ];
+        match compile_pipeline(&redefined) {
+            Err(Error::Parser(ParseError::DuplicateConstant { name, source })) => {
+                assert_eq!(name, "origin");
+                assert_eq!(source, SourceLocation::new(1, 0));
+            }
+            other => panic!("Expected DuplicateConstant, got {:?}", other),
+        }
+
+        // Declared after its use
+        let late = vec![
+            ([0, 0, 0], "@rc(origin,[1,1,1])".to_string()),
+            ([0, 0, 0], "@const origin=[0,0,0]".to_string()),
+        ];
+        assert!(matches!(
+            compile_pipeline(&late),
+            Err(Error::Parser(ParseError::UnknownConstant { .. }))
+        ));
+        // A syntax check of one tuple cannot see the declaration
+        assert!(validate_syntax_pipeline(&late[0].1, &CompileOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_position_axis_order() {
+        let text = "@cpu=rc([0,0,0],[1,2,3])\n@base=ac([5,6,7],[5,6,7])".to_string();
+        let xzy = CompileOptions {
+            position_axis_order: [Axis::X, Axis::Z, Axis::Y],
+            ..Default::default()
+        };
+
+        // [x, z, y] = [10, 30, 64] is the sign at x=10, y=64, z=30
+        let swapped = compile_pipeline_with_options(&[([10, 30, 64], text.clone())], &xzy).unwrap();
+        let canonical = compile_pipeline(&[([10, 64, 30], text)]).unwrap();
+        assert_eq!(swapped, canonical);
+        assert_eq!(
+            swapped["cpu"].bounding_boxes,
+            Some(vec![([10, 64, 30], [11, 66, 33])])
+        );
+
+        // Predefined boxes are reordered too
+        let predefined = BTreeMap::from([("spawn".to_string(), vec![([0, 3, 64], [1, 4, 65])])]);
+        let units = vec![([0, 0, 0], "@all=spawn".to_string())];
+        let (result, _) = compile_pipeline_predefined(&units, &predefined, &xzy).unwrap();
+        assert_eq!(
+            result["all"].bounding_boxes,
+            Some(vec![([0, 64, 3], [1, 65, 4])])
+        );
+
+        let invalid = CompileOptions {
+            position_axis_order: [Axis::X, Axis::X, Axis::Y],
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile_pipeline_with_options(&units, &invalid),
+            Err(Error::Parser(ParseError::InvalidAxisOrder { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_max_tuples_limit() {
+        let units = vec![
+            ([0, 0, 0], "@a=rc([0,0,0],[1,1,1])".to_string()),
+            ([0, 0, 0], "@b=rc([0,0,0],[1,1,1])".to_string()),
+            ([0, 0, 0], "@c=a+b".to_string()),
+        ];
+        let limited = |limit| CompileOptions {
+            max_tuples: Some(limit),
+            ..Default::default()
+        };
+
+        assert!(compile_pipeline_with_options(&units, &limited(3)).is_ok());
+        assert!(matches!(
+            compile_pipeline_with_options(&units, &limited(2)),
+            Err(Error::Parser(ParseError::TooManyTuples {
+                count: 3,
+                limit: 2
+            }))
+        ));
+        // The guard covers every entry point, including lazy compilation
+        assert!(LazyCompiled::new(&units, &limited(2)).is_err());
+
+        // It runs before the units are copied into a foreign axis order
+        let reordered = CompileOptions {
+            position_axis_order: [Axis::X, Axis::X, Axis::Y],
+            ..limited(2)
+        };
+        assert!(matches!(
+            compile_pipeline_with_options(&units, &reordered),
+            Err(Error::Parser(ParseError::TooManyTuples { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_box_labels_in_output() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@wall=rc([0,0,0],[9,0,9])#north\n@wall=rc([0,0,10],[9,0,19])\n#kind=\"wall\""
+                    .to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "@wall=rc([0,0,20],[9,0,29])#south\n@all=wall".to_string(),
+            ),
+            ([0, 0, 0], "@floor=rc([0,0,0],[9,0,9])".to_string()),
+        ];
+        let labels = Some(vec![
+            Some("north".to_string()),
+            None,
+            Some("south".to_string()),
+        ]);
+
+        let dsl_map = compile_pipeline(&units).unwrap();
+        assert_eq!(dsl_map["wall"].box_labels, labels);
+        assert_eq!(dsl_map["wall"].metadata["kind"], json!("wall"));
+        // Only accumulators carry labels; unlabeled regions omit the field
+        assert_eq!(dsl_map["all"].box_labels, None);
+        assert_eq!(dsl_map["floor"].box_labels, None);
+
+        let json = serde_json::to_value(&dsl_map).unwrap();
+        assert_eq!(json["wall"]["box_labels"], json!(["north", null, "south"]));
+        assert!(json["floor"].get("box_labels").is_none());
+
+        let mut lazy = LazyCompiled::new(&units, &CompileOptions::default()).unwrap();
+        assert_eq!(lazy.region("wall").unwrap().box_labels, labels);
+        assert_eq!(lazy.materialize().unwrap(), dsl_map);
+    }
+
+    #[test]
+    #[cfg(feature = "metadata_groups")]
+    fn test_group_by_metadata() {
+        let units = vec![
+            (
+                [0, 0, 0],
+                "@a=rc([0,0,0],[1,1,1])\n#power=\"low\"".to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "@cpu.core=rc([9,0,0],[9,0,0])\n#cpu.*:power=\"low\"\n@b=rc([5,0,0],[6,1,1])\n#power=\"high\""
+                    .to_string(),
+            ),
+            (
+                [0, 0, 0],
+                "@low=group_by(power == \"low\")\n@all=low+group_by(power==\"high\")".to_string(),
+            ),
+        ];
+
+        let result = compile_pipeline(&units).unwrap();
+        // Members in name order; inherited wildcard metadata counts
+        assert_eq!(
+            result["low"].bounding_boxes,
+            Some(vec![([0, 0, 0], [1, 1, 1]), ([9, 0, 0], [9, 0, 0])])
+        );
+        assert_eq!(result["all"].bounding_boxes.as_ref().unwrap().len(), 3);
+
+        let mut lazy = LazyCompiled::new(&units, &CompileOptions::default()).unwrap();
+        assert_eq!(lazy.region("low").unwrap(), result["low"]);
+
+        // A group that matches itself depends on its own geometry
+        let units = vec![(
+            [0, 0, 0],
+            "@low=group_by(power==\"low\")\n#power=\"low\"".to_string(),
+        )];
+        assert!(matches!(
+            compile_pipeline(&units),
+            Err(Error::Parser(ParseError::CycleDetected { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_minimize_boxes_option() {
+        let units = vec![(
+            [0, 0, 0],
+            "@wall=rc([0,0,0],[3,3,0])\n@wall=rc([4,0,0],[7,3,0])\n@wall=rc([0,4,0],[7,4,0])\n\
+             @door=rc([0,0,0],[0,0,0])#hinge\n@door=rc([1,0,0],[1,0,0])#latch"
+                .to_string(),
+        )];
+        let options = CompileOptions {
+            minimize_boxes: true,
+            ..Default::default()
+        };
+
+        let result = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(
+            result["wall"].bounding_boxes,
+            Some(vec![([0, 0, 0], [7, 4, 0])])
+        );
+        // Labeled boxes are kept as written
+        assert_eq!(result["door"].bounding_boxes.as_ref().unwrap().len(), 2);
+
+        let mut lazy = LazyCompiled::new(&units, &options).unwrap();
+        assert_eq!(lazy.region("wall").unwrap(), result["wall"]);
+
+        let plain = compile_pipeline(&units).unwrap();
+        assert_eq!(plain["wall"].bounding_boxes.as_ref().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_coalesce_boxes_option() {
+        let units = vec![(
+            [0, 0, 0],
+            "@a=rc([0,0,0],[3,3,0])\n@b=rc([4,0,0],[7,3,0])\n@c=rc([0,4,0],[0,4,0])\n\
+             @combined=a+b+a\n@bent=a+c"
+                .to_string(),
+        )];
+        let options = CompileOptions {
+            coalesce_boxes: true,
+            ..Default::default()
+        };
+
+        let result = compile_pipeline_with_options(&units, &options).unwrap();
+        assert_eq!(
+            result["combined"].bounding_boxes,
+            Some(vec![([0, 0, 0], [7, 3, 0])])
+        );
+        // Not a box together: kept as evaluated
+        assert_eq!(
+            result["bent"].bounding_boxes,
+            Some(vec![([0, 0, 0], [3, 3, 0]), ([0, 4, 0], [0, 4, 0])])
+        );
+
+        let plain = compile_pipeline(&units).unwrap();
+        assert_eq!(plain["combined"].bounding_boxes.as_ref().unwrap().len(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "namespace_schemas")]
+    fn test_namespace_schemas() {
+        let options = CompileOptions::from_json(
+            json!({"namespace_schemas": {"logic": {
+                "1": {"keys": {"clock_hz": "number"}},
+                "2": {"keys": {"clock_hz": "integer", "mode": "string"}}
+            }}})
+            .as_object()
+            .unwrap()
+            .clone(),
+            true,
+        )
+        .unwrap();
+        let compile = |version: &str, text: &str| {
+            let units = vec![
+                ([0, 0, 0], format!("#$global:_schema.logic={}", version)),
+                ([0, 0, 0], text.to_string()),
+            ];
+            compile_pipeline_with_options(&units, &options)
+        };
+
+        let text = "@cpu=rc([0,0,0],[1,1,1])\n#logic.clock_hz=2.5\n#doc.note=1";
+        assert!(compile("\"1\"", text).is_ok());
+        assert!(matches!(
+            compile("\"2\"", text),
+            Err(Error::Parser(ParseError::SchemaViolation(_)))
+        ));
+        assert!(matches!(
+            compile("\"3\"", text),
+            Err(Error::Parser(ParseError::UnknownSchemaVersion { .. }))
+        ));
+
+        // Wildcard assignments are checked like any other
+        let inherited = "@cpu.core=rc([0,0,0],[1,1,1])\n#cpu.*:logic.mode=4";
+        match compile("2", inherited) {
+            Err(Error::Parser(ParseError::SchemaViolation(violation))) => {
+                assert_eq!(violation.region, "cpu.*")
+            }
+            other => panic!("expected SchemaViolation, got {:?}", other),
+        }
+
+        // Without a declaration the namespace is free-form
+        let units = vec![([0, 0, 0], text.to_string())];
+        assert!(compile_pipeline_with_options(&units, &options).is_ok());
+    }
 }
@@ -0,0 +1,95 @@
+//! Point and box queries directly on a compiled [`DslMap`].
+//!
+//! These scan every box, which suits one-off questions. For many queries
+//! against the same map, build a [`crate::ast::RegionIndex`] with
+//! [`crate::ast::build_index`] instead.
+
+use crate::boxutil::{contains_point, intersects};
+use crate::{BoxPair, Coord, DslMap};
+
+/// Spatial queries on a [`DslMap`].
+///
+/// Entries without boxes (like `$global` and wildcards) never match. Results
+/// follow the map's key order.
+pub trait DslMapQuery {
+    /// Regions with at least one box containing `point` (bounds are inclusive)
+    fn regions_containing(&self, point: [Coord; 3]) -> Vec<&str>;
+
+    /// Regions with at least one box overlapping `query` (touching counts)
+    fn regions_overlapping(&self, query: BoxPair) -> Vec<&str>;
+}
+
+impl DslMapQuery for DslMap {
+    fn regions_containing(&self, point: [Coord; 3]) -> Vec<&str> {
+        regions_matching(self, |bounds| contains_point(bounds, point))
+    }
+
+    fn regions_overlapping(&self, query: BoxPair) -> Vec<&str> {
+        regions_matching(self, |bounds| intersects(bounds, query))
+    }
+}
+
+/// Names of the entries with a box for which `matches` holds
+fn regions_matching(dsl_map: &DslMap, matches: impl Fn(BoxPair) -> bool) -> Vec<&str> {
+    dsl_map
+        .iter()
+        .filter(|(_, entry)| {
+            entry
+                .bounding_boxes
+                .iter()
+                .flatten()
+                .any(|bounds| matches(*bounds))
+        })
+        .map(|(name, _)| name.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+
+    fn sample_map() -> DslMap {
+        compile(&[(
+            [0, 0, 0],
+            "@hall=rc([0,0,0],[9,4,9])\n@door=rc([4,0,9],[5,2,9])\n\
+             @tower=rc([20,0,0],[22,30,2])\n@tower=rc([30,0,0],[31,1,1])\n\
+             #$global:world=\"test\""
+                .to_string(),
+        )])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_regions_containing() {
+        let dsl_map = sample_map();
+
+        // Inside a single box
+        assert_eq!(dsl_map.regions_containing([1, 1, 1]), vec!["hall"]);
+        // On an edge shared by two regions; bounds are inclusive
+        assert_eq!(dsl_map.regions_containing([5, 2, 9]), vec!["door", "hall"]);
+        // In the second box of a region
+        assert_eq!(dsl_map.regions_containing([31, 1, 0]), vec!["tower"]);
+        // Outside all boxes
+        assert!(dsl_map.regions_containing([10, 0, 0]).is_empty());
+        assert!(dsl_map.regions_containing([0, -1, 0]).is_empty());
+    }
+
+    #[test]
+    fn test_regions_overlapping() {
+        let dsl_map = sample_map();
+
+        assert_eq!(
+            dsl_map.regions_overlapping(([-5, -5, -5], [100, 100, 100])),
+            vec!["door", "hall", "tower"]
+        );
+        // Touching a corner counts
+        assert_eq!(
+            dsl_map.regions_overlapping(([9, 4, 9], [12, 12, 12])),
+            vec!["hall"]
+        );
+        assert!(dsl_map
+            .regions_overlapping(([10, 0, 0], [19, 5, 5]))
+            .is_empty());
+    }
+}
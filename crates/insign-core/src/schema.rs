@@ -0,0 +1,233 @@
+use crate::ast::{EvaluatedRegionTable, SourceLocation};
+pub use crate::options::ValueKind;
+use crate::ParseError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// `$global` key prefix declaring a namespace's schema version, e.g.
+/// `#$global:_schema.logic="2"`
+pub const SCHEMA_VERSION_PREFIX: &str = "_schema.";
+
+/// Registered schemas, keyed by `(namespace, version)`
+pub type NamespaceSchemas = BTreeMap<(String, String), Schema>;
+
+/// Keys of one version of a metadata namespace, relative to the namespace
+/// (`clock_hz` for `logic.clock_hz`)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schema {
+    /// Allowed keys and the kind of their values
+    pub keys: BTreeMap<String, ValueKind>,
+    /// Accept keys not listed in `keys`. Default: they are violations.
+    pub allow_unknown_keys: bool,
+}
+
+/// Large error struct for `ParseError::SchemaViolation` to reduce enum size
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SchemaViolationError {
+    pub region: String,
+    pub key: String,
+    pub namespace: String,
+    pub version: String,
+    /// What is wrong with the value
+    pub message: String,
+    pub source: SourceLocation,
+}
+
+/// Check the metadata of every region against the schema its namespace declares.
+///
+/// A namespace opts in with `$global` metadata `_schema.<namespace>` set to its
+/// version; namespaces without a declaration are not checked.
+/// A declared version missing from `schemas` is an error.
+pub fn validate_namespace_schemas(
+    table: &EvaluatedRegionTable,
+    schemas: &NamespaceSchemas,
+) -> Result<(), ParseError> {
+    let Some(global) = table.regions.get("$global") else {
+        return Ok(());
+    };
+
+    for (key, assignment) in &global.metadata {
+        let Some(namespace) = key.strip_prefix(SCHEMA_VERSION_PREFIX) else {
+            continue;
+        };
+        // Other values than strings stand for their JSON text: `2` is version "2"
+        let version = match &assignment.value {
+            Value::String(version) => version.clone(),
+            other => other.to_string(),
+        };
+        let schema = schemas
+            .get(&(namespace.to_string(), version.clone()))
+            .ok_or_else(|| ParseError::UnknownSchemaVersion {
+                namespace: namespace.to_string(),
+                version: version.clone(),
+                source: assignment.source.clone(),
+            })?;
+
+        let prefix = format!("{}.", namespace);
+        for (region, data) in &table.regions {
+            for (key, assignment) in &data.metadata {
+                let Some(field) = key.strip_prefix(&prefix) else {
+                    continue;
+                };
+                let message = match schema.keys.get(field) {
+                    Some(kind) if kind.matches(&assignment.value) => continue,
+                    Some(kind) => format!(
+                        "expected {}, found {}",
+                        serde_json::to_string(kind).unwrap_or_default(),
+                        assignment.value
+                    ),
+                    None if schema.allow_unknown_keys => continue,
+                    None => "key is not in the schema".to_string(),
+                };
+                return Err(ParseError::SchemaViolation(Box::new(
+                    SchemaViolationError {
+                        region: region.clone(),
+                        key: key.clone(),
+                        namespace: namespace.to_string(),
+                        version,
+                        message,
+                        source: assignment.source.clone(),
+                    },
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Serialize [`NamespaceSchemas`] as `{namespace: {version: schema}}`, since
+/// JSON object keys cannot be pairs
+pub(crate) fn serialize_nested<S: Serializer>(
+    schemas: &NamespaceSchemas,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut nested: BTreeMap<&str, BTreeMap<&str, &Schema>> = BTreeMap::new();
+    for ((namespace, version), schema) in schemas {
+        nested.entry(namespace).or_default().insert(version, schema);
+    }
+    nested.serialize(serializer)
+}
+
+/// Inverse of [`serialize_nested`]
+pub(crate) fn deserialize_nested<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<NamespaceSchemas, D::Error> {
+    let nested = BTreeMap::<String, BTreeMap<String, Schema>>::deserialize(deserializer)?;
+    Ok(nested
+        .into_iter()
+        .flat_map(|(namespace, versions)| {
+            versions
+                .into_iter()
+                .map(move |(version, schema)| ((namespace.clone(), version), schema))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::MetadataAssignment;
+    use serde_json::json;
+
+    fn table(entries: &[(&str, &str, Value)]) -> EvaluatedRegionTable {
+        let mut table = EvaluatedRegionTable::new();
+        for (stmt_idx, (region, key, value)) in entries.iter().enumerate() {
+            let assignment = MetadataAssignment {
+                value: value.clone(),
+                source: SourceLocation::new(0, stmt_idx),
+                via: None,
+            };
+            table
+                .add_metadata(region.to_string(), key.to_string(), assignment)
+                .unwrap();
+        }
+        table
+    }
+
+    fn logic_v2() -> NamespaceSchemas {
+        let schema = Schema {
+            keys: BTreeMap::from([
+                ("clock_hz".to_string(), ValueKind::Integer),
+                ("mode".to_string(), ValueKind::String),
+            ]),
+            allow_unknown_keys: false,
+        };
+        BTreeMap::from([(("logic".to_string(), "2".to_string()), schema)])
+    }
+
+    #[test]
+    fn test_validate_declared_namespace() {
+        let valid = table(&[
+            ("$global", "_schema.logic", json!("2")),
+            ("cpu", "logic.clock_hz", json!(4)),
+            ("cpu", "doc.label", json!(12)),
+        ]);
+        assert!(validate_namespace_schemas(&valid, &logic_v2()).is_ok());
+
+        let wrong_kind = table(&[
+            ("$global", "_schema.logic", json!("2")),
+            ("cpu", "logic.clock_hz", json!(4.5)),
+        ]);
+        match validate_namespace_schemas(&wrong_kind, &logic_v2()) {
+            Err(ParseError::SchemaViolation(violation)) => {
+                assert_eq!(violation.region, "cpu");
+                assert_eq!(violation.key, "logic.clock_hz");
+                assert_eq!(violation.source, SourceLocation::new(0, 1));
+            }
+            other => panic!("expected SchemaViolation, got {:?}", other),
+        }
+
+        let unknown_key = table(&[
+            ("$global", "_schema.logic", json!("2")),
+            ("cpu", "logic.voltage", json!(5)),
+        ]);
+        assert!(validate_namespace_schemas(&unknown_key, &logic_v2()).is_err());
+
+        // Undeclared namespaces are not checked
+        let undeclared = table(&[("cpu", "logic.voltage", json!(5))]);
+        assert!(validate_namespace_schemas(&undeclared, &logic_v2()).is_ok());
+    }
+
+    #[test]
+    fn test_schema_versions() {
+        let declare = |version| table(&[("$global", "_schema.logic", version)]);
+
+        // A number names the version of the same spelling
+        assert!(validate_namespace_schemas(&declare(json!(2)), &logic_v2()).is_ok());
+        for version in [json!("3"), json!(2.0), json!(["2"])] {
+            assert!(matches!(
+                validate_namespace_schemas(&declare(version), &logic_v2()),
+                Err(ParseError::UnknownSchemaVersion { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_nested_serialization() {
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper {
+            #[serde(
+                serialize_with = "serialize_nested",
+                deserialize_with = "deserialize_nested"
+            )]
+            schemas: NamespaceSchemas,
+        }
+
+        let json = serde_json::to_value(Wrapper {
+            schemas: logic_v2(),
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            json!({"schemas": {"logic": {"2": {
+                "keys": {"clock_hz": "integer", "mode": "string"},
+                "allow_unknown_keys": false
+            }}}})
+        );
+        let back: Wrapper = serde_json::from_value(json).unwrap();
+        assert_eq!(back.schemas, logic_v2());
+    }
+}
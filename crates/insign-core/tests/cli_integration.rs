@@ -79,6 +79,108 @@ fn test_cli_pretty_print() {
     assert!(stdout.contains("    "));
 }
 
+#[test]
+fn test_cli_format_ndjson_entries() {
+    let input = r#"{"pos": [0, 0, 0], "text": "@a=rc([0,0,0],[1,1,1])\n@b=rc([2,2,2],[3,3,3])\n#$global:v=1"}"#;
+
+    let output = run_cli_with_input(input, &["--format", "ndjson-entries"]);
+
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    // $global, a and b
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(first["id"], "$global");
+    assert_eq!(first["entry"]["metadata"]["v"], 1);
+
+    let output = run_cli_with_input(input, &["--format", "jsonl"]);
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 1);
+}
+
+#[test]
+fn test_cli_print_schema() {
+    let output = Command::new(get_cli_binary())
+        .arg("--print-schema")
+        .output()
+        .expect("Failed to run CLI process");
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(schema, insign::output_json_schema());
+}
+
+#[test]
+fn test_cli_stats() {
+    let args = ["--text", "--stats"];
+    let output = run_cli_with_file("tests/fixtures/inputs/D_wildcards_global.txt", &args);
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.trim(),
+        "regions: 4, boxes: 4, wildcard entries: 2, $global: yes"
+    );
+
+    // stdout is the same JSON as without --stats
+    let plain = run_cli_with_file("tests/fixtures/inputs/D_wildcards_global.txt", &["--text"]);
+    assert_eq!(output.stdout, plain.stdout);
+}
+
+#[test]
+fn test_cli_format_rejects_pretty_lines() {
+    // The CLI exits before reading its input, so pass a file rather than stdin
+    let mut temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    writeln!(
+        temp_file,
+        r#"{{"pos": [0, 0, 0], "text": "@a=rc([0,0,0],[1,1,1])"}}"#
+    )
+    .unwrap();
+
+    let output = run_cli_with_file(
+        temp_file.path().to_str().unwrap(),
+        &["--format", "ndjson-entries", "--pretty"],
+    );
+
+    assert_eq!(output.status.code().unwrap(), 1);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--pretty"));
+}
+
+#[test]
+fn test_cli_origin_for_lines_without_pos() {
+    let input = r#"{"text": "@a=rc([0,0,0],[1,1,1])"}"#;
+
+    let output = run_cli_with_input(input, &["--origin", "100", "-64", "5"]);
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        result["a"]["bounding_boxes"],
+        serde_json::json!([[[100, -64, 5], [101, -63, 6]]])
+    );
+
+    // Without --origin the line is an input error
+    let output = run_cli_with_input(input, &[]);
+    assert_eq!(output.status.code().unwrap(), 1);
+}
+
+#[test]
+fn test_cli_shift_positions() {
+    let input = r#"{"pos": [10, 64, 10], "text": "@a=rc([0,0,0],[1,1,1])"}"#;
+
+    let output = run_cli_with_input(input, &["--shift", "-10", "0", "1000"]);
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        result["a"]["bounding_boxes"],
+        serde_json::json!([[[0, 64, 1010], [1, 65, 1011]]])
+    );
+}
+
 #[test]
 fn test_cli_error_case() {
     let input = r#"{"pos": [10, 64, 10], "text": "@test=rc([0,0,0],[3,2,1])\n#test:value=42"}
@@ -117,6 +219,30 @@ fn test_cli_empty_input() {
     assert_eq!(stdout.trim(), "{}");
 }
 
+#[test]
+fn test_cli_fail_on_empty() {
+    let output = run_cli_with_input("", &["--fail-on-empty"]);
+    assert_eq!(output.status.code().unwrap(), 3);
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "{}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("the input has no signs"));
+
+    // Anonymous boxes without metadata are filtered out of the output
+    let filtered = r#"{"pos": [0, 0, 0], "text": "@rc([0,0,0],[1,1,1])"}"#;
+    let output = run_cli_with_input(filtered, &[]);
+    assert_eq!(output.status.code().unwrap(), 0);
+    let output = run_cli_with_input(filtered, &["--fail-on-empty"]);
+    assert_eq!(output.status.code().unwrap(), 3);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("no anonymous boxes with metadata"));
+
+    let output = run_cli_with_input(
+        r#"{"pos": [0, 0, 0], "text": "@a=rc([0,0,0],[1,1,1])"}"#,
+        &["--fail-on-empty"],
+    );
+    assert_eq!(output.status.code().unwrap(), 0);
+}
+
 #[test]
 fn test_cli_file_not_found() {
     let output = run_cli_with_file("nonexistent_file.jsonl", &[]);
@@ -179,6 +305,8 @@ fn test_cli_help() {
     assert!(stdout.contains("Compiles Insign DSL from JSONL input"));
     assert!(stdout.contains("Usage:"));
     assert!(stdout.contains("--pretty"));
+    assert!(stdout.contains("--exit-zero-on-compile-error"));
+    assert!(stdout.contains("Exit codes:"));
 }
 
 #[test]
@@ -215,3 +343,198 @@ fn test_cli_complex_multiline_dsl() {
     let boxes = region["bounding_boxes"].as_array().unwrap();
     assert_eq!(boxes.len(), 2);
 }
+
+#[test]
+fn test_cli_text_mode_front_matter() {
+    let input = "---\nversion: \"1.0\"\noptions.keep_empty_anonymous: true\n---\n@rc([0,0,0],[1,1,1])\n@named=rc([2,2,2],[3,3,3])\n";
+    let output = run_cli_with_input(input, &["--text"]);
+
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(parsed["$global"]["metadata"]["version"], "1.0");
+    // keep_empty_anonymous from front-matter keeps the metadata-less anonymous box
+    let keys: Vec<&String> = parsed.as_object().unwrap().keys().collect();
+    assert!(keys.iter().any(|key| key.starts_with("__anon_0_")));
+    assert!(keys.iter().any(|key| *key == "named"));
+}
+
+#[test]
+fn test_cli_front_matter_ordering() {
+    let input = "---\noptions.ordering: \"box_count_desc\"\n---\n@a=rc([0,0,0],[1,1,1])\n@b=rc([0,0,0],[1,1,1])\n@b+=rc([5,5,5],[6,6,6])\n";
+    let output = run_cli_with_input(input, &["--text"]);
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // Most boxes first, so `b` is printed before `a`
+    assert!(stdout.find("\"b\"").unwrap() < stdout.find("\"a\"").unwrap());
+}
+
+#[test]
+fn test_cli_text_mode_malformed_front_matter() {
+    let input = "---\nversion: \"1.0\"\n@rc([0,0,0],[1,1,1])\n";
+    let output = run_cli_with_input(input, &["--text"]);
+
+    assert_eq!(output.status.code().unwrap(), 1);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error parsing text input"));
+    assert!(stderr.contains("never closed"));
+}
+
+#[test]
+fn test_cli_exit_zero_on_compile_error() {
+    let input = r#"{"pos": [10, 64, 10], "text": "@test=rc([0,0,0],[3,2,1])\n#test:value=42"}
+{"pos": [0, 64, 0], "text": "@test=ac([100,70,-20],[104,72,-18])\n#test:value=99"}"#;
+
+    let output = run_cli_with_input(input, &["--exit-zero-on-compile-error"]);
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stdout.is_empty());
+
+    // Diagnostics are still printed
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Metadata conflict"));
+}
+
+#[test]
+fn test_cli_exit_zero_on_compile_error_keeps_input_errors() {
+    let output = run_cli_with_input("invalid json line", &["--exit-zero-on-compile-error"]);
+
+    assert_eq!(output.status.code().unwrap(), 1);
+}
+
+#[test]
+fn test_cli_multiple_files_share_regions() {
+    let mut first = NamedTempFile::new().expect("Failed to create temp file");
+    writeln!(
+        first,
+        r#"{{"pos": [0,0,0], "text": "@base=rc([0,0,0],[1,1,1])"}}"#
+    )
+    .unwrap();
+    let mut second = NamedTempFile::new().expect("Failed to create temp file");
+    writeln!(second, r#"{{"pos": [10,0,0], "text": "@all=base+extra"}}"#).unwrap();
+    writeln!(
+        second,
+        r#"{{"pos": [20,0,0], "text": "@extra=rc([0,0,0],[1,1,1])"}}"#
+    )
+    .unwrap();
+
+    let output = Command::new(get_cli_binary())
+        .args([first.path(), second.path()])
+        .output()
+        .expect("Failed to run CLI process");
+
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["all"]["bounding_boxes"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_cli_multiple_files_error_names_file_and_line() {
+    let mut first = NamedTempFile::new().expect("Failed to create temp file");
+    writeln!(
+        first,
+        r#"{{"pos": [0,0,0], "text": "@base=rc([0,0,0],[1,1,1])"}}"#
+    )
+    .unwrap();
+    let mut second = NamedTempFile::new().expect("Failed to create temp file");
+    writeln!(second).unwrap();
+    writeln!(second, r#"{{"pos": [0,0,0], "text": "@all=base+missing"}}"#).unwrap();
+
+    let output = Command::new(get_cli_binary())
+        .args([first.path(), second.path()])
+        .output()
+        .expect("Failed to run CLI process");
+
+    assert_eq!(output.status.code(), Some(2));
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["tuple_index"], 1);
+    assert_eq!(error["file"], second.path().to_str().unwrap());
+    assert_eq!(error["line"], 2);
+}
+
+#[test]
+fn test_cli_watch_recompiles_on_change() {
+    use std::io::{BufRead, BufReader};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let mut temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    write!(temp_file, "@first=rc([0,0,0],[1,1,1])").unwrap();
+    temp_file.flush().unwrap();
+
+    let mut child = Command::new(get_cli_binary())
+        .args([temp_file.path().to_str().unwrap(), "--watch", "--text"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn CLI process");
+
+    // Forward stdout lines so the test can wait with a timeout
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let wait_for = |needle: &str| loop {
+        let line = receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("watch mode produced no matching output");
+        // Piped output carries no terminal escapes between runs
+        assert!(!line.contains('\x1b'), "unexpected escape in {:?}", line);
+        if line.contains(needle) {
+            break;
+        }
+    };
+
+    wait_for("\"first\"");
+
+    // A compile error is reported without ending the watch
+    std::fs::write(temp_file.path(), "@broken=missing").unwrap();
+    std::thread::sleep(Duration::from_millis(600));
+    assert!(child.try_wait().unwrap().is_none());
+
+    std::fs::write(temp_file.path(), "@second=rc([0,0,0],[2,2,2])").unwrap();
+    wait_for("\"second\"");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_cli_watch_reports_missing_file_once() {
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("later.insign");
+
+    let mut child = Command::new(get_cli_binary())
+        .args([path.to_str().unwrap(), "--watch", "--text"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn CLI process");
+
+    // Several polls pass while the file is missing
+    std::thread::sleep(Duration::from_millis(1200));
+    child.kill().unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.matches("Watching").count(), 1, "{}", stderr);
+}
+
+#[test]
+fn test_cli_watch_requires_file() {
+    let output = run_cli_with_input("", &["--watch"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("FILE"));
+}
@@ -114,3 +114,23 @@ mod boolean_ops_enabled_tests {
         assert_eq!(boxes.len(), 2, "Union should produce two bounding boxes");
     }
 }
+
+#[cfg(not(feature = "boolean_ops"))]
+mod boolean_ops_disabled_tests {
+    use insign::{compile_with_options, CompileOptions, Error, ParseError};
+
+    #[test]
+    fn test_feature_gated_matches_outside_the_crate() {
+        // The variant is non-exhaustive, so callers match it with `..`
+        let mut options = CompileOptions::default();
+        options.verbose_feature_errors = true;
+        let units = vec![([0, 0, 0], "@result=a-b".to_string())];
+
+        match compile_with_options(&units, &options) {
+            Err(Error::Parser(ParseError::FeatureGated { operator, left, .. })) => {
+                assert_eq!((operator.as_str(), left.as_str()), ("-", "a"));
+            }
+            other => panic!("Expected FeatureGated, got {:?}", other),
+        }
+    }
+}
@@ -3,29 +3,37 @@
 //! This crate exports a C ABI for the Insign DSL compiler, allowing
 //! integration with Kotlin/JVM applications like Spigot plugins.
 
+use std::cell::RefCell;
 use std::ffi::{c_void, CString};
 use std::os::raw::{c_char, c_int};
+use std::ptr;
 use std::slice;
 
-use insign::compile;
+use insign::envelope::{parse_compile_request, CompileRequest};
+use insign::{compile_ordered, validate_syntax, Error, OrderedDslMap, ParseError};
 
-/// Input format for JSON compilation
-#[derive(serde::Deserialize)]
-struct CompileInput {
-    pos: [i32; 3],
-    text: String,
+/// Returned by `insign_compile_json_into` when the output does not fit the buffer
+pub const INSIGN_BUFFER_TOO_SMALL: c_int = 2;
+
+thread_local! {
+    /// Message of the last failed compile on this thread, for `insign_last_error`
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
 }
 
 /// Returns the ABI version of the library
+///
+/// Version 2 added `insign_compile_json_into`; version 3 added
+/// `insign_compile_check` and `insign_last_error`.
 #[no_mangle]
 pub extern "C" fn insign_abi_version() -> u32 {
-    1
+    3
 }
 
 /// Compiles input JSON to output JSON via C ABI
 ///
 /// # Arguments
-/// * `input_ptr` - Pointer to UTF-8 JSON input (array of {pos: [x,y,z], text: "..."})
+/// * `input_ptr` - Pointer to UTF-8 JSON input: an array of {pos: [x,y,z], text: "..."},
+///   or an envelope `{units, options, pretty, strict_options}` (see `insign::envelope`)
 /// * `input_len` - Length of input in bytes
 /// * `output_ptr` - Pointer to receive allocated output string pointer
 /// * `output_len` - Pointer to receive length of output string
@@ -43,73 +51,261 @@ pub extern "C" fn insign_compile_json(
 ) -> c_int {
     // Validate input parameters
     if input_ptr.is_null() || output_ptr.is_null() || output_len.is_null() {
+        set_last_error(Some("Invalid null pointer parameters"));
         return allocate_error_output(output_ptr, output_len, "Invalid null pointer parameters");
     }
 
+    let input = unsafe { slice::from_raw_parts(input_ptr as *const u8, input_len) };
+    let (code, json) = compile_json_response(input);
+    allocate_output(output_ptr, output_len, &json);
+    code
+}
+
+/// Compiles input JSON to output JSON written into a caller-provided buffer
+///
+/// Same input and output JSON as `insign_compile_json`, without allocating the
+/// output, for hosts compiling many inputs in a loop.
+///
+/// # Arguments
+/// * `input_ptr` - Pointer to UTF-8 JSON input, as for `insign_compile_json`
+/// * `input_len` - Length of input in bytes
+/// * `out_buf` - Buffer receiving the output JSON; may be null if `out_cap` is 0
+/// * `out_cap` - Capacity of `out_buf` in bytes
+/// * `out_len_written` - Pointer to receive the length of the output JSON
+///
+/// # Returns
+/// * 0 on success, 1 on error (error JSON written as with `insign_compile_json`)
+/// * `INSIGN_BUFFER_TOO_SMALL` (2) if the output is longer than `out_cap`:
+///   nothing is written to `out_buf`, and `out_len_written` receives the
+///   required capacity. Call again with a buffer at least that large; the
+///   input is compiled again.
+/// * 1 without writing anything if `out_len_written` is null, or `out_buf` is
+///   null while `out_cap` is not 0
+///
+/// The output is not NUL-terminated; only the first `*out_len_written` bytes
+/// of `out_buf` are valid, and they are never a truncated document.
+///
+/// # Safety
+/// `input_ptr` must point to `input_len` readable bytes, and `out_buf` to
+/// `out_cap` writable bytes that do not overlap the input.
+#[no_mangle]
+pub unsafe extern "C" fn insign_compile_json_into(
+    input_ptr: *const c_char,
+    input_len: usize,
+    out_buf: *mut c_char,
+    out_cap: usize,
+    out_len_written: *mut usize,
+) -> c_int {
+    if out_len_written.is_null() || (out_buf.is_null() && out_cap > 0) {
+        set_last_error(Some("Invalid null pointer parameters"));
+        return 1;
+    }
+
+    let (code, json) = if input_ptr.is_null() {
+        set_last_error(Some("Invalid null pointer parameters"));
+        (1, error_json("Invalid null pointer parameters"))
+    } else {
+        compile_json_response(slice::from_raw_parts(input_ptr as *const u8, input_len))
+    };
+
+    *out_len_written = json.len();
+    if json.len() > out_cap {
+        return INSIGN_BUFFER_TOO_SMALL;
+    }
+    out_buf.copy_from_nonoverlapping(json.as_ptr() as *const c_char, json.len());
+    code
+}
+
+/// Compiles input JSON, reporting only whether it succeeded
+///
+/// Takes the same input as `insign_compile_json` but renders no output, for
+/// hosts that only need a success flag. On failure, the error message is
+/// available from `insign_last_error`.
+///
+/// # Returns
+/// * 0 on success, clearing the last error of this thread
+/// * 1 on error, setting the last error of this thread
+///
+/// # Safety
+/// `input_ptr` must point to `input_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn insign_compile_check(input_ptr: *const c_char, input_len: usize) -> c_int {
+    let result = if input_ptr.is_null() {
+        Err(RequestError::Ffi(
+            "Invalid null pointer parameters".to_string(),
+        ))
+    } else {
+        compile_request(slice::from_raw_parts(input_ptr as *const u8, input_len)).map(|_| ())
+    };
+    match result {
+        Ok(()) => {
+            set_last_error(None);
+            0
+        }
+        Err(error) => {
+            set_last_error(Some(&error.message()));
+            1
+        }
+    }
+}
+
+/// Returns the message of the last failed compile on the calling thread
+///
+/// Set by every failing `insign_compile_json`, `insign_compile_json_into` and
+/// `insign_compile_check` call, and cleared when one succeeds. Returns null
+/// when there is no error. The string is NUL-terminated, owned by the library,
+/// and valid until the next compile call on the same thread; do not free it.
+#[no_mangle]
+pub extern "C" fn insign_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Replace the last error of this thread
+fn set_last_error(message: Option<&str>) {
+    // A C string ends at the first NUL, so escape any inside the message
+    let message = message.map(|message| {
+        CString::new(message.replace('\0', "\\0")).expect("NUL characters were escaped")
+    });
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Why a compile request failed
+enum RequestError {
+    /// The request could not be read
+    Ffi(String),
+    /// The compiler rejected the input
+    Compile(Error),
+}
+
+impl RequestError {
+    fn message(&self) -> String {
+        match self {
+            RequestError::Ffi(message) => message.clone(),
+            RequestError::Compile(error) => error.to_string(),
+        }
+    }
+}
+
+/// Decode and compile one JSON request
+fn compile_request(input: &[u8]) -> Result<(CompileRequest, OrderedDslMap), RequestError> {
     // Convert input to Rust string
-    let input_str = unsafe {
-        let input_slice = slice::from_raw_parts(input_ptr as *const u8, input_len);
-        match std::str::from_utf8(input_slice) {
-            Ok(s) => s,
-            Err(_) => {
-                return allocate_error_output(output_ptr, output_len, "Input is not valid UTF-8")
-            }
+    let input_str = std::str::from_utf8(input)
+        .map_err(|_| RequestError::Ffi("Input is not valid UTF-8".to_string()))?;
+
+    // Parse JSON input
+    let request = parse_compile_request(input_str)
+        .map_err(|e| RequestError::Ffi(format!("JSON parse error: {}", e)))?;
+
+    // Compile using insign-core, in the entry order the options ask for
+    let dsl_map =
+        compile_ordered(&request.units, &request.options).map_err(RequestError::Compile)?;
+    Ok((request, dsl_map))
+}
+
+/// Compile one JSON request, returning the status code and output JSON
+fn compile_json_response(input: &[u8]) -> (c_int, String) {
+    let (request, dsl_map) = match compile_request(input) {
+        Ok(compiled) => compiled,
+        Err(error) => {
+            set_last_error(Some(&error.message()));
+            let json = match &error {
+                RequestError::Ffi(message) => error_json(message),
+                // Compilation error - return structured error JSON
+                RequestError::Compile(e) => serde_json::to_string(&compilation_error_json(e))
+                    .unwrap_or_else(|_| error_json("Failed to serialize error response")),
+            };
+            return (1, json);
         }
     };
 
-    // Parse JSON input
-    let input_array: Vec<CompileInput> = match serde_json::from_str(input_str) {
-        Ok(arr) => arr,
+    // Success - serialize output
+    let json = if request.pretty {
+        serde_json::to_string_pretty(&dsl_map)
+    } else {
+        serde_json::to_string(&dsl_map)
+    };
+    match json {
+        Ok(json) => {
+            set_last_error(None);
+            (0, json)
+        }
         Err(e) => {
-            return allocate_error_output(
-                output_ptr,
-                output_len,
-                &format!("JSON parse error: {}", e),
-            )
+            let message = format!("JSON serialization error: {}", e);
+            set_last_error(Some(&message));
+            (1, error_json(&message))
         }
-    };
+    }
+}
+
+/// Checks that one sign's DSL text parses, without compiling it
+///
+/// # Arguments
+/// * `text_ptr` - Pointer to the UTF-8 DSL text of a single sign (not JSON)
+/// * `text_len` - Length of the text in bytes
+/// * `output_ptr` - Pointer to receive allocated output string pointer
+/// * `output_len` - Pointer to receive length of output string
+///
+/// # Returns
+/// * 0 if every statement parses, with output `{"status":"ok"}`
+/// * non-zero otherwise, with error JSON whose `code` names the parse error,
+///   e.g. `expected` (see `ParseError::code`)
+/// * Caller must free the output with insign_free
+#[no_mangle]
+pub extern "C" fn insign_validate_syntax(
+    text_ptr: *const c_char,
+    text_len: usize,
+    output_ptr: *mut *mut c_char,
+    output_len: *mut usize,
+) -> c_int {
+    if text_ptr.is_null() || output_ptr.is_null() || output_len.is_null() {
+        return allocate_error_output(output_ptr, output_len, "Invalid null pointer parameters");
+    }
 
-    // Convert to insign-core format
-    let units: Vec<([i32; 3], String)> = input_array
-        .into_iter()
-        .map(|input| (input.pos, input.text))
-        .collect();
-
-    // Compile using insign-core
-    match compile(&units) {
-        Ok(dsl_map) => {
-            // Success - serialize output
-            match serde_json::to_string(&dsl_map) {
-                Ok(json) => allocate_success_output(output_ptr, output_len, &json),
-                Err(e) => allocate_error_output(
-                    output_ptr,
-                    output_len,
-                    &format!("JSON serialization error: {}", e),
-                ),
+    let text = unsafe {
+        let text_slice = slice::from_raw_parts(text_ptr as *const u8, text_len);
+        match std::str::from_utf8(text_slice) {
+            Ok(s) => s,
+            Err(_) => {
+                return allocate_error_output(output_ptr, output_len, "Input is not valid UTF-8")
             }
         }
+    };
+
+    match validate_syntax(text) {
+        Ok(()) => allocate_success_output(output_ptr, output_len, r#"{"status":"ok"}"#),
         Err(e) => {
-            // Compilation error - return structured error JSON
             let error_json = serde_json::json!({
                 "status": "error",
-                "code": "CompilationError",
+                "code": e.code(),
                 "message": format!("{}", e)
             });
-            match serde_json::to_string(&error_json) {
-                Ok(json) => {
-                    allocate_output(output_ptr, output_len, &json);
-                    1 // Return error code
-                }
-                Err(_) => allocate_error_output(
-                    output_ptr,
-                    output_len,
-                    "Failed to serialize error response",
-                ),
-            }
+            allocate_output(output_ptr, output_len, &error_json.to_string());
+            1
         }
     }
 }
 
+/// Build the structured error JSON for a compilation error
+///
+/// `code` is the specific error kind from `Error::code`, e.g. `unknown_region`.
+/// Metadata conflicts additionally carry a `conflict` object with both values
+/// and their source locations so hosts can render a diff.
+fn compilation_error_json(error: &Error) -> serde_json::Value {
+    let mut error_json = serde_json::json!({
+        "status": "error",
+        "code": error.code(),
+        "message": format!("{}", error)
+    });
+    if let Error::Parser(ParseError::MetadataConflict(conflict)) = error {
+        error_json["conflict"] = serde_json::json!(conflict);
+    }
+    error_json
+}
+
 /// Helper function to allocate successful output
 fn allocate_success_output(
     output_ptr: *mut *mut c_char,
@@ -126,25 +322,41 @@ fn allocate_error_output(
     output_len: *mut usize,
     message: &str,
 ) -> c_int {
+    allocate_output(output_ptr, output_len, &error_json(message));
+    1 // Error code
+}
+
+/// Build the error JSON for a failure outside the compiler
+fn error_json(message: &str) -> String {
     let error_json = serde_json::json!({
         "status": "error",
         "code": "FFIError",
         "message": message
     });
-    let json = serde_json::to_string(&error_json).unwrap_or_else(|_| {
+    serde_json::to_string(&error_json).unwrap_or_else(|_| {
         r#"{"status":"error","code":"FFIError","message":"Unknown error"}"#.to_string()
-    });
-    allocate_output(output_ptr, output_len, &json);
-    1 // Error code
+    })
 }
 
 /// Helper function to allocate output string
+///
+/// Copies `content` and appends a NUL terminator; `output_len` excludes it.
+/// Writes nothing when either output pointer is null, and a null pointer with
+/// length 0 when allocation fails.
 fn allocate_output(output_ptr: *mut *mut c_char, output_len: *mut usize, content: &str) {
+    if output_ptr.is_null() || output_len.is_null() {
+        return;
+    }
     unsafe {
-        let c_string = CString::new(content).unwrap();
-        let len = c_string.as_bytes().len();
+        let len = content.len();
         let ptr = libc::malloc(len + 1) as *mut c_char;
-        ptr.copy_from(c_string.as_ptr(), len + 1);
+        if ptr.is_null() {
+            *output_ptr = ptr;
+            *output_len = 0;
+            return;
+        }
+        ptr.copy_from(content.as_ptr() as *const c_char, len);
+        *ptr.add(len) = 0;
         *output_ptr = ptr;
         *output_len = len;
     }
@@ -166,3 +378,236 @@ pub unsafe extern "C" fn insign_free(ptr: *mut c_void, _len: usize) {
         libc::free(ptr);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    /// Drive insign_compile_json through the raw ABI and return (code, output)
+    fn call_compile_json(input: &str) -> (c_int, serde_json::Value) {
+        let mut output: *mut c_char = ptr::null_mut();
+        let mut output_len: usize = 0;
+        let code = insign_compile_json(
+            input.as_ptr() as *const c_char,
+            input.len(),
+            &mut output,
+            &mut output_len,
+        );
+        let bytes = unsafe { slice::from_raw_parts(output as *const u8, output_len) };
+        let value = serde_json::from_slice(bytes).unwrap();
+        unsafe { insign_free(output as *mut c_void, output_len) };
+        (code, value)
+    }
+
+    #[test]
+    fn test_compile_json_success() {
+        let (code, value) =
+            call_compile_json(r#"[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])"}]"#);
+        assert_eq!(code, 0);
+        assert!(value.get("a").is_some());
+    }
+
+    #[test]
+    fn test_compile_json_envelope() {
+        let (code, value) = call_compile_json(
+            r#"{"units":[{"pos":[0,0,0],"text":"@rc([0,0,0],[1,1,1])"}],"options":{"keep_empty_anonymous":true}}"#,
+        );
+        assert_eq!(code, 0);
+        assert!(value.get("__anon_0_0").is_some());
+
+        let (code, value) = call_compile_json(
+            r#"{"units":[],"strict_options":true,"options":{"keep_empty_anonymus":true}}"#,
+        );
+        assert_eq!(code, 1);
+        assert!(value["message"]
+            .as_str()
+            .unwrap()
+            .contains("keep_empty_anonymous"));
+    }
+
+    #[test]
+    fn test_compile_json_honors_ordering() {
+        let units = r#"[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])\n@b=rc([0,0,0],[9,9,9])"}]"#;
+        let json = |options: &str| {
+            let input = format!(r#"{{"units":{},"options":{}}}"#, units, options);
+            let (code, json) = compile_json_response(input.as_bytes());
+            assert_eq!(code, 0);
+            json
+        };
+
+        let by_name = json("{}");
+        assert!(by_name.find("\"a\"").unwrap() < by_name.find("\"b\"").unwrap());
+        let by_volume = json(r#"{"ordering":"volume_desc"}"#);
+        assert!(by_volume.find("\"b\"").unwrap() < by_volume.find("\"a\"").unwrap());
+    }
+
+    #[test]
+    fn test_compile_json_preserves_metadata_order() {
+        let input = r#"{"units":[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])\n#z=1\n#a=2"}],"options":{"preserve_metadata_order":true}}"#;
+        let (code, json) = compile_json_response(input.as_bytes());
+        assert_eq!(code, 0);
+        assert!(json.contains(r#""metadata":{"z":1,"a":2}"#), "{}", json);
+    }
+
+    #[test]
+    fn test_metadata_conflict_is_structured() {
+        let input = r#"[
+            {"pos":[0,0,0],"text":"@r=rc([0,0,0],[1,1,1])\n#label=\"First\""},
+            {"pos":[0,0,0],"text":"@r=rc([2,2,2],[3,3,3])\n#label=\"Second\""}
+        ]"#;
+        let (code, value) = call_compile_json(input);
+
+        assert_eq!(code, 1);
+        let conflict = &value["conflict"];
+        assert_eq!(conflict["region"], "r");
+        assert_eq!(conflict["key"], "label");
+        assert_eq!(conflict["first_value"], "First");
+        assert_eq!(conflict["first_source"]["tuple_idx"], 0);
+        assert_eq!(conflict["first_source"]["stmt_idx"], 1);
+        assert_eq!(conflict["conflict_value"], "Second");
+        assert_eq!(conflict["conflict_source"]["tuple_idx"], 1);
+    }
+
+    #[test]
+    fn test_validate_syntax() {
+        let call = |text: &str| {
+            let mut output: *mut c_char = ptr::null_mut();
+            let mut output_len: usize = 0;
+            let code = insign_validate_syntax(
+                text.as_ptr() as *const c_char,
+                text.len(),
+                &mut output,
+                &mut output_len,
+            );
+            let bytes = unsafe { slice::from_raw_parts(output as *const u8, output_len) };
+            let value: serde_json::Value = serde_json::from_slice(bytes).unwrap();
+            unsafe { insign_free(output as *mut c_void, output_len) };
+            (code, value)
+        };
+
+        let (code, value) = call("@a=rc([0,0,0],[1,1,1])\n#label=\"ok\"");
+        assert_eq!(code, 0);
+        assert_eq!(value["status"], "ok");
+
+        let (code, value) = call("@a=rc([0,0,0],[1,1");
+        assert_eq!(code, 1);
+        assert_eq!(value["code"], "expected");
+    }
+
+    #[test]
+    fn test_nul_characters_round_trip() {
+        // The DSL text `#label="x\u0000y"` puts a NUL character into the metadata
+        let input = r#"[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])\n#label=\"x\\u0000y\""}]"#;
+        let (code, value) = call_compile_json(input);
+        assert_eq!(code, 0);
+        assert_eq!(value["a"]["metadata"]["label"], "x\u{0}y");
+    }
+
+    #[test]
+    fn test_null_output_pointers_are_not_written() {
+        let input = "[]";
+        let mut output_len: usize = 0;
+        let code = insign_compile_json(
+            input.as_ptr() as *const c_char,
+            input.len(),
+            ptr::null_mut(),
+            &mut output_len,
+        );
+        assert_ne!(code, 0);
+        assert_eq!(output_len, 0);
+    }
+
+    #[test]
+    fn test_compile_json_into_buffer() {
+        let input = r#"[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])"}]"#;
+        let call = |buf: &mut [u8], written: &mut usize| unsafe {
+            insign_compile_json_into(
+                input.as_ptr() as *const c_char,
+                input.len(),
+                buf.as_mut_ptr() as *mut c_char,
+                buf.len(),
+                written,
+            )
+        };
+
+        // Too small: the required size is reported and the buffer left alone
+        let mut small = [0u8; 8];
+        let mut required = 0;
+        assert_eq!(call(&mut small, &mut required), INSIGN_BUFFER_TOO_SMALL);
+        assert!(required > small.len());
+        assert_eq!(small, [0u8; 8]);
+
+        let mut buf = vec![0u8; required];
+        let mut written = 0;
+        assert_eq!(call(&mut buf, &mut written), 0);
+        assert_eq!(written, required);
+        let value: serde_json::Value = serde_json::from_slice(&buf[..written]).unwrap();
+        assert_eq!(value, call_compile_json(input).1);
+
+        // Errors are written like results
+        let input = "not json";
+        let mut buf = vec![0u8; 256];
+        let code = unsafe {
+            insign_compile_json_into(
+                input.as_ptr() as *const c_char,
+                input.len(),
+                buf.as_mut_ptr() as *mut c_char,
+                buf.len(),
+                &mut written,
+            )
+        };
+        assert_eq!(code, 1);
+        let value: serde_json::Value = serde_json::from_slice(&buf[..written]).unwrap();
+        assert_eq!(value["code"], "FFIError");
+    }
+
+    /// The last error of this thread as a Rust string
+    fn last_error() -> Option<String> {
+        let message = insign_last_error();
+        if message.is_null() {
+            return None;
+        }
+        Some(
+            unsafe { CStr::from_ptr(message) }
+                .to_str()
+                .unwrap()
+                .to_string(),
+        )
+    }
+
+    #[test]
+    fn test_compile_check_sets_last_error() {
+        let check = |input: &str| unsafe {
+            insign_compile_check(input.as_ptr() as *const c_char, input.len())
+        };
+
+        let broken = r#"[{"pos":[0,0,0],"text":"@a=b"}]"#;
+        assert_eq!(check(broken), 1);
+        assert!(last_error().unwrap().contains("Unknown region 'b'"));
+
+        // Success clears the message
+        assert_eq!(
+            check(r#"[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])"}]"#),
+            0
+        );
+        assert_eq!(last_error(), None);
+
+        assert_eq!(check("not json"), 1);
+        assert!(last_error().unwrap().starts_with("JSON parse error"));
+        assert_eq!(unsafe { insign_compile_check(ptr::null(), 0) }, 1);
+        assert_eq!(
+            last_error().as_deref(),
+            Some("Invalid null pointer parameters")
+        );
+
+        // The JSON entry point sets the same message alongside its output
+        let (code, value) = call_compile_json(broken);
+        assert_eq!(code, 1);
+        assert_eq!(value["code"], "unknown_region");
+        assert!(value["message"]
+            .as_str()
+            .unwrap()
+            .contains(&last_error().unwrap()));
+    }
+}
@@ -3,12 +3,20 @@
 //! This crate exports a WASM interface for the Insign DSL compiler,
 //! allowing integration with web browsers and Node.js applications.
 
-use insign::compile;
+use insign::ast::{GeomStmtWide, MetaStmt, SourceLocation};
+use insign::envelope::parse_compile_request;
+use insign::parser::geom::GeometryStatement;
+use insign::parser::meta::MetadataStatement;
+use insign::{
+    compile, compile_ordered, parse_units_with_options, validate_syntax as validate_dsl_syntax,
+    Error, ParseError,
+};
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
-/// Input format for JSON compilation
-#[derive(serde::Deserialize)]
-struct CompileInput {
+/// One input unit of `compile_to_value`: a sign position and its DSL text
+#[derive(Deserialize)]
+struct UnitInput {
     pos: [i32; 3],
     text: String,
 }
@@ -19,35 +27,51 @@ pub fn abi_version() -> u32 {
     1
 }
 
+/// Returns the version of the insign-wasm package, e.g. `"0.1.1"`
+#[wasm_bindgen]
+pub fn get_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+/// Makes Rust panics log their message and a stack trace with `console.error`
+/// instead of an opaque `unreachable` trap. `compile_json` calls it on first
+/// use; calling it again has no effect.
+#[wasm_bindgen]
+pub fn set_panic_hook() {
+    console_error_panic_hook::set_once();
+}
+
 /// Compiles input JSON string to output JSON string
 ///
 /// # Arguments
-/// * `input` - UTF-8 JSON string (array of {pos: [x,y,z], text: "..."})
+/// * `input` - UTF-8 JSON string: an array of {pos: [x,y,z], text: "..."}, or an
+///   envelope `{units, options, pretty, strict_options}` (see `insign::envelope`)
 ///
 /// # Returns
 /// * JSON string - either success result or structured error JSON
 /// * Never throws exceptions - all errors are returned as JSON
 #[wasm_bindgen]
 pub fn compile_json(input: String) -> String {
+    set_panic_hook();
+
     // Parse JSON input
-    let input_array: Vec<CompileInput> = match serde_json::from_str(&input) {
-        Ok(arr) => arr,
+    let request = match parse_compile_request(&input) {
+        Ok(request) => request,
         Err(e) => {
             return create_error_json("JSONParseError", &format!("JSON parse error: {}", e));
         }
     };
 
-    // Convert to insign-core format
-    let units: Vec<([i32; 3], String)> = input_array
-        .into_iter()
-        .map(|input| (input.pos, input.text))
-        .collect();
-
-    // Compile using insign-core
-    match compile(&units) {
+    // Compile using insign-core, in the entry order the options ask for
+    match compile_ordered(&request.units, &request.options) {
         Ok(dsl_map) => {
             // Success - serialize output
-            match serde_json::to_string(&dsl_map) {
+            let json = if request.pretty {
+                serde_json::to_string_pretty(&dsl_map)
+            } else {
+                serde_json::to_string(&dsl_map)
+            };
+            match json {
                 Ok(json) => json,
                 Err(e) => create_error_json(
                     "SerializationError",
@@ -57,11 +81,167 @@ pub fn compile_json(input: String) -> String {
         }
         Err(e) => {
             // Compilation error - return structured error JSON
-            create_error_json("CompilationError", &format!("{}", e))
+            create_compilation_error_json(&e)
+        }
+    }
+}
+
+/// Compiles a JS array of units to a JS object, without JSON round trips
+///
+/// # Arguments
+/// * `input` - JS array of `{pos: [x,y,z], text: "..."}`
+///
+/// # Returns
+/// * The compiled region map as a plain JS object, shaped like the output of
+///   `compile_json`
+/// * Throws a JS `Error` with `code` (`InputError`, or the compile error kind
+///   such as `unknown_region`) and `message`; metadata conflicts also carry
+///   `conflict` as in `compile_json`
+#[wasm_bindgen]
+pub fn compile_to_value(input: JsValue) -> Result<JsValue, JsValue> {
+    let units: Vec<UnitInput> = serde_wasm_bindgen::from_value(input)
+        .map_err(|e| js_error("InputError", &format!("Invalid input: {}", e)))?;
+    let units: Vec<([i32; 3], String)> = units.into_iter().map(|u| (u.pos, u.text)).collect();
+
+    match compile(&units) {
+        Ok(dsl_map) => {
+            to_js_value(&dsl_map).map_err(|e| js_error("SerializationError", &format!("{}", e)))
+        }
+        Err(e) => {
+            let error = js_error(e.code(), &format!("{}", e));
+            if let Error::Parser(ParseError::MetadataConflict(conflict)) = &e {
+                if let Ok(conflict) = to_js_value(conflict) {
+                    let _ = js_sys::Reflect::set(&error, &"conflict".into(), &conflict);
+                }
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Convert to a JS value with plain objects for maps, as `JSON.parse` would give
+fn to_js_value<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, serde_wasm_bindgen::Error> {
+    value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+}
+
+/// Build a JS `Error` carrying a `code` property
+fn js_error(code: &str, message: &str) -> JsValue {
+    let error: JsValue = js_sys::Error::new(message).into();
+    let _ = js_sys::Reflect::set(&error, &"code".into(), &code.into());
+    error
+}
+
+/// Checks that one sign's DSL text parses, without compiling it
+///
+/// # Arguments
+/// * `text` - DSL text of a single sign (not JSON)
+///
+/// # Returns
+/// * `{"status":"ok"}`, or error JSON for the first parse error, with its kind
+///   (e.g. `expected`) as `code`
+#[wasm_bindgen]
+pub fn validate_syntax(text: String) -> String {
+    match validate_dsl_syntax(&text) {
+        Ok(()) => r#"{"status":"ok"}"#.to_string(),
+        Err(e) => create_error_json(e.code(), &format!("{}", e)),
+    }
+}
+
+/// Parses input units into their statements for editor tooling, without
+/// evaluating anything
+///
+/// # Arguments
+/// * `input` - the same JSON as `compile_json`
+///
+/// # Returns
+/// * `{"status":"ok","geometry":[...],"metadata":[...]}` listing the statements
+///   in input order with their `source`, or error JSON (`JSONParseError`, or
+///   the compile error kind) as from `compile_json`
+#[wasm_bindgen]
+pub fn parse_only(input: String) -> String {
+    let request = match parse_compile_request(&input) {
+        Ok(request) => request,
+        Err(e) => {
+            return create_error_json("JSONParseError", &format!("JSON parse error: {}", e));
+        }
+    };
+
+    match parse_units_with_options(&request.units, &request.options) {
+        Ok(program) => {
+            let mut geometry: Vec<&GeomStmtWide> = program
+                .geom_stmts
+                .iter()
+                .chain(&program.constants)
+                .collect();
+            geometry.sort_by_key(|stmt| (stmt.tuple_idx, stmt.stmt_idx));
+            let output = serde_json::json!({
+                "status": "ok",
+                "geometry": geometry.into_iter().map(geometry_json).collect::<Vec<_>>(),
+                "metadata": program.meta_stmts.iter().map(metadata_json).collect::<Vec<_>>(),
+            });
+            output.to_string()
         }
+        Err(e) => create_compilation_error_json(&e),
     }
 }
 
+/// `{region, kind, source}` of a geometry statement; `region` is null for
+/// anonymous boxes
+fn geometry_json(stmt: &GeomStmtWide) -> serde_json::Value {
+    let kind = match stmt.statement {
+        GeometryStatement::RelativeCoordinate { .. } => "rc",
+        GeometryStatement::AbsoluteCoordinate { .. } => "ac",
+        GeometryStatement::Expression { .. } => "expression",
+        GeometryStatement::Shell { .. } => "shell",
+        GeometryStatement::Sphere { .. } => "sphere",
+        GeometryStatement::Const { .. } => "const",
+        GeometryStatement::Alias { .. } => "alias",
+    };
+    serde_json::json!({
+        "region": stmt.region(),
+        "kind": kind,
+        "source": SourceLocation::new(stmt.tuple_idx, stmt.stmt_idx),
+    })
+}
+
+/// `{target, key, kind, source}` of a metadata statement; `target` is null for
+/// the current region and `kind` is `set`, `default` or `delete`
+fn metadata_json(stmt: &MetaStmt) -> serde_json::Value {
+    let (key, kind) = match &stmt.statement {
+        MetadataStatement::Current { key, default, .. }
+        | MetadataStatement::Targeted { key, default, .. } => {
+            (key, if *default { "default" } else { "set" })
+        }
+        MetadataStatement::Delete { key, .. } => (key, "delete"),
+    };
+    serde_json::json!({
+        "target": stmt.target(),
+        "key": key,
+        "kind": kind,
+        "source": SourceLocation::new(stmt.tuple_idx, stmt.stmt_idx),
+    })
+}
+
+/// Helper function to create structured error JSON for a compilation error
+///
+/// `code` is the specific error kind from `Error::code`, e.g. `unknown_region`.
+/// Metadata conflicts additionally carry a `conflict` object with both values
+/// and their source locations so callers can render a diff.
+fn create_compilation_error_json(error: &Error) -> String {
+    let mut error_json = serde_json::json!({
+        "status": "error",
+        "code": error.code(),
+        "message": format!("{}", error)
+    });
+    if let Error::Parser(ParseError::MetadataConflict(conflict)) = error {
+        error_json["conflict"] = serde_json::json!(conflict);
+    }
+
+    serde_json::to_string(&error_json).unwrap_or_else(|_| {
+        create_error_json("SerializationError", "Failed to serialize error response")
+    })
+}
+
 /// Helper function to create structured error JSON
 fn create_error_json(code: &str, message: &str) -> String {
     let error_json = serde_json::json!({
@@ -74,3 +254,164 @@ fn create_error_json(code: &str, message: &str) -> String {
         r#"{"status":"error","code":"UnknownError","message":"Failed to serialize error response"}"#.to_string()
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strict_options_rejects_typos() {
+        let input = r#"{"units":[],"prety":true,"strict_options":true}"#;
+        let output: serde_json::Value =
+            serde_json::from_str(&compile_json(input.to_string())).unwrap();
+
+        assert_eq!(output["code"], "JSONParseError");
+        assert!(output["message"].as_str().unwrap().contains("'prety'"));
+
+        // Lenient by default
+        let input = r#"{"units":[],"prety":true}"#;
+        assert_eq!(compile_json(input.to_string()), "{}");
+    }
+
+    #[test]
+    fn test_compile_json_honors_ordering() {
+        let input = r#"{"units":[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])\n@b=rc([0,0,0],[9,9,9])"}],"options":{"ordering":"volume_desc"}}"#;
+        let output = compile_json(input.to_string());
+        assert!(output.find("\"b\"").unwrap() < output.find("\"a\"").unwrap());
+    }
+
+    #[test]
+    fn test_metadata_conflict_is_structured() {
+        let input = r#"[
+            {"pos":[0,0,0],"text":"@r=rc([0,0,0],[1,1,1])\n#label=\"First\""},
+            {"pos":[0,0,0],"text":"@r=rc([2,2,2],[3,3,3])\n#label=\"Second\""}
+        ]"#;
+        let output: serde_json::Value =
+            serde_json::from_str(&compile_json(input.to_string())).unwrap();
+
+        assert_eq!(output["status"], "error");
+        assert_eq!(output["code"], "metadata_conflict");
+        assert_eq!(output["conflict"]["first_value"], "First");
+        assert_eq!(output["conflict"]["first_source"]["stmt_idx"], 1);
+        assert_eq!(output["conflict"]["conflict_value"], "Second");
+        assert_eq!(output["conflict"]["conflict_source"]["tuple_idx"], 1);
+    }
+
+    #[test]
+    fn test_validate_syntax() {
+        assert_eq!(
+            validate_syntax("@a=rc([0,0,0],[1,1,1])".to_string()),
+            r#"{"status":"ok"}"#
+        );
+
+        let output: serde_json::Value =
+            serde_json::from_str(&validate_syntax("#label=".to_string())).unwrap();
+        assert_eq!(output["status"], "error");
+        assert_eq!(output["code"], "unexpected_end");
+    }
+
+    #[test]
+    fn test_compile_error_codes() {
+        for (text, code) in [
+            ("@a=b", "unknown_region"),
+            ("@a=b\\n@b=a", "cycle_detected"),
+            ("@a=rc([0,0,0],[1,1", "expected"),
+        ] {
+            let input = format!(r#"[{{"pos":[0,0,0],"text":"{}"}}]"#, text);
+            let output: serde_json::Value = serde_json::from_str(&compile_json(input)).unwrap();
+            assert_eq!(output["status"], "error");
+            assert_eq!(output["code"], code, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_only_lists_statements() {
+        let input = r#"[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])\n#doc=1\n@b=a+a"},
+                        {"pos":[5,0,0],"text":"@rc([0,0,0],[1,1,1])\n#a:-doc"}]"#;
+        let output: serde_json::Value =
+            serde_json::from_str(&parse_only(input.to_string())).unwrap();
+
+        assert_eq!(output["status"], "ok");
+        assert_eq!(output["geometry"].as_array().unwrap().len(), 3);
+        assert_eq!(output["metadata"].as_array().unwrap().len(), 2);
+        assert_eq!(output["geometry"][1]["kind"], "expression");
+        assert_eq!(output["geometry"][2]["region"], serde_json::Value::Null);
+        assert_eq!(output["metadata"][1]["kind"], "delete");
+        assert_eq!(output["metadata"][1]["source"]["tuple_idx"], 1);
+
+        let output: serde_json::Value = serde_json::from_str(&parse_only(
+            r#"[{"pos":[0,0,0],"text":"@const o=[1,1,1]\n@a=rc([0,0,0],o)"}]"#.to_string(),
+        ))
+        .unwrap();
+        assert_eq!(output["geometry"][0]["kind"], "const");
+        assert_eq!(output["geometry"][0]["region"], serde_json::Value::Null);
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm_tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn units(json: &str) -> JsValue {
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        to_js_value(&value).unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_compile_to_value() {
+        let input = units(
+            r#"[{"pos":[0,0,0],"text":"@a=rc([0,0,0],[1,1,1])\n#doc=1"},
+                {"pos":[5,0,0],"text":"@b=rc([0,0,0],[1,1,1])"}]"#,
+        );
+        let output = compile_to_value(input).unwrap();
+
+        let keys: Vec<String> = js_sys::Object::keys(output.unchecked_ref())
+            .iter()
+            .map(|key| key.as_string().unwrap())
+            .collect();
+        assert_eq!(keys, vec!["a", "b"]);
+        let a = js_sys::Reflect::get(&output, &"a".into()).unwrap();
+        let metadata = js_sys::Reflect::get(&a, &"metadata".into()).unwrap();
+        assert_eq!(
+            js_sys::Reflect::get(&metadata, &"doc".into()).unwrap(),
+            JsValue::from(1)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_compile_to_value_throws_structured_error() {
+        let error = compile_to_value(units(r#"[{"pos":[0,0,0],"text":"@a=b"}]"#)).unwrap_err();
+        assert!(error.is_instance_of::<js_sys::Error>());
+        assert_eq!(
+            js_sys::Reflect::get(&error, &"code".into()).unwrap(),
+            JsValue::from("unknown_region")
+        );
+
+        let error = compile_to_value(JsValue::from(3)).unwrap_err();
+        assert_eq!(
+            js_sys::Reflect::get(&error, &"code".into()).unwrap(),
+            JsValue::from("InputError")
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_parse_only_shape_in_js() {
+        let input = r#"[{"pos":[0,0,0],"text":"@const o=[1,1,1]\n@a=rc([0,0,0],o)\n#doc=1"}]"#;
+        let output = js_sys::JSON::parse(&parse_only(input.to_string())).unwrap();
+        let get = |value: &JsValue, key: &str| js_sys::Reflect::get(value, &key.into()).unwrap();
+
+        assert_eq!(get(&output, "status"), JsValue::from("ok"));
+        let geometry: js_sys::Array = get(&output, "geometry").unchecked_into();
+        assert_eq!(geometry.length(), 2);
+        assert_eq!(get(&geometry.get(0), "kind"), JsValue::from("const"));
+        assert!(get(&geometry.get(0), "region").is_null());
+        assert_eq!(get(&geometry.get(1), "region"), JsValue::from("a"));
+        let source = get(&geometry.get(1), "source");
+        assert_eq!(get(&source, "stmt_idx"), JsValue::from(1));
+
+        let metadata: js_sys::Array = get(&output, "metadata").unchecked_into();
+        assert_eq!(get(&metadata.get(0), "kind"), JsValue::from("set"));
+        assert!(get(&metadata.get(0), "target").is_null());
+    }
+}
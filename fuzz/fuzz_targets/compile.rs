@@ -0,0 +1,25 @@
+//! Drive `compile` and `validate_syntax` with arbitrary positions and text.
+//!
+//! Run with `cargo fuzz run compile` from the repository root. Errors are
+//! expected; only panics are failures. Add minimized crashes to the
+//! `CRASH_CORPUS` test in `crates/insign-core/src/lib.rs`.
+#![no_main]
+
+use insign::{compile, compile_with_options, validate_syntax, CompileOptions};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|units: Vec<([i32; 3], String)>| {
+    let _ = compile(&units);
+
+    let mut lenient = CompileOptions::default();
+    lenient.allow_trailing_commas = true;
+    lenient.keep_empty_anonymous = true;
+    lenient.current_region_binds_forward = true;
+    lenient.warn_coordinate_style = true;
+    lenient.geometry_keywords_case_insensitive = true;
+    let _ = compile_with_options(&units, &lenient);
+
+    for (_, text) in &units {
+        let _ = validate_syntax(text);
+    }
+});